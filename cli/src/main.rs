@@ -8,6 +8,7 @@ use matex_common::node::ASTGraphGenerator;
 use matex_compiler::cas::{
     eval::runtime::Runtime,
     syntax::{lexer, parser},
+    types::checker::TypeChecker,
 };
 
 use clap::{Parser, Subcommand};
@@ -75,11 +76,17 @@ fn main() {
                 .expect("Couldn't write to dot file!");
         }
 
-        let mut runtime = Runtime::new();
+        if let Err(e) = TypeChecker::new().check(&program) {
+            eprintln!("{}", e);
+            exit(-1);
+        }
 
-        let exit_value = runtime.run(&program);
+        let mut runtime = Runtime::new();
 
-        println!("EXIT VALUE: {:?}", exit_value);
+        match runtime.run(&program) {
+            Ok(exit_value) => println!("EXIT VALUE: {:?}", exit_value),
+            Err(e) => eprintln!("Error occurred:\n{}", e),
+        }
     } else {
         let mut repl = Repl::new();
         let _ = repl.run();
@@ -5,14 +5,17 @@ use matex_common::node::{ASTGraphGenerator, Program};
 use matex_compiler::cas::{
     eval::{
         format::{NormalFormatter, ValueFormatter},
+        latex::LatexFormatter,
         runtime::Runtime,
     },
     syntax::{lexer, parser},
+    types::checker::TypeChecker,
 };
 use rustyline::error::ReadlineError;
 
 pub struct Repl {
     runtime: Runtime,
+    checker: TypeChecker,
 }
 
 impl Repl {
@@ -20,7 +23,8 @@ impl Repl {
         let mut runtime = Runtime::new();
         runtime.add_standard_environment();
         Self {
-            runtime
+            runtime,
+            checker: TypeChecker::new(),
         }
     }
     pub fn run(&mut self) -> Result<(), ReadlineError> {
@@ -36,7 +40,7 @@ impl Repl {
                     continue;
                 };
 
-                let result = command.execute();
+                let result = command.execute(&mut self.runtime);
 
                 match result {
                     CommandResult::Exit => break,
@@ -49,7 +53,18 @@ impl Repl {
             let result = parser.parse();
             match result {
                 Ok(ast) => {
-                    let mut exit_value = self.runtime.run(&ast);
+                    if let Err(e) = self.checker.check(&ast) {
+                        eprintln!("Error occurred:\n{}", e);
+                        continue;
+                    }
+
+                    let mut exit_value = match self.runtime.run(&ast) {
+                        Ok(value) => value,
+                        Err(e) => {
+                            eprintln!("Error occurred:\n{}", e);
+                            continue;
+                        }
+                    };
                     exit_value.rearrange();
 
                     let formatted_value = NormalFormatter::format(&exit_value);
@@ -79,7 +94,7 @@ impl Command {
         }
     }
 
-    pub fn execute(&self) -> CommandResult {
+    pub fn execute(&self, runtime: &mut Runtime) -> CommandResult {
         match self.cmd_type {
             CommandType::Lexer => {
                 for tok in lexer::Lexer::new(&self.input) {
@@ -112,6 +127,41 @@ impl Command {
                 file.write_all(graph_buf.as_bytes())
                     .expect("Couldn't write to dot file!");
             }
+            CommandType::Steps => {
+                let Ok(ast) = self.run_parser() else {
+                    return CommandResult::None
+                };
+
+                let (mut value, steps) = match runtime.run_traced(&ast) {
+                    Ok(result) => result,
+                    Err(e) => {
+                        eprintln!("Error occurred:\n{}", e);
+                        return CommandResult::None;
+                    }
+                };
+                value.rearrange();
+
+                for (i, step) in steps.iter().enumerate() {
+                    println!("{:>3}. {}", i + 1, step);
+                }
+                println!("o> {}", NormalFormatter::format(&value));
+            }
+            CommandType::Latex => {
+                let Ok(ast) = self.run_parser() else {
+                    return CommandResult::None
+                };
+
+                let mut value = match runtime.run(&ast) {
+                    Ok(value) => value,
+                    Err(e) => {
+                        eprintln!("Error occurred:\n{}", e);
+                        return CommandResult::None;
+                    }
+                };
+                value.rearrange();
+
+                println!("o> {}", LatexFormatter::format(&value));
+            }
             CommandType::Exit => return CommandResult::Exit,
         }
         CommandResult::None
@@ -155,6 +205,8 @@ pub enum CommandType {
     Lexer,
     Parser,
     GenerateDot,
+    Steps,
+    Latex,
     Exit,
 }
 
@@ -166,6 +218,8 @@ impl FromStr for CommandType {
             "lexer" | "l" => Ok(CommandType::Lexer),
             "parser" | "p" => Ok(CommandType::Parser),
             "dot" => Ok(CommandType::GenerateDot),
+            "steps" | "s" => Ok(CommandType::Steps),
+            "latex" | "tex" => Ok(CommandType::Latex),
             "quit" | "q" => Ok(CommandType::Exit),
             _ => Err(()),
         }
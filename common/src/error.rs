@@ -1,6 +1,9 @@
 use std::fmt::Display;
 
-use crate::token::{KeywordType, Token, TokenType};
+use crate::{
+    token::{KeywordType, Token, TokenType},
+    util::Position,
+};
 
 #[cfg(target_arch = "wasm32")]
 use serde::Serialize;
@@ -27,6 +30,11 @@ pub enum ParseError {
         message: String,
         actual: Token,
     },
+    /// A token that can't start an expression, e.g. a lone `%`
+    UnexpectedToken {
+        message: String,
+        actual: Token,
+    },
     UnexpectedEndOfStream {
         message: String
     },
@@ -50,7 +58,163 @@ impl Display for ParseError {
             UnexpectedEndOfStream { message } => f.write_str(message),
             NotIdentifier { message, actual: _ } => f.write_str(message),
             NotComparison { message, actual: _ } => f.write_str(message),
+            UnexpectedToken { message, actual: _ } => f.write_str(message),
             EndOfStream => f.write_str("End of stream"),
         }
     }
 }
+
+/// An error while running a program, at the position of the expression that failed.
+#[derive(Debug, Clone)]
+#[cfg_attr(target_arch = "wasm32", derive(Serialize))]
+pub enum RuntimeError {
+    InvalidOperation {
+        message: String,
+        pos: Position,
+    },
+    InvalidAssignment {
+        message: String,
+        pos: Position,
+    },
+    NotBoolean {
+        message: String,
+        pos: Position,
+    },
+    WrongArgumentCount {
+        message: String,
+        expected: usize,
+        actual: usize,
+        pos: Position,
+    },
+    CyclicDependency {
+        message: String,
+        cycle: Vec<String>,
+        pos: Position,
+    },
+    RecursionLimit {
+        message: String,
+        depth: usize,
+        pos: Position,
+    },
+    NoMatchingOverload {
+        message: String,
+        pos: Position,
+    },
+    AmbiguousCall {
+        message: String,
+        candidates: Vec<String>,
+        pos: Position,
+    },
+    OutsideDomain {
+        message: String,
+        pos: Position,
+    },
+    WrongReturnType {
+        message: String,
+        expected: String,
+        actual: String,
+        pos: Position,
+    },
+    UnknownType {
+        message: String,
+        name: String,
+        pos: Position,
+    },
+    InvalidAssumption {
+        message: String,
+        pos: Position,
+    },
+}
+
+impl RuntimeError {
+    pub fn pos(&self) -> Position {
+        use RuntimeError::*;
+        match self {
+            InvalidOperation { pos, .. }
+            | InvalidAssignment { pos, .. }
+            | NotBoolean { pos, .. }
+            | WrongArgumentCount { pos, .. }
+            | CyclicDependency { pos, .. }
+            | RecursionLimit { pos, .. }
+            | NoMatchingOverload { pos, .. }
+            | AmbiguousCall { pos, .. }
+            | OutsideDomain { pos, .. }
+            | WrongReturnType { pos, .. }
+            | UnknownType { pos, .. }
+            | InvalidAssumption { pos, .. } => *pos,
+        }
+    }
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use RuntimeError::*;
+        let message = match self {
+            InvalidOperation { message, .. }
+            | InvalidAssignment { message, .. }
+            | NotBoolean { message, .. }
+            | WrongArgumentCount { message, .. }
+            | CyclicDependency { message, .. }
+            | RecursionLimit { message, .. }
+            | NoMatchingOverload { message, .. }
+            | AmbiguousCall { message, .. }
+            | OutsideDomain { message, .. }
+            | WrongReturnType { message, .. }
+            | UnknownType { message, .. }
+            | InvalidAssumption { message, .. } => message,
+        };
+        write!(f, "{} at {}", message, self.pos())
+    }
+}
+
+/// An error found by the type checker, before the program runs.
+#[derive(Debug, Clone)]
+#[cfg_attr(target_arch = "wasm32", derive(Serialize))]
+pub enum TypeError {
+    UnknownType {
+        message: String,
+        name: String,
+        pos: Position,
+    },
+    Mismatch {
+        message: String,
+        expected: String,
+        actual: String,
+        pos: Position,
+    },
+    WrongArgumentCount {
+        message: String,
+        expected: usize,
+        actual: usize,
+        pos: Position,
+    },
+    NoMatchingOverload {
+        message: String,
+        pos: Position,
+    },
+}
+
+impl TypeError {
+    pub fn pos(&self) -> Position {
+        use TypeError::*;
+        match self {
+            UnknownType { pos, .. }
+            | Mismatch { pos, .. }
+            | WrongArgumentCount { pos, .. }
+            | NoMatchingOverload { pos, .. } => *pos,
+        }
+    }
+}
+
+impl Display for TypeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use TypeError::*;
+        let message = match self {
+            UnknownType { message, .. }
+            | Mismatch { message, .. }
+            | WrongArgumentCount { message, .. }
+            | NoMatchingOverload { message, .. } => message,
+        };
+        write!(f, "{} at {}", message, self.pos())
+    }
+}
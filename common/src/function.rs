@@ -1,10 +1,15 @@
-use crate::node::Expr;
+use crate::{node::Expr, util::Position};
 
 #[derive(Debug, Clone)]
 pub struct Function {
     pub name: String,
     pub params: Vec<Parameter>,
+    /// The declared type of the result, empty if there is none
+    pub return_type: String,
+    /// A condition on the parameters, e.g. `x > 0`
+    pub domain: Option<Expr>,
     pub body: Expr,
+    pub pos: Position,
 }
 
 #[derive(Debug, Clone)]
@@ -12,4 +17,5 @@ pub struct Function {
 pub struct Parameter {
     pub name: String,
     pub type_name: String,
+    pub pos: Position,
 }
@@ -1,5 +1,7 @@
 pub mod error;
 pub mod function;
 pub mod node;
+pub mod rule;
 pub mod token;
+pub mod types;
 pub mod util;
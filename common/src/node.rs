@@ -1,6 +1,12 @@
 use std::fmt::{Error, Write};
 
-use crate::{function::Function, token::TokenType};
+use crate::{
+    function::Function,
+    rule::Rule,
+    token::{KeywordType, TokenType},
+    types::TypeAlias,
+    util::Position,
+};
 
 type Statements = Vec<Statement>;
 
@@ -11,6 +17,8 @@ pub struct Program(pub Statements);
 pub enum Statement {
     FunctionDefinition(Function),
     UnsetVariable(String),
+    RuleDefinition(Rule),
+    TypeDefinition(TypeAlias),
     Expression(Expr),
 }
 
@@ -19,10 +27,31 @@ pub enum Expr {
     Number(f64),
     Variable(String),
 
+    // The m of 5 m, only read after a number
+    Unit(String),
+
     Vector(Vec<Expr>),
 
+    // {1, 2, x}
+    Set(Vec<Expr>),
+
+    // [0, 1), or [0 .. 1] if closed
+    Interval {
+        lower: Box<Expr>,
+        upper: Box<Expr>,
+        lower_closed: bool,
+        upper_closed: bool,
+    },
+
+    // {x in Z | x^2 < 10}
+    SetBuilder {
+        variable: String,
+        domain: Option<Box<Expr>>,
+        condition: Box<Expr>,
+    },
+
     // Currently only negation unary (-expr)
-    Unary(Box<Expr>),
+    Unary(Box<Expr>, Position),
 
     Simplify(Box<Expr>),
 
@@ -30,22 +59,39 @@ pub enum Expr {
         left: Box<Expr>,
         operation: BinOp,
         right: Box<Expr>,
+        pos: Position,
     },
 
     Assignment {
         holder: Box<Expr>,
         value: Box<Expr>,
+        pos: Position,
     },
 
     If {
         condition: Box<Expr>,
         body: Box<Expr>,
         else_body: Box<Expr>,
+        pos: Position,
     },
 
     FunctionCall {
         name: String,
         args: Vec<Expr>,
+        pos: Position,
+    },
+
+    // with assume(x > 0) { sqrt(x^2) }
+    With {
+        facts: Vec<Expr>,
+        body: Box<Expr>,
+        pos: Position,
+    },
+
+    // expr | x = 2, y = 3
+    Substitution {
+        expr: Box<Expr>,
+        bindings: Vec<(String, Expr)>,
     },
 }
 
@@ -55,6 +101,8 @@ pub enum BinOp {
     Subtract,
     Multiply,
     Divide,
+    // a % m
+    Modulo,
     Power,
 
     Equal,
@@ -64,6 +112,9 @@ pub enum BinOp {
     Greater,
     GreaterEqual,
 
+    // x in S
+    In,
+
     Assignment,
 
     None,
@@ -74,10 +125,10 @@ impl BinOp {
         use BinOp::*;
         match self {
             Add | Subtract => Precedence::Term,
-            Multiply | Divide => Precedence::Factor,
+            Multiply | Divide | Modulo => Precedence::Factor,
             Power => Precedence::Exponent,
 
-            Equal | Less | LessEqual | Greater | GreaterEqual => {
+            Equal | Less | LessEqual | Greater | GreaterEqual | In => {
                 Precedence::Comparison
             }
 
@@ -96,12 +147,14 @@ impl From<&TokenType> for BinOp {
             TokenType::Minus => Subtract,
             TokenType::Star => Multiply,
             TokenType::Slash => Divide,
+            TokenType::Percent => Modulo,
             TokenType::Caret => Power,
             TokenType::EqualEqual => Equal,
             TokenType::Less => Less,
             TokenType::LessEqual => LessEqual,
             TokenType::Greater => Greater,
             TokenType::GreaterEqual => GreaterEqual,
+            TokenType::Keyword(KeywordType::In) => In,
             _ => None,
         }
     }
@@ -177,11 +230,7 @@ impl<'a, W: Write> Visitor<Result<u32, Error>> for ASTGraphGenerator<'a, W> {
     fn visit_statement(&mut self, statement: &Statement) -> Result<u32, Error> {
         let current = self.count;
         match statement {
-            Statement::FunctionDefinition(Function {
-                name,
-                params: _,
-                body,
-            }) => {
+            Statement::FunctionDefinition(Function { name, body, .. }) => {
                 self.create_node(&format!("func: {}", name))?;
 
                 let body = self.visit_expr(body)?;
@@ -191,6 +240,30 @@ impl<'a, W: Write> Visitor<Result<u32, Error>> for ASTGraphGenerator<'a, W> {
             Statement::UnsetVariable(symbol) => {
                 self.create_node(&format!("unset: {}", symbol))?;
             }
+            Statement::RuleDefinition(Rule {
+                name,
+                pattern,
+                replacement,
+                condition,
+            }) => {
+                self.create_node(&format!("rule: {}", name.as_deref().unwrap_or("")))?;
+
+                let pattern = self.visit_expr(pattern)?;
+                let replacement = self.visit_expr(replacement)?;
+
+                self.create_edge_label(current, pattern, "pattern")?;
+                self.create_edge_label(current, replacement, "replacement")?;
+
+                if let Some(condition) = condition {
+                    let condition = self.visit_expr(condition)?;
+                    self.create_edge_label(current, condition, "condition")?;
+                }
+            }
+            Statement::TypeDefinition(TypeAlias {
+                name, type_name, ..
+            }) => {
+                self.create_node(&format!("type: {} = {}", name, type_name))?;
+            }
             Statement::Expression(expr) => {
                 self.visit_expr(expr)?;
                 self.count += 1;
@@ -211,13 +284,57 @@ impl<'a, W: Write> Visitor<Result<u32, Error>> for ASTGraphGenerator<'a, W> {
                     self.create_edge_label(current, node, &index.to_string())?;
                 }
             }
+            Expr::Set(elements) => {
+                self.create_node("{}")?;
+
+                for (index, node) in elements.iter().enumerate() {
+                    let node = self.visit_expr(node)?;
+                    self.create_edge_label(current, node, &index.to_string())?;
+                }
+            }
+            Expr::Interval {
+                lower,
+                upper,
+                lower_closed,
+                upper_closed,
+            } => {
+                self.create_node(&format!(
+                    "interval: {}{}",
+                    if *lower_closed { "[" } else { "(" },
+                    if *upper_closed { "]" } else { ")" }
+                ))?;
+
+                let lower = self.visit_expr(lower)?;
+                let upper = self.visit_expr(upper)?;
+
+                self.create_edge_label(current, lower, "lower")?;
+                self.create_edge_label(current, upper, "upper")?;
+            }
+            Expr::SetBuilder {
+                variable,
+                domain,
+                condition,
+            } => {
+                self.create_node(&format!("set: {}", variable))?;
+
+                if let Some(domain) = domain {
+                    let domain = self.visit_expr(domain)?;
+                    self.create_edge_label(current, domain, "domain")?;
+                }
+
+                let condition = self.visit_expr(condition)?;
+                self.create_edge_label(current, condition, "condition")?;
+            }
             Expr::Number(n) => {
                 self.create_node(&format!("num: {}", n))?;
             }
             Expr::Variable(name) => {
                 self.create_node(&format!("var: {}", name))?;
             }
-            Expr::Unary(expr) => {
+            Expr::Unit(name) => {
+                self.create_node(&format!("unit: {}", name))?;
+            }
+            Expr::Unary(expr, _) => {
                 self.create_node("unary (-)")?;
 
                 let expr = self.visit_expr(expr)?;
@@ -235,6 +352,7 @@ impl<'a, W: Write> Visitor<Result<u32, Error>> for ASTGraphGenerator<'a, W> {
                 left,
                 operation,
                 right,
+                pos: _,
             } => {
                 self.create_node(&format!("{:?}", operation))?;
 
@@ -257,7 +375,7 @@ impl<'a, W: Write> Visitor<Result<u32, Error>> for ASTGraphGenerator<'a, W> {
                 }
             }
 
-            Expr::Assignment { holder, value } => {
+            Expr::Assignment { holder, value, pos: _ } => {
                 self.create_node("Assignment")?;
 
                 let holder = self.visit_expr(holder)?;
@@ -271,6 +389,7 @@ impl<'a, W: Write> Visitor<Result<u32, Error>> for ASTGraphGenerator<'a, W> {
                 condition,
                 body,
                 else_body,
+                pos: _,
             } => {
                 self.create_node("if")?;
 
@@ -283,7 +402,7 @@ impl<'a, W: Write> Visitor<Result<u32, Error>> for ASTGraphGenerator<'a, W> {
                 self.create_edge_label(current, body, "truthy")?;
                 self.create_edge_label(current, else_body, "falsy")?;
             }
-            Expr::FunctionCall { name, args } => {
+            Expr::FunctionCall { name, args, pos: _ } => {
                 self.create_node(&format!("func_call: {}", name))?;
 
                 for (index, node) in args.iter().enumerate() {
@@ -291,6 +410,32 @@ impl<'a, W: Write> Visitor<Result<u32, Error>> for ASTGraphGenerator<'a, W> {
                     self.create_edge_label(current, target_id, &index.to_string())?;
                 }
             }
+            Expr::With {
+                facts,
+                body,
+                pos: _,
+            } => {
+                self.create_node("with")?;
+
+                for (index, node) in facts.iter().enumerate() {
+                    let fact = self.visit_expr(node)?;
+                    self.create_edge_label(current, fact, &format!("fact {}", index))?;
+                }
+
+                let body = self.visit_expr(body)?;
+                self.create_edge_label(current, body, "body")?;
+            }
+            Expr::Substitution { expr, bindings } => {
+                self.create_node("substitution")?;
+
+                let expr = self.visit_expr(expr)?;
+                self.create_edge_label(current, expr, "expr")?;
+
+                for (name, value) in bindings {
+                    let value = self.visit_expr(value)?;
+                    self.create_edge_label(current, value, name)?;
+                }
+            }
         }
         Ok(current)
     }
@@ -0,0 +1,12 @@
+use crate::node::Expr;
+
+/// A user-defined rewrite rule, `rule name: pattern -> replacement where condition`.
+///
+/// Every symbol in the pattern is a pattern variable.
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub name: Option<String>,
+    pub pattern: Expr,
+    pub replacement: Expr,
+    pub condition: Option<Expr>,
+}
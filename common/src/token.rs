@@ -34,7 +34,9 @@ pub enum TokenType {
     Minus,
     Star,
     Slash,
+    Percent,
     Caret,
+    Bang,
 
     Equal,
     EqualEqual,
@@ -43,6 +45,9 @@ pub enum TokenType {
     LessEqual,
     GreaterEqual,
 
+    Arrow,
+    Pipe,
+
     Colon,
     Semicolon,
 
@@ -74,6 +79,11 @@ pub enum KeywordType {
     Else,
     Then,
     Simplify,
+    Rule,
+    Where,
+    Type,
+    With,
+    In,
 }
 
 impl FromStr for KeywordType {
@@ -85,6 +95,11 @@ impl FromStr for KeywordType {
             "else" => Ok(KeywordType::Else),
             "then" => Ok(KeywordType::Then),
             "simplify" => Ok(KeywordType::Simplify),
+            "rule" => Ok(KeywordType::Rule),
+            "where" => Ok(KeywordType::Where),
+            "type" => Ok(KeywordType::Type),
+            "with" => Ok(KeywordType::With),
+            "in" => Ok(KeywordType::In),
             _ => Err(()),
         }
     }
@@ -0,0 +1,11 @@
+use crate::util::Position;
+
+/// A name for a type, `type Num = integer | rational | real`.
+///
+/// Types are written as they are in parameter declarations, one or several types separated by `|`.
+#[derive(Debug, Clone)]
+pub struct TypeAlias {
+    pub name: String,
+    pub type_name: String,
+    pub pos: Position,
+}
@@ -0,0 +1,503 @@
+//! Facts about symbols that simplifications may rely on, e.g. `x > 0` makes `sqrt(x^2)` => `x`.
+//!
+//! Facts are relations like `greater(x, 0)`, or types of symbols like `assume(n, integer)`.
+//! Simplifications that were remembered without the facts may not hold with them and the other
+//! way around, so they're forgotten whenever the facts change (see
+//! [`super::intern::forget_simplifications`]).
+//!
+//! Symbols are taken to be real numbers, as they are everywhere else, e.g. `x^2 >= 0`.
+
+use std::cell::RefCell;
+
+use log::debug;
+
+use crate::cas::types::{Type, Union};
+
+use super::{
+    intern,
+    value::{self, Factors, Pieces, RunType, RunVal, Terms},
+};
+
+macro_rules! assumptions_debug {
+    ($($arg:tt)+) => (debug!(target: "matex::assumptions", "[{}:{}] {}", file!(), line!(), &format!($($arg)+)));
+}
+
+#[derive(Debug, Clone)]
+pub enum Fact {
+    /// A relation that holds, e.g. `greater(x, 0)`
+    Holds(RunVal),
+    /// The symbol belongs to the type, e.g. `n` to `integer`
+    Belongs(String, Union),
+}
+
+thread_local! {
+    static FACTS: RefCell<Vec<Fact>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Runs `f` with the facts assumed to hold, and any facts assumed by `f` itself.
+pub(crate) fn assuming<T>(facts: Vec<Fact>, f: impl FnOnce() -> T) -> T {
+    assumptions_debug!("assuming {:?}", facts);
+    let outer = FACTS.with(|it| it.borrow().len());
+    assume(facts);
+
+    let result = f();
+
+    let changed = FACTS.with(|it| {
+        let mut known = it.borrow_mut();
+        let changed = known.len() != outer;
+        known.truncate(outer);
+        changed
+    });
+    if changed {
+        intern::forget_simplifications();
+    }
+    result
+}
+
+/// Assumes the facts until the [`assuming`] around it ends.
+pub(crate) fn assume(facts: Vec<Fact>) {
+    if facts.is_empty() {
+        return;
+    }
+
+    FACTS.with(|it| it.borrow_mut().extend(facts));
+    intern::forget_simplifications();
+}
+
+/// The type a symbol is assumed to belong to, the latest one.
+///
+/// Symbols bounded by a relation, e.g. `x > 0`, are real.
+pub(crate) fn assumed_type(symbol: &str) -> Option<Union> {
+    let facts = FACTS.with(|it| it.borrow().clone());
+    let value: RunVal = RunType::Symbol(symbol.to_string()).into();
+
+    let mut real = false;
+    for fact in facts.iter().rev() {
+        match fact {
+            Fact::Belongs(name, union) if name == symbol => return Some(union.clone()),
+            Fact::Holds(relation) => real |= bound_from_fact(&value, relation).is_some(),
+            Fact::Belongs(_, _) => {}
+        }
+    }
+    real.then(|| Type::Real.into())
+}
+
+/// Whether the symbol may have the value, i.e. no fact about the symbol is known to fail for it,
+/// e.g. `-2` is ruled out for `x` by `x > 0`.
+pub(crate) fn admits(symbol: &str, value: &RunVal) -> bool {
+    let facts = FACTS.with(|it| it.borrow().clone());
+    let target: RunVal = RunType::Symbol(symbol.to_string()).into();
+
+    facts.iter().all(|fact| match fact {
+        Fact::Holds(relation) => {
+            let mut holds = relation.substitute(&target, value);
+            holds.simplify();
+            !matches!(holds.typ, RunType::Bool(false))
+        }
+        Fact::Belongs(name, union) => name != symbol || union.accepts(value) != Some(false),
+    })
+}
+
+/// Decides a relation between values that can't be compared numerically, e.g. `x > -1` if
+/// `x > 0` is assumed, or `x^2 + 1 > 0`.
+pub(crate) fn decide(relation: &str, lhs: &RunVal, rhs: &RunVal) -> Option<bool> {
+    let mut difference = lhs
+        .clone()
+        .add(rhs.clone().multiply(RunType::Number(-1.0).into()));
+    difference.simplify();
+
+    let sign = sign(&difference)?;
+    assumptions_debug!("{:?} has sign {:?}", difference, sign);
+
+    use Sign::*;
+    match (relation, sign) {
+        ("greater", Positive) | ("less", Negative) => Some(true),
+        ("greater", Zero | Negative | Nonpositive) | ("less", Zero | Positive | Nonnegative) => {
+            Some(false)
+        }
+        ("greater_equal", Positive | Zero | Nonnegative)
+        | ("less_equal", Negative | Zero | Nonpositive) => Some(true),
+        ("greater_equal", Negative) | ("less_equal", Positive) => Some(false),
+        ("equal", Zero) => Some(true),
+        ("equal", Positive | Negative) => Some(false),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Sign {
+    Zero,
+    Positive,
+    Negative,
+    Nonnegative,
+    Nonpositive,
+}
+
+impl Sign {
+    fn of_number(n: f64) -> Option<Sign> {
+        match n {
+            _ if n > 0.0 => Some(Sign::Positive),
+            _ if n < 0.0 => Some(Sign::Negative),
+            _ if n == 0.0 => Some(Sign::Zero),
+            _ => None,
+        }
+    }
+
+    /// The direction, and whether zero is ruled out.
+    fn parts(self) -> (i8, bool) {
+        match self {
+            Sign::Zero => (0, false),
+            Sign::Positive => (1, true),
+            Sign::Negative => (-1, true),
+            Sign::Nonnegative => (1, false),
+            Sign::Nonpositive => (-1, false),
+        }
+    }
+
+    fn from_parts(direction: i8, nonzero: bool) -> Sign {
+        match (direction, nonzero) {
+            (0, _) => Sign::Zero,
+            (1, true) => Sign::Positive,
+            (1, false) => Sign::Nonnegative,
+            (_, true) => Sign::Negative,
+            (_, false) => Sign::Nonpositive,
+        }
+    }
+
+    fn multiply(self, other: Sign) -> Sign {
+        let (lhs, lhs_nonzero) = self.parts();
+        let (rhs, rhs_nonzero) = other.parts();
+        Sign::from_parts(lhs * rhs, lhs_nonzero && rhs_nonzero)
+    }
+
+    /// The sign of a sum, if all terms lean the same way.
+    fn add(self, other: Sign) -> Option<Sign> {
+        let (lhs, lhs_nonzero) = self.parts();
+        let (rhs, rhs_nonzero) = other.parts();
+        match (lhs, rhs) {
+            (0, _) => Some(other),
+            (_, 0) => Some(self),
+            _ if lhs == rhs => Some(Sign::from_parts(lhs, lhs_nonzero || rhs_nonzero)),
+            _ => None,
+        }
+    }
+}
+
+/// Whether the value is greater than zero, if that is known.
+pub(crate) fn is_positive(value: &RunVal) -> Option<bool> {
+    match sign(value)? {
+        Sign::Positive => Some(true),
+        Sign::Zero | Sign::Negative | Sign::Nonpositive => Some(false),
+        Sign::Nonnegative => None,
+    }
+}
+
+/// Whether the value is less than zero, if that is known.
+pub(crate) fn is_negative(value: &RunVal) -> Option<bool> {
+    match sign(value)? {
+        Sign::Negative => Some(true),
+        Sign::Zero | Sign::Positive | Sign::Nonnegative => Some(false),
+        Sign::Nonpositive => None,
+    }
+}
+
+/// Whether the value is at least zero, if that is known.
+pub(crate) fn is_nonnegative(value: &RunVal) -> Option<bool> {
+    match sign(value)? {
+        Sign::Zero | Sign::Positive | Sign::Nonnegative => Some(true),
+        Sign::Negative => Some(false),
+        Sign::Nonpositive => None,
+    }
+}
+
+/// Whether the value is an integer, if that is known.
+pub(crate) fn is_integer(value: &RunVal) -> Option<bool> {
+    use RunType::*;
+    match &value.typ {
+        Number(n) => Some(n.fract() == 0.0),
+        Symbol(name) => {
+            let union = assumed_type(name)?;
+            if union.is_subset_of(&Type::Integer.into()) {
+                Some(true)
+            } else if !union.types().iter().any(|it| it.is_numeric()) {
+                Some(false)
+            } else {
+                None
+            }
+        }
+        Sum(Terms(values)) | Product(Factors(values)) => values
+            .iter()
+            .all(|it| is_integer(it) == Some(true))
+            .then_some(true),
+        Exponent(base, exp) => match exp.typ {
+            Number(n) if n >= 0.0 && n.fract() == 0.0 => is_integer(base).filter(|it| *it),
+            _ => None,
+        },
+        Constant(_) | Bool(_) | Vector(_) | Set(_) | Unit | Undefined => Some(false),
+        Piecewise(Pieces(pieces)) => pieces
+            .iter()
+            .all(|(_, value)| is_integer(value) == Some(true))
+            .then_some(true),
+        Function(_, _) | Interval(_) | PhysicalUnit(_) => None,
+    }
+}
+
+fn sign(value: &RunVal) -> Option<Sign> {
+    use RunType::*;
+    let structural = match &value.typ {
+        Number(n) => Sign::of_number(*n),
+        Constant(value::Constant::Pi | value::Constant::E) => Some(Sign::Positive),
+        // Units are positive amounts, so that `sqrt(4 m^2)` is `2 m`
+        PhysicalUnit(_) => Some(Sign::Positive),
+        Product(Factors(factors)) => factors.iter().try_fold(Sign::Positive, |sign, it| {
+            Some(sign.multiply(self::sign(it)?))
+        }),
+        Sum(Terms(terms)) => terms
+            .iter()
+            .try_fold(Sign::Zero, |sign, it| sign.add(self::sign(it)?)),
+        Exponent(base, exp) => match (sign(base), &exp.typ) {
+            (Some(Sign::Positive), _) => Some(Sign::Positive),
+            (Some(Sign::Zero | Sign::Nonnegative), Number(n)) if *n > 0.0 => {
+                Some(Sign::Nonnegative)
+            }
+            // Even powers of real numbers, e.g. `x^2`
+            (_, Number(n)) if n % 2.0 == 0.0 => Some(Sign::Nonnegative),
+            _ => None,
+        },
+        Function(name, _) if name == "exp" => Some(Sign::Positive),
+        _ => None,
+    };
+
+    // Sums like `x - 1` lean both ways, unless `x` is known to be large enough
+    structural.or_else(|| bounds(value)?.sign())
+}
+
+/// The interval a value is known to lie in, the bounds tell whether they're excluded.
+#[derive(Clone, Copy, Debug, Default)]
+struct Bounds {
+    lower: Option<(f64, bool)>,
+    upper: Option<(f64, bool)>,
+}
+
+impl Bounds {
+    fn exactly(n: f64) -> Bounds {
+        Bounds {
+            lower: Some((n, false)),
+            upper: Some((n, false)),
+        }
+    }
+
+    fn add(self, other: Bounds) -> Bounds {
+        let add = |lhs: Option<(f64, bool)>, rhs: Option<(f64, bool)>| {
+            let ((lhs, lhs_strict), (rhs, rhs_strict)) = (lhs?, rhs?);
+            Some((lhs + rhs, lhs_strict || rhs_strict))
+        };
+        Bounds {
+            lower: add(self.lower, other.lower),
+            upper: add(self.upper, other.upper),
+        }
+    }
+
+    fn scale(self, factor: f64) -> Bounds {
+        let scale = |bound: Option<(f64, bool)>| bound.map(|(n, strict)| (n * factor, strict));
+        match factor {
+            _ if factor > 0.0 => Bounds {
+                lower: scale(self.lower),
+                upper: scale(self.upper),
+            },
+            _ if factor < 0.0 => Bounds {
+                lower: scale(self.upper),
+                upper: scale(self.lower),
+            },
+            _ => Bounds::exactly(0.0),
+        }
+    }
+
+    /// Narrows the bounds by what the relation `value OP bound` says.
+    fn narrow(&mut self, relation: &str, bound: f64) {
+        let raise = |lower: &mut Option<(f64, bool)>, new: (f64, bool)| match lower {
+            Some((n, strict)) if *n > new.0 || (*n == new.0 && *strict) => {}
+            _ => *lower = Some(new),
+        };
+        let negate = |(n, strict): (f64, bool)| (-n, strict);
+        match relation {
+            "greater" => raise(&mut self.lower, (bound, true)),
+            "greater_equal" => raise(&mut self.lower, (bound, false)),
+            "equal" => {
+                raise(&mut self.lower, (bound, false));
+                self.narrow("less_equal", bound);
+            }
+            "less" | "less_equal" => {
+                // Upper bounds are lowered like the negated lower bounds are raised
+                let mut negated = self.upper.map(negate);
+                raise(&mut negated, (-bound, relation == "less"));
+                self.upper = negated.map(negate);
+            }
+            _ => {}
+        }
+    }
+
+    fn sign(self) -> Option<Sign> {
+        if let (Some((lower, false)), Some((upper, false))) = (self.lower, self.upper) {
+            if lower == upper {
+                return Sign::of_number(lower);
+            }
+        }
+
+        match (self.lower, self.upper) {
+            (Some((n, strict)), _) if n > 0.0 || (n == 0.0 && strict) => Some(Sign::Positive),
+            (_, Some((n, strict))) if n < 0.0 || (n == 0.0 && strict) => Some(Sign::Negative),
+            (Some((0.0, _)), _) => Some(Sign::Nonnegative),
+            (_, Some((0.0, _))) => Some(Sign::Nonpositive),
+            _ => None,
+        }
+    }
+}
+
+/// The bounds of sums and multiples of symbols, e.g. `2x - 1` is positive if `x > 1`.
+fn bounds(value: &RunVal) -> Option<Bounds> {
+    use RunType::*;
+    match &value.typ {
+        Number(n) => Some(Bounds::exactly(*n)),
+        Interval(interval) => {
+            let bound = |n: f64| n.is_finite().then_some((n, false));
+            Some(Bounds {
+                lower: bound(interval.lower),
+                upper: bound(interval.upper),
+            })
+        }
+        Symbol(_) => bounds_from_facts(value),
+        Sum(Terms(terms)) => terms
+            .iter()
+            .try_fold(Bounds::exactly(0.0), |sum, it| Some(sum.add(bounds(it)?))),
+        Product(Factors(factors)) => {
+            let [lhs, rhs] = factors.as_slice() else {
+                return None;
+            };
+            match (&lhs.typ, &rhs.typ) {
+                (Number(factor), _) => Some(bounds(rhs)?.scale(*factor)),
+                (_, Number(factor)) => Some(bounds(lhs)?.scale(*factor)),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// What the facts say about the bounds of a symbol, all of them together.
+fn bounds_from_facts(symbol: &RunVal) -> Option<Bounds> {
+    let facts = FACTS.with(|it| it.borrow().clone());
+
+    let mut bounds = None;
+    for fact in &facts {
+        let Fact::Holds(relation) = fact else {
+            continue;
+        };
+        if let Some((relation, bound)) = bound_from_fact(symbol, relation) {
+            bounds
+                .get_or_insert_with(Bounds::default)
+                .narrow(relation, bound);
+        }
+    }
+    bounds
+}
+
+/// The fact written as `symbol OP bound`, if it bounds the symbol by a number.
+fn bound_from_fact(symbol: &RunVal, fact: &RunVal) -> Option<(&'static str, f64)> {
+    let RunType::Function(name, args) = &fact.typ else {
+        return None;
+    };
+    let [lhs, rhs] = args.as_slice() else {
+        return None;
+    };
+
+    let relation = match name.as_str() {
+        "less" => "less",
+        "less_equal" => "less_equal",
+        "greater" => "greater",
+        "greater_equal" => "greater_equal",
+        "equal" => "equal",
+        _ => return None,
+    };
+
+    let (relation, bound) = if lhs == symbol {
+        (relation, rhs)
+    } else if rhs == symbol {
+        let flipped = match relation {
+            "less" => "greater",
+            "less_equal" => "greater_equal",
+            "greater" => "less",
+            "greater_equal" => "less_equal",
+            other => other,
+        };
+        (flipped, lhs)
+    } else {
+        return None;
+    };
+
+    match bound.typ {
+        RunType::Number(bound) => Some((relation, bound)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cas::eval::testing::{eval, Session};
+
+    #[test]
+    fn sign_queries() {
+        let mut session = Session::new();
+        assert_eq!(session.eval("is_positive(x)"), "unknown");
+        assert_eq!(session.eval("x^2 + 1 > 0"), "true");
+
+        session.eval("assume(y > 0)");
+        assert_eq!(session.eval("is_positive(y + 1)"), "true");
+        assert_eq!(session.eval("is_negative(-2*y)"), "true");
+        assert_eq!(session.eval("y < 0"), "false");
+
+        session.eval("assume(z > 3)");
+        assert_eq!(session.eval("z - 2 > 0"), "true");
+        assert_eq!(session.eval("is_positive(z - 5)"), "unknown");
+    }
+
+    #[test]
+    fn types_of_symbols() {
+        let mut session = Session::new();
+        session.eval("assume(n, integer)");
+        assert_eq!(session.eval("is_integer(n^2 + 2*n)"), "true");
+        assert_eq!(session.eval("(x^a)^n"), "x^(a * n)");
+        assert_eq!(eval("(x^a)^b"), "(x^a)^b");
+    }
+
+    #[test]
+    fn simplification_uses_assumptions() {
+        let mut session = Session::new();
+        session.eval("assume(y > 0)");
+        assert_eq!(session.eval("sqrt(y^2)"), "y");
+        assert_eq!(eval("(x^2)^3"), "x^6");
+    }
+
+    #[test]
+    fn with_only_assumes_in_its_body() {
+        let mut session = Session::new();
+        assert_eq!(
+            session.eval("with assume(w < 0) { is_negative(w) }"),
+            "true"
+        );
+        assert_eq!(session.eval("is_negative(w)"), "unknown");
+    }
+
+    #[test]
+    fn invalid_assumptions() {
+        let mut session = Session::new();
+        assert_eq!(
+            session.error("assume(x)"),
+            "Only relations like x > 0 can be assumed, not x at (row 1, col 6)"
+        );
+        assert_eq!(
+            session.error("assume(1 > 2)"),
+            "The assumption doesn't hold at (row 1, col 6)"
+        );
+    }
+}
@@ -0,0 +1,249 @@
+//! The dependencies between variables, e.g. `y = x + 1` makes `y` depend on `x`.
+//!
+//! Variables are resolved reactively, so the graph is kept free of cycles: an assignment that
+//! would make a variable depend on itself is refused. The resolved value of every variable is
+//! remembered until the variable, or anything it depends on, is assigned again.
+
+use std::collections::{BTreeSet, HashMap};
+
+use log::debug;
+
+use super::value::{Factors, RunType, RunVal, Terms};
+
+macro_rules! dependency_debug {
+    ($($arg:tt)+) => (debug!(target: "matex::dependency", "[{}:{}] {}", file!(), line!(), &format!($($arg)+)));
+}
+
+#[derive(Default, Debug)]
+pub struct DependencyGraph {
+    /// The symbols each variable is defined in terms of
+    dependencies: HashMap<String, BTreeSet<String>>,
+    /// The variables defined in terms of each symbol
+    dependents: HashMap<String, BTreeSet<String>>,
+    /// Resolved values of variables, that are still up to date
+    resolved: HashMap<String, RunVal>,
+}
+
+impl DependencyGraph {
+    /// Makes `name` depend on the symbols of its new value.
+    ///
+    /// Returns the cycle, e.g. `["x", "y", "x"]`, if that would make `name` depend on itself.
+    pub fn set(&mut self, name: &str, value: &RunVal) -> Result<(), Vec<String>> {
+        let mut symbols = BTreeSet::new();
+        collect_symbols(value, &mut symbols);
+
+        for symbol in &symbols {
+            if let Some(mut path) = self.path(symbol, name) {
+                path.insert(0, name.to_string());
+                dependency_debug!("cycle: {:?}", path);
+                return Err(path);
+            }
+        }
+
+        self.remove(name);
+        for symbol in &symbols {
+            self.dependents
+                .entry(symbol.clone())
+                .or_default()
+                .insert(name.to_string());
+        }
+        self.dependencies.insert(name.to_string(), symbols);
+
+        Ok(())
+    }
+
+    /// Removes what `name` depends on, e.g. when it is unset. Its dependents stay.
+    pub fn remove(&mut self, name: &str) {
+        self.invalidate(name);
+
+        let Some(symbols) = self.dependencies.remove(name) else {
+            return;
+        };
+        for symbol in symbols {
+            if let Some(dependents) = self.dependents.get_mut(&symbol) {
+                dependents.remove(name);
+                if dependents.is_empty() {
+                    self.dependents.remove(&symbol);
+                }
+            }
+        }
+    }
+
+    /// The symbols `name` is directly defined in terms of.
+    pub fn dependencies(&self, name: &str) -> Vec<String> {
+        self.dependencies
+            .get(name)
+            .map(|it| it.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// The variables directly defined in terms of `name`.
+    pub fn dependents(&self, name: &str) -> Vec<String> {
+        self.dependents
+            .get(name)
+            .map(|it| it.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn resolved(&self, name: &str) -> Option<&RunVal> {
+        self.resolved.get(name)
+    }
+
+    pub fn set_resolved(&mut self, name: &str, value: RunVal) {
+        self.resolved.insert(name.to_string(), value);
+    }
+
+    /// Forgets the resolved values of `name` and everything depending on it.
+    fn invalidate(&mut self, name: &str) {
+        let mut pending = vec![name.to_string()];
+        let mut visited = BTreeSet::new();
+
+        while let Some(name) = pending.pop() {
+            if !visited.insert(name.clone()) {
+                continue;
+            }
+            self.resolved.remove(&name);
+            pending.extend(self.dependents(&name));
+        }
+        dependency_debug!("invalidated {:?}", visited);
+    }
+
+    /// The chain of dependencies leading from `from` to `to`, both included.
+    fn path(&self, from: &str, to: &str) -> Option<Vec<String>> {
+        if from == to {
+            return Some(vec![to.to_string()]);
+        }
+
+        let mut visited = BTreeSet::new();
+        let mut pending = vec![vec![from.to_string()]];
+
+        while let Some(path) = pending.pop() {
+            let last = path.last()?;
+            if !visited.insert(last.clone()) {
+                continue;
+            }
+
+            for next in self.dependencies(last) {
+                let mut longer = path.clone();
+                longer.push(next.clone());
+                if next == to {
+                    return Some(longer);
+                }
+                pending.push(longer);
+            }
+        }
+        None
+    }
+}
+
+fn collect_symbols(value: &RunVal, symbols: &mut BTreeSet<String>) {
+    use RunType::*;
+    match &value.typ {
+        Symbol(name) => {
+            symbols.insert(name.clone());
+        }
+        Sum(Terms(values)) | Product(Factors(values)) | Vector(values) | Function(_, values) => {
+            values.iter().for_each(|it| collect_symbols(it, symbols))
+        }
+        Exponent(base, exp) => {
+            collect_symbols(base, symbols);
+            collect_symbols(exp, symbols);
+        }
+        Piecewise(pieces) => pieces.parts().for_each(|it| collect_symbols(it, symbols)),
+        // The variable of a set-builder isn't a variable outside of it
+        Set(set) => {
+            let mut inner = BTreeSet::new();
+            set.parts().into_iter().for_each(|it| collect_symbols(it, &mut inner));
+            symbols.extend(inner.into_iter().filter(|it| !set.binds(&Symbol(it.clone()).into())));
+        }
+        Unit
+        | Undefined
+        | Number(_)
+        | Interval(_)
+        | PhysicalUnit(_)
+        | Constant(_)
+        | Bool(_) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cas::eval::testing::Session;
+
+    fn value(input: &str) -> RunVal {
+        Session::new().run(input).unwrap()
+    }
+
+    #[test]
+    fn dependencies_and_dependents() {
+        let mut graph = DependencyGraph::default();
+        graph.set("y", &value("x + 1")).unwrap();
+        graph.set("z", &value("x * y")).unwrap();
+
+        assert_eq!(graph.dependencies("z"), ["x", "y"]);
+        assert_eq!(graph.dependents("x"), ["y", "z"]);
+
+        graph.remove("y");
+        assert!(graph.dependencies("y").is_empty());
+        assert_eq!(graph.dependents("x"), ["z"]);
+        assert_eq!(graph.dependencies("z"), ["x", "y"]);
+    }
+
+    #[test]
+    fn cycles_are_refused() {
+        let mut graph = DependencyGraph::default();
+        graph.set("y", &value("x + 1")).unwrap();
+        graph.set("z", &value("2 * y")).unwrap();
+
+        let cycle = |names: &[&str]| Err(names.iter().map(|it| it.to_string()).collect());
+        assert_eq!(graph.set("x", &value("z")), cycle(&["x", "z", "y", "x"]));
+        assert_eq!(graph.set("x", &value("x + 1")), cycle(&["x", "x"]));
+        assert!(graph.dependencies("x").is_empty());
+    }
+
+    #[test]
+    fn resolved_values_are_invalidated() {
+        let mut graph = DependencyGraph::default();
+        graph.set("y", &value("x + 1")).unwrap();
+        graph.set_resolved("y", value("3"));
+        assert_eq!(graph.resolved("y"), Some(&value("3")));
+
+        graph.set("x", &value("5")).unwrap();
+        assert_eq!(graph.resolved("y"), None);
+    }
+
+    #[test]
+    fn variables_are_reactive() {
+        let mut session = Session::new();
+        session.eval("x = 2");
+        session.eval("y = x + 1");
+        assert_eq!(session.eval("y"), "3");
+        session.eval("x = 5");
+        assert_eq!(session.eval("y"), "6");
+        assert_eq!(session.eval("deps(y)"), "[x]");
+        assert_eq!(session.eval("dependents(x)"), "[y]");
+
+        session.eval("~y");
+        assert_eq!(session.eval("dependents(x)"), "[]");
+    }
+
+    #[test]
+    fn cyclic_assignments_fail() {
+        let mut session = Session::new();
+        session.eval("y = x + 1");
+        assert_eq!(session.error("x = y"), "Cyclic dependency x -> y -> x at (row 1, col 2)");
+        assert_eq!(session.eval("x"), "x");
+    }
+
+    #[test]
+    fn examples_run() {
+        let mut session = Session::new();
+        assert_eq!(session.eval(include_str!("../../../../examples/test.mx")), "y + 18");
+        let mut session = Session::new();
+        assert_eq!(
+            session.eval(include_str!("../../../../examples/test2.mx")),
+            "2 * y - 45 + 5 * sin(x)"
+        );
+    }
+}
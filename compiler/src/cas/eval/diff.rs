@@ -0,0 +1,124 @@
+use log::debug;
+
+use super::{
+    functions,
+    value::{Factors, Pieces, RunType, RunVal, Terms},
+};
+
+macro_rules! diff_debug {
+    ($($arg:tt)+) => (debug!(target: "matex::diff", "[{}:{}] {}", file!(), line!(), &format!($($arg)+)));
+}
+
+/// Symbolic derivative of `value` with respect to `var`.
+///
+/// Derivatives of unknown functions are kept unevaluated as `diff(f(x), x)`.
+/// The result is not simplified.
+pub fn differentiate(value: &RunVal, var: &RunVal) -> RunVal {
+    use RunType::*;
+    diff_debug!("d/d{:?} {:?}", var, value);
+
+    if !value.depends_on(var) {
+        return Number(0.0).into();
+    }
+
+    match &value.typ {
+        Symbol(_) => Number(1.0).into(),
+
+        Sum(Terms(terms)) => Sum(Terms(
+            terms.iter().map(|it| differentiate(it, var)).collect(),
+        ))
+        .into(),
+
+        // Product rule: (fg)' = f'g + fg'
+        Product(Factors(factors)) => {
+            let mut terms = Vec::new();
+            for (i, factor) in factors.iter().enumerate() {
+                if !factor.depends_on(var) {
+                    continue;
+                }
+                let mut term = factors.clone();
+                term[i] = differentiate(factor, var);
+                terms.push(Product(Factors(term)).into());
+            }
+            Sum(Terms(terms)).into()
+        }
+
+        Exponent(base, exp) => {
+            let base = *base.clone();
+            let exp = *exp.clone();
+
+            if !exp.depends_on(var) {
+                // Power rule: (f^n)' = n * f^(n - 1) * f'
+                let lowered = exp.clone().add(Number(-1.0).into());
+                Product(Factors(vec![
+                    exp,
+                    base.clone().power(lowered),
+                    differentiate(&base, var),
+                ]))
+                .into()
+            } else {
+                // (f^g)' = f^g * (g' * ln(f) + g * f' / f)
+                let ln = functions::call("ln", &base);
+                let inner = Sum(Terms(vec![
+                    Product(Factors(vec![differentiate(&exp, var), ln])).into(),
+                    Product(Factors(vec![
+                        exp.clone(),
+                        differentiate(&base, var),
+                        base.clone().power(Number(-1.0).into()),
+                    ]))
+                    .into(),
+                ]));
+                Product(Factors(vec![value.clone(), inner.into()])).into()
+            }
+        }
+
+        Function(name, args) => match args.as_slice() {
+            // Chain rule: f(g)' = f'(g) * g'
+            [arg] => match functions::derivative(name, arg) {
+                Some(outer) => Product(Factors(vec![outer, differentiate(arg, var)])).into(),
+                None => unevaluated(value, var),
+            },
+            // e.g. max(f, g) is differentiated by cases
+            _ => match functions::piecewise(name, args) {
+                Some(pieces) => differentiate(&Piecewise(pieces).into(), var),
+                None => unevaluated(value, var),
+            },
+        },
+
+        Vector(values) => Vector(values.iter().map(|it| differentiate(it, var)).collect()).into(),
+
+        // Piece by piece, the derivative at the boundaries between pieces isn't told apart
+        Piecewise(Pieces(pieces)) => Piecewise(Pieces(
+            pieces
+                .iter()
+                .map(|(cond, value)| (cond.clone(), differentiate(value, var)))
+                .collect(),
+        ))
+        .into(),
+
+        Unit
+        | Undefined
+        | Number(_)
+        | Interval(_)
+        | PhysicalUnit(_)
+        | Constant(_)
+        | Bool(_)
+        | Set(_) => {
+            Undefined.into()
+        }
+    }
+}
+
+/// The `n`-th derivative of `value` with respect to `var`, simplified after every step.
+pub fn differentiate_n(value: &RunVal, var: &RunVal, n: u32) -> RunVal {
+    let mut value = value.clone();
+    for _ in 0..n {
+        value = differentiate(&value, var);
+        value.simplify();
+    }
+    value
+}
+
+fn unevaluated(value: &RunVal, var: &RunVal) -> RunVal {
+    RunType::Function("diff".to_string(), vec![value.clone(), var.clone()]).into()
+}
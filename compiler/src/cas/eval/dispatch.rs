@@ -0,0 +1,157 @@
+//! Picking the overload of a function to call, by the number and types of the arguments.
+//!
+//! Of the overloads accepting the arguments the most specific one is called, e.g. `abs(q: integer)`
+//! rather than `abs(x: real)` for `abs(2)`, while `abs(x)` stays as it is until `x` is known.
+
+use log::debug;
+use matex_common::{
+    function::{Function, Parameter},
+    util::SymbolTable,
+};
+
+use crate::cas::types::Union;
+
+use super::value::RunVal;
+
+macro_rules! dispatch_debug {
+    ($($arg:tt)+) => (debug!(target: "matex::dispatch", "[{}:{}] {}", file!(), line!(), &format!($($arg)+)));
+}
+
+#[derive(Debug)]
+pub(crate) enum Dispatch<'a> {
+    Call(&'a Function),
+    /// The arguments aren't known well enough to pick an overload
+    Unevaluated,
+    NoMatch,
+    Ambiguous(Vec<&'a Function>),
+}
+
+/// The declared types of the parameters, `None` for parameters without a type.
+pub fn signature(params: &[Parameter], aliases: &SymbolTable<Union>) -> Vec<Option<Union>> {
+    params
+        .iter()
+        .map(|it| Union::resolve(&it.type_name, aliases).ok())
+        .collect()
+}
+
+/// The function as it was declared, e.g. `abs(x: real)`.
+pub fn describe(function: &Function) -> String {
+    let params = function
+        .params
+        .iter()
+        .map(|it| match it.type_name.as_str() {
+            "" => it.name.clone(),
+            typ => format!("{}: {}", it.name, typ),
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    match function.return_type.as_str() {
+        "" => format!("{}({})", function.name, params),
+        typ => format!("{}({}) -> {}", function.name, params, typ),
+    }
+}
+
+/// Whether every argument accepted by `lhs` is accepted by `rhs` as well.
+pub fn is_as_specific(lhs: &[Option<Union>], rhs: &[Option<Union>]) -> bool {
+    lhs.iter().zip(rhs).all(|it| match it {
+        (_, None) => true,
+        (None, Some(_)) => false,
+        (Some(lhs), Some(rhs)) => lhs.is_subset_of(rhs),
+    })
+}
+
+pub(crate) fn select<'a>(
+    overloads: &'a [Function],
+    args: &[RunVal],
+    aliases: &SymbolTable<Union>,
+) -> Dispatch<'a> {
+    let mut applicable = Vec::new();
+    let mut undecided = Vec::new();
+
+    for function in overloads.iter().filter(|it| it.params.len() == args.len()) {
+        let accepted: Vec<Option<bool>> = signature(&function.params, aliases)
+            .iter()
+            .zip(args)
+            .map(|(typ, arg)| typ.as_ref().map_or(Some(true), |typ| typ.accepts(arg)))
+            .collect();
+
+        if accepted.contains(&Some(false)) {
+            continue;
+        }
+        if accepted.contains(&None) {
+            undecided.push(function);
+        } else {
+            applicable.push(function);
+        }
+    }
+    dispatch_debug!("applicable: {:?}, undecided: {:?}", applicable, undecided);
+
+    if !undecided.is_empty() {
+        // Symbolic arguments are passed on when there is no choice to make
+        return match (applicable.as_slice(), undecided.as_slice()) {
+            ([], [function]) => Dispatch::Call(function),
+            _ => Dispatch::Unevaluated,
+        };
+    }
+
+    let most_specific = applicable.iter().find(|function| {
+        let signature = signature(&function.params, aliases);
+        applicable
+            .iter()
+            .all(|other| is_as_specific(&signature, &self::signature(&other.params, aliases)))
+    });
+
+    match (most_specific, applicable.len()) {
+        (Some(function), _) => Dispatch::Call(function),
+        (None, 0) => Dispatch::NoMatch,
+        (None, _) => Dispatch::Ambiguous(applicable),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cas::eval::testing::Session;
+
+    #[test]
+    fn most_specific_overload_is_called() {
+        let mut session = Session::new();
+        session.eval("g(x: integer) = 1");
+        session.eval("g(x: real) = 2");
+        session.eval("g(x) = 3");
+        assert_eq!(session.eval("[g(2), g(PI), g([1])]"), "[1, 2, 3]");
+    }
+
+    #[test]
+    fn unknown_arguments_stay_unevaluated() {
+        let mut session = Session::new();
+        session.eval("g(x: integer) = 1");
+        session.eval("g(x: real) = 2");
+        assert_eq!(session.eval("g(y)"), "g(y)");
+    }
+
+    #[test]
+    fn redefinition_replaces_the_overload() {
+        let mut session = Session::new();
+        session.eval("g(x: integer) = 1");
+        session.eval("g(n: integer) = 4");
+        assert_eq!(session.eval("g(2)"), "4");
+    }
+
+    #[test]
+    fn ambiguous_and_missing_overloads() {
+        let mut session = Session::new();
+        session.eval("k(x: integer, y) = 1");
+        session.eval("k(x, y: integer) = 2");
+        assert_eq!(
+            session.error("k(1, 2)"),
+            "Ambiguous call of k, it could be k(x: integer, y) or k(x, y: integer) at (row 1, col 1)"
+        );
+
+        session.eval("m(x: vector) = 1");
+        session.eval("m(x: bool) = 2");
+        assert_eq!(
+            session.error("m(2)"),
+            "No overload of m accepts (integer) at (row 1, col 1)"
+        );
+    }
+}
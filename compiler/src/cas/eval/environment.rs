@@ -1,6 +1,10 @@
 use matex_common::{function::Function, util::SymbolTable};
 
-use super::value::RunVal;
+use crate::cas::types::Union;
+
+use super::{
+    assumptions::Fact, dependency::DependencyGraph, dispatch, rules::Rules, value::RunVal,
+};
 
 type Intrinsic = fn(&Vec<RunVal>) -> RunVal;
 
@@ -9,6 +13,13 @@ pub struct Environment {
     pub scopes: Vec<Scope>,
     pub constants: SymbolTable<RunVal>,
     pub intrinsics: SymbolTable<Intrinsic>,
+    pub dependencies: DependencyGraph,
+    /// The types declared by `type Num = integer | rational | real`
+    pub aliases: SymbolTable<Union>,
+    /// The facts assumed by `assume(x > 0)`, for every program that runs
+    pub assumptions: Vec<Fact>,
+    /// The rewrite rules, the default ones and those defined by `rule`
+    pub rules: Rules,
 }
 
 impl Environment {
@@ -22,10 +33,22 @@ impl Environment {
         self.get_scope_mut().variables.remove(name);
     }
 
+    /// Adds an overload of the function, replacing the one with the same parameter types.
     pub fn set_func(&mut self, name: &str, func: Function) {
-        self.get_scope_mut()
-            .functions
-            .insert(name.to_string(), func);
+        let aliases = &self.aliases;
+        let signature = dispatch::signature(&func.params, aliases);
+        let Some(scope) = self.scopes.last_mut() else {
+            panic!("No scope?!");
+        };
+        let overloads = scope.functions.entry(name.to_string()).or_default();
+
+        match overloads.iter_mut().find(|it| {
+            it.params.len() == func.params.len()
+                && dispatch::signature(&it.params, aliases) == signature
+        }) {
+            Some(overload) => *overload = func,
+            None => overloads.push(func),
+        }
     }
 
     pub fn remove_func(&mut self, name: &str) {
@@ -60,10 +83,10 @@ impl Environment {
         return self.intrinsics.get(name);
     }
 
-    pub(crate) fn get_function(&self, name: &str) -> Option<&Function> {
+    pub(crate) fn get_overloads(&self, name: &str) -> Option<&Vec<Function>> {
         for scope in self.scopes.iter().rev() {
-            if let Some(function) = scope.functions.get(name) {
-                return Some(function);
+            if let Some(overloads) = scope.functions.get(name) {
+                return Some(overloads);
             }
         }
         None
@@ -80,6 +103,7 @@ impl Environment {
 
 #[derive(Default, Debug)]
 pub struct Scope {
-    pub functions: SymbolTable<Function>,
+    /// The overloads of every function
+    pub functions: SymbolTable<Vec<Function>>,
     pub variables: SymbolTable<RunVal>,
 }
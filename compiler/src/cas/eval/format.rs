@@ -1,8 +1,13 @@
 use matex_common::node::Precedence;
 
-use crate::cas::eval::value::{Factors, Terms};
+use crate::cas::eval::value::{Factors, Pieces, Terms};
 
-use super::value::{RunType, RunVal};
+use super::{
+    functions::RELATIONS,
+    sets::Set,
+    units,
+    value::{RunType, RunVal},
+};
 
 pub trait ValueFormatter {
     fn format(value: &RunVal) -> String;
@@ -15,7 +20,7 @@ impl NormalFormatter {
         use RunType::*;
         match &value.typ {
             Unit => "Unit value".to_owned(),
-            Undefined => "Undefined?".to_owned(),
+            Undefined => "undefined".to_owned(),
             Number(n) if prec == Precedence::Term => {
                 if n.is_sign_negative() {
                     format!(" - {}", -n)
@@ -31,6 +36,20 @@ impl NormalFormatter {
                     s.clone()
                 }
             }
+            Constant(c) => {
+                if prec == Precedence::Term {
+                    format!(" + {}", c.name())
+                } else {
+                    c.name().to_owned()
+                }
+            }
+            PhysicalUnit(unit) => {
+                if prec == Precedence::Term {
+                    format!(" + {}", unit.name)
+                } else {
+                    unit.name.clone()
+                }
+            }
             Bool(b) => format!("{}", b),
             Vector(vec) => {
                 let formatted_items = vec
@@ -57,6 +76,43 @@ impl NormalFormatter {
 
                 buffer
             }
+            // Units are written after the rest like they are entered, e.g. `2.5 m/s`
+            Product(Factors(factors)) if factors.iter().any(units::is_unit_factor) => {
+                let (unit_factors, others): (Vec<_>, Vec<_>) =
+                    factors.iter().cloned().partition(units::is_unit_factor);
+                let unit_str = units::format(&unit_factors);
+
+                let str = match others.as_slice() {
+                    [] => unit_str,
+                    [single] => match single.typ {
+                        // Keeps the sign of the number in front, e.g. ` - 2 m`
+                        Number(_) if prec == Precedence::Term => {
+                            return format!("{} {}", Self::format_impl(single, prec), unit_str);
+                        }
+                        _ => format!(
+                            "{} {}",
+                            Self::format_impl(single, Precedence::Factor),
+                            unit_str
+                        ),
+                    },
+                    _ => {
+                        let rest = Product(Factors(others)).into();
+                        format!(
+                            "{} {}",
+                            Self::format_impl(&rest, Precedence::Factor),
+                            unit_str
+                        )
+                    }
+                };
+
+                if prec == Precedence::Term {
+                    format!(" + {}", str)
+                } else if prec == Precedence::Exponent {
+                    format!("({})", str)
+                } else {
+                    str
+                }
+            }
             Product(Factors(factors)) => {
                 let mut vec = Vec::new();
 
@@ -99,6 +155,52 @@ impl NormalFormatter {
                     str
                 }
             }
+            // Held values look like any other value
+            Function(name, arguments) if name == "hold" && arguments.len() == 1 => {
+                Self::format_impl(&arguments[0], prec)
+            }
+            Function(name, arguments) if arguments.len() == 2 && relation(name).is_some() => {
+                let operator = relation(name).unwrap_or_default();
+                let str = format!(
+                    "{} {} {}",
+                    Self::format_impl(&arguments[0], Precedence::Comparison),
+                    operator,
+                    Self::format_impl(&arguments[1], Precedence::Comparison)
+                );
+                if prec > Precedence::Assignment {
+                    format!("({})", str)
+                } else {
+                    str
+                }
+            }
+            // Written with the postfix operator, e.g. `(n + 1)!`
+            Function(name, arguments) if name == "factorial" && arguments.len() == 1 => {
+                let str = format!(
+                    "{}!",
+                    Self::format_impl(&arguments[0], Precedence::Exponent)
+                );
+                if prec == Precedence::Term {
+                    format!(" + {}", str)
+                } else {
+                    str
+                }
+            }
+            // Written with the index and its range, e.g. `Σ_{k=1}^{n} k^2`
+            Function(name, arguments) if arguments.len() == 4 && big_operator(name).is_some() => {
+                let str = format!(
+                    "{}_{{{}={}}}^{{{}}} {}",
+                    big_operator(name).unwrap_or_default(),
+                    Self::format(&arguments[1]),
+                    Self::format(&arguments[2]),
+                    Self::format(&arguments[3]),
+                    Self::format_impl(&arguments[0], Precedence::Factor)
+                );
+                match prec {
+                    Precedence::Term => format!(" + {}", str),
+                    _ if prec >= Precedence::Factor => format!("({})", str),
+                    _ => str,
+                }
+            }
             Function(name, arguments) => {
                 let mut args = Vec::new();
 
@@ -107,12 +209,104 @@ impl NormalFormatter {
                     args.push(argument_str);
                 }
 
-                format!("{}({})", name, args.join(", "))
+                let str = format!("{}({})", name, args.join(", "));
+                if prec == Precedence::Term {
+                    format!(" + {}", str)
+                } else {
+                    str
+                }
+            }
+            // Written like the `if` it comes from, e.g. `if x > 0 then x else -x`
+            Piecewise(Pieces(pieces)) => {
+                let mut str = String::new();
+                for (cond, value) in pieces {
+                    if let Bool(true) = cond.typ {
+                        str.push_str(&Self::format(value));
+                        break;
+                    }
+                    str.push_str(&format!(
+                        "if {} then {} else ",
+                        Self::format(cond),
+                        Self::format(value)
+                    ));
+                }
+                if !matches!(pieces.last(), Some((cond, _)) if cond.typ == Bool(true)) {
+                    str.push_str("undefined");
+                }
+
+                match prec {
+                    Precedence::None => str,
+                    Precedence::Term => format!(" + ({})", str),
+                    _ => format!("({})", str),
+                }
+            }
+            Set(set) => Self::format_set(set),
+            Interval(interval) => {
+                let str = format!("interval({}, {})", interval.lower, interval.upper);
+                match prec {
+                    Precedence::Term => format!(" + {}", str),
+                    _ => str,
+                }
+            }
+        }
+    }
+
+    /// Written like it is entered, e.g. `{1, 2}`, `[0, 1)` or `{x in Z | x^2 < 10}`.
+    /// Closed intervals are `[0 .. 1]`, since `[0, 1]` is a vector.
+    fn format_set(set: &Set) -> String {
+        match set {
+            Set::Finite(elements) => {
+                let elements = elements.iter().map(Self::format).collect::<Vec<_>>();
+                format!("{{{}}}", elements.join(", "))
             }
+            Set::Interval {
+                lower,
+                upper,
+                lower_closed,
+                upper_closed,
+            } => format!(
+                "{}{}{}{}{}",
+                if *lower_closed { "[" } else { "(" },
+                Self::format(lower),
+                if *lower_closed && *upper_closed { " .. " } else { ", " },
+                Self::format(upper),
+                if *upper_closed { "]" } else { ")" }
+            ),
+            Set::Builder {
+                variable,
+                domain,
+                condition,
+            } => format!(
+                "{{{} in {} | {}}}",
+                variable,
+                Self::format(domain),
+                Self::format(condition)
+            ),
+            Set::Numbers(numbers) => numbers.name().to_owned(),
         }
     }
 }
 
+/// The operator of a relation that is kept as a function, e.g. `<` for `less`.
+fn relation(name: &str) -> Option<&'static str> {
+    if name == "element" {
+        return Some("in");
+    }
+    RELATIONS
+        .iter()
+        .find(|(relation, _)| *relation == name)
+        .map(|(_, operator)| *operator)
+}
+
+/// The symbol of a sum or product over a range, e.g. `Σ` for `sum`.
+pub(crate) fn big_operator(name: &str) -> Option<&'static str> {
+    match name {
+        "sum" => Some("Σ"),
+        "product" => Some("Π"),
+        _ => None,
+    }
+}
+
 impl ValueFormatter for NormalFormatter {
     fn format(value: &RunVal) -> String {
         NormalFormatter::format_impl(value, Precedence::None)
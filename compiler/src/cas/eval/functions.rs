@@ -0,0 +1,628 @@
+use std::f64::consts::PI;
+
+use log::debug;
+
+use super::{
+    assumptions,
+    helpers::{negate, number, simplified},
+    integers, interval, sets, units,
+    value::{Constant, Factors, Pieces, RunType, RunVal, Terms},
+};
+
+macro_rules! functions_debug {
+    ($($arg:tt)+) => (debug!(target: "matex::functions", "[{}:{}] {}", file!(), line!(), &format!($($arg)+)));
+}
+
+/// Evaluates a known mathematical function with numeric arguments, or a set operation on known
+/// sets, see [`sets::evaluate`].
+///
+/// Arguments outside of the (real) domain, e.g. `ln(-1)`, aren't evaluated.
+pub(crate) fn evaluate(name: &str, args: &[RunVal]) -> Option<RunVal> {
+    if let Some(holds) = compare(name, args) {
+        return Some(RunType::Bool(holds).into());
+    }
+    if let Some(value) = sets::evaluate(name, args) {
+        return Some(value);
+    }
+    if let Some(value) = interval::evaluate(name, args) {
+        return Some(value);
+    }
+    if let Some(value) = integers::evaluate(name, args) {
+        return Some(value);
+    }
+
+    let numbers: Vec<f64> = args
+        .iter()
+        .map(|arg| match arg.typ {
+            RunType::Number(n) => Some(n),
+            _ => None,
+        })
+        .collect::<Option<_>>()?;
+
+    let value = match (name, numbers.as_slice()) {
+        ("sin" | "cos" | "tan", [x]) => {
+            if let Some(exact) = special_angle(name, x / PI) {
+                return Some(exact);
+            }
+            match name {
+                "sin" => x.sin(),
+                "cos" => x.cos(),
+                _ => x.tan(),
+            }
+        }
+        ("asin", [x]) if x.abs() <= 1.0 => return Some(angle(x.asin())),
+        ("acos", [x]) if x.abs() <= 1.0 => return Some(angle(x.acos())),
+        ("atan", [x]) => return Some(angle(x.atan())),
+        ("atan2", [y, x]) => return Some(angle(y.atan2(*x))),
+        ("sinh", [x]) => x.sinh(),
+        ("cosh", [x]) => x.cosh(),
+        ("tanh", [x]) => x.tanh(),
+        // Kept exact as a power of E, e.g. `exp(2)` => `E^2`, until it is approximated
+        ("exp", [x]) if x.fract() == 0.0 => {
+            let e = RunVal::from(RunType::Constant(Constant::E));
+            return Some(simplified(e.power(number(*x))));
+        }
+        ("exp", [x]) => x.exp(),
+        ("ln", [x]) if *x == 0.0 => return Some(RunType::Undefined.into()),
+        ("ln", [x]) if *x > 0.0 => x.ln(),
+        ("log", [b, x]) if *b > 0.0 && *b != 1.0 && *x > 0.0 => logarithm(*b, *x),
+        ("sqrt", [x]) if *x >= 0.0 => x.sqrt(),
+        ("abs", [x]) => x.abs(),
+        ("sign", [x]) if *x == 0.0 => 0.0,
+        ("sign", [x]) => x.signum(),
+        ("floor", [x]) => x.floor(),
+        ("ceil", [x]) => x.ceil(),
+        ("min", [_, ..]) => numbers.iter().copied().fold(f64::INFINITY, f64::min),
+        ("max", [_, ..]) => numbers.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        _ => return None,
+    };
+
+    Some(RunType::Number(value).into())
+}
+
+/// The names of relations, which are kept as functions while they can't be decided.
+pub(crate) const RELATIONS: [(&str, &str); 5] = [
+    ("less", "<"),
+    ("less_equal", "<="),
+    ("greater", ">"),
+    ("greater_equal", ">="),
+    ("equal", "=="),
+];
+
+/// Decides a relation between real values, e.g. `PI > 3`.
+///
+/// Identical values are equal, even if they aren't numbers. Symbolic values are compared by what
+/// is assumed about them, e.g. `x > -1` holds if `x > 0` does, see [`assumptions::decide`].
+fn compare(name: &str, args: &[RunVal]) -> Option<bool> {
+    let [lhs, rhs] = args else {
+        return None;
+    };
+    if !RELATIONS.iter().any(|(relation, _)| *relation == name) {
+        return None;
+    }
+    if name == "equal" && lhs == rhs {
+        return Some(true);
+    }
+    // Values with units are compared in the base units, e.g. 1 m < 2 km
+    if let (Some((lhs, a)), Some((rhs, b))) = (units::magnitude(lhs), units::magnitude(rhs)) {
+        return (a == b).then(|| compare(name, &[lhs, rhs]))?;
+    }
+
+    let approximate = |value: &RunVal| {
+        let mut value = value.approximate();
+        value.simplify();
+        match value.typ {
+            RunType::Number(n) if !n.is_nan() => Some(n),
+            _ => None,
+        }
+    };
+    let (Some(lhs), Some(rhs)) = (approximate(lhs), approximate(rhs)) else {
+        return assumptions::decide(name, lhs, rhs);
+    };
+
+    Some(match name {
+        "less" => lhs < rhs,
+        "less_equal" => lhs <= rhs,
+        "greater" => lhs > rhs,
+        "greater_equal" => lhs >= rhs,
+        _ => lhs == rhs,
+    })
+}
+
+/// Symbolic simplification rules for known functions, e.g. `exp(ln(x))` => `x`.
+///
+/// The identities of logarithms are pattern rules instead, see [`super::rules`].
+/// The returned value isn't simplified.
+pub(crate) fn rewrite(name: &str, args: &[RunVal]) -> Option<RunVal> {
+    use RunType::*;
+
+    match (name, args) {
+        // Definitions in terms of other functions
+        ("sqrt", [x]) => {
+            return Some(Exponent(Box::new(x.clone()), Box::new(Number(0.5).into())).into())
+        }
+        ("log", [b, x]) => {
+            return Some(call("ln", x).multiply(call("ln", b).power(Number(-1.0).into())))
+        }
+        ("min" | "max", [x]) => return Some(x.clone()),
+        ("min" | "max", [_, _, ..]) => return extremum(name, args),
+        ("binomial" | "mod", _) => return integers::rewrite(name, args),
+        _ => {}
+    }
+
+    let [arg] = args else {
+        return None;
+    };
+
+    // Inverse functions cancel each other
+    let inverse = match name {
+        "sin" => Some("asin"),
+        "cos" => Some("acos"),
+        "tan" => Some("atan"),
+        "exp" => Some("ln"),
+        "ln" => Some("exp"),
+        _ => None,
+    };
+    if let Function(inner, inner_args) = &arg.typ {
+        if inverse == Some(inner.as_str()) && inner_args.len() == 1 {
+            functions_debug!("{}({}(x)) => x", name, inner);
+            return Some(inner_args[0].clone());
+        }
+    }
+
+    if let ("sin" | "cos" | "tan", Some(multiple)) = (name, multiple_of_pi(arg)) {
+        if let Some(exact) = special_angle(name, multiple) {
+            return Some(exact);
+        }
+    }
+
+    // Symmetry, e.g. sin(-x) => -sin(x) and cos(-x) => cos(x)
+    if let Some(positive) = negated(arg) {
+        match name {
+            "sin" | "tan" | "asin" | "atan" | "sinh" | "tanh" | "sign" => {
+                return Some(negate(call(name, &positive)))
+            }
+            "cos" | "cosh" | "abs" => return Some(call(name, &positive)),
+            _ => {}
+        }
+    }
+
+    // Decided by the sign of the argument, e.g. abs(x) => x if x >= 0 is assumed
+    if let Some(pieces) = piecewise(name, args) {
+        let decided = RunVal::reduce_pieces(&decide(pieces));
+        if let Some(value) = decided.filter(|it| !matches!(it.typ, Piecewise(_))) {
+            functions_debug!("{}({:?}) is decided: {:?}", name, arg, value);
+            return Some(value);
+        }
+    }
+
+    match (name, &arg.typ) {
+        ("ln", Constant(super::value::Constant::E)) => Some(Number(1.0).into()),
+        ("abs", Function(inner, _)) if inner == "abs" => Some(arg.clone()),
+        ("floor" | "ceil", _) if assumptions::is_integer(arg) == Some(true) => Some(arg.clone()),
+        _ => None,
+    }
+}
+
+/// The definition of a function by cases, e.g. `abs(x)` is `x` if `x >= 0` and `-x` otherwise.
+///
+/// The returned value isn't simplified.
+pub(crate) fn piecewise(name: &str, args: &[RunVal]) -> Option<Pieces> {
+    let number = |n: f64| -> RunVal { RunType::Number(n).into() };
+    let otherwise = || -> RunVal { RunType::Bool(true).into() };
+
+    let pieces = match (name, args) {
+        ("abs", [x]) => vec![
+            (x.clone().greater_equal(number(0.0)), x.clone()),
+            (otherwise(), negate(x.clone())),
+        ],
+        ("sign", [x]) => vec![
+            (x.clone().greater(number(0.0)), number(1.0)),
+            (x.clone().less(number(0.0)), number(-1.0)),
+            (otherwise(), number(0.0)),
+        ],
+        // min(a, b, c) is min(a, min(b, c))
+        ("min" | "max", [a, rest @ ..]) if !rest.is_empty() => {
+            let b = match rest {
+                [b] => b.clone(),
+                _ => RunType::Function(name.to_string(), rest.to_vec()).into(),
+            };
+            let holds = match name {
+                "min" => a.clone().less_equal(b.clone()),
+                _ => a.clone().greater_equal(b.clone()),
+            };
+            vec![(holds, a.clone()), (otherwise(), b)]
+        }
+        _ => return None,
+    };
+
+    Some(Pieces(pieces))
+}
+
+/// The pieces with the conditions that can be decided replaced by `true` or `false`.
+fn decide(Pieces(pieces): Pieces) -> Pieces {
+    let pieces = pieces.into_iter().map(|(cond, value)| {
+        let holds = match &cond.typ {
+            RunType::Function(name, args) => compare(name, args),
+            _ => None,
+        };
+        (holds.map_or(cond, |it| RunType::Bool(it).into()), value)
+    });
+    Pieces(pieces.collect())
+}
+
+/// `min` or `max` without the arguments that can't be the result, e.g. `max(x, x + 1)` =>
+/// `x + 1`, or `None` if every argument can be.
+fn extremum(name: &str, args: &[RunVal]) -> Option<RunVal> {
+    // Whether `a` can be the result instead of `b`
+    let beats = |a: &RunVal, b: &RunVal| {
+        let relation = if name == "min" { "less_equal" } else { "greater_equal" };
+        compare(relation, &[a.clone(), b.clone()]) == Some(true)
+    };
+
+    let mut kept: Vec<RunVal> = Vec::new();
+    for arg in args {
+        if kept.iter().any(|it| beats(it, arg)) {
+            continue;
+        }
+        kept.retain(|it| !beats(arg, it));
+        kept.push(arg.clone());
+    }
+
+    match kept.as_slice() {
+        [value] => Some(value.clone()),
+        _ if kept.len() < args.len() => Some(RunType::Function(name.to_string(), kept).into()),
+        _ => None,
+    }
+}
+
+/// Replaces `a * sin(u)^2 + a * cos(u)^2` with `a` in the terms of a simplified sum.
+///
+/// Returns whether anything was replaced.
+pub(crate) fn pythagorean_identity(Terms(terms): &mut Terms) -> bool {
+    let mut replaced = false;
+    let mut i = 0;
+
+    while i < terms.len() {
+        let Some((name, arg, rest)) = squared_trigonometric(&terms[i]) else {
+            i += 1;
+            continue;
+        };
+
+        let partner = terms.iter().enumerate().position(|(j, term)| {
+            j != i
+                && squared_trigonometric(term).is_some_and(|(other, other_arg, other_rest)| {
+                    other != name && other_arg.struct_equal(&arg) && other_rest.struct_equal(&rest)
+                })
+        });
+
+        match partner {
+            Some(j) => {
+                functions_debug!("sin({:?})^2 + cos({:?})^2 => 1", arg, arg);
+                terms[i] = rest;
+                terms.remove(j);
+                replaced = true;
+            }
+            None => i += 1,
+        }
+    }
+
+    replaced
+}
+
+/// Splits a term `rest * sin(u)^2` (or `cos`) into the function name, `u` and `rest`.
+fn squared_trigonometric(term: &RunVal) -> Option<(&str, RunVal, RunVal)> {
+    if let Some((name, arg)) = is_square(term) {
+        return Some((name, arg, RunType::Number(1.0).into()));
+    }
+
+    let RunType::Product(Factors(factors)) = &term.typ else {
+        return None;
+    };
+
+    let (index, (name, arg)) = factors
+        .iter()
+        .enumerate()
+        .find_map(|(index, factor)| is_square(factor).map(|it| (index, it)))?;
+
+    let mut rest: RunVal = RunType::Product(Factors(
+        factors
+            .iter()
+            .enumerate()
+            .filter(|(other, _)| *other != index)
+            .map(|(_, factor)| factor.clone())
+            .collect(),
+    ))
+    .into();
+    rest.simplify();
+
+    Some((name, arg, rest))
+}
+
+/// `(name, u)` for a factor `sin(u)^2` or `cos(u)^2`.
+fn is_square(factor: &RunVal) -> Option<(&str, RunVal)> {
+    match &factor.typ {
+        RunType::Exponent(base, exp) => match (&base.typ, &exp.typ) {
+            (RunType::Function(name, args), RunType::Number(2.0))
+                if (name == "sin" || name == "cos") && args.len() == 1 =>
+            {
+                Some((name.as_str(), args[0].clone()))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Exact values of `sin`, `cos` and `tan` at multiples of 15°, where they are rational.
+///
+/// The angle is given as a multiple of `PI`.
+fn special_angle(name: &str, multiple: f64) -> Option<RunVal> {
+    let steps = multiple * 12.0;
+    if (steps - steps.round()).abs() > 1e-9 {
+        return None;
+    }
+
+    // The angle in multiples of 15°, in [0, 24)
+    let n = (steps.round() as i64).rem_euclid(24);
+
+    let sine = |n: i64| match n {
+        0 | 12 => Some(0.0),
+        6 => Some(1.0),
+        18 => Some(-1.0),
+        2 | 10 => Some(0.5),
+        14 | 22 => Some(-0.5),
+        _ => None,
+    };
+
+    let value = match name {
+        "sin" => sine(n)?,
+        "cos" => sine((n + 6) % 24)?,
+        _ => match n % 12 {
+            0 => 0.0,
+            3 => 1.0,
+            9 => -1.0,
+            6 => return Some(RunType::Undefined.into()),
+            _ => return None,
+        },
+    };
+
+    functions_debug!("{}({} * PI) is a special angle: {}", name, multiple, value);
+    Some(RunType::Number(value).into())
+}
+
+/// An angle found by an inverse function, exact at multiples of 15°, e.g. `acos(0.5)` =>
+/// `PI/3`, see [`special_angle`].
+fn angle(radians: f64) -> RunVal {
+    let steps = radians / PI * 12.0;
+    if (steps - steps.round()).abs() > 1e-9 {
+        return number(radians);
+    }
+    let pi = RunVal::from(RunType::Constant(Constant::Pi));
+    simplified(number(steps.round() / 12.0).multiply(pi))
+}
+
+/// `q` for the value `q * PI`.
+fn multiple_of_pi(value: &RunVal) -> Option<f64> {
+    let pi = |value: &RunVal| matches!(value.typ, RunType::Constant(Constant::Pi));
+    let number = |value: &RunVal| match value.typ {
+        RunType::Number(n) => Some(n),
+        _ => None,
+    };
+
+    match &value.typ {
+        RunType::Constant(Constant::Pi) => Some(1.0),
+        RunType::Product(Factors(factors)) => match factors.as_slice() {
+            [a, b] if pi(a) => number(b),
+            [a, b] if pi(b) => number(a),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// `log_b(x)`, exact when `x` is an integer power of `b`.
+fn logarithm(b: f64, x: f64) -> f64 {
+    let value = x.ln() / b.ln();
+    let rounded = value.round();
+    if b.powf(rounded) == x {
+        rounded
+    } else {
+        value
+    }
+}
+
+/// The derivative of a known single-argument function with respect to its argument,
+/// e.g. `sin` gives `cos(arg)`.
+pub(crate) fn derivative(name: &str, arg: &RunVal) -> Option<RunVal> {
+    let zero = || -> RunVal { RunType::Number(0.0).into() };
+    let one = || -> RunVal { RunType::Number(1.0).into() };
+    let square = || arg.clone().power(RunType::Number(2.0).into());
+
+    let derivative = match name {
+        "sin" => call("cos", arg),
+        "cos" => negate(call("sin", arg)),
+        "tan" => call("cos", arg).power(RunType::Number(-2.0).into()),
+        "asin" => one()
+            .add(negate(square()))
+            .power(RunType::Number(-0.5).into()),
+        "acos" => negate(
+            one()
+                .add(negate(square()))
+                .power(RunType::Number(-0.5).into()),
+        ),
+        "atan" => one().add(square()).power(RunType::Number(-1.0).into()),
+        "sinh" => call("cosh", arg),
+        "cosh" => call("sinh", arg),
+        "tanh" => call("cosh", arg).power(RunType::Number(-2.0).into()),
+        "exp" => call("exp", arg),
+        "ln" => arg.clone().power(RunType::Number(-1.0).into()),
+        "sqrt" => arg
+            .clone()
+            .power(RunType::Number(-0.5).into())
+            .multiply(RunType::Number(0.5).into()),
+        // Undefined where they jump or have a corner
+        "abs" => except_at(arg.clone().equal(zero()), call("sign", arg)),
+        "sign" => except_at(arg.clone().equal(zero()), zero()),
+        "floor" | "ceil" => except_at(call(name, arg).equal(arg.clone()), zero()),
+        _ => return None,
+    };
+
+    Some(derivative)
+}
+
+/// `value`, except that it's undefined where `cond` holds.
+fn except_at(cond: RunVal, value: RunVal) -> RunVal {
+    RunType::Piecewise(Pieces(vec![
+        (cond, RunType::Undefined.into()),
+        (RunType::Bool(true).into(), value),
+    ]))
+    .into()
+}
+
+/// Whether a function of one argument has no value at `arg` that it approaches, e.g. `ln` at 0,
+/// or jumps there, e.g. `floor` at integers.
+pub(crate) fn is_singular(name: &str, arg: f64) -> bool {
+    match name {
+        "ln" | "sign" => arg == 0.0,
+        "floor" | "ceil" => arg.fract() == 0.0,
+        _ => false,
+    }
+}
+
+/// Whether a function of one argument has a Taylor series around `arg`, i.e. it's neither
+/// singular there nor has a corner, like `abs` at 0.
+pub(crate) fn is_smooth(name: &str, arg: f64) -> bool {
+    let corner = name == "abs" && arg == 0.0;
+    !is_singular(name, arg) && !corner
+}
+
+pub(crate) fn call(name: &str, arg: &RunVal) -> RunVal {
+    RunType::Function(name.to_string(), vec![arg.clone()]).into()
+}
+
+/// `x` for a simplified value `-x`, i.e. a product with a negative coefficient.
+fn negated(value: &RunVal) -> Option<RunVal> {
+    let RunType::Product(Factors(factors)) = &value.typ else {
+        return None;
+    };
+
+    let index = factors
+        .iter()
+        .position(|factor| matches!(factor.typ, RunType::Number(n) if n < 0.0))?;
+    let RunType::Number(n) = factors[index].typ else {
+        return None;
+    };
+
+    let mut positive = factors.clone();
+    positive[index] = RunType::Number(-n).into();
+    let mut positive: RunVal = RunType::Product(Factors(positive)).into();
+    positive.simplify();
+    Some(positive)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cas::eval::testing::{eval, Session};
+
+    #[test]
+    fn special_angles() {
+        assert_eq!(eval("sin(PI/6)"), "0.5");
+        assert_eq!(eval("cos(PI/3)"), "0.5");
+        assert_eq!(eval("tan(PI/4)"), "1");
+    }
+
+    #[test]
+    fn inverse_special_angles() {
+        assert_eq!(eval("acos(1/2)"), eval("PI/3"));
+        assert_eq!(eval("asin(-1)"), eval("-PI/2"));
+        assert_eq!(eval("atan(1)"), eval("PI/4"));
+        assert_eq!(eval("atan2(1, 1)"), eval("PI/4"));
+        assert_eq!(eval("atan2(0, -1)"), "PI");
+        assert_eq!(eval("asin(0)"), "0");
+        assert_eq!(eval("acos(sqrt(2)/2)"), eval("PI/4"));
+        assert_eq!(eval("atan(2)"), "1.1071487177940904");
+    }
+
+    #[test]
+    fn symbolic_arguments() {
+        assert_eq!(eval("sin(x)"), "sin(x)");
+        assert_eq!(eval("sqrt(x)"), "x^0.5");
+        assert_eq!(eval("asin(2)"), "asin(2)");
+        assert_eq!(eval("ln(-1)"), "ln(-1)");
+    }
+
+    #[test]
+    fn identities() {
+        assert_eq!(eval("sin(x)^2 + cos(x)^2"), "1");
+        assert_eq!(eval("2*sin(x)^2 + 2*cos(x)^2 + 1"), "3");
+        assert_eq!(eval("exp(ln(x))"), "x");
+        assert_eq!(eval("ln(exp(x))"), "x");
+        assert_eq!(eval("sin(-x)"), "-sin(x)");
+        assert_eq!(eval("cos(-x)"), "cos(x)");
+        assert_eq!(eval("log(10, 1000)"), "3");
+    }
+
+    #[test]
+    fn logarithm_of_zero_is_undefined() {
+        assert_eq!(eval("ln(0)"), "undefined");
+        assert_eq!(eval("log(2, 0)"), "undefined");
+    }
+
+    #[test]
+    fn logarithm_laws_need_positive_arguments() {
+        assert_eq!(eval("ln(2*x)"), "ln(2 * x)");
+        assert_eq!(eval("ln(x^y)"), "ln(x^y)");
+        assert_eq!(eval("ln(x^2)"), "2 * ln(abs(x))");
+
+        let mut session = Session::new();
+        session.eval("assume(x > 0)");
+        session.eval("assume(y > 0)");
+        assert_eq!(session.eval("ln(x*y)"), "ln(x) + ln(y)");
+        assert_eq!(session.eval("ln(x^2)"), "2 * ln(x)");
+        assert_eq!(session.eval("ln(x^y)"), "ln(x) * y");
+
+        let mut session = Session::new();
+        session.eval("assume(a < 0)");
+        session.eval("assume(b < 0)");
+        assert_eq!(session.eval("ln(a*b)"), "ln(a * b)");
+        assert_eq!(session.eval("ln(a^2)"), "2 * ln(-a)");
+    }
+
+    #[test]
+    fn piecewise_functions() {
+        assert_eq!(eval("abs(-3)"), "3");
+        assert_eq!(eval("abs(-x)"), "abs(x)");
+        assert_eq!(eval("sign(0)"), "0");
+        assert_eq!(eval("min(3, 1, 2)"), "1");
+        assert_eq!(eval("max(x, x + 1)"), "x + 1");
+        assert_eq!(eval("floor(27/10)"), "2");
+        assert_eq!(eval("ceil(-3/2)"), "-1");
+
+        let mut session = Session::new();
+        session.eval("assume(z > 0)");
+        assert_eq!(session.eval("abs(z)"), "z");
+        assert_eq!(session.eval("sign(z)"), "1");
+    }
+
+    #[test]
+    fn derivatives_where_they_jump() {
+        assert_eq!(eval("diff(abs(x), x)"), "if x == 0 then undefined else sign(x)");
+        assert_eq!(eval("diff(sign(x), x)"), "if x == 0 then undefined else 0");
+        assert_eq!(eval("diff(floor(x), x)"), "if floor(x) == x then undefined else 0");
+        assert_eq!(eval("diff(abs(x), x) | x = 0"), "undefined");
+        assert_eq!(eval("diff(abs(x), x) | x = -2"), "-1");
+    }
+
+    #[test]
+    fn even_roots_of_even_powers() {
+        assert_eq!(eval("sqrt(x^2)"), "abs(x)");
+        assert_eq!(eval("(x^6)^(1/2)"), "abs(x)^3");
+        assert_eq!(eval("abs(x)^2"), "x^2");
+
+        let mut session = Session::new();
+        session.eval("assume(t < 0)");
+        assert_eq!(session.eval("sqrt(t^2)"), "-t");
+    }
+
+}
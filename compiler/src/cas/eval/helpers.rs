@@ -0,0 +1,68 @@
+//! Small helpers for building and inspecting values, shared by the modules that compute with
+//! them, e.g. `subtract(x, number(1.0))` for `x - 1`.
+//!
+//! The values they build aren't simplified, except for [`simplified`] itself.
+
+use super::{
+    poly::Polynomial,
+    value::{Factors, RunType, RunVal},
+};
+
+pub(crate) fn number(n: f64) -> RunVal {
+    RunType::Number(n).into()
+}
+
+pub(crate) fn negate(value: RunVal) -> RunVal {
+    value.multiply(number(-1.0))
+}
+
+pub(crate) fn subtract(lhs: RunVal, rhs: RunVal) -> RunVal {
+    lhs.add(negate(rhs))
+}
+
+pub(crate) fn reciprocal(value: RunVal) -> RunVal {
+    value.power(number(-1.0))
+}
+
+/// The product of the factors, 1 if there are none.
+pub(crate) fn product_of(factors: Vec<RunVal>) -> RunVal {
+    match factors.is_empty() {
+        true => number(1.0),
+        false => RunType::Product(Factors(factors)).into(),
+    }
+}
+
+pub(crate) fn simplified(mut value: RunVal) -> RunVal {
+    value.simplify();
+    value
+}
+
+/// The number a value approximates, e.g. `0.25` for `1/4`. `None` if it depends on symbols or
+/// isn't defined.
+pub(crate) fn approximate(value: &RunVal) -> Option<f64> {
+    match simplified(value.approximate()).typ {
+        RunType::Number(n) if !n.is_nan() => Some(n),
+        _ => None,
+    }
+}
+
+/// `a` and `b` of `a * var + b`, where neither depends on `var`. `None` if the value isn't of
+/// degree 1 in `var`.
+pub(crate) fn linear_in(value: &RunVal, var: &RunVal) -> Option<(RunVal, RunVal)> {
+    let poly = Polynomial::from_value(value)?;
+    let index = poly.gen_index(var)?;
+    let mut others = poly.gens().iter().enumerate().filter(|(i, _)| *i != index);
+    if poly.degree(index) != Some(1) || others.any(|(_, gen)| gen.depends_on(var)) {
+        return None;
+    }
+    let coeffs = poly.coeffs(index);
+    Some((
+        simplified(coeffs[1].to_value()),
+        simplified(coeffs[0].to_value()),
+    ))
+}
+
+/// The `a` of `a * var + b`, where neither `a` nor `b` depends on `var`.
+pub(crate) fn linear_coefficient(value: &RunVal, var: &RunVal) -> Option<RunVal> {
+    linear_in(value, var).map(|(a, _)| a)
+}
@@ -0,0 +1,385 @@
+//! Number theory and combinatorics on integers, e.g. `factorint(360)` or `binomial(10, 3)`.
+//!
+//! Integers are numbers without a fractional part that floats represent exactly, so up to
+//! `2^53`. Computations are done on 64 and 128 bit integers, and results that don't fit into a
+//! float exactly, like `factorial(30)`, are left unevaluated instead of being rounded.
+//!
+//! Factorials of symbols cancel in quotients, e.g. `n!/(n - 1)!` => `n`.
+
+use log::debug;
+
+use super::{
+    poly::Polynomial,
+    value::{Factors, RunType, RunVal},
+};
+
+macro_rules! integers_debug {
+    ($($arg:tt)+) => (debug!(target: "matex::integers", "[{}:{}] {}", file!(), line!(), &format!($($arg)+)));
+}
+
+/// The largest integer that floats represent exactly, together with every smaller one.
+const MAX_EXACT: f64 = 9007199254740992.0;
+
+/// Factorials further apart than this are left as they are, e.g. `n!/(n - 100)!`.
+const MAX_RATIO: i64 = 16;
+
+/// Evaluates a function of integers, `None` unless the arguments are integers it's defined for.
+pub(crate) fn evaluate(name: &str, args: &[RunVal]) -> Option<RunVal> {
+    let integers = args.iter().map(integer).collect::<Option<Vec<_>>>()?;
+
+    let value: RunVal = match (name, integers.as_slice()) {
+        ("mod", [a, m]) if *m != 0 => number(modulo(*a, *m)),
+        ("isprime", [n]) => RunType::Bool(*n >= 0 && is_prime(*n as u64)).into(),
+        ("nextprime", [n]) => number(next_prime(*n) as i64),
+        ("factorint", [n]) if *n >= 1 => {
+            let factors = factorize(*n as u64)
+                .into_iter()
+                .map(|(p, e)| RunType::Vector(vec![number(p as i64), number(e as i64)]).into())
+                .collect();
+            RunType::Vector(factors).into()
+        }
+        ("totient", [n]) if *n >= 1 => {
+            let totient = factorize(*n as u64)
+                .into_iter()
+                .fold(*n as u64, |acc, (p, _)| acc / p * (p - 1));
+            number(totient as i64)
+        }
+        ("factorial", [n]) if *n < 0 => RunType::Undefined.into(),
+        ("factorial", [n]) => exact(factorial(*n as u64)? as i128)?,
+        ("binomial", [n, k]) => exact(binomial(*n, *k)?)?,
+        ("fibonacci", [n]) => exact(fibonacci(*n)?)?,
+        ("modinv", [a, m]) if *m > 0 => match inverse(*a, *m) {
+            Some(inverse) => number(inverse),
+            None => RunType::Undefined.into(),
+        },
+        ("modpow", [a, e, m]) if *m > 0 => {
+            let base = match *e < 0 {
+                true => inverse(*a, *m),
+                false => Some(a.rem_euclid(*m)),
+            };
+            match base {
+                Some(base) => number(power(base as u64, e.unsigned_abs(), *m as u64) as i64),
+                None => RunType::Undefined.into(),
+            }
+        }
+        _ => return None,
+    };
+
+    integers_debug!("{}({:?}) = {:?}", name, integers, value);
+    Some(value)
+}
+
+/// Identities of symbolic arguments, e.g. `binomial(n, 1)` => `n`.
+///
+/// The returned value isn't simplified.
+pub(crate) fn rewrite(name: &str, args: &[RunVal]) -> Option<RunVal> {
+    match (name, args) {
+        ("binomial", [_, k]) if integer(k) == Some(0) => Some(number(1)),
+        ("binomial", [n, k]) if n == k => Some(number(1)),
+        ("binomial", [n, k]) if integer(k) == Some(1) => Some(n.clone()),
+        // mod(mod(x, m), m) => mod(x, m)
+        ("mod", [x, m]) => match &x.typ {
+            RunType::Function(inner, inner_args) if inner == "mod" && inner_args[1] == *m => {
+                Some(x.clone())
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Cancels quotients of factorials whose arguments differ by an integer, e.g. `n!/(n - 2)!` =>
+/// `n * (n - 1)`. Returns whether anything was cancelled.
+pub(crate) fn cancel_factorials(Factors(factors): &mut Factors) -> bool {
+    let mut cancelled = false;
+
+    while let Some((i, j, lowest, difference)) = factorial_pair(factors) {
+        integers_debug!("cancel factorials {:?} and {:?}", factors[i], factors[j]);
+
+        // a!/b! is (b + 1) * ... * a, or the reciprocal of (a + 1) * ... * b if a < b
+        let terms: Vec<RunVal> = (1..=difference.abs())
+            .map(|k| {
+                let mut term = lowest.clone().add(number(k));
+                term.simplify();
+                match difference > 0 {
+                    true => term,
+                    false => term.power(number(-1)),
+                }
+            })
+            .collect();
+
+        factors.remove(i.max(j));
+        factors.remove(i.min(j));
+        factors.extend(terms);
+        cancelled = true;
+    }
+
+    cancelled
+}
+
+/// The positions of a factorial `a!` and a divisor `b!` where `a - b` is a small integer,
+/// together with the smaller of `a` and `b` and the difference.
+fn factorial_pair(factors: &[RunVal]) -> Option<(usize, usize, RunVal, i64)> {
+    let argument = |value: &RunVal| match &value.typ {
+        RunType::Function(name, args) if name == "factorial" && args.len() == 1 => {
+            Some(args[0].clone())
+        }
+        _ => None,
+    };
+    let divisor = |value: &RunVal| match &value.typ {
+        RunType::Exponent(base, exp) if exp.typ == RunType::Number(-1.0) => argument(base),
+        _ => None,
+    };
+
+    for (i, numerator) in factors.iter().enumerate() {
+        let Some(a) = argument(numerator) else {
+            continue;
+        };
+        for (j, denominator) in factors.iter().enumerate() {
+            let Some(b) = divisor(denominator) else {
+                continue;
+            };
+            // Polynomials cancel what the simplifier keeps apart, e.g. `n - (n - 1)`
+            let difference = Polynomial::from_values(&[&a, &b])
+                .and_then(|polys| polys[0].sub(&polys[1]).as_constant())
+                .filter(|it| it.fract() == 0.0 && *it != 0.0 && it.abs() <= MAX_RATIO as f64);
+            if let Some(difference) = difference {
+                let lowest = if difference > 0.0 { b } else { a };
+                return Some((i, j, lowest, difference as i64));
+            }
+        }
+    }
+    None
+}
+
+fn number(n: i64) -> RunVal {
+    RunType::Number(n as f64).into()
+}
+
+/// The number of an integer result, `None` if floats can't represent it exactly.
+fn exact(n: i128) -> Option<RunVal> {
+    (n.unsigned_abs() <= MAX_EXACT as u128).then(|| RunType::Number(n as f64).into())
+}
+
+/// The integer a value is, if it is one that floats represent exactly.
+fn integer(value: &RunVal) -> Option<i64> {
+    match value.typ {
+        RunType::Number(n) if n.fract() == 0.0 && n.abs() <= MAX_EXACT => Some(n as i64),
+        _ => None,
+    }
+}
+
+/// The remainder of `a / m` with the sign of `m`, e.g. `mod(-7, 3)` => `2`.
+fn modulo(a: i64, m: i64) -> i64 {
+    let remainder = a.rem_euclid(m);
+    if m < 0 && remainder != 0 {
+        remainder + m
+    } else {
+        remainder
+    }
+}
+
+/// `base^exp mod m`
+fn power(base: u64, mut exp: u64, m: u64) -> u64 {
+    let multiply = |a: u64, b: u64| (a as u128 * b as u128 % m as u128) as u64;
+    let mut result = 1 % m;
+    let mut base = base % m;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = multiply(result, base);
+        }
+        base = multiply(base, base);
+        exp >>= 1;
+    }
+    result
+}
+
+/// The `x` with `a * x = 1 mod m`, if `a` and `m` are coprime.
+fn inverse(a: i64, m: i64) -> Option<i64> {
+    // The extended Euclidean algorithm, keeping the coefficients of a
+    let (mut r, mut next_r) = (m, a.rem_euclid(m));
+    let (mut t, mut next_t) = (0i64, 1i64);
+    while next_r != 0 {
+        let quotient = r / next_r;
+        (r, next_r) = (next_r, r - quotient * next_r);
+        (t, next_t) = (next_t, t - quotient * next_t);
+    }
+    (r == 1).then(|| t.rem_euclid(m))
+}
+
+/// A deterministic Miller-Rabin test, these bases suffice for every 64 bit number.
+fn is_prime(n: u64) -> bool {
+    const BASES: [u64; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+    if n < 2 {
+        return false;
+    }
+    for p in BASES {
+        if n.is_multiple_of(p) {
+            return n == p;
+        }
+    }
+
+    let shift = (n - 1).trailing_zeros();
+    let odd = (n - 1) >> shift;
+    BASES.iter().all(|&base| {
+        let mut x = power(base, odd, n);
+        if x == 1 || x == n - 1 {
+            return true;
+        }
+        for _ in 1..shift {
+            x = (x as u128 * x as u128 % n as u128) as u64;
+            if x == n - 1 {
+                return true;
+            }
+        }
+        false
+    })
+}
+
+/// The smallest prime larger than `n`.
+fn next_prime(n: i64) -> u64 {
+    let mut candidate = n.max(1) as u64 + 1;
+    while !is_prime(candidate) {
+        candidate += 1;
+    }
+    candidate
+}
+
+/// The prime factors with their multiplicities, smallest first.
+fn factorize(n: u64) -> Vec<(u64, u32)> {
+    let mut primes = Vec::new();
+    let mut rest = n;
+
+    // Small factors are divided out directly, the rest is split by Pollard's rho
+    for p in 2..1000 {
+        while rest.is_multiple_of(p) {
+            primes.push(p);
+            rest /= p;
+        }
+    }
+    let mut pending = vec![rest];
+    while let Some(n) = pending.pop() {
+        if n == 1 {
+            continue;
+        }
+        if is_prime(n) {
+            primes.push(n);
+            continue;
+        }
+        let divisor = pollard_rho(n);
+        pending.push(divisor);
+        pending.push(n / divisor);
+    }
+
+    primes.sort_unstable();
+    let mut factors: Vec<(u64, u32)> = Vec::new();
+    for p in primes {
+        match factors.last_mut() {
+            Some((last, count)) if *last == p => *count += 1,
+            _ => factors.push((p, 1)),
+        }
+    }
+    factors
+}
+
+/// A non-trivial divisor of a composite number without small factors.
+fn pollard_rho(n: u64) -> u64 {
+    let multiply = |a: u64, b: u64| (a as u128 * b as u128 % n as u128) as u64;
+    for c in 1.. {
+        let step = |x: u64| (multiply(x, x) + c) % n;
+        let (mut x, mut y, mut divisor) = (2, 2, 1);
+        while divisor == 1 {
+            x = step(x);
+            y = step(step(y));
+            divisor = gcd(x.abs_diff(y), n);
+        }
+        if divisor != n {
+            return divisor;
+        }
+    }
+    unreachable!("Every composite number has a divisor")
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// `None` from `35!` on, which doesn't fit into 128 bits.
+fn factorial(n: u64) -> Option<u128> {
+    (1..=n).try_fold(1u128, |acc, k| acc.checked_mul(k as u128))
+}
+
+/// `n choose k`, where `n` may be negative, e.g. `binomial(-1, k)` is `(-1)^k`.
+fn binomial(n: i64, k: i64) -> Option<i128> {
+    if k < 0 || (n >= 0 && k > n) {
+        return Some(0);
+    }
+    if n < 0 {
+        let sign = if k % 2 == 0 { 1 } else { -1 };
+        return Some(sign * binomial(k - n - 1, k)?);
+    }
+
+    // Every partial product is itself a binomial coefficient, so the divisions are exact
+    let k = k.min(n - k) as u128;
+    let mut result: u128 = 1;
+    for i in 0..k {
+        result = result.checked_mul(n as u128 - i)? / (i + 1);
+    }
+    i128::try_from(result).ok()
+}
+
+/// The n-th Fibonacci number, with `fibonacci(-n) = (-1)^(n + 1) * fibonacci(n)`.
+fn fibonacci(n: i64) -> Option<i128> {
+    let (mut a, mut b): (i128, i128) = (0, 1);
+    for _ in 0..n.unsigned_abs() {
+        (a, b) = (b, a.checked_add(b)?);
+    }
+    let sign = if n < 0 && n % 2 == 0 { -1 } else { 1 };
+    Some(sign * a)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cas::eval::testing::eval;
+
+    #[test]
+    fn number_theory() {
+        assert_eq!(eval("-7 % 3"), "2");
+        assert_eq!(eval("7 % -3"), "-2");
+        assert_eq!(eval("isprime(2147483647)"), "true");
+        assert_eq!(eval("factorint(360)"), "[[2, 3], [3, 2], [5, 1]]");
+        assert_eq!(eval("totient(36)"), "12");
+        assert_eq!(eval("modinv(2, 4)"), "undefined");
+        assert_eq!(eval("modpow(3, -1, 11)"), "4");
+    }
+
+    #[test]
+    fn combinatorics() {
+        assert_eq!(eval("5!"), "120");
+        assert_eq!(eval("2^3!"), "64");
+        assert_eq!(eval("binomial(10, 3)"), "120");
+        assert_eq!(eval("binomial(-1, 3)"), "-1");
+        assert_eq!(eval("fibonacci(-8)"), "-21");
+        assert_eq!(eval("n!/(n - 2)!"), eval("n * (n - 1)"));
+    }
+
+    #[test]
+    fn results_are_exact_or_unevaluated() {
+        assert_eq!(eval("factorial(18)"), "6402373705728000");
+        assert_eq!(eval("factorial(23)"), "23!");
+        assert_eq!(eval("binomial(50, 25)"), "126410606437752");
+        assert_eq!(eval("binomial(100, 50)"), "binomial(100, 50)");
+        assert_eq!(eval("fibonacci(78)"), "8944394323791464");
+        assert_eq!(eval("fibonacci(80)"), "fibonacci(80)");
+        assert_eq!(eval("factorial(200)"), "200!");
+    }
+
+    #[test]
+    fn negative_factorials_are_undefined() {
+        assert_eq!(eval("factorial(-1)"), "undefined");
+        assert_eq!(eval("(-3)!"), "undefined");
+    }
+}
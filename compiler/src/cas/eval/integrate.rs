@@ -0,0 +1,320 @@
+//! Antiderivatives of elementary functions, for `integrate(expr, x)` and as far as the differential
+//! equation solver needs them, see [`super::ode`].
+//!
+//! Integrals are found by linearity, a table of functions of linear arguments, partial fractions,
+//! products of polynomials with `exp`, `sin` and `cos`, and substitution where the derivative of
+//! the inner function is a factor, e.g. `x * exp(x^2)`. The constant of integration is left out.
+
+use log::debug;
+
+use super::{
+    diff,
+    functions::call,
+    helpers::{linear_coefficient, negate, number, product_of, reciprocal, simplified, subtract},
+    poly::Polynomial,
+    rational,
+    value::{Constant, Factors, RunType, RunVal, Terms},
+};
+
+macro_rules! integrate_debug {
+    ($($arg:tt)+) => (debug!(target: "matex::integrate", "[{}:{}] {}", file!(), line!(), &format!($($arg)+)));
+}
+
+/// Products of polynomials with these degrees or higher aren't integrated by parts.
+const MAX_DEGREE: u32 = 12;
+
+/// An antiderivative of `value` with respect to `var`, `None` if none was found.
+///
+/// The result is not simplified.
+pub(crate) fn antiderivative(value: &RunVal, var: &RunVal) -> Option<RunVal> {
+    integrate_debug!("integrate {:?} d{:?}", value, var);
+    let mut value = value.clone();
+    value.simplify();
+    integrate(&value, var, true)
+}
+
+/// The argument `apart` tells whether partial fractions are still to be tried.
+fn integrate(value: &RunVal, var: &RunVal, apart: bool) -> Option<RunVal> {
+    use RunType::*;
+
+    if !value.depends_on(var) {
+        return Some(value.clone().multiply(var.clone()));
+    }
+
+    let integral = match &value.typ {
+        Symbol(_) => Some(half(var.clone().power(number(2.0)))),
+        Sum(Terms(terms)) => terms
+            .iter()
+            .map(|it| integrate(it, var, apart))
+            .collect::<Option<Vec<_>>>()
+            .map(|it| Sum(Terms(it)).into()),
+        Product(Factors(factors)) => {
+            let (constant, dependent): (Vec<_>, Vec<_>) =
+                factors.iter().cloned().partition(|it| !it.depends_on(var));
+            let integral = match dependent.as_slice() {
+                [single] => integrate(single, var, apart),
+                _ => product(&dependent, var),
+            };
+            integral.map(|it| product_of(constant).multiply(it))
+        }
+        Exponent(base, exp) => power(base, exp, var),
+        Function(name, args) if args.len() == 1 => function(name, &args[0], var),
+        _ => None,
+    };
+
+    integral.or_else(|| substitution(value, var)).or_else(|| {
+        let fractions = partial_fractions(value, var).filter(|_| apart)?;
+        integrate(&fractions, var, false)
+    })
+}
+
+/// `(a * x + b)^n`, `c^(a * x + b)`, polynomials and `1/(a * x^2 + b * x + c)`.
+fn power(base: &RunVal, exp: &RunVal, var: &RunVal) -> Option<RunVal> {
+    if !exp.depends_on(var) {
+        if let (Some(slope), RunType::Number(n)) = (linear_coefficient(base, var), &exp.typ) {
+            let integral = match *n == -1.0 {
+                true => call("ln", base),
+                false => base
+                    .clone()
+                    .power(number(n + 1.0))
+                    .multiply(number(1.0 / (n + 1.0))),
+            };
+            return Some(integral.multiply(reciprocal(slope)));
+        }
+        return match exp.typ {
+            RunType::Number(n) if n > 0.0 && n.fract() == 0.0 && n <= MAX_DEGREE as f64 => {
+                let poly = Polynomial::from_value(&base.clone().power(exp.clone()))?;
+                integrate(&poly.to_value(), var, false)
+            }
+            RunType::Number(-1.0) => arctangent(base, var),
+            _ => None,
+        };
+    }
+
+    // c^(a * x + b) = exp(ln(c) * (a * x + b))
+    if base.depends_on(var) {
+        return None;
+    }
+    let slope = linear_coefficient(exp, var)?;
+    let logarithm = match base.typ {
+        RunType::Constant(Constant::E) => number(1.0),
+        _ => call("ln", base),
+    };
+    Some(
+        base.clone()
+            .power(exp.clone())
+            .multiply(reciprocal(slope.multiply(logarithm))),
+    )
+}
+
+/// Functions of a linear argument `a * x + b`.
+fn function(name: &str, arg: &RunVal, var: &RunVal) -> Option<RunVal> {
+    let slope = linear_coefficient(arg, var)?;
+    let integral = match name {
+        "exp" => call("exp", arg),
+        "sin" => negate(call("cos", arg)),
+        "cos" => call("sin", arg),
+        "sinh" => call("cosh", arg),
+        "cosh" => call("sinh", arg),
+        "tan" => negate(call("ln", &call("cos", arg))),
+        "ln" => subtract(arg.clone().multiply(call("ln", arg)), arg.clone()),
+        _ => return None,
+    };
+    Some(integral.multiply(reciprocal(slope)))
+}
+
+/// Products of a polynomial and a function of a linear argument, integrated by parts, and
+/// `exp(a * x + b)` times `sin` or `cos` of a linear argument.
+fn product(factors: &[RunVal], var: &RunVal) -> Option<RunVal> {
+    let (polynomial, others): (Vec<_>, Vec<_>) = factors
+        .iter()
+        .cloned()
+        .partition(|it| is_polynomial(it, var));
+
+    match others.as_slice() {
+        // A product of polynomials is one polynomial
+        [] => {
+            let poly = Polynomial::from_value(&RunType::Product(Factors(polynomial)).into())?;
+            integrate(&poly.to_value(), var, false)
+        }
+        // ∫ p * f = p * F1 - p' * F2 + p'' * F3 - ..., where Fk is the k-th antiderivative of f
+        [other] => {
+            let mut p = simplified(RunType::Product(Factors(polynomial)).into());
+            let mut f = simplified(other.clone());
+            let mut terms = Vec::new();
+            for k in 0..=MAX_DEGREE {
+                if is_zero(&p) {
+                    return Some(RunType::Sum(Terms(terms)).into());
+                }
+                f = simplified(integrate(&f, var, false)?);
+                let term = p.clone().multiply(f.clone());
+                terms.push(if k % 2 == 0 { term } else { negate(term) });
+                p = simplified(diff::differentiate(&p, var));
+            }
+            None
+        }
+        [first, second] if polynomial.is_empty() => {
+            exponential_sine(first, second, var).or_else(|| exponential_sine(second, first, var))
+        }
+        _ => None,
+    }
+}
+
+/// `∫ exp(u) * sin(v) = exp(u) * (a * sin(v) - c * cos(v))/(a^2 + c^2)` and
+/// `∫ exp(u) * cos(v) = exp(u) * (a * cos(v) + c * sin(v))/(a^2 + c^2)` for `u = a * x + b` and
+/// `v = c * x + d`.
+fn exponential_sine(exponential: &RunVal, sine: &RunVal, var: &RunVal) -> Option<RunVal> {
+    let RunType::Function(exp, u) = &exponential.typ else {
+        return None;
+    };
+    let RunType::Function(name, v) = &sine.typ else {
+        return None;
+    };
+    if exp != "exp" || u.len() != 1 || v.len() != 1 {
+        return None;
+    }
+    let (a, c) = (
+        linear_coefficient(&u[0], var)?,
+        linear_coefficient(&v[0], var)?,
+    );
+
+    let (sin, cos) = (call("sin", &v[0]), call("cos", &v[0]));
+    let combination = match name.as_str() {
+        "sin" => subtract(a.clone().multiply(sin), c.clone().multiply(cos)),
+        "cos" => a.clone().multiply(cos).add(c.clone().multiply(sin)),
+        _ => return None,
+    };
+    let norm = a.power(number(2.0)).add(c.power(number(2.0)));
+    Some(
+        exponential
+            .clone()
+            .multiply(combination)
+            .multiply(reciprocal(norm)),
+    )
+}
+
+/// `∫ 1/(a * x^2 + b * x + c) = 2/sqrt(4ac - b^2) * atan((2ax + b)/sqrt(4ac - b^2))` if the
+/// quadratic has no real roots.
+fn arctangent(base: &RunVal, var: &RunVal) -> Option<RunVal> {
+    let poly = Polynomial::from_value(base)?;
+    let index = poly.gen_index(var)?;
+    let coeffs = poly.coeffs(index);
+    let [c, b, a] = coeffs.as_slice() else {
+        return None;
+    };
+    let (a, b, c) = (a.as_constant()?, b.as_constant()?, c.as_constant()?);
+    let discriminant = 4.0 * a * c - b * b;
+    if poly.gens().len() != 1 || discriminant <= 0.0 {
+        return None;
+    }
+
+    let root = discriminant.sqrt();
+    let arg = var
+        .clone()
+        .multiply(number(2.0 * a / root))
+        .add(number(b / root));
+    Some(number(2.0 / root).multiply(call("atan", &arg)))
+}
+
+/// `∫ f(u) * u' = F(u)`, for a factor `f(u)` where the rest of the product is a constant
+/// multiple of `u'`, e.g. `x/(x^2 + 1)` => `0.5 * ln(x^2 + 1)`.
+fn substitution(value: &RunVal, var: &RunVal) -> Option<RunVal> {
+    let factors = match &value.typ {
+        RunType::Product(Factors(factors)) => factors.clone(),
+        _ => vec![value.clone()],
+    };
+    let u = RunVal::from(RunType::Symbol("_u".to_string()));
+
+    for (i, factor) in factors.iter().enumerate() {
+        let (inner, outer) = match &factor.typ {
+            RunType::Function(name, args) if args.len() == 1 => (&args[0], call(name, &u)),
+            RunType::Exponent(base, exp) if !exp.depends_on(var) => {
+                (base.as_ref(), u.clone().power(exp.as_ref().clone()))
+            }
+            RunType::Exponent(base, exp) if !base.depends_on(var) => {
+                (exp.as_ref(), base.as_ref().clone().power(u.clone()))
+            }
+            _ => continue,
+        };
+        if linear_coefficient(inner, var).is_some() || !inner.depends_on(var) {
+            continue;
+        }
+
+        // The rest divided by u' has to be a constant
+        let mut rest = factors.clone();
+        rest.remove(i);
+        let derivative = diff::differentiate(inner, var);
+        let ratio = product_of(rest).multiply(reciprocal(derivative));
+        let ratio = rational::cancel(&simplified(ratio));
+        if ratio.depends_on(var) {
+            continue;
+        }
+
+        if let Some(integral) = integrate(&simplified(outer), &u, true) {
+            return Some(ratio.multiply(integral.substitute(&u, inner)));
+        }
+    }
+    None
+}
+
+/// The partial fractions of a rational function, if that changes it.
+fn partial_fractions(value: &RunVal, var: &RunVal) -> Option<RunVal> {
+    let fractions = simplified(rational::apart(value, var)?);
+    (fractions != *value).then_some(fractions)
+}
+
+/// Whether a value is a polynomial in `var` and nothing else depends on it.
+fn is_polynomial(value: &RunVal, var: &RunVal) -> bool {
+    Polynomial::from_value(value).is_some_and(|poly| {
+        poly.gens()
+            .iter()
+            .all(|gen| gen == var || !gen.depends_on(var))
+    })
+}
+
+fn is_zero(value: &RunVal) -> bool {
+    matches!(value.typ, RunType::Number(n) if n == 0.0)
+}
+
+fn half(value: RunVal) -> RunVal {
+    value.multiply(number(0.5))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cas::eval::testing::eval;
+
+    #[test]
+    fn table_integrals() {
+        assert_eq!(eval("integrate(x^2, x)"), "0.3333333333333333 * x^3");
+        assert_eq!(eval("integrate(3 * x + 1, x)"), "1.5 * x^2 + x");
+        assert_eq!(eval("integrate(exp(2 * x), x)"), "0.5 * exp(2 * x)");
+        assert_eq!(
+            eval("integrate(cos(3 * x), x)"),
+            "0.3333333333333333 * sin(3 * x)"
+        );
+        assert_eq!(eval("integrate(1 / (2 * x + 1), x)"), "0.5 * ln(2 * x + 1)");
+        assert_eq!(eval("integrate(1 / (x^2 + 1), x)"), "atan(x)");
+    }
+
+    #[test]
+    fn by_parts_and_substitution() {
+        assert_eq!(eval("integrate(x * exp(x), x)"), "exp(x) * x - exp(x)");
+        assert_eq!(eval("integrate(x * exp(x^2), x)"), "0.5 * exp(x^2)");
+        assert_eq!(eval("diff(integrate(x^3 * sin(x), x), x)"), "sin(x) * x^3");
+    }
+
+    #[test]
+    fn unknown_integrals_stay() {
+        assert_eq!(eval("integrate(exp(-x^2), x)"), "integrate(exp(-x^2), x)");
+        assert_eq!(eval("integrate(x, 2)"), "integrate(x, 2)");
+    }
+
+    #[test]
+    fn variable_is_bound() {
+        assert_eq!(
+            eval("subs(integrate(f(x) * y, x), y, x)"),
+            "integrate(f(x1) * x, x1)"
+        );
+    }
+}
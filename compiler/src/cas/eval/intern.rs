@@ -0,0 +1,170 @@
+//! Sharing of structurally identical values and memoization of simplification.
+//!
+//! Every node that is created goes through [`intern`], which hands out an existing node
+//! with the same structure if there is one. Equal subexpressions therefore share memory,
+//! and most equality checks succeed on the pointer comparison alone.
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    rc::{Rc, Weak},
+};
+
+use log::debug;
+
+use super::value::{Node, RunVal};
+
+macro_rules! intern_debug {
+    ($($arg:tt)+) => (debug!(target: "matex::intern", "[{}:{}] {}", file!(), line!(), &format!($($arg)+)));
+}
+
+/// Dead nodes are removed from the table once it grows beyond this many entries.
+const INITIAL_CAPACITY: usize = 1 << 12;
+
+/// Remembered simplifications are forgotten once there are more than this many.
+const MAX_SIMPLIFICATIONS: usize = 1 << 14;
+
+#[derive(Default)]
+struct Interner {
+    nodes: HashMap<u64, Vec<Weak<Node>>>,
+    len: usize,
+    capacity: usize,
+    simplifications: HashMap<RunVal, RunVal>,
+    /// Changes whenever the rules of simplification do, see [`forget_simplifications`]
+    generation: u32,
+}
+
+thread_local! {
+    static INTERNER: RefCell<Interner> = RefCell::new(Interner {
+        capacity: INITIAL_CAPACITY,
+        generation: 1,
+        ..Default::default()
+    });
+}
+
+/// The shared node structurally identical to `node`, `node` itself if there is none yet.
+pub(crate) fn intern(node: Rc<Node>) -> Rc<Node> {
+    let hash = node.hash_value();
+
+    INTERNER.with(|interner| {
+        let mut interner = interner.borrow_mut();
+        let bucket = interner.nodes.entry(hash).or_default();
+
+        let existing = bucket
+            .iter()
+            .filter_map(Weak::upgrade)
+            .find(|it| Rc::ptr_eq(it, &node) || it.typ == node.typ);
+        if let Some(existing) = existing {
+            return existing;
+        }
+
+        bucket.push(Rc::downgrade(&node));
+        interner.len += 1;
+
+        if interner.len > interner.capacity {
+            interner.collect_garbage();
+        }
+
+        node
+    })
+}
+
+/// The result of simplifying `value` before, if it is still remembered.
+pub(crate) fn simplified(value: &RunVal) -> Option<RunVal> {
+    INTERNER.with(|interner| interner.borrow().simplifications.get(value).cloned())
+}
+
+pub(crate) fn remember_simplified(value: RunVal, simplified: RunVal) {
+    INTERNER.with(|interner| {
+        let mut interner = interner.borrow_mut();
+        if interner.simplifications.len() >= MAX_SIMPLIFICATIONS {
+            intern_debug!(
+                "forgetting {} simplifications",
+                interner.simplifications.len()
+            );
+            interner.simplifications.clear();
+        }
+        interner.simplifications.insert(value, simplified);
+    })
+}
+
+/// The current generation of simplification rules.
+pub(crate) fn generation() -> u32 {
+    INTERNER.with(|interner| interner.borrow().generation)
+}
+
+/// Forgets every simplification, e.g. after a rewrite rule was added or disabled.
+///
+/// Values simplified before are simplified again when they are next used.
+pub(crate) fn forget_simplifications() {
+    INTERNER.with(|interner| {
+        let mut interner = interner.borrow_mut();
+        interner.simplifications.clear();
+        interner.generation += 1;
+        intern_debug!("simplification generation {}", interner.generation);
+    })
+}
+
+impl Interner {
+    fn collect_garbage(&mut self) {
+        self.nodes.retain(|_, bucket| {
+            bucket.retain(|it| it.strong_count() > 0);
+            !bucket.is_empty()
+        });
+        self.len = self.nodes.values().map(Vec::len).sum();
+
+        // Grow when most nodes are still alive, to keep collecting rare
+        self.capacity = (self.len * 2).max(INITIAL_CAPACITY);
+        intern_debug!("{} live nodes after collecting garbage", self.len);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cas::eval::value::RunType;
+
+    fn symbol(name: &str) -> RunVal {
+        RunType::Symbol(name.to_string()).into()
+    }
+
+    fn call(args: &[&str]) -> RunVal {
+        RunVal::new(RunType::Function(
+            "f".to_string(),
+            args.iter().map(|it| symbol(it)).collect(),
+        ))
+    }
+
+    #[test]
+    fn identical_values_are_shared() {
+        assert!(symbol("x").ptr_eq(&symbol("x")));
+        assert!(call(&["x", "y"]).ptr_eq(&call(&["x", "y"])));
+        assert!(!call(&["x", "y"]).ptr_eq(&call(&["y", "x"])));
+    }
+
+    #[test]
+    fn simplifications_are_remembered() {
+        let mut value = symbol("a").add(symbol("a"));
+        let original = value.clone();
+        value.simplify();
+        assert!(value.is_simplified());
+        assert_eq!(simplified(&original), Some(value.clone()));
+
+        let mut again = symbol("a").add(symbol("a"));
+        again.simplify();
+        assert!(again.ptr_eq(&value));
+    }
+
+    #[test]
+    fn forgetting_starts_a_new_generation() {
+        let mut value = symbol("b").add(symbol("b"));
+        let original = value.clone();
+        value.simplify();
+
+        let before = generation();
+        forget_simplifications();
+        assert_eq!(generation(), before + 1);
+        assert!(!value.is_simplified());
+        assert_eq!(simplified(&original), None);
+    }
+}
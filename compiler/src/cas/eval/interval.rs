@@ -0,0 +1,542 @@
+//! Interval arithmetic, e.g. `interval(1, 2) * 3` => `interval(3, 6)`.
+//!
+//! An interval stands for an unknown real number between its bounds. The bounds are rounded
+//! outward, so the interval always contains the exact result even though every operation on
+//! floats is rounded. Sums, products and quotients are rounded exactly with error-free
+//! transformations, the functions of the standard library are widened by one ulp.
+//!
+//! Every occurrence of an interval is independent of the others, so `X * X` is multiplied like
+//! any two intervals: for `X = interval(-1, 2)` it's `interval(-2, 4)`, not the square
+//! `interval(0, 4)`. Only `X^2` is squared.
+
+use std::{
+    cmp::Ordering,
+    f64::consts::{FRAC_PI_2, PI, TAU},
+};
+
+use log::debug;
+
+#[cfg(target_arch = "wasm32")]
+use serde::{Deserialize, Serialize};
+
+use super::value::{Factors, RunType, RunVal, Terms};
+
+macro_rules! interval_debug {
+    ($($arg:tt)+) => (debug!(target: "matex::interval", "[{}:{}] {}", file!(), line!(), &format!($($arg)+)));
+}
+
+/// The real numbers from `lower` to `upper`, both included. The bounds may be infinite.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(target_arch = "wasm32", derive(Serialize, Deserialize))]
+pub struct Interval {
+    pub lower: f64,
+    pub upper: f64,
+}
+
+impl Interval {
+    /// `None` if the bounds aren't ordered, or are undefined.
+    pub fn new(lower: f64, upper: f64) -> Option<Interval> {
+        (lower <= upper).then_some(Interval { lower, upper })
+    }
+
+    pub fn point(n: f64) -> Interval {
+        Interval { lower: n, upper: n }
+    }
+
+    /// The smallest interval around a rounded value, for values like `PI` that aren't floats.
+    pub fn around(n: f64) -> Interval {
+        Interval {
+            lower: next_down(n),
+            upper: next_up(n),
+        }
+    }
+
+    pub fn entire() -> Interval {
+        Interval {
+            lower: f64::NEG_INFINITY,
+            upper: f64::INFINITY,
+        }
+    }
+
+    pub fn contains(&self, n: f64) -> bool {
+        self.lower <= n && n <= self.upper
+    }
+
+    pub fn width(&self) -> f64 {
+        self.upper - self.lower
+    }
+
+    pub fn add(&self, other: &Interval) -> Interval {
+        Interval {
+            lower: round_down(sum(self.lower, other.lower)),
+            upper: round_up(sum(self.upper, other.upper)),
+        }
+    }
+
+    pub fn neg(&self) -> Interval {
+        Interval {
+            lower: -self.upper,
+            upper: -self.lower,
+        }
+    }
+
+    pub fn multiply(&self, other: &Interval) -> Interval {
+        let products = [
+            (self.lower, other.lower),
+            (self.lower, other.upper),
+            (self.upper, other.lower),
+            (self.upper, other.upper),
+        ]
+        .map(|(a, b)| product(a, b));
+
+        Interval {
+            lower: products
+                .iter()
+                .map(|&it| round_down(it))
+                .fold(f64::INFINITY, f64::min),
+            upper: products
+                .iter()
+                .map(|&it| round_up(it))
+                .fold(f64::NEG_INFINITY, f64::max),
+        }
+    }
+
+    /// `1 / self`, everything if the interval has zero inside, and `None` for `[0, 0]`.
+    pub fn reciprocal(&self) -> Option<Interval> {
+        let Interval { lower, upper } = *self;
+        if lower == 0.0 && upper == 0.0 {
+            return None;
+        }
+        if lower < 0.0 && upper > 0.0 {
+            return Some(Interval::entire());
+        }
+
+        // [0, b] => [1/b, inf] and [a, 0] => [-inf, 1/a]
+        let reciprocal = Interval {
+            lower: match upper == 0.0 {
+                true => f64::NEG_INFINITY,
+                false => round_down(quotient(1.0, upper)),
+            },
+            upper: match lower == 0.0 {
+                true => f64::INFINITY,
+                false => round_up(quotient(1.0, lower)),
+            },
+        };
+        Some(reciprocal)
+    }
+
+    pub fn divide(&self, other: &Interval) -> Option<Interval> {
+        Some(self.multiply(&other.reciprocal()?))
+    }
+
+    /// `self^n` for an integer `n`, where `[-1, 2]^2` => `[0, 4]` unlike `[-1, 2] * [-1, 2]`.
+    pub fn powi(&self, n: i32) -> Option<Interval> {
+        if n < 0 {
+            return self.powi(-n)?.reciprocal();
+        }
+
+        let magnitude = |x: f64, up: bool| -> f64 {
+            (0..n).fold(1.0, |acc, _| match up {
+                true => round_up(product(acc, x)),
+                false => round_down(product(acc, x)),
+            })
+        };
+        let (lower, upper) = (self.lower, self.upper);
+
+        let power = if n % 2 == 1 {
+            // Odd powers keep the sign and the order
+            let signed = |x: f64, up: bool| match x < 0.0 {
+                true => -magnitude(-x, !up),
+                false => magnitude(x, up),
+            };
+            Interval {
+                lower: signed(lower, false),
+                upper: signed(upper, true),
+            }
+        } else if lower >= 0.0 {
+            Interval {
+                lower: magnitude(lower, false),
+                upper: magnitude(upper, true),
+            }
+        } else if upper <= 0.0 {
+            Interval {
+                lower: magnitude(-upper, false),
+                upper: magnitude(-lower, true),
+            }
+        } else {
+            Interval {
+                lower: 0.0,
+                upper: magnitude(upper.max(-lower), true),
+            }
+        };
+        Some(power)
+    }
+
+    /// `self^exp`, for bases that aren't negative unless the exponent is a single integer.
+    pub fn pow(&self, exp: &Interval) -> Option<Interval> {
+        // Larger powers are multiplied out too slowly
+        if exp.lower == exp.upper && exp.lower.fract() == 0.0 && exp.lower.abs() <= 1024.0 {
+            return self.powi(exp.lower as i32);
+        }
+        if exp.lower == 0.5 && exp.upper == 0.5 {
+            return self.sqrt();
+        }
+        // x^y = exp(y * ln(x))
+        self.ln().map(|it| exp.multiply(&it).exp())
+    }
+
+    pub fn exp(&self) -> Interval {
+        Interval {
+            lower: widen_down(self.lower.exp()).max(0.0),
+            upper: widen_up(self.upper.exp()),
+        }
+    }
+
+    /// The logarithm of the positive part, `None` if there is none.
+    pub fn ln(&self) -> Option<Interval> {
+        if self.upper <= 0.0 {
+            return None;
+        }
+        let lower = match self.lower <= 0.0 {
+            true => f64::NEG_INFINITY,
+            false => widen_down(self.lower.ln()),
+        };
+        Some(Interval {
+            lower,
+            upper: widen_up(self.upper.ln()),
+        })
+    }
+
+    /// The square root of the part that isn't negative, `None` if there is none.
+    pub fn sqrt(&self) -> Option<Interval> {
+        if self.upper < 0.0 {
+            return None;
+        }
+        Some(Interval {
+            lower: round_down(square_root(self.lower.max(0.0))),
+            upper: round_up(square_root(self.upper)),
+        })
+    }
+
+    pub fn abs(&self) -> Interval {
+        match (self.lower, self.upper) {
+            (lower, _) if lower >= 0.0 => *self,
+            (_, upper) if upper <= 0.0 => self.neg(),
+            (lower, upper) => Interval {
+                lower: 0.0,
+                upper: upper.max(-lower),
+            },
+        }
+    }
+
+    pub fn sin(&self) -> Interval {
+        self.periodic(f64::sin, FRAC_PI_2, -FRAC_PI_2)
+    }
+
+    pub fn cos(&self) -> Interval {
+        self.periodic(f64::cos, 0.0, PI)
+    }
+
+    /// The range of `sin` or `cos` with their maxima at `maximum + 2πk` and minima at
+    /// `minimum + 2πk`. Extrema close to the bounds are included, since `π` isn't exact.
+    fn periodic(&self, f: fn(f64) -> f64, maximum: f64, minimum: f64) -> Interval {
+        if !self.width().is_finite() || self.width() >= TAU {
+            return Interval::new(-1.0, 1.0).unwrap();
+        }
+
+        let margin = 1e-9 * self.lower.abs().max(self.upper.abs()).max(1.0);
+        let reaches = |extremum: f64| {
+            let k = ((self.lower - margin - extremum) / TAU).ceil();
+            extremum + k * TAU <= self.upper + margin
+        };
+
+        let (a, b) = (f(self.lower), f(self.upper));
+        Interval {
+            lower: match reaches(minimum) {
+                true => -1.0,
+                false => widen_down(a.min(b)).max(-1.0),
+            },
+            upper: match reaches(maximum) {
+                true => 1.0,
+                false => widen_up(a.max(b)).min(1.0),
+            },
+        }
+    }
+}
+
+impl Eq for Interval {}
+
+impl Ord for Interval {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.lower
+            .total_cmp(&other.lower)
+            .then_with(|| self.upper.total_cmp(&other.upper))
+    }
+}
+
+impl PartialOrd for Interval {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// A rounded result and the sign of its error, the exact result minus the rounded one.
+type Rounded = (f64, f64);
+
+/// The error of an infinite result, which is exact for infinite operands and an overflow of
+/// finite ones.
+fn overflow(value: f64, operands: [f64; 2]) -> Rounded {
+    match operands.iter().all(|it| it.is_finite()) {
+        true => (value, -value),
+        false => (value, 0.0),
+    }
+}
+
+/// `a + b` with the error of the rounding (TwoSum).
+fn sum(a: f64, b: f64) -> Rounded {
+    let s = a + b;
+    if s.is_infinite() {
+        return overflow(s, [a, b]);
+    }
+    let b_virtual = s - a;
+    let error = (a - (s - b_virtual)) + (b - b_virtual);
+    (s, error)
+}
+
+/// `a * b` with the error of the rounding, where `0 * inf` is 0 as the bounds are reached
+/// only in the limit.
+fn product(a: f64, b: f64) -> Rounded {
+    if a == 0.0 || b == 0.0 {
+        return (0.0, 0.0);
+    }
+    let p = a * b;
+    if p.is_infinite() {
+        return overflow(p, [a, b]);
+    }
+    (p, a.mul_add(b, -p))
+}
+
+/// `a / b` with the sign of the error of the rounding, from the exact remainder `a - q * b`.
+fn quotient(a: f64, b: f64) -> Rounded {
+    let q = a / b;
+    if q.is_infinite() {
+        return overflow(q, [a, b]);
+    }
+    if b.is_infinite() {
+        return (q, 0.0);
+    }
+    let remainder = (-q).mul_add(b, a);
+    (q, remainder * b.signum())
+}
+
+/// `sqrt(a)` with the sign of the error of the rounding, from the exact remainder `a - s^2`.
+fn square_root(a: f64) -> Rounded {
+    let s = a.sqrt();
+    if s.is_infinite() {
+        return (s, 0.0);
+    }
+    (s, (-s).mul_add(s, a))
+}
+
+fn round_down((value, error): Rounded) -> f64 {
+    match error < 0.0 {
+        true => next_down(value),
+        false => value,
+    }
+}
+
+fn round_up((value, error): Rounded) -> f64 {
+    match error > 0.0 {
+        true => next_up(value),
+        false => value,
+    }
+}
+
+/// The smallest float greater than `value`, like `f64::next_up` which isn't stable on the
+/// supported compilers.
+fn next_up(value: f64) -> f64 {
+    if value.is_nan() || value == f64::INFINITY {
+        return value;
+    }
+    if value == 0.0 {
+        // The smallest subnormal, for 0 and -0
+        return f64::from_bits(1);
+    }
+    let bits = value.to_bits();
+    f64::from_bits(if value > 0.0 { bits + 1 } else { bits - 1 })
+}
+
+/// The greatest float less than `value`.
+fn next_down(value: f64) -> f64 {
+    -next_up(-value)
+}
+
+/// The lower bound of a value from the standard library, which is off by less than an ulp.
+fn widen_down(value: f64) -> f64 {
+    match value.is_finite() {
+        true => next_down(value),
+        false => value,
+    }
+}
+
+fn widen_up(value: f64) -> f64 {
+    match value.is_finite() {
+        true => next_up(value),
+        false => value,
+    }
+}
+
+/// The interval a value lies in, computed from its numbers and constants.
+///
+/// `None` if the value depends on anything else, or isn't defined everywhere in the interval.
+pub(crate) fn enclose(value: &RunVal) -> Option<Interval> {
+    let interval = match &value.typ {
+        RunType::Number(n) => Interval::point(*n),
+        RunType::Constant(constant) => Interval::around(constant.approximate()?),
+        RunType::Interval(interval) => *interval,
+        RunType::Sum(Terms(terms)) => terms
+            .iter()
+            .try_fold(Interval::point(0.0), |acc, it| Some(acc.add(&enclose(it)?)))?,
+        RunType::Product(Factors(factors)) => {
+            factors.iter().try_fold(Interval::point(1.0), |acc, it| {
+                Some(acc.multiply(&enclose(it)?))
+            })?
+        }
+        RunType::Exponent(base, exp) => enclose(base)?.pow(&enclose(exp)?)?,
+        RunType::Function(name, args) => {
+            let args = args.iter().map(enclose).collect::<Option<Vec<_>>>()?;
+            apply(name, &args)?
+        }
+        _ => return None,
+    };
+    interval_debug!("{:?} is in {:?}", value, interval);
+    Some(interval)
+}
+
+/// The interval of `N(value)` for values with intervals in them, so that the constants are
+/// enclosed too instead of being rounded. `None` if the value depends on symbols.
+pub(crate) fn approximate(value: &RunVal) -> Option<RunVal> {
+    if !has_interval(value) {
+        return None;
+    }
+    Some(RunType::Interval(enclose(value)?).into())
+}
+
+fn has_interval(value: &RunVal) -> bool {
+    match &value.typ {
+        RunType::Interval(_) => true,
+        RunType::Sum(Terms(values))
+        | RunType::Product(Factors(values))
+        | RunType::Function(_, values) => values.iter().any(has_interval),
+        RunType::Exponent(base, exp) => has_interval(base) || has_interval(exp),
+        _ => false,
+    }
+}
+
+/// Functions of intervals, e.g. `sin(interval(0, 1))`.
+pub(crate) fn evaluate(name: &str, args: &[RunVal]) -> Option<RunVal> {
+    if !args.iter().any(|it| matches!(it.typ, RunType::Interval(_))) {
+        return None;
+    }
+    let args = args.iter().map(enclose).collect::<Option<Vec<_>>>()?;
+    Some(RunType::Interval(apply(name, &args)?).into())
+}
+
+fn apply(name: &str, args: &[Interval]) -> Option<Interval> {
+    match (name, args) {
+        ("sin", [x]) => Some(x.sin()),
+        ("cos", [x]) => Some(x.cos()),
+        ("exp", [x]) => Some(x.exp()),
+        ("ln", [x]) => x.ln(),
+        ("sqrt", [x]) => x.sqrt(),
+        ("abs", [x]) => Some(x.abs()),
+        _ => None,
+    }
+}
+
+/// The interval of a power where the base or the exponent is an interval.
+pub(crate) fn power(base: &RunVal, exp: &RunVal) -> Option<RunVal> {
+    let is_interval = |it: &RunVal| matches!(it.typ, RunType::Interval(_));
+    if !is_interval(base) && !is_interval(exp) {
+        return None;
+    }
+    let power = enclose(base)?.pow(&enclose(exp)?);
+    Some(power.map_or(RunType::Undefined, RunType::Interval).into())
+}
+
+/// Combines the numbers and intervals of a sum or product into a single interval, e.g.
+/// `x + 1 + interval(1, 2)` => `x + interval(2, 3)`.
+pub(crate) fn collect(
+    values: &mut Vec<RunVal>,
+    identity: Interval,
+    combine: fn(&Interval, &Interval) -> Interval,
+) {
+    if !values
+        .iter()
+        .any(|it| matches!(it.typ, RunType::Interval(_)))
+    {
+        return;
+    }
+
+    let mut combined = identity;
+    values.retain(|it| match it.typ {
+        RunType::Number(n) => {
+            combined = combine(&combined, &Interval::point(n));
+            false
+        }
+        RunType::Interval(interval) => {
+            combined = combine(&combined, &interval);
+            false
+        }
+        _ => true,
+    });
+    values.push(RunType::Interval(combined).into());
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cas::eval::testing::{eval, Session};
+
+    use super::{next_down, next_up};
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(eval("interval(1, 2) * 3"), "interval(3, 6)");
+        assert_eq!(eval("1 / interval(0, 2)"), "interval(0.5, inf)");
+        assert_eq!(eval("1 / interval(-1, 2)"), "interval(-inf, inf)");
+        assert_eq!(eval("interval(-2, -1)^3"), "interval(-8, -1)");
+        assert_eq!(eval("sqrt(interval(1, 4))"), "interval(1, 2)");
+        assert_eq!(eval("x + interval(1, 2) + 1"), "x + interval(2, 3)");
+    }
+
+    #[test]
+    fn occurrences_are_independent() {
+        let mut session = Session::new();
+        session.eval("X = interval(-1, 2)");
+        assert_eq!(session.eval("X * X"), "interval(-2, 4)");
+        assert_eq!(session.eval("X^2"), "interval(0, 4)");
+        assert_eq!(session.eval("X - X"), "interval(-3, 3)");
+    }
+
+    #[test]
+    fn bounds_are_rounded_outward() {
+        assert_eq!(
+            eval("N(1/10 + 2/10, interval)"),
+            "interval(0.30000000000000004, 0.30000000000000004)"
+        );
+        assert_eq!(
+            eval("N(PI, interval)"),
+            "interval(3.1415926535897927, 3.1415926535897936)"
+        );
+
+        assert_eq!(next_up(1.0), 1.0 + f64::EPSILON);
+        assert_eq!(next_down(1.0), 1.0 - f64::EPSILON / 2.0);
+        assert_eq!(next_up(0.0), f64::from_bits(1));
+        assert_eq!(next_up(-0.0), f64::from_bits(1));
+        assert_eq!(next_down(0.0), -f64::from_bits(1));
+        assert_eq!(next_up(-f64::from_bits(1)), -0.0);
+        assert_eq!(next_up(f64::MAX), f64::INFINITY);
+        assert_eq!(next_up(f64::INFINITY), f64::INFINITY);
+        assert_eq!(next_down(f64::NEG_INFINITY), f64::NEG_INFINITY);
+    }
+}
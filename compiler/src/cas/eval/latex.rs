@@ -0,0 +1,364 @@
+//! Formats values as LaTeX math, e.g. `0.5 * x^-2` => `\frac{0.5}{x^{2}}` or
+//! `sum(k^2, k, 1, n)` => `\sum_{k=1}^{n} k^{2}`.
+
+use matex_common::node::Precedence;
+
+use super::{
+    format::ValueFormatter,
+    functions::RELATIONS,
+    sets::{Numbers, Set},
+    value::{Constant, Factors, Pieces, RunType, RunVal, Terms},
+};
+
+/// Functions that LaTeX has a command for, e.g. `\sin`.
+const COMMANDS: [&str; 14] = [
+    "sin", "cos", "tan", "sinh", "cosh", "tanh", "exp", "ln", "min", "max", "gcd", "lim", "det",
+    "arg",
+];
+
+/// Symbols that are written as Greek letters, e.g. `alpha` => `\alpha`.
+const GREEK: [&str; 24] = [
+    "alpha", "beta", "gamma", "delta", "epsilon", "zeta", "eta", "theta", "iota", "kappa",
+    "lambda", "mu", "nu", "xi", "rho", "sigma", "tau", "upsilon", "phi", "chi", "psi", "omega",
+    "Gamma", "Delta",
+];
+
+pub struct LatexFormatter;
+
+impl LatexFormatter {
+    fn format_impl(value: &RunVal, prec: Precedence) -> String {
+        use RunType::*;
+        let str = match &value.typ {
+            Unit => String::new(),
+            Undefined => "\\mathrm{undefined}".to_owned(),
+            Number(n) if prec == Precedence::Term && n.is_sign_negative() => {
+                return format!(" - {}", Self::number(-n));
+            }
+            Number(n) => Self::number(*n),
+            Symbol(s) => Self::symbol(s),
+            Constant(c) => Self::constant(*c).to_owned(),
+            PhysicalUnit(unit) => format!("\\mathrm{{{}}}", unit.name),
+            Bool(b) => format!("\\mathrm{{{}}}", b),
+            Vector(values) => format!("\\left[{}\\right]", Self::list(values)),
+            Sum(Terms(terms)) => {
+                let mut buffer = String::new();
+                for (i, term) in terms.iter().enumerate() {
+                    if i == 0 {
+                        buffer.push_str(&Self::format(term));
+                    } else {
+                        buffer.push_str(&Self::format_impl(term, Precedence::Term));
+                    }
+                }
+                match prec > Precedence::Term {
+                    true => format!("\\left({}\\right)", buffer),
+                    false => buffer,
+                }
+            }
+            Product(Factors(factors)) => {
+                let (negative, str) = Self::product(factors);
+                let sign = if negative { "-" } else { "" };
+                match prec {
+                    Precedence::Term => {
+                        return format!(" {} {}", if negative { "-" } else { "+" }, str)
+                    }
+                    Precedence::Exponent => format!("\\left({}{}\\right)", sign, str),
+                    _ => format!("{}{}", sign, str),
+                }
+            }
+            // x^-1 is a fraction and x^0.5 a root
+            Exponent(base, exp) => match exp.typ {
+                Number(n) if n < 0.0 => Self::product(std::slice::from_ref(value)).1,
+                Number(0.5) => format!("\\sqrt{{{}}}", Self::format(base)),
+                _ => format!(
+                    "{}^{{{}}}",
+                    Self::format_impl(base, Precedence::Exponent),
+                    Self::format(exp)
+                ),
+            },
+            Function(name, args) => Self::function(name, args, &prec),
+            Piecewise(Pieces(pieces)) => {
+                let cases = pieces
+                    .iter()
+                    .map(|(cond, value)| match cond.typ {
+                        Bool(true) => format!("{} & \\text{{otherwise}}", Self::format(value)),
+                        _ => format!(
+                            "{} & \\text{{if }} {}",
+                            Self::format(value),
+                            Self::format(cond)
+                        ),
+                    })
+                    .collect::<Vec<_>>();
+                format!("\\begin{{cases}} {} \\end{{cases}}", cases.join(" \\\\ "))
+            }
+            Set(set) => Self::set(set),
+            Interval(interval) => format!(
+                "\\left[{}, {}\\right]",
+                Self::number(interval.lower),
+                Self::number(interval.upper)
+            ),
+        };
+
+        match prec {
+            Precedence::Term => format!(" + {}", str),
+            _ => str,
+        }
+    }
+
+    fn number(n: f64) -> String {
+        match n {
+            f64::INFINITY => "\\infty".to_owned(),
+            f64::NEG_INFINITY => "-\\infty".to_owned(),
+            _ => n.to_string(),
+        }
+    }
+
+    fn constant(constant: Constant) -> &'static str {
+        match constant {
+            Constant::Pi => "\\pi",
+            Constant::E => "e",
+            Constant::I => "i",
+        }
+    }
+
+    fn symbol(name: &str) -> String {
+        if GREEK.contains(&name) {
+            format!("\\{}", name)
+        } else if name.chars().count() == 1 {
+            name.to_owned()
+        } else {
+            format!("\\mathit{{{}}}", name)
+        }
+    }
+
+    fn list(values: &[RunVal]) -> String {
+        values
+            .iter()
+            .map(Self::format)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Whether a product is negative and the product without the sign, as a fraction if any of
+    /// its factors has a negative exponent, e.g. `-2 * x^-1` => `\frac{2}{x}`.
+    fn product(factors: &[RunVal]) -> (bool, String) {
+        let mut negative = false;
+        let mut numerator = Vec::new();
+        let mut denominator = Vec::new();
+
+        for factor in factors {
+            match &factor.typ {
+                RunType::Number(n) if n.is_sign_negative() => {
+                    negative = !negative;
+                    if *n != -1.0 {
+                        numerator.push(RunType::Number(-n).into());
+                    }
+                }
+                RunType::Exponent(base, exp) => match exp.typ {
+                    RunType::Number(-1.0) => denominator.push(base.as_ref().clone()),
+                    RunType::Number(n) if n < 0.0 => denominator.push(
+                        RunType::Exponent(base.clone(), Box::new(RunType::Number(-n).into()))
+                            .into(),
+                    ),
+                    _ => numerator.push(factor.clone()),
+                },
+                _ => numerator.push(factor.clone()),
+            }
+        }
+
+        if denominator.is_empty() {
+            return (negative, Self::juxtapose(&numerator));
+        }
+        // A single factor doesn't need parentheses in a fraction, e.g. `\frac{1}{x + 1}`
+        let part = |factors: &[RunVal]| match factors {
+            [] => "1".to_owned(),
+            [single] => Self::format(single),
+            _ => Self::juxtapose(factors),
+        };
+        let fraction = format!("\\frac{{{}}}{{{}}}", part(&numerator), part(&denominator));
+        (negative, fraction)
+    }
+
+    /// Factors written next to each other, with a dot before numbers, e.g. `2 \cdot 3 x`.
+    fn juxtapose(factors: &[RunVal]) -> String {
+        let mut buffer = String::new();
+        for (i, factor) in factors.iter().enumerate() {
+            let factor = Self::format_impl(factor, Precedence::Factor);
+            if i > 0 {
+                match factor.starts_with(|c: char| c.is_ascii_digit()) {
+                    true => buffer.push_str(" \\cdot "),
+                    false => buffer.push(' '),
+                }
+            }
+            buffer.push_str(&factor);
+        }
+        buffer
+    }
+
+    fn function(name: &str, args: &[RunVal], prec: &Precedence) -> String {
+        match (name, args) {
+            ("hold", [value]) => Self::format(value),
+            ("sqrt", [value]) => format!("\\sqrt{{{}}}", Self::format(value)),
+            ("abs", [value]) => format!("\\left|{}\\right|", Self::format(value)),
+            ("floor", [value]) => format!("\\left\\lfloor {} \\right\\rfloor", Self::format(value)),
+            ("ceil", [value]) => format!("\\left\\lceil {} \\right\\rceil", Self::format(value)),
+            ("factorial", [value]) => {
+                format!("{}!", Self::format_impl(value, Precedence::Exponent))
+            }
+            ("log", [base, value]) => {
+                format!(
+                    "\\log_{{{}}}\\left({}\\right)",
+                    Self::format(base),
+                    Self::format(value)
+                )
+            }
+            ("mod", [value, modulus]) => format!(
+                "{} \\bmod {}",
+                Self::format_impl(value, Precedence::Factor),
+                Self::format_impl(modulus, Precedence::Factor)
+            ),
+            ("binomial", [n, k]) => {
+                format!("\\binom{{{}}}{{{}}}", Self::format(n), Self::format(k))
+            }
+            ("sum" | "product", [summand, var, lower, upper]) => {
+                let str = format!(
+                    "\\{}_{{{}={}}}^{{{}}} {}",
+                    if name == "sum" { "sum" } else { "prod" },
+                    Self::format(var),
+                    Self::format(lower),
+                    Self::format(upper),
+                    Self::format_impl(summand, Precedence::Factor)
+                );
+                match *prec >= Precedence::Factor {
+                    true => format!("\\left({}\\right)", str),
+                    false => str,
+                }
+            }
+            (_, [lhs, rhs]) if relation(name).is_some() => {
+                let str = format!(
+                    "{} {} {}",
+                    Self::format_impl(lhs, Precedence::Comparison),
+                    relation(name).unwrap_or_default(),
+                    Self::format_impl(rhs, Precedence::Comparison)
+                );
+                match *prec > Precedence::Assignment {
+                    true => format!("\\left({}\\right)", str),
+                    false => str,
+                }
+            }
+            _ if COMMANDS.contains(&name) => {
+                format!("\\{}\\left({}\\right)", name, Self::list(args))
+            }
+            _ => format!(
+                "\\operatorname{{{}}}\\left({}\\right)",
+                name,
+                Self::list(args)
+            ),
+        }
+    }
+
+    fn set(set: &Set) -> String {
+        match set {
+            Set::Finite(elements) => format!("\\left\\{{{}\\right\\}}", Self::list(elements)),
+            Set::Interval {
+                lower,
+                upper,
+                lower_closed,
+                upper_closed,
+            } => format!(
+                "\\left{}{}, {}\\right{}",
+                if *lower_closed { "[" } else { "(" },
+                Self::format(lower),
+                Self::format(upper),
+                if *upper_closed { "]" } else { ")" }
+            ),
+            Set::Builder {
+                variable,
+                domain,
+                condition,
+            } => format!(
+                "\\left\\{{{} \\in {} \\mid {}\\right\\}}",
+                Self::symbol(variable),
+                Self::format(domain),
+                Self::format(condition)
+            ),
+            Set::Numbers(numbers) => {
+                let letter = match numbers {
+                    Numbers::Naturals => "N",
+                    Numbers::Integers => "Z",
+                    Numbers::Rationals => "Q",
+                    Numbers::Reals => "R",
+                };
+                format!("\\mathbb{{{}}}", letter)
+            }
+        }
+    }
+}
+
+/// The LaTeX operator of a relation, e.g. `\leq` for `less_equal`.
+fn relation(name: &str) -> Option<&'static str> {
+    match name {
+        "element" => Some("\\in"),
+        "less_equal" => Some("\\leq"),
+        "greater_equal" => Some("\\geq"),
+        "equal" => Some("="),
+        _ => RELATIONS
+            .iter()
+            .find(|(relation, _)| *relation == name)
+            .map(|(_, operator)| *operator),
+    }
+}
+
+impl ValueFormatter for LatexFormatter {
+    fn format(value: &RunVal) -> String {
+        LatexFormatter::format_impl(value, Precedence::None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cas::eval::{format::ValueFormatter, testing::Session};
+
+    use super::LatexFormatter;
+
+    fn latex(input: &str) -> String {
+        LatexFormatter::format(&Session::new().run(input).unwrap())
+    }
+
+    #[test]
+    fn sums_and_products() {
+        assert_eq!(
+            latex("sum(sin(k), k, 1, n) + product(sin(k), k, 0, n)"),
+            "\\prod_{k=0}^{n} \\sin\\left(k\\right) + \\sum_{k=1}^{n} \\sin\\left(k\\right)"
+        );
+    }
+
+    #[test]
+    fn fractions_and_roots() {
+        assert_eq!(
+            latex("1/(x + 1) - 2 * x^-2 + sqrt(y) + 3 * x^3"),
+            "3 x^{3} + \\sqrt{y} + \\frac{1}{x + 1} - \\frac{2}{x^{2}}"
+        );
+    }
+
+    #[test]
+    fn functions_and_symbols() {
+        assert_eq!(
+            latex("abs(alpha) + ln(x) + binomial(n, k) + n! + foo(x)"),
+            "\\left|\\alpha\\right| + \\binom{n}{k} + n! + \\operatorname{foo}\\left(x\\right) + \\ln\\left(x\\right)"
+        );
+        assert_eq!(latex("[1, 2, PI, E]"), "\\left[1, 2, \\pi, e\\right]");
+    }
+
+    #[test]
+    fn cases_sets_and_units() {
+        assert_eq!(
+            latex("if x > 0 then x else -x"),
+            "\\begin{cases} x & \\text{if } x > 0 \\\\ -x & \\text{otherwise} \\end{cases}"
+        );
+        assert_eq!(
+            latex("{x in R | x <= 2}"),
+            "\\left\\{x \\in \\mathbb{R} \\mid x \\leq 2\\right\\}"
+        );
+        assert_eq!(latex("5 m / 2 s"), "\\frac{2.5 \\mathrm{m}}{\\mathrm{s}}");
+    }
+}
@@ -0,0 +1,464 @@
+use log::debug;
+
+use super::{
+    assumptions::{self, Fact},
+    diff,
+    functions::{self, call},
+    rational,
+    series::{self, Point, Series},
+    value::{Factors, RunType, RunVal, Terms},
+};
+
+macro_rules! limit_debug {
+    ($($arg:tt)+) => (debug!(target: "matex::limit", "[{}:{}] {}", file!(), line!(), &format!($($arg)+)));
+}
+
+/// How many times L'Hôpital's rule is applied before giving up.
+const MAX_LHOPITAL: u32 = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+    Both,
+    Left,
+    Right,
+}
+
+/// The limit of `value` as `var` approaches `point`, `None` if it couldn't be determined.
+///
+/// Limits that don't exist, e.g. `1/x` at 0 from both sides, give `undefined`.
+pub fn limit(value: &RunVal, var: &RunVal, point: &RunVal, direction: Direction) -> Option<RunVal> {
+    limit_debug!(
+        "limit {:?} as {:?} -> {:?} ({:?})",
+        value,
+        var,
+        point,
+        direction
+    );
+
+    let point = Point::from_value(point);
+
+    // At infinity the expansion variable always approaches 0 from above
+    let direction = match point {
+        Point::Finite(_) => direction,
+        Point::PositiveInfinity | Point::NegativeInfinity => Direction::Right,
+    };
+
+    let shifted = series::shift_to_origin(value, var, &point);
+    let t = series::expansion_variable();
+
+    limit_at_origin(&shifted, &t, direction, 0)
+}
+
+fn limit_at_origin(value: &RunVal, t: &RunVal, direction: Direction, depth: u32) -> Option<RunVal> {
+    if !value.depends_on(t) {
+        return Some(value.clone());
+    }
+
+    if let Some(limit) = limit_from_series(value, direction) {
+        return Some(limit);
+    }
+
+    if depth < MAX_LHOPITAL {
+        if let Some(limit) = limit_from_lhopital(value, t, direction, depth) {
+            return Some(limit);
+        }
+    }
+
+    limit_from_substitution(value, t, direction, depth)
+}
+
+/// The leading term of the series decides the limit.
+fn limit_from_series(value: &RunVal, direction: Direction) -> Option<RunVal> {
+    let mut order = 1;
+
+    while order <= 16 {
+        let series = Series::expand(value, order)?;
+        limit_debug!("series: {:?}", series);
+
+        if let Some((k, coeff)) = series.leading_term() {
+            return Some(match k {
+                k if k > 0 => RunType::Number(0.0).into(),
+                0 => coeff.clone(),
+                k => infinite_limit(coeff, k, direction),
+            });
+        }
+
+        order *= 2;
+    }
+
+    None
+}
+
+/// The limit of `coeff * t^k` for a negative `k`.
+fn infinite_limit(coeff: &RunVal, k: i32, direction: Direction) -> RunVal {
+    let RunType::Number(c) = coeff.typ else {
+        return RunType::Undefined.into();
+    };
+
+    let odd = k % 2 != 0;
+    let sign = match direction {
+        Direction::Right => c.signum(),
+        Direction::Left if odd => -c.signum(),
+        Direction::Left => c.signum(),
+        Direction::Both if odd => return RunType::Undefined.into(),
+        Direction::Both => c.signum(),
+    };
+
+    RunType::Number(sign * f64::INFINITY).into()
+}
+
+/// Indeterminate forms `0/0` and `inf/inf`: lim f/g = lim f'/g'.
+fn limit_from_lhopital(
+    value: &RunVal,
+    t: &RunVal,
+    direction: Direction,
+    depth: u32,
+) -> Option<RunVal> {
+    let (numerator, denominator) = rational::numer_denom(value);
+    if !denominator.depends_on(t) {
+        return None;
+    }
+    quotient_limit(&numerator, &denominator, t, direction, depth)
+}
+
+/// The limit of `numerator/denominator`, kept apart so that the simplifier can't merge them.
+fn quotient_limit(
+    numerator: &RunVal,
+    denominator: &RunVal,
+    t: &RunVal,
+    direction: Direction,
+    depth: u32,
+) -> Option<RunVal> {
+    let numerator_limit = limit_at_origin(numerator, t, direction, depth + 1)?;
+    let denominator_limit = limit_at_origin(denominator, t, direction, depth + 1)?;
+
+    let (n, d) = match (&numerator_limit.typ, &denominator_limit.typ) {
+        (RunType::Number(n), RunType::Number(d)) => (*n, *d),
+        _ => (f64::NAN, f64::NAN),
+    };
+    let indeterminate = (n == 0.0 && d == 0.0) || (n.is_infinite() && d.is_infinite());
+
+    if !indeterminate {
+        // n/0 is infinite, with a sign from the side the denominator approaches 0 from
+        if d == 0.0 && n.is_finite() {
+            return pole(n, denominator, direction);
+        }
+        let mut quotient = rational::fraction(numerator_limit, denominator_limit);
+        quotient.simplify();
+        return Some(quotient);
+    }
+
+    limit_debug!("applying L'Hôpital's rule");
+    let mut quotient = rational::fraction(
+        diff::differentiate(numerator, t),
+        diff::differentiate(denominator, t),
+    );
+    quotient.simplify();
+
+    limit_at_origin(&quotient, t, direction, depth + 1)
+}
+
+/// Continuous expressions can just be evaluated at the point. Where that hits a singularity,
+/// e.g. `ln(0)` or `1/0`, the limits from each side are taken instead.
+fn limit_from_substitution(
+    value: &RunVal,
+    t: &RunVal,
+    direction: Direction,
+    depth: u32,
+) -> Option<RunVal> {
+    let substituted = value.substitute(t, &RunType::Number(0.0).into());
+    if !is_singular(&substituted) {
+        let substituted = simplified(&substituted);
+        match substituted.typ {
+            RunType::Number(n) if n.is_nan() => {}
+            RunType::Undefined => {}
+            _ => return Some(substituted),
+        }
+    }
+
+    limit_debug!("{:?} is singular at the point", value);
+    match direction {
+        Direction::Both => {
+            let left = one_sided(value, t, Direction::Left, depth)?;
+            let right = one_sided(value, t, Direction::Right, depth)?;
+            Some(match left == right {
+                true => left,
+                false => RunType::Undefined.into(),
+            })
+        }
+        side => one_sided(value, t, side, depth),
+    }
+}
+
+/// Whether a value has a part that has no value at its arguments, e.g. `ln(0)` or `0^0`.
+fn is_singular(value: &RunVal) -> bool {
+    let numeric = |value: &RunVal| match simplified(value).typ {
+        RunType::Number(n) => Some(n),
+        _ => None,
+    };
+
+    match &value.typ {
+        RunType::Sum(Terms(values))
+        | RunType::Product(Factors(values))
+        | RunType::Function(_, values)
+            if values.iter().any(is_singular) =>
+        {
+            true
+        }
+        RunType::Function(name, args) => match args.as_slice() {
+            [arg] => numeric(arg).is_some_and(|arg| functions::is_singular(name, arg)),
+            _ => false,
+        },
+        RunType::Exponent(base, exp) => {
+            is_singular(base)
+                || is_singular(exp)
+                || (numeric(base) == Some(0.0) && numeric(exp).is_some_and(|exp| exp <= 0.0))
+        }
+        _ => false,
+    }
+}
+
+/// The limit from one side, from the limits of the parts of `value`. The indeterminate forms
+/// `0 * inf` and `inf - inf` are written as quotients for L'Hôpital's rule.
+fn one_sided(value: &RunVal, t: &RunVal, side: Direction, depth: u32) -> Option<RunVal> {
+    use RunType::*;
+
+    let limit = |value: &RunVal| limit_at_origin(value, t, side, depth + 1);
+    let limits = |values: &[RunVal]| values.iter().map(limit).collect::<Option<Vec<_>>>();
+    let lhopital = depth < MAX_LHOPITAL;
+
+    match &value.typ {
+        _ if !value.depends_on(t) => Some(value.clone()),
+        Sum(Terms(terms)) => {
+            let limits = limits(terms)?;
+            let sum = determinate(Sum(Terms(limits.clone())).into(), &limits);
+            sum.or_else(|| {
+                let (numerator, denominator) = rational::numer_denom(&rational::together(value));
+                let fraction = lhopital && denominator.depends_on(t);
+                fraction.then(|| quotient_limit(&numerator, &denominator, t, side, depth))?
+            })
+        }
+        Product(Factors(factors)) => {
+            let limits = limits(factors)?;
+            let product = determinate(Product(Factors(limits.clone())).into(), &limits);
+            product.or_else(|| {
+                // 0 * inf = inf / (1/0)
+                let (zeros, rest): (Vec<_>, Vec<_>) = factors
+                    .iter()
+                    .zip(&limits)
+                    .partition(|(_, limit)| matches!(limit.typ, Number(n) if n == 0.0));
+                let zeros = zeros
+                    .into_iter()
+                    .map(|(factor, _)| factor.clone())
+                    .collect();
+                let rest = rest.into_iter().map(|(factor, _)| factor.clone()).collect();
+                let denominator =
+                    simplified(&Product(Factors(zeros)).into()).power(Number(-1.0).into());
+                let numerator = Product(Factors(rest)).into();
+                lhopital.then(|| {
+                    quotient_limit(&numerator, &simplified(&denominator), t, side, depth)
+                })?
+            })
+        }
+        Exponent(base, exp) if !exp.depends_on(t) => {
+            let Number(e) = simplified(exp).typ else {
+                return None;
+            };
+            let base_limit = limit(base)?;
+            if !matches!(base_limit.typ, Number(n) if n == 0.0) || e >= 0.0 {
+                let power = base_limit.clone().power(exp.as_ref().clone());
+                return determinate(power, &[base_limit]);
+            }
+
+            // 1/0 is infinite, with the sign of the side the base approaches 0 from
+            let sign = approach(base, side)?;
+            let integer = e.fract() == 0.0;
+            Some(match sign > 0.0 {
+                true => Number(f64::INFINITY).into(),
+                false if integer && e % 2.0 != 0.0 => Number(f64::NEG_INFINITY).into(),
+                false if integer => Number(f64::INFINITY).into(),
+                false => Undefined.into(),
+            })
+        }
+        // f^g = exp(g * ln(f)), kept apart from the simplifier that would merge it again
+        Exponent(base, exp) => {
+            let exponent = simplified(&exp.as_ref().clone().multiply(call("ln", base)));
+            limit(&call("exp", &exponent))
+        }
+        Function(name, args) if args.len() == 1 => {
+            let arg = limit(&args[0])?;
+            match arg.typ {
+                Number(a) if functions::is_singular(name, a) => {
+                    let distance = args[0].clone().add(arg.multiply(Number(-1.0).into()));
+                    let sign = approach(&simplified(&distance), side)?;
+                    Some(jump(name, a, sign))
+                }
+                _ => determinate(call(name, &arg), &[arg]),
+            }
+        }
+        // Near the point only the piece for the side it's approached from is left
+        Piecewise(_) => {
+            let zero: RunVal = Number(0.0).into();
+            let near = match side {
+                Direction::Left => t.clone().less(zero),
+                _ => t.clone().greater(zero),
+            };
+            let piece = assumptions::assuming(vec![Fact::Holds(near)], || simplified(value));
+            match piece.typ {
+                Piecewise(_) => None,
+                _ => limit(&piece),
+            }
+        }
+        _ => None,
+    }
+}
+
+/// The limit of a function at a singular argument, approached from above if `sign` is positive
+/// and from below otherwise.
+fn jump(name: &str, a: f64, sign: f64) -> RunVal {
+    let above = sign > 0.0;
+    match (name, above) {
+        ("ln", true) => RunType::Number(f64::NEG_INFINITY).into(),
+        ("sign", _) => RunType::Number(sign.signum()).into(),
+        ("floor", _) => RunType::Number(if above { a } else { a - 1.0 }).into(),
+        ("ceil", _) => RunType::Number(if above { a + 1.0 } else { a }).into(),
+        _ => RunType::Undefined.into(),
+    }
+}
+
+/// The limit of `n/d` for a number `n` that isn't 0 and a `d` that approaches 0.
+fn pole(n: f64, denominator: &RunVal, direction: Direction) -> Option<RunVal> {
+    let infinity = |side| approach(denominator, side).map(|sign| sign * n.signum() * f64::INFINITY);
+    let limit = match direction {
+        Direction::Both => {
+            let (left, right) = (infinity(Direction::Left)?, infinity(Direction::Right)?);
+            if left != right {
+                return Some(RunType::Undefined.into());
+            }
+            left
+        }
+        side => infinity(side)?,
+    };
+    Some(RunType::Number(limit).into())
+}
+
+/// The sign of the values of an expression near 0 that approaches 0, e.g. `-t` is negative from
+/// the right. Found from the leading term of its series, or from the kind of value.
+fn approach(value: &RunVal, side: Direction) -> Option<f64> {
+    match &value.typ {
+        RunType::Function(name, _) if name == "abs" => return Some(1.0),
+        RunType::Exponent(_, exp) if matches!(exp.typ, RunType::Number(e) if e % 2.0 == 0.0) => {
+            return Some(1.0)
+        }
+        _ => {}
+    }
+
+    let mut order = 1;
+    let (k, coeff) = loop {
+        let series = Series::expand(value, order)?;
+        if let Some((k, coeff)) = series.leading_term() {
+            break (k, coeff.clone());
+        }
+        if order >= 16 {
+            return None;
+        }
+        order *= 2;
+    };
+    let RunType::Number(c) = coeff.typ else {
+        return None;
+    };
+
+    Some(match side == Direction::Left && k % 2 != 0 {
+        true => -c.signum(),
+        false => c.signum(),
+    })
+}
+
+/// The simplified combination of limits, `None` if it's an indeterminate form, e.g. `0 * inf`.
+/// Undefined if one of the limits is.
+fn determinate(value: RunVal, limits: &[RunVal]) -> Option<RunVal> {
+    if limits.iter().any(|it| matches!(it.typ, RunType::Undefined)) {
+        return Some(RunType::Undefined.into());
+    }
+    let value = simplified(&value);
+    match value.typ {
+        RunType::Number(n) if n.is_nan() => None,
+        RunType::Undefined => None,
+        _ => Some(value),
+    }
+}
+
+fn simplified(value: &RunVal) -> RunVal {
+    let mut value = value.clone();
+    value.simplify();
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cas::eval::testing::eval;
+
+    #[test]
+    fn continuous() {
+        assert_eq!(eval("limit(x^2 + 1, x, 2)"), "5");
+        assert_eq!(eval("limit(x/(x + 1), x, inf)"), "1");
+        assert_eq!(eval("limit(exp(x), x, -inf)"), "0");
+    }
+
+    #[test]
+    fn indeterminate_forms() {
+        assert_eq!(eval("limit((x^2 - 1)/(x - 1), x, 1)"), "2");
+        assert_eq!(eval("limit(sin(x)/x, x, 0)"), "1");
+        assert_eq!(eval("limit((1 - cos(x))/x^2, x, 0)"), "0.5");
+        assert_eq!(eval("limit(x * ln(x), x, 0, 1)"), "0");
+        assert_eq!(eval("limit(x^x, x, 0, 1)"), "1");
+        assert_eq!(eval("limit(ln(x) + 1/x, x, 0, 1)"), "inf");
+    }
+
+    #[test]
+    fn poles() {
+        assert_eq!(eval("limit(1/x, x, 0)"), "undefined");
+        assert_eq!(eval("limit(1/x, x, 0, 1)"), "inf");
+        assert_eq!(eval("limit(1/x, x, 0, -1)"), "-inf");
+        assert_eq!(eval("limit(1/x^2, x, 0)"), "inf");
+    }
+
+    #[test]
+    fn singular_substitution() {
+        assert_eq!(eval("limit(ln(x), x, 0, 1)"), "-inf");
+        assert_eq!(eval("limit(ln(x), x, 0, -1)"), "undefined");
+        assert_eq!(eval("limit(ln(x), x, 0)"), "undefined");
+        assert_eq!(eval("limit(ln(x - 1), x, 1, 1)"), "-inf");
+        assert_eq!(eval("limit(1/ln(x), x, 0, 1)"), "0");
+        assert_eq!(eval("limit(ln(x), x, inf)"), "inf");
+    }
+
+    #[test]
+    fn jumps() {
+        assert_eq!(eval("limit(sign(x), x, 0)"), "undefined");
+        assert_eq!(eval("limit(sign(x), x, 0, 1)"), "1");
+        assert_eq!(eval("limit(sign(x), x, 0, -1)"), "-1");
+        assert_eq!(eval("limit(floor(x), x, 1)"), "undefined");
+        assert_eq!(eval("limit(floor(x), x, 1, 1)"), "1");
+        assert_eq!(eval("limit(floor(x), x, 1, -1)"), "0");
+        assert_eq!(eval("limit(ceil(x), x, 2, -1)"), "2");
+        assert_eq!(eval("limit(floor(x), x, 3/2)"), "1");
+        assert_eq!(eval("limit(abs(x)/x, x, 0)"), "undefined");
+        assert_eq!(eval("limit(abs(x)/x, x, 0, 1)"), "1");
+        assert_eq!(eval("limit(abs(x), x, 0)"), "0");
+    }
+
+    #[test]
+    fn series() {
+        assert_eq!(
+            eval("series(sin(x), x, 0, 4)"),
+            "x - 0.16666666666666666 * x^3 + O(x^4)"
+        );
+        assert_eq!(eval("series(1/(1 - x), x, 0, 3)"), "x^2 + x + 1 + O(x^3)");
+        assert_eq!(eval("series(1/x + x, x, 0, 2)"), "x + x^-1 + O(x^2)");
+        assert_eq!(
+            eval("series(ln(x), x, 1, 3)"),
+            "x - 0.5 * (x - 1)^2 - 1 + O((x - 1)^3)"
+        );
+    }
+}
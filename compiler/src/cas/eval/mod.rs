@@ -1,5 +1,31 @@
+pub mod dependency;
+pub mod assumptions;
+pub mod diff;
+pub mod dispatch;
 pub mod environment;
 pub mod format;
+pub mod functions;
+pub mod helpers;
+pub mod integrate;
+pub mod intern;
+pub mod integers;
+pub mod interval;
+pub mod latex;
+pub mod limit;
+pub mod ode;
+pub mod order;
+pub mod poly;
+pub mod rational;
+pub mod rules;
 pub mod runtime;
+pub mod series;
+pub mod sets;
+pub mod solve;
+pub mod stats;
 pub mod stdenv;
+pub mod sums;
+#[cfg(test)]
+pub(crate) mod testing;
+pub mod trace;
+pub mod units;
 pub mod value;
@@ -0,0 +1,967 @@
+//! Ordinary differential equations, solved symbolically by `dsolve` or numerically by `odeint`.
+//!
+//! `dsolve(eq, y(x))` solves first order equations that are linear, separable or exact, and
+//! linear equations of any order with constant coefficients, whose right hand side is a sum of
+//! polynomials times `exp`, `sin` and `cos`. Derivatives are written `diff(y(x), x)`, and the
+//! solution has the constants `C1`, `C2`, ... unless the initial values are given, e.g.
+//! `dsolve(diff(y(x), x) == y(x), y(x), 0, 1)` => `y(x) == exp(x)`.
+//!
+//! `odeint` integrates a system `y' = f(t, y)` with the Dormand-Prince method (RK45), adapting the
+//! step size to the estimated error.
+
+use log::debug;
+
+use super::{
+    diff,
+    functions::{self, call},
+    helpers::{self, approximate, negate, number, product_of, reciprocal, simplified},
+    integrate::antiderivative,
+    poly::Polynomial,
+    sets, solve,
+    value::{Factors, Pieces, RunType, RunVal, Terms},
+};
+
+macro_rules! ode_debug {
+    ($($arg:tt)+) => (debug!(target: "matex::ode", "[{}:{}] {}", file!(), line!(), &format!($($arg)+)));
+}
+
+/// The highest derivative that is looked for in an equation.
+const MAX_ORDER: usize = 8;
+
+/// Numeric results this close to a fraction with a small denominator are taken to be it.
+const SNAP: f64 = 1e-8;
+
+/// The allowed error of a step of `odeint`, relative to the size of the values.
+const TOLERANCE: f64 = 1e-10;
+
+/// `odeint` gives up after this many steps, e.g. for stiff equations.
+const MAX_STEPS: usize = 100_000;
+
+/// How many intervals `odeint` samples by default.
+pub(crate) const SAMPLES: usize = 10;
+
+/// A solution `y(x) == value`, or an equation `lhs == rhs` that `y` couldn't be solved from.
+enum Solution {
+    Explicit(RunVal),
+    Implicit(RunVal, RunVal),
+}
+
+/// Solves a differential equation for the function `y(x)`. The initial values are the value of
+/// `x` and the values of `y(x)`, `y'(x)`, ... there, one for every order of the equation.
+///
+/// `None` if the equation isn't one of the kinds that are solved.
+pub(crate) fn dsolve(
+    equation: &RunVal,
+    function: &RunVal,
+    initial: Option<(&RunVal, &[RunVal])>,
+) -> Option<RunVal> {
+    let RunType::Function(_, args) = &function.typ else {
+        return None;
+    };
+    let [x] = args.as_slice() else {
+        return None;
+    };
+    if !matches!(x.typ, RunType::Symbol(_)) {
+        return None;
+    }
+
+    // The derivatives are replaced with the symbols _y0 = y(x), _y1 = y'(x), ...
+    let mut derivatives = vec![function.clone()];
+    for k in 1..=MAX_ORDER {
+        let derivative = RunType::Function(
+            "diff".to_string(),
+            vec![derivatives[k - 1].clone(), x.clone()],
+        );
+        derivatives.push(derivative.into());
+    }
+    let mut ys: Vec<RunVal> = (0..=MAX_ORDER)
+        .map(|k| RunType::Symbol(format!("_y{}", k)).into())
+        .collect();
+    let mut value = difference(equation);
+    for k in (0..=MAX_ORDER).rev() {
+        value = value.substitute(&derivatives[k], &ys[k]);
+    }
+    let value = simplified(value);
+
+    let order = (0..=MAX_ORDER).rev().find(|k| value.depends_on(&ys[*k]))?;
+    if order == 0 {
+        return None;
+    }
+    ys.truncate(order + 1);
+    ode_debug!("{:?} = 0 of order {}", value, order);
+
+    let solution = match order {
+        1 => first_order(&value, x, &ys[0], &ys[1]),
+        _ => None,
+    }
+    .or_else(|| constant_coefficients(&value, x, &ys))?;
+
+    let solution = match initial {
+        None => solution,
+        Some((x0, values)) if values.len() == order => match order {
+            1 => first_order_initial(solution, x, &ys[0], x0, &values[0])?,
+            _ => initial_values(solution, x, order, x0, values)?,
+        },
+        Some(_) => return None,
+    };
+
+    Some(match solution {
+        Solution::Explicit(value) => equal(function.clone(), value),
+        Solution::Implicit(lhs, rhs) => equal(lhs.substitute(&ys[0], function), rhs),
+    })
+}
+
+/// `y' = f(x, y)` where `F = a * y' + b`, tried as a linear, separable and exact equation.
+fn first_order(value: &RunVal, x: &RunVal, y: &RunVal, p: &RunVal) -> Option<Solution> {
+    let (a, b) = linear_in(value, p)?;
+    if a.depends_on(p) || b.depends_on(p) {
+        return None;
+    }
+
+    // y' + P * y = Q is solved by y = exp(-∫P) * (∫exp(∫P) * Q + C1)
+    if let Some((c, d)) = linear_in(&b, y).filter(|_| !a.depends_on(y)) {
+        if !c.depends_on(y) {
+            let p = simplified(c.multiply(reciprocal(a.clone())));
+            let q = simplified(negate(d).multiply(reciprocal(a.clone())));
+            let integral = simplified(antiderivative(&p, x)?);
+            let factor = simplified(call("exp", &integral));
+            if let Some(particular) = antiderivative(&factor.multiply(q), x) {
+                let sum = particular.add(constant(1));
+                let y = call("exp", &negate(integral)).multiply(sum);
+                return Some(Solution::Explicit(expanded(y)));
+            }
+        }
+    }
+
+    // y' = g(x) * h(y) is solved by ∫1/h(y) dy = ∫g(x) dx + C1
+    let slope = simplified(negate(b.clone()).multiply(reciprocal(a.clone())));
+    let factors = match &slope.typ {
+        RunType::Product(Factors(factors)) => factors.clone(),
+        _ => vec![slope.clone()],
+    };
+    let (h, g): (Vec<_>, Vec<_>) = factors.into_iter().partition(|it| it.depends_on(y));
+    if !h.is_empty() && h.iter().all(|it| !it.depends_on(x)) {
+        let h = product_of(h);
+        let lhs = antiderivative(&reciprocal(h), y).map(simplified);
+        let rhs = antiderivative(&product_of(g), x).map(simplified);
+        if let (Some(lhs), Some(rhs)) = (lhs, rhs) {
+            return Some(solved(lhs, y, rhs.add(constant(1))));
+        }
+    }
+
+    // M + N * y' = 0 with dM/dy = dN/dx has a potential ψ with dψ/dx = M and dψ/dy = N
+    let (m, n) = (b, a);
+    let exactness = diff::differentiate(&m, y).add(negate(diff::differentiate(&n, x)));
+    if !is_zero(&exactness) {
+        return None;
+    }
+    let potential = simplified(antiderivative(&m, x)?);
+    let rest = simplified(n.add(negate(diff::differentiate(&potential, y))));
+    if rest.depends_on(x) {
+        return None;
+    }
+    let potential = simplified(potential.add(antiderivative(&rest, y)?));
+    Some(solved(potential, y, constant(1)))
+}
+
+/// `y` solved from `lhs == rhs`, or the equation itself.
+fn solved(lhs: RunVal, y: &RunVal, rhs: RunVal) -> Solution {
+    match invert(&lhs, y, rhs.clone()) {
+        Some(value) => Solution::Explicit(simplified(value)),
+        None => Solution::Implicit(lhs, simplified(rhs)),
+    }
+}
+
+/// `a_n * y^(n) + ... + a_1 * y' + a_0 * y = g(x)` for numbers `a_k`, solved by the roots of the
+/// characteristic polynomial and a particular solution of the same form as `g`.
+fn constant_coefficients(value: &RunVal, x: &RunVal, ys: &[RunVal]) -> Option<Solution> {
+    let mut rest = value.clone();
+    let mut coeffs = vec![0.0; ys.len()];
+    for (k, y) in ys.iter().enumerate().rev() {
+        let (coeff, remainder) = linear_in(&rest, y)?;
+        coeffs[k] = approximate(&coeff)?;
+        rest = remainder;
+    }
+    let forcing = simplified(negate(rest));
+
+    let roots = characteristic_roots(&coeffs)?;
+    ode_debug!("{:?} has the characteristic roots {:?}", coeffs, roots);
+
+    let mut terms = Vec::new();
+    for (i, basis) in basis(&roots, x).into_iter().enumerate() {
+        terms.push(constant(i + 1).multiply(basis));
+    }
+    if !is_zero(&forcing) {
+        terms.push(particular(&forcing, x, &coeffs, &roots)?);
+    }
+    Some(Solution::Explicit(expanded(
+        RunType::Sum(Terms(terms)).into(),
+    )))
+}
+
+/// A root of the characteristic polynomial, `Complex(a, b)` is the pair `a ± bi`.
+#[derive(Debug)]
+enum Root {
+    Real(f64),
+    Complex(f64, f64),
+}
+
+/// The roots with their multiplicities, `None` if not all of them are found.
+fn characteristic_roots(coeffs: &[f64]) -> Option<Vec<(Root, usize)>> {
+    let r: RunVal = RunType::Symbol("_r".to_string()).into();
+    let terms = coeffs
+        .iter()
+        .enumerate()
+        .map(|(k, a)| number(*a).multiply(r.clone().power(number(k as f64))))
+        .collect();
+    let poly = Polynomial::from_value(&simplified(RunType::Sum(Terms(terms)).into()))?;
+
+    let mut coeffs = coeffs.to_vec();
+    let mut roots = Vec::new();
+    for root in poly.rational_roots(0) {
+        let mut multiplicity = 0;
+        while coeffs.len() > 1 && horner(&coeffs, root).abs() < SNAP {
+            coeffs = deflate(&coeffs, root);
+            multiplicity += 1;
+        }
+        roots.push((Root::Real(root), multiplicity));
+    }
+
+    match *coeffs.as_slice() {
+        [_] => {}
+        [c, b] => roots.push((Root::Real(-c / b), 1)),
+        [c, b, a] => {
+            let discriminant = b * b - 4.0 * a * c;
+            let center = -b / (2.0 * a);
+            let offset = discriminant.abs().sqrt() / (2.0 * a);
+            if discriminant.abs() < SNAP {
+                roots.push((Root::Real(center), 2));
+            } else if discriminant > 0.0 {
+                roots.push((Root::Real(center - offset), 1));
+                roots.push((Root::Real(center + offset), 1));
+            } else {
+                roots.push((Root::Complex(center, offset.abs()), 1));
+            }
+        }
+        _ => return None,
+    }
+    Some(roots)
+}
+
+/// Solutions of the homogeneous equation, `x^j * exp(r * x)` for a real root and
+/// `x^j * exp(a * x) * cos(b * x)` and `sin` for a pair `a ± bi`.
+fn basis(roots: &[(Root, usize)], x: &RunVal) -> Vec<RunVal> {
+    let mut basis = Vec::new();
+    for (root, multiplicity) in roots {
+        for j in 0..*multiplicity {
+            let power = x.clone().power(number(j as f64));
+            match root {
+                Root::Real(r) => basis.push(power.multiply(exponential(*r, x))),
+                Root::Complex(a, b) => {
+                    for name in ["cos", "sin"] {
+                        let oscillation = call(name, &x.clone().multiply(number(*b)));
+                        let function = power.clone().multiply(exponential(*a, x));
+                        basis.push(function.multiply(oscillation));
+                    }
+                }
+            }
+        }
+    }
+    basis.into_iter().map(simplified).collect()
+}
+
+/// A particular solution by undetermined coefficients: for every term `p(x) * exp(a * x)`,
+/// possibly times `sin(b * x)` or `cos(b * x)`, of the right hand side, the same kind of function
+/// with unknown coefficients is tried, times `x^s` if `a + bi` is a root of multiplicity `s`.
+///
+/// The coefficients are fitted numerically at sample points.
+fn particular(
+    forcing: &RunVal,
+    x: &RunVal,
+    coeffs: &[f64],
+    roots: &[(Root, usize)],
+) -> Option<RunVal> {
+    let terms = match &forcing.typ {
+        RunType::Sum(Terms(terms)) => terms.clone(),
+        _ => vec![forcing.clone()],
+    };
+
+    // The exponent, frequency and degree of every kind of term
+    let mut kinds: Vec<(f64, f64, u32)> = Vec::new();
+    for term in &terms {
+        let (a, b, degree) = forcing_kind(term, x)?;
+        match kinds.iter_mut().find(|(ka, kb, _)| *ka == a && *kb == b) {
+            Some(kind) => kind.2 = kind.2.max(degree),
+            None => kinds.push((a, b, degree)),
+        }
+    }
+
+    let mut functions = Vec::new();
+    for (a, b, degree) in kinds {
+        let shift = roots
+            .iter()
+            .find(|(root, _)| match root {
+                Root::Real(r) => b == 0.0 && (r - a).abs() < SNAP,
+                Root::Complex(ra, rb) => (ra - a).abs() < SNAP && (rb - b).abs() < SNAP,
+            })
+            .map_or(0, |(_, multiplicity)| *multiplicity);
+        for j in 0..=degree {
+            let power = x.clone().power(number((shift + j as usize) as f64));
+            let function = power.multiply(exponential(a, x));
+            match b == 0.0 {
+                true => functions.push(simplified(function)),
+                false => {
+                    for name in ["cos", "sin"] {
+                        let oscillation = call(name, &x.clone().multiply(number(b)));
+                        functions.push(simplified(function.clone().multiply(oscillation)));
+                    }
+                }
+            }
+        }
+    }
+
+    // L[f] = a_n * f^(n) + ... + a_0 * f for every function, at as many points as there are
+    let applied = functions
+        .iter()
+        .map(|function| {
+            let terms = coeffs
+                .iter()
+                .enumerate()
+                .map(|(k, a)| number(*a).multiply(diff::differentiate_n(function, x, k as u32)))
+                .collect();
+            simplified(RunType::Sum(Terms(terms)).into())
+        })
+        .collect::<Vec<_>>();
+    let vars = [x.clone()];
+    let points = (0..functions.len()).map(|i| 0.3 + 0.37 * i as f64);
+    let mut matrix = Vec::new();
+    let mut rhs = Vec::new();
+    for point in points {
+        let row = applied.iter().map(|it| numeric(it, &vars, &[point]));
+        matrix.push(row.collect::<Option<Vec<_>>>()?);
+        rhs.push(numeric(forcing, &vars, &[point])?);
+    }
+    let solution = solve_linear(matrix, rhs)?;
+
+    let terms = functions
+        .into_iter()
+        .zip(solution)
+        .filter(|(_, c)| *c != 0.0)
+        .map(|(function, c)| number(snap(c)).multiply(function))
+        .collect();
+    Some(RunType::Sum(Terms(terms)).into())
+}
+
+/// The exponent `a`, frequency `b` and degree of a term `c * p(x) * exp(a * x) * sin(b * x)`.
+fn forcing_kind(term: &RunVal, x: &RunVal) -> Option<(f64, f64, u32)> {
+    let factors = match &term.typ {
+        RunType::Product(Factors(factors)) => factors.clone(),
+        _ => vec![term.clone()],
+    };
+
+    let (mut a, mut b, mut degree) = (0.0, 0.0, 0);
+    for factor in factors.iter().filter(|it| it.depends_on(x)) {
+        let slope = |arg: &RunVal| slope(arg, x);
+        match &factor.typ {
+            RunType::Function(name, args) if name == "exp" && args.len() == 1 => {
+                a += slope(&args[0])?
+            }
+            RunType::Function(name, args)
+                if (name == "sin" || name == "cos") && b == 0.0 && args.len() == 1 =>
+            {
+                b = slope(&args[0])?.abs()
+            }
+            _ => {
+                let poly = Polynomial::from_value(factor)?;
+                if poly.gens().len() != 1 {
+                    return None;
+                }
+                degree += poly.degree(poly.gen_index(x)?)?;
+            }
+        }
+    }
+    Some((a, b, degree))
+}
+
+/// The constants of a solution from the values of it and its derivatives at `x0`, which are
+/// linear equations in the constants.
+fn initial_values(
+    solution: Solution,
+    x: &RunVal,
+    order: usize,
+    x0: &RunVal,
+    values: &[RunVal],
+) -> Option<Solution> {
+    let Solution::Explicit(value) = solution else {
+        return None;
+    };
+    let x0 = approximate(x0)?;
+    let mut vars = vec![x.clone()];
+    vars.extend((1..=order).map(constant));
+
+    let mut matrix = Vec::new();
+    let mut rhs = Vec::new();
+    for (k, target) in values.iter().enumerate() {
+        let derivative = diff::differentiate_n(&value, x, k as u32);
+        let at = |constants: Vec<f64>| {
+            let mut point = vec![x0];
+            point.extend(constants);
+            numeric(&derivative, &vars, &point)
+        };
+        let offset = at(vec![0.0; order])?;
+        let row = (0..order).map(|i| {
+            let mut unit = vec![0.0; order];
+            unit[i] = 1.0;
+            Some(at(unit)? - offset)
+        });
+        matrix.push(row.collect::<Option<Vec<_>>>()?);
+        rhs.push(approximate(target)? - offset);
+    }
+
+    let constants = solve_linear(matrix, rhs)?;
+    let bindings = constants
+        .into_iter()
+        .enumerate()
+        .map(|(i, c)| (constant(i + 1), number(snap(c))))
+        .collect::<Vec<_>>();
+    Some(Solution::Explicit(expanded(
+        value.substitute_simultaneously(&bindings),
+    )))
+}
+
+/// The constant `C1` of a first order solution from the value `y0` at `x0`.
+fn first_order_initial(
+    solution: Solution,
+    x: &RunVal,
+    y: &RunVal,
+    x0: &RunVal,
+    y0: &RunVal,
+) -> Option<Solution> {
+    let c1 = constant(1);
+    match solution {
+        Solution::Explicit(value) => {
+            let at = simplified(value.substitute(x, x0));
+            let c = simplified(invert(&at, &c1, y0.clone())?);
+            Some(Solution::Explicit(simplified(value.substitute(&c1, &c))))
+        }
+        Solution::Implicit(lhs, rhs) => {
+            let bindings = [(x.clone(), x0.clone()), (y.clone(), y0.clone())];
+            let at = simplified(lhs.substitute_simultaneously(&bindings));
+            let c = simplified(invert(&rhs, &c1, at)?);
+            Some(solved(lhs, y, rhs.substitute(&c1, &c)))
+        }
+    }
+}
+
+/// The `var` with `value == rhs`, if `var` occurs once in the value or it is a polynomial with
+/// one root. The result isn't simplified.
+fn invert(value: &RunVal, var: &RunVal, rhs: RunVal) -> Option<RunVal> {
+    use RunType::*;
+    if value == var {
+        return Some(rhs);
+    }
+
+    let dependent = |values: &[RunVal]| -> (Vec<RunVal>, Vec<RunVal>) {
+        values.iter().cloned().partition(|it| it.depends_on(var))
+    };
+    match &value.typ {
+        Sum(Terms(terms)) => {
+            let (dependent, rest) = dependent(terms);
+            if let [single] = dependent.as_slice() {
+                return invert(single, var, rhs.add(negate(Sum(Terms(rest)).into())));
+            }
+        }
+        Product(Factors(factors)) => {
+            let (dependent, rest) = dependent(factors);
+            if let [single] = dependent.as_slice() {
+                return invert(single, var, rhs.multiply(reciprocal(product_of(rest))));
+            }
+        }
+        Exponent(base, exp) if !exp.depends_on(var) => {
+            return invert(base, var, rhs.power(reciprocal(exp.as_ref().clone())));
+        }
+        Function(name, args) if args.len() == 1 => {
+            let inverse = match name.as_str() {
+                "ln" => return invert(&args[0], var, exponential_of(rhs)),
+                "exp" => "ln",
+                "sin" => "asin",
+                "cos" => "acos",
+                "tan" => "atan",
+                "asin" => "sin",
+                "acos" => "cos",
+                "atan" => "tan",
+                _ => return None,
+            };
+            return invert(&args[0], var, call(inverse, &rhs));
+        }
+        _ => {}
+    }
+
+    let equation = equal(value.clone(), rhs);
+    match &solve::solve(&equation, var)?.typ {
+        Set(sets::Set::Finite(roots)) if roots.len() == 1 => Some(roots[0].clone()),
+        _ => None,
+    }
+}
+
+/// `exp(value)`, where `exp(f + C1)` is written `C1 * exp(f)` for another constant `C1`.
+fn exponential_of(value: RunVal) -> RunVal {
+    let c1 = constant(1);
+    let value = simplified(value);
+    if let RunType::Sum(Terms(terms)) = &value.typ {
+        if terms.contains(&c1) {
+            let rest = terms.iter().filter(|it| **it != c1).cloned().collect();
+            return c1.multiply(call("exp", &RunType::Sum(Terms(rest)).into()));
+        }
+    }
+    if value == c1 {
+        return c1;
+    }
+    call("exp", &value)
+}
+
+/// Integrates `y' = f(t, y)` from `t0` to `t1`, where `f` and `y` are vectors for a system, and
+/// samples `[t, y1, y2, ...]` at the ends of `samples` equally long intervals.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn odeint(
+    f: &RunVal,
+    y: &RunVal,
+    t: &RunVal,
+    y0: &RunVal,
+    t0: &RunVal,
+    t1: &RunVal,
+    samples: usize,
+) -> Option<RunVal> {
+    let elements = |value: &RunVal| match &value.typ {
+        RunType::Vector(values) => values.clone(),
+        _ => vec![value.clone()],
+    };
+    let (fs, ys) = (elements(f), elements(y));
+    let y0 = elements(y0)
+        .iter()
+        .map(approximate)
+        .collect::<Option<Vec<_>>>()?;
+    let (t0, t1) = (approximate(t0)?, approximate(t1)?);
+    let symbols = ys
+        .iter()
+        .chain([t])
+        .all(|it| matches!(it.typ, RunType::Symbol(_)));
+    if !symbols || fs.len() != ys.len() || y0.len() != ys.len() || samples == 0 || t0 == t1 {
+        return None;
+    }
+
+    let mut vars = vec![t.clone()];
+    vars.extend(ys);
+    let rhs = |t: f64, y: &[f64]| -> Option<Vec<f64>> {
+        let mut point = vec![t];
+        point.extend(y);
+        fs.iter().map(|f| numeric(f, &vars, &point)).collect()
+    };
+
+    let sample = |t: f64, y: &[f64]| -> RunVal {
+        let row = std::iter::once(t)
+            .chain(y.iter().copied())
+            .map(number)
+            .collect();
+        RunType::Vector(row).into()
+    };
+    let mut rows = vec![sample(t0, &y0)];
+    let (mut t, mut y) = (t0, y0);
+    let mut h = (t1 - t0) / samples as f64;
+    let mut steps = 0;
+
+    for i in 1..=samples {
+        let target = t0 + (t1 - t0) * i as f64 / samples as f64;
+        while (target - t) * h.signum() > 0.0 {
+            steps += 1;
+            if steps > MAX_STEPS || h.abs() < f64::EPSILON * t.abs().max(1.0) {
+                ode_debug!("odeint gave up at t = {} after {} steps", t, steps);
+                return None;
+            }
+
+            let last = (t + h - target) * h.signum() >= 0.0;
+            let step = if last { target - t } else { h };
+            let (next, error) = dormand_prince(&rhs, t, &y, step)?;
+            let error = next
+                .iter()
+                .zip(&y)
+                .zip(&error)
+                .map(|((next, y), error)| {
+                    error.abs() / (TOLERANCE * (1.0 + next.abs().max(y.abs())))
+                })
+                .fold(0.0, f64::max);
+            if !error.is_finite() {
+                return None;
+            }
+
+            if error <= 1.0 {
+                (t, y) = (if last { target } else { t + step }, next);
+            }
+            let factor = match error == 0.0 {
+                true => 5.0,
+                false => (0.9 * error.powf(-0.2)).clamp(0.2, 5.0),
+            };
+            h = step * factor;
+        }
+        rows.push(sample(t, &y));
+    }
+
+    ode_debug!("odeint took {} steps", steps);
+    Some(RunType::Vector(rows).into())
+}
+
+/// The nodes, coefficients and weights of the Dormand-Prince method, and the differences of the
+/// weights of its fifth and fourth order solutions.
+const NODES: [f64; 6] = [0.0, 1.0 / 5.0, 3.0 / 10.0, 4.0 / 5.0, 8.0 / 9.0, 1.0];
+const COEFFICIENTS: [&[f64]; 6] = [
+    &[],
+    &[1.0 / 5.0],
+    &[3.0 / 40.0, 9.0 / 40.0],
+    &[44.0 / 45.0, -56.0 / 15.0, 32.0 / 9.0],
+    &[
+        19372.0 / 6561.0,
+        -25360.0 / 2187.0,
+        64448.0 / 6561.0,
+        -212.0 / 729.0,
+    ],
+    &[
+        9017.0 / 3168.0,
+        -355.0 / 33.0,
+        46732.0 / 5247.0,
+        49.0 / 176.0,
+        -5103.0 / 18656.0,
+    ],
+];
+const WEIGHTS: [f64; 7] = [
+    35.0 / 384.0,
+    0.0,
+    500.0 / 1113.0,
+    125.0 / 192.0,
+    -2187.0 / 6784.0,
+    11.0 / 84.0,
+    0.0,
+];
+const ERRORS: [f64; 7] = [
+    71.0 / 57600.0,
+    0.0,
+    -71.0 / 16695.0,
+    71.0 / 1920.0,
+    -17253.0 / 339200.0,
+    22.0 / 525.0,
+    -1.0 / 40.0,
+];
+
+/// One step of size `h`, the fifth order solution and the estimated error of the step.
+fn dormand_prince(
+    f: &impl Fn(f64, &[f64]) -> Option<Vec<f64>>,
+    t: f64,
+    y: &[f64],
+    h: f64,
+) -> Option<(Vec<f64>, Vec<f64>)> {
+    let combine = |weights: &[f64], slopes: &[Vec<f64>]| -> Vec<f64> {
+        (0..y.len())
+            .map(|i| {
+                y[i] + h * weights
+                    .iter()
+                    .zip(slopes)
+                    .map(|(w, k)| w * k[i])
+                    .sum::<f64>()
+            })
+            .collect()
+    };
+
+    let mut slopes: Vec<Vec<f64>> = Vec::with_capacity(7);
+    for (node, coefficients) in NODES.iter().zip(COEFFICIENTS) {
+        let slope = f(t + node * h, &combine(coefficients, &slopes))?;
+        slopes.push(slope);
+    }
+    let next = combine(&WEIGHTS, &slopes);
+    // The last slope is taken at the solution itself
+    slopes.push(f(t + h, &next)?);
+
+    let error = (0..y.len())
+        .map(|i| {
+            h * ERRORS
+                .iter()
+                .zip(&slopes)
+                .map(|(e, k)| e * k[i])
+                .sum::<f64>()
+        })
+        .collect();
+    Some((next, error))
+}
+
+/// The numeric value of an expression, where the variables have the given values.
+fn numeric(value: &RunVal, vars: &[RunVal], values: &[f64]) -> Option<f64> {
+    use RunType::*;
+    match &value.typ {
+        Number(n) => Some(*n),
+        Symbol(_) => vars.iter().position(|it| it == value).map(|i| values[i]),
+        Constant(c) => c.approximate(),
+        Sum(Terms(terms)) => terms.iter().map(|it| numeric(it, vars, values)).sum(),
+        Product(Factors(factors)) => factors.iter().map(|it| numeric(it, vars, values)).product(),
+        Exponent(base, exp) => Some(numeric(base, vars, values)?.powf(numeric(exp, vars, values)?)),
+        Function(name, args) => {
+            let args = args
+                .iter()
+                .map(|it| Some(RunVal::from(Number(numeric(it, vars, values)?))))
+                .collect::<Option<Vec<_>>>()?;
+            match functions::evaluate(name, &args)?.typ {
+                Number(n) => Some(n),
+                _ => None,
+            }
+        }
+        Piecewise(Pieces(pieces)) => {
+            for (condition, value) in pieces {
+                let holds = match &condition.typ {
+                    Bool(holds) => *holds,
+                    Function(name, args) => {
+                        let args = args
+                            .iter()
+                            .map(|it| Some(RunVal::from(Number(numeric(it, vars, values)?))))
+                            .collect::<Option<Vec<_>>>()?;
+                        functions::evaluate(name, &args)?.typ == Bool(true)
+                    }
+                    _ => return None,
+                };
+                if holds {
+                    return numeric(value, vars, values);
+                }
+            }
+            None
+        }
+        _ => None,
+    }
+}
+
+/// Solves `matrix * x = rhs` by Gaussian elimination, `None` if the matrix is singular.
+fn solve_linear(mut matrix: Vec<Vec<f64>>, mut rhs: Vec<f64>) -> Option<Vec<f64>> {
+    let n = rhs.len();
+    for column in 0..n {
+        let pivot = (column..n).max_by(|a, b| {
+            matrix[*a][column]
+                .abs()
+                .total_cmp(&matrix[*b][column].abs())
+        })?;
+        if matrix[pivot][column].abs() < 1e-12 {
+            return None;
+        }
+        matrix.swap(column, pivot);
+        rhs.swap(column, pivot);
+
+        for row in column + 1..n {
+            let (upper, lower) = matrix.split_at_mut(row);
+            let (pivot, current) = (&upper[column], &mut lower[0]);
+            let factor = current[column] / pivot[column];
+            for (entry, above) in current[column..].iter_mut().zip(&pivot[column..]) {
+                *entry -= factor * above;
+            }
+            rhs[row] -= factor * rhs[column];
+        }
+    }
+
+    let mut solution = vec![0.0; n];
+    for row in (0..n).rev() {
+        let known: f64 = (row + 1..n).map(|k| matrix[row][k] * solution[k]).sum();
+        solution[row] = (rhs[row] - known) / matrix[row][row];
+    }
+    Some(solution)
+}
+
+/// The closest fraction with a small denominator, if the number is that close to one.
+fn snap(n: f64) -> f64 {
+    for denominator in 1..=100 {
+        let scaled = n * denominator as f64;
+        if (scaled - scaled.round()).abs() < SNAP * denominator as f64 {
+            return scaled.round() / denominator as f64;
+        }
+    }
+    n
+}
+
+/// The value of a polynomial with the coefficients, lowest first.
+fn horner(coeffs: &[f64], x: f64) -> f64 {
+    coeffs.iter().rev().fold(0.0, |acc, c| acc * x + c)
+}
+
+/// The quotient of a polynomial and `x - root`.
+fn deflate(coeffs: &[f64], root: f64) -> Vec<f64> {
+    let mut quotient = vec![0.0; coeffs.len() - 1];
+    let mut carry = 0.0;
+    for k in (1..coeffs.len()).rev() {
+        carry = carry * root + coeffs[k];
+        quotient[k - 1] = carry;
+    }
+    quotient
+}
+
+/// `a` and `b` of `a * var + b`, where `b` doesn't depend on `var`. A value `var` doesn't occur in
+/// is `0 * var + value`.
+fn linear_in(value: &RunVal, var: &RunVal) -> Option<(RunVal, RunVal)> {
+    if !value.depends_on(var) {
+        return Some((number(0.0), value.clone()));
+    }
+    helpers::linear_in(value, var)
+}
+
+/// The `a` of `a * x + b` for a number `a`.
+fn slope(value: &RunVal, x: &RunVal) -> Option<f64> {
+    let (a, b) = linear_in(value, x)?;
+    if b.depends_on(x) {
+        return None;
+    }
+    approximate(&a)
+}
+
+/// `lhs - rhs` of an equation `lhs == rhs`, or the value of `value == 0`.
+fn difference(equation: &RunVal) -> RunVal {
+    match &equation.typ {
+        RunType::Function(name, args) if name == "equal" && args.len() == 2 => {
+            args[0].clone().add(negate(args[1].clone()))
+        }
+        _ => equation.clone(),
+    }
+}
+
+/// Multiplies out a solution and merges the `exp` of every product, e.g.
+/// `exp(-x) * (x * exp(x) + C1)` => `x + C1 * exp(-x)`.
+fn expanded(value: RunVal) -> RunVal {
+    let value = simplified(value);
+    let value = match Polynomial::from_value(&value) {
+        Some(poly) => simplified(poly.to_value()),
+        None => value,
+    };
+    simplified(merge_exponentials(&value))
+}
+
+/// Products of `exp` are one `exp` of the sum, e.g. `exp(x) * exp(-x)` => `exp(x - x)`.
+fn merge_exponentials(value: &RunVal) -> RunVal {
+    use RunType::*;
+    let is_exp = |value: &RunVal| matches!(&value.typ, Function(name, args) if name == "exp" && args.len() == 1);
+
+    let typ = match &value.typ {
+        Sum(Terms(terms)) => Sum(Terms(terms.iter().map(merge_exponentials).collect())),
+        Product(Factors(factors)) => {
+            let factors = factors.iter().map(merge_exponentials);
+            let (exps, mut others): (Vec<_>, Vec<_>) = factors.partition(is_exp);
+            if exps.len() < 2 {
+                others.extend(exps);
+                return Product(Factors(others)).into();
+            }
+            let exponents = exps
+                .into_iter()
+                .filter_map(|it| match &it.typ {
+                    Function(_, args) => Some(args[0].clone()),
+                    _ => None,
+                })
+                .collect();
+            others.push(call("exp", &Sum(Terms(exponents)).into()));
+            Product(Factors(others))
+        }
+        _ => return value.clone(),
+    };
+    typ.into()
+}
+
+/// `exp(a * x)`
+fn exponential(a: f64, x: &RunVal) -> RunVal {
+    call("exp", &x.clone().multiply(number(a)))
+}
+
+/// The constant of integration `Ci`.
+fn constant(i: usize) -> RunVal {
+    RunType::Symbol(format!("C{}", i)).into()
+}
+
+/// Polynomials cancel what the simplifier keeps apart, e.g. `a - (a - b) - b`.
+fn is_zero(value: &RunVal) -> bool {
+    Polynomial::from_value(&simplified(value.clone())).is_some_and(|it| it.is_zero())
+}
+
+fn equal(lhs: RunVal, rhs: RunVal) -> RunVal {
+    RunType::Function("equal".to_string(), vec![lhs, rhs]).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cas::eval::testing::eval;
+
+    #[test]
+    fn first_order() {
+        assert_eq!(
+            eval("dsolve(diff(y(x), x) == y(x), y(x))"),
+            "y(x) == C1 * exp(x)"
+        );
+        assert_eq!(
+            eval("dsolve(diff(y(x), x) + y(x) == x, y(x))"),
+            "y(x) == C1 * exp(-x) + x - 1"
+        );
+        assert_eq!(
+            eval("dsolve(diff(y(x), x) == x * y(x), y(x))"),
+            "y(x) == C1 * exp(0.5 * x^2)"
+        );
+        assert_eq!(
+            eval("dsolve(diff(y(x), x) == 1 + y(x)^2, y(x))"),
+            "y(x) == -tan(C1 - x)"
+        );
+        assert_eq!(
+            eval("dsolve(2 * x * y(x) + (x^2 + 2 * y(x)) * diff(y(x), x) == 0, y(x))"),
+            "x^2 * y(x) + y(x)^2 == C1"
+        );
+    }
+
+    #[test]
+    fn higher_order() {
+        assert_eq!(
+            eval("dsolve(diff(diff(y(x), x), x) + y(x) == 0, y(x))"),
+            "y(x) == C1 * cos(x) + C2 * sin(x)"
+        );
+        assert_eq!(
+            eval(
+                "dsolve(diff(diff(y(x), x), x) - 3 * diff(y(x), x) + 2 * y(x) == exp(3 * x), y(x))"
+            ),
+            "y(x) == C1 * exp(x) + C2 * exp(2 * x) + 0.5 * exp(3 * x)"
+        );
+        assert_eq!(
+            eval("dsolve(diff(diff(y(x), x), x) - 2 * diff(y(x), x) + y(x) == 0, y(x))"),
+            "y(x) == C2 * exp(x) * x + C1 * exp(x)"
+        );
+    }
+
+    #[test]
+    fn initial_conditions() {
+        assert_eq!(
+            eval("dsolve(diff(y(x), x) == y(x), y(x), 0, 1)"),
+            "y(x) == exp(x)"
+        );
+        assert_eq!(
+            eval("dsolve(diff(y(x), x) == 1 + y(x)^2, y(x), 0, 0)"),
+            "y(x) == tan(x)"
+        );
+        assert_eq!(
+            eval("dsolve(diff(diff(y(x), x), x) + y(x) == 0, y(x), 0, [0, 1])"),
+            "y(x) == sin(x)"
+        );
+    }
+
+    #[test]
+    fn unsolved_equations_stay() {
+        assert_eq!(
+            eval("dsolve(diff(y(x), x) == sin(y(x)) * x, y(x))"),
+            "dsolve(diff(y(x), x) == sin(y(x)) * x, y(x))"
+        );
+    }
+
+    #[test]
+    fn numeric_solutions() {
+        assert_eq!(
+            eval("odeint(t, y, t, 0, 0, 2, 2)"),
+            "[[0, 0], [1, 0.5000000000000001], [2, 2]]"
+        );
+        assert_eq!(
+            eval("odeint(y, y, t, 1, 0, 1, 4)"),
+            "[[0, 1], [0.25, 1.2840254166989058], [0.5, 1.6487212707279613], \
+             [0.75, 2.1170000166646585], [1, 2.7182818285428643]]"
+        );
+    }
+}
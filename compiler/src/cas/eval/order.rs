@@ -0,0 +1,269 @@
+//! The canonical ordering of values.
+//!
+//! Simplified sums and products keep their operands sorted in this order, so two
+//! simplified values are equal exactly when their operands are equal pairwise.
+//! Numbers come first, then symbols, constants and functions, sorted together by
+//! their names, e.g. `PI < cos(x) < x < y`. Powers are sorted next to their base,
+//! so that `x < x^2 < y`, products and sums by their last operands, and units
+//! come last, as in `2 x m`.
+
+use std::{
+    cmp::Ordering,
+    hash::{Hash, Hasher},
+};
+
+use super::value::{Factors, Pieces, RunType, RunVal, Terms};
+
+impl RunType {
+    /// Breaks ties between different kinds of values that would otherwise compare equal.
+    fn rank(&self) -> u8 {
+        use RunType::*;
+        match self {
+            Unit => 0,
+            Undefined => 1,
+            Bool(_) => 2,
+            Number(_) => 3,
+            Constant(_) => 4,
+            Symbol(_) => 5,
+            Function(_, _) => 6,
+            Exponent(_, _) => 7,
+            Product(_) => 8,
+            Sum(_) => 9,
+            Vector(_) => 10,
+            Piecewise(_) => 11,
+            Set(_) => 12,
+            Interval(_) => 13,
+            PhysicalUnit(_) => 14,
+        }
+    }
+
+    /// The name of symbols and constants, which are ordered alphabetically.
+    fn symbol_name(&self) -> Option<&str> {
+        match self {
+            RunType::Symbol(name) => Some(name),
+            RunType::Constant(constant) => Some(constant.name()),
+            _ => None,
+        }
+    }
+}
+
+impl Ord for RunType {
+    fn cmp(&self, other: &Self) -> Ordering {
+        use RunType::*;
+
+        let ordering = match (self, other) {
+            (Bool(a), Bool(b)) => a.cmp(b),
+            (Vector(a), Vector(b)) => a.cmp(b),
+            (Piecewise(Pieces(a)), Piecewise(Pieces(b))) => a.cmp(b),
+            (Set(a), Set(b)) => a.cmp(b),
+            (Unit | Undefined | Bool(_) | Vector(_) | Piecewise(_) | Set(_), _)
+            | (_, Unit | Undefined | Bool(_) | Vector(_) | Piecewise(_) | Set(_)) => Ordering::Equal,
+
+            (Number(a), Number(b)) => compare_numbers(*a, *b),
+            (Number(_), _) => Ordering::Less,
+            (_, Number(_)) => Ordering::Greater,
+
+            // Intervals are numbers that aren't known exactly
+            (Interval(a), Interval(b)) => a.cmp(b),
+            (Interval(_), _) => Ordering::Less,
+            (_, Interval(_)) => Ordering::Greater,
+
+            (Product(Factors(a)), Product(Factors(b))) | (Sum(Terms(a)), Sum(Terms(b))) => {
+                compare_from_end(a, b)
+            }
+            (Product(Factors(a)), _) => compare_with_single(a, other),
+            (_, Product(Factors(b))) => compare_with_single(b, self).reverse(),
+
+            (Exponent(base, exp), Exponent(other_base, other_exp)) => {
+                base.cmp(other_base).then_with(|| exp.cmp(other_exp))
+            }
+            (Exponent(base, exp), _) => base.typ.cmp(other).then_with(|| exp.typ.cmp(&Number(1.0))),
+            (_, Exponent(base, exp)) => self.cmp(&base.typ).then_with(|| Number(1.0).cmp(&exp.typ)),
+
+            (Sum(Terms(a)), _) => compare_with_single(a, other),
+            (_, Sum(Terms(b))) => compare_with_single(b, self).reverse(),
+
+            // Units come after everything else, like they are written, e.g. `2 x m`
+            (PhysicalUnit(a), PhysicalUnit(b)) => a.cmp(b),
+            (PhysicalUnit(_), _) => Ordering::Greater,
+            (_, PhysicalUnit(_)) => Ordering::Less,
+
+            (Function(name, args), Function(other_name, other_args)) => {
+                name.cmp(other_name).then_with(|| args.cmp(other_args))
+            }
+            (Function(name, _), _) => name.as_str().cmp(other.symbol_name().unwrap_or_default()),
+            (_, Function(name, _)) => self.symbol_name().unwrap_or_default().cmp(name),
+
+            _ => self.symbol_name().cmp(&other.symbol_name()),
+        };
+
+        ordering.then_with(|| self.rank().cmp(&other.rank()))
+    }
+}
+
+impl PartialOrd for RunType {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for RunType {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for RunType {}
+
+impl Hash for RunType {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        use RunType::*;
+        self.rank().hash(state);
+        match self {
+            Unit | Undefined => {}
+            // 0 and -0 are equal, so they have to hash the same
+            Number(n) if *n == 0.0 => 0.0f64.to_bits().hash(state),
+            Number(n) => n.to_bits().hash(state),
+            Symbol(name) => name.hash(state),
+            Constant(constant) => constant.name().hash(state),
+            Bool(b) => b.hash(state),
+            Vector(values) => values.hash(state),
+            Sum(terms) => terms.hash(state),
+            Product(factors) => factors.hash(state),
+            Exponent(base, exp) => {
+                base.hash(state);
+                exp.hash(state);
+            }
+            Function(name, args) => {
+                name.hash(state);
+                args.hash(state);
+            }
+            Piecewise(pieces) => pieces.hash(state),
+            Set(set) => set.hash(state),
+            PhysicalUnit(unit) => unit.hash(state),
+            Interval(interval) => {
+                interval.lower.to_bits().hash(state);
+                interval.upper.to_bits().hash(state);
+            }
+        }
+    }
+}
+
+impl Ord for RunVal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        if self.ptr_eq(other) {
+            return Ordering::Equal;
+        }
+        self.typ.cmp(&other.typ)
+    }
+}
+
+impl PartialOrd for RunVal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Whether a value has been simplified doesn't change what it is.
+impl PartialEq for RunVal {
+    fn eq(&self, other: &Self) -> bool {
+        self.ptr_eq(other) || (self.hash_value() == other.hash_value() && self.typ == other.typ)
+    }
+}
+
+impl Eq for RunVal {}
+
+/// Uses the cached hash of the node, so hashing is constant time.
+impl Hash for RunVal {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.hash_value());
+    }
+}
+
+fn compare_numbers(a: f64, b: f64) -> Ordering {
+    a.partial_cmp(&b).unwrap_or_else(|| a.total_cmp(&b))
+}
+
+/// Sorted operands are compared starting with the largest ones, e.g. `x + y < x^2 + y`.
+fn compare_from_end(a: &[RunVal], b: &[RunVal]) -> Ordering {
+    a.iter()
+        .rev()
+        .zip(b.iter().rev())
+        .map(|(a, b)| a.cmp(b))
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or_else(|| a.len().cmp(&b.len()))
+}
+
+/// Compares operands with a value, as if the value was the only operand.
+fn compare_with_single(a: &[RunVal], b: &RunType) -> Ordering {
+    match a.last() {
+        Some(last) => last.typ.cmp(b).then_with(|| a.len().cmp(&1)),
+        None => Ordering::Less,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cmp::Ordering,
+        collections::hash_map::DefaultHasher,
+        hash::{Hash, Hasher},
+    };
+
+    use crate::cas::eval::{
+        testing::{eval, Session},
+        value::RunVal,
+    };
+
+    fn value(input: &str) -> RunVal {
+        Session::new().run(input).unwrap()
+    }
+
+    fn hash(value: &RunVal) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn operands_are_sorted() {
+        assert_eq!(eval("y + x"), "x + y");
+        assert_eq!(eval("b*a + 2"), "a * b + 2");
+        assert_eq!(eval("y + 1 + x + x^2"), "x^2 + x + y + 1");
+        assert_eq!(eval("y*2*x"), "2 * x * y");
+    }
+
+    #[test]
+    fn equal_values_hash_the_same() {
+        let a = value("x*y + sin(z)");
+        let b = value("sin(z) + y*x");
+        assert_eq!(a, b);
+        assert_eq!(hash(&a), hash(&b));
+        assert_eq!(hash(&value("0")), hash(&value("-0")));
+    }
+
+    #[test]
+    fn order_is_total() {
+        let mut values: Vec<_> = ["x^2", "y", "2", "x", "sin(x)", "x*y", "x + 1"]
+            .into_iter()
+            .map(value)
+            .collect();
+        values.sort();
+        for (i, a) in values.iter().enumerate() {
+            for (j, b) in values.iter().enumerate() {
+                assert_eq!(a.cmp(b), i.cmp(&j));
+                assert_eq!(a.cmp(b), b.cmp(a).reverse());
+            }
+        }
+        assert_eq!(value("x").cmp(&value("x^2")), Ordering::Less);
+        assert_eq!(value("x^2").cmp(&value("y")), Ordering::Less);
+    }
+
+    #[test]
+    fn functions_are_sorted_by_name() {
+        let mut values: Vec<_> = ["y", "x", "cos(x)", "PI", "2"].into_iter().map(value).collect();
+        values.sort();
+        assert_eq!(values, ["2", "PI", "cos(x)", "x", "y"].map(value));
+        assert_eq!(value("sin(x)").cmp(&value("a")), Ordering::Greater);
+    }
+}
@@ -0,0 +1,876 @@
+use std::collections::BTreeMap;
+
+use log::debug;
+
+use super::value::{Factors, RunType, RunVal, Terms};
+
+macro_rules! poly_debug {
+    ($($arg:tt)+) => (debug!(target: "matex::poly", "[{}:{}] {}", file!(), line!(), &format!($($arg)+)));
+}
+
+/// Coefficients smaller than this are considered to be zero.
+const EPSILON: f64 = 1e-12;
+
+/// Exponents of every generator in a term, in the same order as `Polynomial::gens`.
+pub type Monomial = Vec<u32>;
+
+/// A sparse multivariate polynomial with floating point coefficients.
+///
+/// The generators are usually symbols, but any non-polynomial subexpression
+/// (e.g. `sin(x)` or `x^y`) is treated as an opaque generator as well.
+/// Terms are ordered lexicographically, with `gens[0]` as the most significant generator.
+#[derive(Clone, Debug)]
+pub struct Polynomial {
+    gens: Vec<RunVal>,
+    terms: BTreeMap<Monomial, f64>,
+}
+
+impl Polynomial {
+    pub fn zero(gens: &[RunVal]) -> Self {
+        Self {
+            gens: gens.to_vec(),
+            terms: BTreeMap::new(),
+        }
+    }
+
+    pub fn constant(value: f64, gens: &[RunVal]) -> Self {
+        let mut poly = Self::zero(gens);
+        poly.add_term(vec![0; gens.len()], value);
+        poly
+    }
+
+    pub fn generator(index: usize, gens: &[RunVal]) -> Self {
+        let mut monomial = vec![0; gens.len()];
+        monomial[index] = 1;
+
+        let mut poly = Self::zero(gens);
+        poly.add_term(monomial, 1.0);
+        poly
+    }
+
+    /// Converts a single value into a polynomial, using the generators found in the value.
+    pub fn from_value(value: &RunVal) -> Option<Self> {
+        Self::from_values(&[value])?.pop()
+    }
+
+    /// Converts several values into polynomials sharing the same generators.
+    pub fn from_values(values: &[&RunVal]) -> Option<Vec<Self>> {
+        let mut gens = Vec::new();
+        for value in values {
+            Self::collect_generators(value, &mut gens)?;
+        }
+
+        // Symbols first, sorted by name, so that the main variable is predictable.
+        gens.sort_by(|a: &RunVal, b: &RunVal| match (&a.typ, &b.typ) {
+            (RunType::Symbol(a), RunType::Symbol(b)) => a.cmp(b),
+            (RunType::Symbol(_), _) => std::cmp::Ordering::Less,
+            (_, RunType::Symbol(_)) => std::cmp::Ordering::Greater,
+            _ => std::cmp::Ordering::Equal,
+        });
+
+        poly_debug!("generators: {:?}", gens);
+
+        values
+            .iter()
+            .map(|value| Self::convert(value, &gens))
+            .collect()
+    }
+
+    fn collect_generators(value: &RunVal, gens: &mut Vec<RunVal>) -> Option<()> {
+        use RunType::*;
+        match &value.typ {
+            Number(_) => {}
+            Sum(Terms(values)) | Product(Factors(values)) => {
+                for value in values {
+                    Self::collect_generators(value, gens)?;
+                }
+            }
+            Exponent(base, exp) if Self::natural_exponent(exp).is_some() => {
+                Self::collect_generators(base, gens)?;
+            }
+            Symbol(_) | Constant(_) | Exponent(_, _) | Function(_, _) => {
+                if !gens.iter().any(|it| it.struct_equal(value)) {
+                    gens.push(value.clone());
+                }
+            }
+            Unit
+            | Undefined
+            | Bool(_)
+            | Vector(_)
+            | Piecewise(_)
+            | Set(_)
+            | Interval(_)
+            | PhysicalUnit(_) => {
+                return None
+            }
+        }
+        Some(())
+    }
+
+    fn convert(value: &RunVal, gens: &[RunVal]) -> Option<Self> {
+        use RunType::*;
+        let poly = match &value.typ {
+            Number(n) => Self::constant(*n, gens),
+            Sum(Terms(terms)) => {
+                let mut sum = Self::zero(gens);
+                for term in terms {
+                    sum = sum.add(&Self::convert(term, gens)?);
+                }
+                sum
+            }
+            Product(Factors(factors)) => {
+                let mut product = Self::constant(1.0, gens);
+                for factor in factors {
+                    product = product.mul(&Self::convert(factor, gens)?);
+                }
+                product
+            }
+            Exponent(base, exp) if Self::natural_exponent(exp).is_some() => {
+                let exp = Self::natural_exponent(exp)?;
+                Self::convert(base, gens)?.pow(exp)
+            }
+            _ => {
+                let index = gens.iter().position(|it| it.struct_equal(value))?;
+                Self::generator(index, gens)
+            }
+        };
+        Some(poly)
+    }
+
+    fn natural_exponent(exp: &RunVal) -> Option<u32> {
+        match exp.typ {
+            RunType::Number(n) if n >= 0.0 && n.fract() == 0.0 && n <= u32::MAX as f64 => {
+                Some(n as u32)
+            }
+            _ => None,
+        }
+    }
+
+    /// Converts the polynomial back into a sum of products, highest degree terms first.
+    pub fn to_value(&self) -> RunVal {
+        let mut terms = Vec::new();
+
+        for (monomial, coeff) in self.terms.iter().rev() {
+            let mut factors = Vec::new();
+
+            if *coeff != 1.0 || monomial.iter().all(|it| *it == 0) {
+                factors.push(RunType::Number(*coeff).into());
+            }
+
+            for (gen, exp) in self.gens.iter().zip(monomial) {
+                match exp {
+                    0 => {}
+                    1 => factors.push(gen.clone()),
+                    _ => factors.push(
+                        RunType::Exponent(
+                            Box::new(gen.clone()),
+                            Box::new(RunType::Number(*exp as f64).into()),
+                        )
+                        .into(),
+                    ),
+                }
+            }
+
+            terms.push(Self::wrap(factors, |it| RunType::Product(Factors(it))));
+        }
+
+        if terms.is_empty() {
+            return RunType::Number(0.0).into();
+        }
+
+        Self::wrap(terms, |it| RunType::Sum(Terms(it)))
+    }
+
+    fn wrap(mut values: Vec<RunVal>, constructor: fn(Vec<RunVal>) -> RunType) -> RunVal {
+        if values.len() == 1 {
+            values.remove(0)
+        } else {
+            constructor(values).into()
+        }
+    }
+}
+
+impl Polynomial {
+    pub fn gens(&self) -> &[RunVal] {
+        &self.gens
+    }
+
+    /// Index of `value` among the generators of this polynomial.
+    pub fn gen_index(&self, value: &RunVal) -> Option<usize> {
+        self.gens.iter().position(|it| it.struct_equal(value))
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// The value of the polynomial if it doesn't depend on any generator.
+    pub fn as_constant(&self) -> Option<f64> {
+        match self.terms.len() {
+            0 => Some(0.0),
+            1 => self.terms.get(&vec![0; self.gens.len()]).copied(),
+            _ => None,
+        }
+    }
+
+    /// The degree in the generator `var`, `None` for the zero polynomial.
+    pub fn degree(&self, var: usize) -> Option<u32> {
+        self.terms.keys().map(|monomial| monomial[var]).max()
+    }
+
+    pub fn total_degree(&self) -> Option<u32> {
+        self.terms
+            .keys()
+            .map(|monomial| monomial.iter().sum())
+            .max()
+    }
+
+    /// The lexicographically leading term.
+    pub fn leading_term(&self) -> Option<(&Monomial, f64)> {
+        self.terms.iter().next_back().map(|(m, c)| (m, *c))
+    }
+
+    /// Dense coefficients in the generator `var`, lowest degree first.
+    /// Every coefficient is a polynomial in the remaining generators.
+    pub fn coeffs(&self, var: usize) -> Vec<Polynomial> {
+        let Some(degree) = self.degree(var) else {
+            return Vec::new();
+        };
+
+        let mut coeffs = vec![Self::zero(&self.gens); degree as usize + 1];
+        for (monomial, coeff) in &self.terms {
+            let mut monomial = monomial.clone();
+            let power = std::mem::take(&mut monomial[var]);
+            coeffs[power as usize].add_term(monomial, *coeff);
+        }
+        coeffs
+    }
+
+    /// The inverse of [`Polynomial::coeffs`].
+    pub fn from_coeffs(coeffs: &[Polynomial], var: usize, gens: &[RunVal]) -> Self {
+        let mut poly = Self::zero(gens);
+        for (power, coeff) in coeffs.iter().enumerate() {
+            for (monomial, c) in &coeff.terms {
+                let mut monomial = monomial.clone();
+                monomial[var] += power as u32;
+                poly.add_term(monomial, *c);
+            }
+        }
+        poly
+    }
+
+    /// The coefficient of the highest power of `var`.
+    pub fn leading_coeff(&self, var: usize) -> Polynomial {
+        self.coeffs(var)
+            .pop()
+            .unwrap_or_else(|| Self::zero(&self.gens))
+    }
+
+    fn add_term(&mut self, monomial: Monomial, coeff: f64) {
+        let entry = self.terms.entry(monomial).or_insert(0.0);
+        *entry += coeff;
+
+        if entry.abs() < EPSILON {
+            self.terms.retain(|_, c| c.abs() >= EPSILON);
+        }
+    }
+}
+
+impl Polynomial {
+    pub fn add(&self, other: &Polynomial) -> Polynomial {
+        let mut sum = self.clone();
+        for (monomial, coeff) in &other.terms {
+            sum.add_term(monomial.clone(), *coeff);
+        }
+        sum
+    }
+
+    pub fn sub(&self, other: &Polynomial) -> Polynomial {
+        self.add(&other.neg())
+    }
+
+    pub fn neg(&self) -> Polynomial {
+        self.scale(-1.0)
+    }
+
+    pub fn scale(&self, factor: f64) -> Polynomial {
+        let mut scaled = Self::zero(&self.gens);
+        for (monomial, coeff) in &self.terms {
+            scaled.add_term(monomial.clone(), coeff * factor);
+        }
+        scaled
+    }
+
+    pub fn mul(&self, other: &Polynomial) -> Polynomial {
+        let mut product = Self::zero(&self.gens);
+        for (monomial, coeff) in &self.terms {
+            for (other_monomial, other_coeff) in &other.terms {
+                let monomial = monomial
+                    .iter()
+                    .zip(other_monomial)
+                    .map(|(a, b)| a + b)
+                    .collect();
+                product.add_term(monomial, coeff * other_coeff);
+            }
+        }
+        product
+    }
+
+    pub fn pow(&self, exp: u32) -> Polynomial {
+        let mut result = Self::constant(1.0, &self.gens);
+        let mut base = self.clone();
+        let mut exp = exp;
+
+        while exp > 0 {
+            if exp & 1 == 1 {
+                result = result.mul(&base);
+            }
+            base = base.mul(&base);
+            exp >>= 1;
+        }
+        result
+    }
+
+    fn mul_monomial(&self, monomial: &Monomial, coeff: f64) -> Polynomial {
+        let mut product = Self::zero(&self.gens);
+        for (m, c) in &self.terms {
+            let m = m.iter().zip(monomial).map(|(a, b)| a + b).collect();
+            product.add_term(m, c * coeff);
+        }
+        product
+    }
+
+    pub fn derivative(&self, var: usize) -> Polynomial {
+        let mut derivative = Self::zero(&self.gens);
+        for (monomial, coeff) in &self.terms {
+            if monomial[var] == 0 {
+                continue;
+            }
+            let mut monomial = monomial.clone();
+            let power = monomial[var];
+            monomial[var] -= 1;
+            derivative.add_term(monomial, coeff * power as f64);
+        }
+        derivative
+    }
+
+    /// Division with remainder with respect to the generator `var`.
+    ///
+    /// Only possible when the leading coefficient of `divisor` doesn't depend on any generator,
+    /// otherwise the quotient wouldn't be a polynomial.
+    pub fn div_rem(&self, divisor: &Polynomial, var: usize) -> Option<(Polynomial, Polynomial)> {
+        let divisor_degree = divisor.degree(var)?;
+        let lc = divisor.leading_coeff(var).as_constant()?;
+
+        let mut quotient = Self::zero(&self.gens);
+        let mut remainder = self.clone();
+
+        while let Some(degree) = remainder.degree(var) {
+            if degree < divisor_degree {
+                break;
+            }
+
+            let mut shift = vec![0; self.gens.len()];
+            shift[var] = degree - divisor_degree;
+
+            let factor = remainder.leading_coeff(var).scale(1.0 / lc);
+            let term = Self::from_coeffs(&[factor], var, &self.gens).mul_monomial(&shift, 1.0);
+
+            quotient = quotient.add(&term);
+            remainder = remainder.sub(&term.mul(divisor));
+
+            // Make sure the leading power is gone even if rounding errors occurred.
+            remainder
+                .terms
+                .retain(|monomial, _| monomial[var] != degree);
+        }
+
+        Some((quotient, remainder))
+    }
+
+    /// Pseudo-remainder of `self` divided by `divisor` with respect to `var`.
+    /// Unlike [`Polynomial::div_rem`] it never needs to divide coefficients.
+    pub fn pseudo_rem(&self, divisor: &Polynomial, var: usize) -> Polynomial {
+        let Some(divisor_degree) = divisor.degree(var) else {
+            return self.clone();
+        };
+        let lc = divisor.leading_coeff(var);
+        let mut remainder = self.clone();
+
+        while let Some(degree) = remainder.degree(var) {
+            if degree < divisor_degree {
+                break;
+            }
+            let mut shift = vec![0; self.gens.len()];
+            shift[var] = degree - divisor_degree;
+
+            let lc_r = remainder.leading_coeff(var);
+            remainder = remainder
+                .mul(&lc)
+                .sub(&lc_r.mul(divisor).mul_monomial(&shift, 1.0));
+            remainder
+                .terms
+                .retain(|monomial, _| monomial[var] != degree);
+        }
+        remainder
+    }
+
+    /// Exact multivariate division, `None` if `divisor` doesn't divide `self`.
+    pub fn exact_div(&self, divisor: &Polynomial) -> Option<Polynomial> {
+        let (lm_divisor, lc_divisor) = divisor.leading_term()?;
+        let lm_divisor = lm_divisor.clone();
+
+        let mut quotient = Self::zero(&self.gens);
+        let mut remainder = self.clone();
+
+        while let Some((lm, lc)) = remainder.leading_term() {
+            if lm.iter().zip(&lm_divisor).any(|(a, b)| a < b) {
+                poly_debug!("{:?} does not divide {:?}", divisor, self);
+                return None;
+            }
+            let lm = lm.clone();
+            let shift: Monomial = lm.iter().zip(&lm_divisor).map(|(a, b)| a - b).collect();
+            let coeff = lc / lc_divisor;
+
+            quotient.add_term(shift.clone(), coeff);
+            remainder = remainder.sub(&divisor.mul_monomial(&shift, coeff));
+            remainder.terms.remove(&lm);
+        }
+
+        Some(quotient)
+    }
+
+    /// Greatest common divisor. Integer coefficients give a primitive result with a positive
+    /// leading coefficient, otherwise the result is monic.
+    pub fn gcd(&self, other: &Polynomial) -> Polynomial {
+        let gcd = Self::gcd_impl(self, other, 0);
+        gcd.normalize()
+    }
+
+    /// Least common multiple, normalized like [`Polynomial::gcd`].
+    pub fn lcm(&self, other: &Polynomial) -> Polynomial {
+        if self.is_zero() || other.is_zero() {
+            return Self::zero(&self.gens);
+        }
+        let gcd = self.gcd(other);
+        let lcm = self
+            .mul(other)
+            .exact_div(&gcd)
+            .expect("gcd always divides the product");
+        lcm.normalize()
+    }
+
+    fn gcd_impl(a: &Polynomial, b: &Polynomial, var: usize) -> Polynomial {
+        if a.is_zero() {
+            return b.clone();
+        }
+        if b.is_zero() {
+            return a.clone();
+        }
+
+        if var == a.gens.len() {
+            let gcd = numeric_gcd(a.as_constant().unwrap(), b.as_constant().unwrap());
+            return Self::constant(gcd, &a.gens);
+        }
+
+        if a.degree(var) == Some(0) && b.degree(var) == Some(0) {
+            return Self::gcd_impl(a, b, var + 1);
+        }
+
+        let content_a = a.content(var);
+        let content_b = b.content(var);
+        let content = Self::gcd_impl(&content_a, &content_b, var + 1);
+
+        let mut f = a.exact_div(&content_a).unwrap();
+        let mut g = b.exact_div(&content_b).unwrap();
+        if f.degree(var) < g.degree(var) {
+            std::mem::swap(&mut f, &mut g);
+        }
+
+        // Primitive polynomial remainder sequence
+        while !g.is_zero() {
+            let remainder = f.pseudo_rem(&g, var);
+            f = g;
+            g = if remainder.is_zero() {
+                remainder
+            } else {
+                remainder.primitive_part(var)
+            };
+        }
+
+        f.primitive_part(var).mul(&content)
+    }
+
+    /// The gcd of the coefficients with respect to `var`.
+    pub fn content(&self, var: usize) -> Polynomial {
+        self.coeffs(var)
+            .iter()
+            .fold(Self::zero(&self.gens), |acc, coeff| {
+                Self::gcd_impl(&acc, coeff, var + 1)
+            })
+    }
+
+    pub fn primitive_part(&self, var: usize) -> Polynomial {
+        let content = self.content(var);
+        if content.is_zero() {
+            return self.clone();
+        }
+        self.exact_div(&content).unwrap_or_else(|| self.clone())
+    }
+
+    fn normalize(&self) -> Polynomial {
+        let Some((_, lc)) = self.leading_term() else {
+            return self.clone();
+        };
+
+        if self.terms.values().all(|c| c.fract() == 0.0) {
+            self.scale(lc.signum())
+        } else {
+            self.scale(1.0 / lc)
+        }
+    }
+
+    /// The resultant with respect to `var`, computed as the determinant of the Sylvester matrix.
+    pub fn resultant(&self, other: &Polynomial, var: usize) -> Polynomial {
+        let (Some(n), Some(m)) = (self.degree(var), other.degree(var)) else {
+            return Self::zero(&self.gens);
+        };
+
+        if n == 0 {
+            return self.leading_coeff(var).pow(m);
+        }
+        if m == 0 {
+            return other.leading_coeff(var).pow(n);
+        }
+
+        let (n, m) = (n as usize, m as usize);
+        let size = n + m;
+
+        let a = self.coeffs(var);
+        let b = other.coeffs(var);
+
+        let mut matrix = vec![vec![Self::zero(&self.gens); size]; size];
+        for row in 0..m {
+            for (i, coeff) in a.iter().rev().enumerate() {
+                matrix[row][row + i] = coeff.clone();
+            }
+        }
+        for row in 0..n {
+            for (i, coeff) in b.iter().rev().enumerate() {
+                matrix[m + row][row + i] = coeff.clone();
+            }
+        }
+
+        Self::determinant(matrix, &self.gens)
+    }
+
+    /// Fraction-free determinant using Bareiss' algorithm.
+    fn determinant(mut matrix: Vec<Vec<Polynomial>>, gens: &[RunVal]) -> Polynomial {
+        let size = matrix.len();
+        let mut sign = 1.0;
+        let mut previous = Self::constant(1.0, gens);
+
+        for k in 0..size.saturating_sub(1) {
+            if matrix[k][k].is_zero() {
+                let Some(swap) = (k + 1..size).find(|&i| !matrix[i][k].is_zero()) else {
+                    return Self::zero(gens);
+                };
+                matrix.swap(k, swap);
+                sign = -sign;
+            }
+
+            for i in k + 1..size {
+                for j in k + 1..size {
+                    let value = matrix[i][j]
+                        .mul(&matrix[k][k])
+                        .sub(&matrix[i][k].mul(&matrix[k][j]));
+                    matrix[i][j] = value
+                        .exact_div(&previous)
+                        .expect("Bareiss division is always exact");
+                }
+            }
+            previous = matrix[k][k].clone();
+        }
+
+        matrix[size - 1][size - 1].scale(sign)
+    }
+
+    /// The discriminant with respect to `var`, `None` for constant polynomials.
+    pub fn discriminant(&self, var: usize) -> Option<Polynomial> {
+        let n = self.degree(var)?;
+        if n == 0 {
+            return None;
+        }
+        if n == 1 {
+            return Some(Self::constant(1.0, &self.gens));
+        }
+
+        let resultant = self.resultant(&self.derivative(var), var);
+        let sign = if (n * (n - 1) / 2) % 2 == 0 {
+            1.0
+        } else {
+            -1.0
+        };
+
+        resultant
+            .exact_div(&self.leading_coeff(var))
+            .map(|it| it.scale(sign))
+    }
+}
+
+impl Polynomial {
+    /// Replaces the generator `var` with a number.
+    pub fn substitute(&self, var: usize, value: f64) -> Polynomial {
+        let mut result = Self::zero(&self.gens);
+        for (monomial, coeff) in &self.terms {
+            let mut monomial = monomial.clone();
+            let power = std::mem::take(&mut monomial[var]);
+            result.add_term(monomial, coeff * value.powi(power as i32));
+        }
+        result
+    }
+
+    pub fn monic(&self, var: usize) -> Polynomial {
+        match self.leading_coeff(var).as_constant() {
+            Some(lc) if lc != 0.0 => self.scale(1.0 / lc),
+            _ => self.clone(),
+        }
+    }
+
+    /// Square-free decomposition using Yun's algorithm.
+    ///
+    /// Returns monic, pairwise coprime factors together with their multiplicity,
+    /// the product of which equals the monic version of `self`.
+    pub fn square_free(&self, var: usize) -> Vec<(Polynomial, u32)> {
+        let mut factors = Vec::new();
+
+        if self.degree(var).unwrap_or(0) == 0 {
+            return factors;
+        }
+
+        let derivative = self.derivative(var);
+        let a = self.gcd(&derivative);
+
+        let Some(mut b) = self.exact_div(&a) else {
+            return vec![(self.monic(var), 1)];
+        };
+        let Some(mut c) = derivative.exact_div(&a) else {
+            return vec![(self.monic(var), 1)];
+        };
+        let mut d = c.sub(&b.derivative(var));
+
+        let mut multiplicity = 1;
+        while b.degree(var).unwrap_or(0) > 0 {
+            let a = b.gcd(&d);
+
+            if a.degree(var).unwrap_or(0) > 0 {
+                factors.push((a.monic(var), multiplicity));
+            }
+
+            (b, c) = match (b.exact_div(&a), d.exact_div(&a)) {
+                (Some(b), Some(c)) => (b, c),
+                _ => break,
+            };
+            d = c.sub(&b.derivative(var));
+            multiplicity += 1;
+        }
+
+        factors
+    }
+
+    /// Rational roots of a univariate polynomial with integer coefficients,
+    /// found with the rational root theorem.
+    pub fn rational_roots(&self, var: usize) -> Vec<f64> {
+        const LIMIT: f64 = 1e6;
+
+        let coeffs: Option<Vec<f64>> = self.coeffs(var).iter().map(|it| it.as_constant()).collect();
+        let Some(mut coeffs) = coeffs else {
+            return Vec::new();
+        };
+
+        if coeffs.iter().any(|c| c.fract() != 0.0) {
+            return Vec::new();
+        }
+
+        let mut roots = Vec::new();
+
+        // Factor out x^k first, so that the constant coefficient is non-zero
+        let zeros = coeffs.iter().take_while(|c| **c == 0.0).count();
+        if zeros > 0 && zeros < coeffs.len() {
+            roots.push(0.0);
+            coeffs.drain(..zeros);
+        }
+
+        let (Some(constant), Some(leading)) = (coeffs.first(), coeffs.last()) else {
+            return roots;
+        };
+        if coeffs.len() < 2 || constant.abs() > LIMIT || leading.abs() > LIMIT {
+            return roots;
+        }
+
+        let evaluate = |x: f64| coeffs.iter().rev().fold(0.0, |acc, c| acc * x + c);
+        let scale = coeffs.iter().fold(0.0_f64, |acc, c| acc.max(c.abs()));
+
+        for p in divisors(*constant) {
+            for q in divisors(*leading) {
+                for candidate in [p / q, -p / q] {
+                    let already_found = roots.iter().any(|r: &f64| (r - candidate).abs() < EPSILON);
+                    if !already_found && evaluate(candidate).abs() <= EPSILON * scale {
+                        roots.push(candidate);
+                    }
+                }
+            }
+        }
+
+        roots
+    }
+}
+
+/// Positive divisors of an integer.
+fn divisors(n: f64) -> Vec<f64> {
+    let n = n.abs();
+    let mut divisors = Vec::new();
+    let mut i = 1.0;
+    while i * i <= n {
+        if n % i == 0.0 {
+            divisors.push(i);
+            if i * i != n {
+                divisors.push(n / i);
+            }
+        }
+        i += 1.0;
+    }
+    divisors
+}
+
+/// Gcd of two numbers, falls back to 1 unless both are integers.
+pub(crate) fn numeric_gcd(a: f64, b: f64) -> f64 {
+    if a.fract() != 0.0 || b.fract() != 0.0 {
+        return 1.0;
+    }
+
+    let (mut a, mut b) = (a.abs(), b.abs());
+    while b != 0.0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cas::eval::testing::eval;
+
+    /// `x` and `y` as generators `0` and `1`.
+    fn xy() -> (Polynomial, Polynomial) {
+        let gens: Vec<RunVal> = vec![
+            RunType::Symbol("x".to_string()).into(),
+            RunType::Symbol("y".to_string()).into(),
+        ];
+        (
+            Polynomial::generator(0, &gens),
+            Polynomial::generator(1, &gens),
+        )
+    }
+
+    fn constant(n: f64) -> Polynomial {
+        let (x, _) = xy();
+        Polynomial::constant(n, x.gens())
+    }
+
+    fn same(a: &Polynomial, b: &Polynomial) -> bool {
+        a.sub(b).is_zero()
+    }
+
+    #[test]
+    fn division_with_remainder() {
+        let (x, _) = xy();
+        // x^3 + 2 = (x - 1)(x^2 + x + 1) + 3
+        let dividend = x.pow(3).add(&constant(2.0));
+        let divisor = x.sub(&constant(1.0));
+        let (quotient, remainder) = dividend.div_rem(&divisor, 0).unwrap();
+
+        assert!(same(&quotient, &x.pow(2).add(&x).add(&constant(1.0))));
+        assert!(same(&remainder, &constant(3.0)));
+    }
+
+    #[test]
+    fn exact_division() {
+        let (x, y) = xy();
+        let product = x.add(&y).mul(&x.sub(&y));
+        assert!(same(&product.exact_div(&x.add(&y)).unwrap(), &x.sub(&y)));
+        assert!(product.exact_div(&x.add(&constant(1.0))).is_none());
+    }
+
+    #[test]
+    fn gcd_and_lcm() {
+        let (x, y) = xy();
+        let a = x.pow(2).sub(&constant(1.0)).mul(&y);
+        let b = x.add(&constant(1.0)).mul(&y);
+        assert!(same(&a.gcd(&b), &b));
+
+        let lcm = x.sub(&constant(1.0)).lcm(&x.add(&constant(1.0)));
+        assert!(same(&lcm, &x.pow(2).sub(&constant(1.0))));
+    }
+
+    #[test]
+    fn resultant_and_discriminant() {
+        let (x, _) = xy();
+        // x^2 - 2 at the root of x - 1
+        let resultant = x
+            .pow(2)
+            .sub(&constant(2.0))
+            .resultant(&x.sub(&constant(1.0)), 0);
+        assert_eq!(resultant.as_constant(), Some(-1.0));
+
+        let quadratic = x.pow(2).add(&x.scale(3.0)).add(&constant(2.0));
+        assert_eq!(quadratic.discriminant(0).unwrap().as_constant(), Some(1.0));
+    }
+
+    #[test]
+    fn degrees_and_coefficients() {
+        let (x, y) = xy();
+        let poly = x.pow(2).mul(&y.pow(3)).add(&y);
+        assert_eq!(poly.degree(0), Some(2));
+        assert_eq!(poly.degree(1), Some(3));
+        assert_eq!(poly.total_degree(), Some(5));
+        assert_eq!(constant(0.0).degree(0), None);
+    }
+
+    #[test]
+    fn converts_from_and_to_values() {
+        let (x, y) = xy();
+        let poly = x.add(&y).pow(2);
+        let value = poly.to_value();
+        let back = Polynomial::from_value(&value).unwrap();
+        assert_eq!(back.total_degree(), Some(2));
+        assert_eq!(
+            Polynomial::from_value(&back.to_value()).unwrap().to_value(),
+            value
+        );
+    }
+
+    #[test]
+    fn rational_roots() {
+        let (x, _) = xy();
+        // (2x - 1)(x + 3)
+        let poly = x.scale(2.0).sub(&constant(1.0)).mul(&x.add(&constant(3.0)));
+        let mut roots = poly.rational_roots(0);
+        roots.sort_by(f64::total_cmp);
+        assert_eq!(roots, vec![-3.0, 0.5]);
+    }
+
+    #[test]
+    fn intrinsics() {
+        assert_eq!(eval("degree(x^3 + 2*x + 1, x)"), "3");
+        assert_eq!(eval("degree(0, x)"), "-inf");
+        assert_eq!(eval("coeffs(3*x^2 + 2*x + 1, x)"), "[3, 2, 1]");
+        assert_eq!(eval("quo(x^3 - 1, x - 1, x)"), "x^2 + x + 1");
+        assert_eq!(eval("rem(x^3 + 2, x - 1, x)"), "3");
+        assert_eq!(eval("divrem(x^2 + 3*x + 5, x + 1, x)"), "[x + 2, 3]");
+        assert_eq!(eval("gcd(x^2 - 1, x^2 + 2*x + 1)"), "x + 1");
+        assert_eq!(eval("lcm(4, 6)"), "12");
+        assert_eq!(eval("resultant(x^2 - 2, x - 1, x)"), "-1");
+        assert_eq!(eval("discriminant(a*x^2 + b*x + c, x)"), "b^2 - 4 * a * c");
+    }
+}
@@ -0,0 +1,393 @@
+use log::debug;
+
+use super::{
+    poly::Polynomial,
+    value::{Factors, RunType, RunVal, Terms},
+};
+
+macro_rules! rational_debug {
+    ($($arg:tt)+) => (debug!(target: "matex::rational", "[{}:{}] {}", file!(), line!(), &format!($($arg)+)));
+}
+
+/// Splits a value into numerator and denominator.
+///
+/// Division is encoded as `base^-n`, so every factor with a negative integer exponent
+/// ends up in the denominator. Sums are brought to a common denominator first.
+pub fn numer_denom(value: &RunVal) -> (RunVal, RunVal) {
+    use RunType::*;
+    match &value.typ {
+        Exponent(base, exp) => match exp.typ {
+            Number(n) if n < 0.0 => (
+                Number(1.0).into(),
+                RunType::Exponent(base.clone(), Box::new(Number(-n).into())).into(),
+            ),
+            _ => (value.clone(), Number(1.0).into()),
+        },
+        Product(Factors(factors)) => {
+            let mut numerators = Vec::new();
+            let mut denominators = Vec::new();
+
+            for factor in factors {
+                let (numerator, denominator) = numer_denom(factor);
+                numerators.push(numerator);
+                denominators.push(denominator);
+            }
+
+            (product(numerators), product(denominators))
+        }
+        Sum(_) => {
+            let together = together(value);
+            match &together.typ {
+                Product(_) | Exponent(_, _) => numer_denom(&together),
+                _ => (together, Number(1.0).into()),
+            }
+        }
+        _ => (value.clone(), Number(1.0).into()),
+    }
+}
+
+/// Brings the terms of a sum to a common denominator, e.g. `1/x + 1/y` => `(y + x)/(x * y)`.
+pub fn together(value: &RunVal) -> RunVal {
+    let RunType::Sum(Terms(terms)) = &value.typ else {
+        return value.clone();
+    };
+
+    let mut numerator: RunVal = RunType::Number(0.0).into();
+    let mut denominator: RunVal = RunType::Number(1.0).into();
+
+    for term in terms {
+        let term = together(term);
+        let (n, d) = numer_denom(&term);
+
+        let common = common_multiple(&denominator, &d);
+
+        let scaled = quotient(&common, &denominator).multiply(numerator);
+        let other_scaled = quotient(&common, &d).multiply(n);
+
+        numerator = scaled.add(other_scaled);
+        denominator = common;
+    }
+
+    // Expand the numerator when possible, the terms were multiplied with the cofactors
+    if let Some(poly) = Polynomial::from_value(&numerator) {
+        numerator = poly.to_value();
+    }
+
+    rational_debug!("together: ({:?}) / ({:?})", numerator, denominator);
+
+    fraction(numerator, denominator)
+}
+
+/// Cancels common factors of the numerator and denominator,
+/// e.g. `(x^2 - 1)/(x - 1)` => `x + 1`.
+pub fn cancel(value: &RunVal) -> RunVal {
+    let (numerator, denominator) = numer_denom(value);
+
+    match cancel_polynomials(&numerator, &denominator) {
+        Some((numerator, denominator)) => fraction(numerator.to_value(), denominator.to_value()),
+        None => value.clone(),
+    }
+}
+
+/// Used by the automatic simplification; only returns something if there was
+/// a non-constant common factor to cancel.
+pub(crate) fn cancel_product(value: &RunVal) -> Option<RunVal> {
+    let RunType::Product(Factors(factors)) = &value.typ else {
+        return None;
+    };
+
+    let is_denominator = |factor: &RunVal| match &factor.typ {
+        RunType::Exponent(_, exp) => {
+            matches!(exp.typ, RunType::Number(n) if n < 0.0 && n.fract() == 0.0)
+        }
+        _ => false,
+    };
+    let has_sum = |factor: &RunVal| match &factor.typ {
+        RunType::Exponent(base, _) => matches!(base.typ, RunType::Sum(_)),
+        typ => matches!(typ, RunType::Sum(_)),
+    };
+
+    // Monomials are already handled by combining like factors
+    if !factors.iter().any(is_denominator) || !factors.iter().any(has_sum) {
+        return None;
+    }
+
+    let (numerator, denominator) = numer_denom(value);
+    let polys = Polynomial::from_values(&[&numerator, &denominator])?;
+
+    let gcd = polys[0].gcd(&polys[1]);
+    if gcd.total_degree().unwrap_or(0) == 0 {
+        return None;
+    }
+
+    let (numerator, denominator) = cancel_polynomials(&numerator, &denominator)?;
+    rational_debug!("cancelled: ({:?}) / ({:?})", numerator, denominator);
+
+    Some(fraction(numerator.to_value(), denominator.to_value()))
+}
+
+fn cancel_polynomials(
+    numerator: &RunVal,
+    denominator: &RunVal,
+) -> Option<(Polynomial, Polynomial)> {
+    let polys = Polynomial::from_values(&[numerator, denominator])?;
+    let (numerator, denominator) = (&polys[0], &polys[1]);
+
+    if denominator.is_zero() {
+        return None;
+    }
+
+    let gcd = numerator.gcd(denominator);
+    let mut numerator = numerator.exact_div(&gcd)?;
+    let mut denominator = denominator.exact_div(&gcd)?;
+
+    if let Some(constant) = denominator.as_constant() {
+        numerator = numerator.scale(1.0 / constant);
+        denominator = Polynomial::constant(1.0, denominator.gens());
+    } else if denominator.leading_term().is_some_and(|(_, lc)| lc < 0.0) {
+        numerator = numerator.neg();
+        denominator = denominator.neg();
+    }
+
+    Some((numerator, denominator))
+}
+
+/// Partial fraction decomposition with respect to `var`,
+/// e.g. `1/(x^2 - 1)` => `0.5/(x - 1) - 0.5/(x + 1)`.
+///
+/// Only univariate rational functions are supported. The denominator is split into
+/// square-free parts and rational linear factors, any remaining factor is kept whole.
+pub fn apart(value: &RunVal, var: &RunVal) -> Option<RunVal> {
+    let (numerator, denominator) = numer_denom(value);
+    let polys = Polynomial::from_values(&[&numerator, &denominator, var])?;
+
+    if polys[0].gens().len() != 1 {
+        return None;
+    }
+    let var = polys[0].gen_index(var)?;
+    let gens = polys[0].gens().to_vec();
+
+    let (numerator, denominator) = cancel_polynomials(&polys[0].to_value(), &polys[1].to_value())?;
+    if denominator.degree(var).unwrap_or(0) == 0 {
+        return Some(numerator.to_value());
+    }
+
+    let (quotient, remainder) = numerator.div_rem(&denominator, var)?;
+
+    let lc = denominator.leading_coeff(var).as_constant()?;
+    let denominator = denominator.scale(1.0 / lc);
+    let remainder = remainder.scale(1.0 / lc);
+
+    let factors = linear_factors(&denominator, var);
+    rational_debug!("factors of denominator: {:?}", factors);
+
+    // Every factor q^k contributes A_1/q + ... + A_k/q^k, where deg(A_j) < deg(q).
+    // Multiplying with the denominator gives a linear system for the coefficients of all A_j.
+    let mut basis = Vec::new();
+    for (index, (factor, multiplicity)) in factors.iter().enumerate() {
+        let degree = factor.degree(var)?;
+        for power in 1..=*multiplicity {
+            let cofactor = denominator.exact_div(&factor.pow(power))?;
+            for t in 0..degree {
+                let column = cofactor.mul(&Polynomial::generator(var, &gens).pow(t));
+                basis.push((index, power, t, column));
+            }
+        }
+    }
+
+    let size = denominator.degree(var)? as usize;
+    if basis.len() != size {
+        return None;
+    }
+
+    let dense = |poly: &Polynomial| -> Option<Vec<f64>> {
+        let mut coeffs: Vec<f64> = poly
+            .coeffs(var)
+            .iter()
+            .map(|it| it.as_constant())
+            .collect::<Option<_>>()?;
+        coeffs.resize(size, 0.0);
+        Some(coeffs)
+    };
+
+    let columns = basis
+        .iter()
+        .map(|(_, _, _, column)| dense(column))
+        .collect::<Option<Vec<_>>>()?;
+    let rhs = dense(&remainder)?;
+
+    let solution = solve_linear(
+        (0..size)
+            .map(|row| columns.iter().map(|column| column[row]).collect())
+            .collect(),
+        rhs,
+    )?;
+
+    let mut terms = vec![quotient.to_value()];
+
+    for (index, (factor, multiplicity)) in factors.iter().enumerate() {
+        for power in 1..=*multiplicity {
+            let mut part = Polynomial::zero(&gens);
+            for ((i, p, t, _), coeff) in basis.iter().zip(&solution) {
+                if *i == index && *p == power {
+                    part = part.add(&Polynomial::generator(var, &gens).pow(*t).scale(*coeff));
+                }
+            }
+
+            if part.is_zero() {
+                continue;
+            }
+
+            let denominator = RunVal::new(RunType::Exponent(
+                Box::new(factor.to_value()),
+                Box::new(RunType::Number(power as f64).into()),
+            ));
+            terms.push(fraction(part.to_value(), denominator));
+        }
+    }
+
+    Some(RunType::Sum(Terms(terms)).into())
+}
+
+/// Splits a monic polynomial into coprime factors, extracting rational roots as linear factors.
+fn linear_factors(poly: &Polynomial, var: usize) -> Vec<(Polynomial, u32)> {
+    let gens = poly.gens();
+    let x = Polynomial::generator(var, gens);
+    let mut factors = Vec::new();
+
+    for (square_free, multiplicity) in poly.square_free(var) {
+        let mut rest = square_free;
+
+        for root in rest.rational_roots(var) {
+            let linear = x.sub(&Polynomial::constant(root, gens));
+            if let Some((quotient, _)) = rest.div_rem(&linear, var) {
+                rest = quotient;
+                factors.push((linear, multiplicity));
+            }
+        }
+
+        if rest.degree(var).unwrap_or(0) > 0 {
+            factors.push((rest.monic(var), multiplicity));
+        }
+    }
+
+    factors
+}
+
+/// Gaussian elimination with partial pivoting, `None` if the system is singular.
+fn solve_linear(mut matrix: Vec<Vec<f64>>, mut rhs: Vec<f64>) -> Option<Vec<f64>> {
+    const EPSILON: f64 = 1e-12;
+    let size = rhs.len();
+
+    for k in 0..size {
+        let pivot = (k..size).max_by(|&a, &b| matrix[a][k].abs().total_cmp(&matrix[b][k].abs()))?;
+        if matrix[pivot][k].abs() < EPSILON {
+            return None;
+        }
+        matrix.swap(k, pivot);
+        rhs.swap(k, pivot);
+
+        let pivot_row = matrix[k].clone();
+        for i in k + 1..size {
+            let factor = matrix[i][k] / pivot_row[k];
+            for (value, pivot) in matrix[i][k..].iter_mut().zip(&pivot_row[k..]) {
+                *value -= factor * pivot;
+            }
+            rhs[i] -= factor * rhs[k];
+        }
+    }
+
+    let mut solution = vec![0.0; size];
+    for k in (0..size).rev() {
+        let sum: f64 = (k + 1..size).map(|j| matrix[k][j] * solution[j]).sum();
+        let value = (rhs[k] - sum) / matrix[k][k];
+
+        // Hide rounding errors for solutions that should be integers
+        solution[k] = if (value - value.round()).abs() < 1e-9 {
+            value.round()
+        } else {
+            value
+        };
+    }
+
+    Some(solution)
+}
+
+/// Least common multiple of two denominators, the plain product if they aren't polynomials.
+fn common_multiple(a: &RunVal, b: &RunVal) -> RunVal {
+    match Polynomial::from_values(&[a, b]) {
+        Some(polys) => polys[0].lcm(&polys[1]).to_value(),
+        None => a.clone().multiply(b.clone()),
+    }
+}
+
+/// `a / b` for a `b` known to divide `a`.
+fn quotient(a: &RunVal, b: &RunVal) -> RunVal {
+    Polynomial::from_values(&[a, b])
+        .and_then(|polys| polys[0].exact_div(&polys[1]))
+        .map(|it| it.to_value())
+        .unwrap_or_else(|| fraction(a.clone(), b.clone()))
+}
+
+fn product(factors: Vec<RunVal>) -> RunVal {
+    let factors: Vec<RunVal> = factors
+        .into_iter()
+        .filter(|it| !matches!(it.typ, RunType::Number(n) if n == 1.0))
+        .collect();
+
+    match factors.len() {
+        0 => RunType::Number(1.0).into(),
+        1 => factors.into_iter().next().unwrap(),
+        _ => RunType::Product(Factors(factors)).into(),
+    }
+}
+
+/// Builds `numerator * denominator^-1`.
+pub fn fraction(numerator: RunVal, denominator: RunVal) -> RunVal {
+    match denominator.typ {
+        RunType::Number(1.0) => numerator,
+        RunType::Number(n) => numerator.multiply(RunType::Number(1.0 / n).into()),
+        _ => numerator.multiply(denominator.power(RunType::Number(-1.0).into())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cas::eval::testing::eval;
+
+    #[test]
+    fn division_cancels_by_default() {
+        assert_eq!(eval("(x^2 - 1)/(x - 1)"), "x + 1");
+        assert_eq!(eval("simplify (x*x)/(x*x)"), "1");
+    }
+
+    #[test]
+    fn cancel() {
+        assert_eq!(
+            eval("cancel((x^2 + 2*x + 1)/(x^2 - 1))"),
+            "(x - 1)^-1 * (x + 1)"
+        );
+        assert_eq!(eval("cancel((x*y + y)/(x + 1))"), "y");
+        assert_eq!(eval("cancel(x/y)"), "x * y^-1");
+    }
+
+    #[test]
+    fn together() {
+        assert_eq!(
+            eval("together(1/x + 1/(x + 1))"),
+            "(2 * x + 1) * (x^2 + x)^-1"
+        );
+        assert_eq!(eval("together(a/b + c/d)"), "(b * d)^-1 * (b * c + a * d)");
+    }
+
+    #[test]
+    fn apart() {
+        assert_eq!(
+            eval("apart(1/(x^2 - 1))"),
+            "0.5 * (x - 1)^-1 - 0.5 * (x + 1)^-1"
+        );
+        assert_eq!(eval("apart(x^2/(x - 1), x)"), "x + 1 + (x - 1)^-1");
+        // Repeated factors give every power
+        assert_eq!(eval("apart(1/(x^2*(x+1)), x)"), "(x + 1)^-1 - x^-1 + x^-2");
+    }
+}
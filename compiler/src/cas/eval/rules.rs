@@ -0,0 +1,661 @@
+//! Rewrite rules, the steps of the simplifier as well as rules defined by the user.
+//!
+//! Every rule has a name, and can be disabled, enabled again or applied on its own with
+//! `rewrite(expr, name)`. Rules are either built into the simplifier, or patterns like
+//! `ln(a * b) -> ln(a) + ln(b)`, where every symbol of the pattern is a pattern variable.
+//!
+//! Sums and products in patterns match regardless of the order of the operands. The last
+//! pattern variable of a nested sum (or product) takes every operand that is left, and a
+//! pattern sum matches part of a larger sum, e.g. `sin(x)^2 + cos(x)^2` in `sin(x)^2 + cos(x)^2 + 1`.
+//!
+//! The rules belong to the environment, like the assumptions, and are in effect while it runs
+//! a program (see [`using`]). Changes made inside a `with`, e.g. `disable(cancel)`, last until
+//! its end.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::BTreeMap,
+    rc::Rc,
+};
+
+use log::debug;
+
+use super::{
+    functions, integers, intern, rational, trace,
+    value::{Factors, RunType, RunVal, Terms},
+};
+
+macro_rules! rules_debug {
+    ($($arg:tt)+) => (debug!(target: "matex::rules", "[{}:{}] {}", file!(), line!(), &format!($($arg)+)));
+}
+
+/// `x + x` => `2 * x`
+pub(crate) const COLLECT_TERMS: &str = "collect_terms";
+/// `x * x` => `x^2`
+pub(crate) const COLLECT_FACTORS: &str = "collect_factors";
+/// `(x^a)^b` => `x^(a * b)`
+pub(crate) const MERGE_POWERS: &str = "merge_powers";
+/// `2^3` => `8`, `x^1` => `x`, `x^0` => `1`
+pub(crate) const EVALUATE_POWERS: &str = "evaluate_powers";
+/// `sin(0.5)` => `0.479425538604203`
+pub(crate) const EVALUATE_FUNCTIONS: &str = "evaluate_functions";
+/// `sin(-x)` => `-sin(x)`, `exp(ln(x))` => `x`
+pub(crate) const FUNCTION_IDENTITIES: &str = "function_identities";
+/// `sin(x)^2 + cos(x)^2` => `1`
+pub(crate) const PYTHAGOREAN: &str = "pythagorean";
+/// `n! / (n - 1)!` => `n`
+pub(crate) const CANCEL_FACTORIALS: &str = "cancel_factorials";
+/// `(x^2 - 1) / (x - 1)` => `x + 1`
+pub(crate) const CANCEL: &str = "cancel";
+/// `if 1 > 0 then x else y` => `x`
+pub(crate) const DECIDE_PIECES: &str = "decide_pieces";
+
+/// How many rules may rewrite the results of other rules in a row,
+/// which stops rules that undo each other from going on forever.
+const MAX_DEPTH: usize = 64;
+
+#[derive(Clone, Debug)]
+pub enum Rewrite {
+    /// A step of the simplifier
+    Builtin,
+    Pattern {
+        pattern: RunVal,
+        replacement: RunVal,
+        /// Has to simplify to `true` for the bound pattern variables, as does every condition
+        /// of a vector of conditions
+        condition: Option<RunVal>,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct Rule {
+    pub name: String,
+    pub rewrite: Rewrite,
+    pub enabled: bool,
+}
+
+/// The values bound to pattern variables.
+pub type Bindings = BTreeMap<String, RunVal>;
+
+/// The rules of an environment, starting with the default ones.
+#[derive(Clone)]
+pub struct Rules {
+    /// Shared, so that rules can be applied without holding on to the table
+    rules: Rc<Vec<Rule>>,
+    /// Rules defined without a name so far
+    unnamed: usize,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self {
+            rules: Rc::new(default_rules()),
+            unnamed: 0,
+        }
+    }
+}
+
+thread_local! {
+    /// The rules in effect, those of the environment running a program
+    static RULES: RefCell<Rules> = RefCell::new(Rules::default());
+    /// How many rules are being applied to the results of other rules
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Runs `f` with the rules in effect, and returns the rules as `f` left them.
+///
+/// The rules in effect before are restored afterwards.
+pub(crate) fn using<T>(rules: Rules, f: impl FnOnce() -> T) -> (T, Rules) {
+    let outer = install(rules);
+    let result = f();
+    let rules = install(outer);
+    (result, rules)
+}
+
+/// Runs `f`, undoing any change it makes to the rules.
+pub(crate) fn scoped<T>(f: impl FnOnce() -> T) -> T {
+    let rules = RULES.with(|rules| rules.borrow().clone());
+    using(rules, f).0
+}
+
+/// Puts the rules in effect, and returns the ones that were.
+fn install(rules: Rules) -> Rules {
+    let previous = RULES.with(|it| it.replace(rules));
+    let changed = RULES.with(|it| !Rc::ptr_eq(&it.borrow().rules, &previous.rules));
+    if changed {
+        intern::forget_simplifications();
+    }
+    previous
+}
+
+/// The steps of the simplifier, followed by the identities of logarithms as patterns. These only
+/// hold for positive arguments, e.g. `ln((-1) * (-1))` isn't `ln(-1) + ln(-1)`.
+fn default_rules() -> Vec<Rule> {
+    let builtin = |name: &str| Rule {
+        name: name.to_string(),
+        rewrite: Rewrite::Builtin,
+        enabled: true,
+    };
+    let pattern =
+        |name: &str, pattern: RunVal, replacement: RunVal, condition: Option<RunVal>| Rule {
+            name: name.to_string(),
+            rewrite: Rewrite::Pattern {
+                pattern,
+                replacement,
+                condition,
+            },
+            enabled: true,
+        };
+
+    let a: RunVal = RunType::Symbol("a".to_string()).into();
+    let b: RunVal = RunType::Symbol("b".to_string()).into();
+    let zero = || -> RunVal { RunType::Number(0.0).into() };
+    let positive = |value: &RunVal| value.clone().greater(zero());
+    let ln = |value: &RunVal| functions::call("ln", value);
+    let even = |value: &RunVal| {
+        let remainder = vec![value.clone(), RunType::Number(2.0).into()];
+        RunVal::from(RunType::Function("mod".to_string(), remainder)).equal(zero())
+    };
+    let product = |values: Vec<RunVal>| -> RunVal { RunType::Product(Factors(values)).into() };
+    let power = |base: &RunVal, exp: &RunVal| -> RunVal {
+        RunType::Exponent(Box::new(base.clone()), Box::new(exp.clone())).into()
+    };
+
+    vec![
+        builtin(COLLECT_TERMS),
+        builtin(COLLECT_FACTORS),
+        builtin(MERGE_POWERS),
+        builtin(EVALUATE_POWERS),
+        builtin(EVALUATE_FUNCTIONS),
+        builtin(FUNCTION_IDENTITIES),
+        builtin(PYTHAGOREAN),
+        builtin(CANCEL_FACTORIALS),
+        builtin(CANCEL),
+        builtin(DECIDE_PIECES),
+        pattern(
+            "log_product",
+            ln(&product(vec![a.clone(), b.clone()])),
+            RunType::Sum(Terms(vec![ln(&a), ln(&b)])).into(),
+            Some(RunType::Vector(vec![positive(&a), positive(&b)]).into()),
+        ),
+        pattern(
+            "log_power",
+            ln(&power(&a, &b)),
+            product(vec![b.clone(), ln(&a)]),
+            Some(positive(&a)),
+        ),
+        // ln(x^2) => 2 * ln(abs(x))
+        pattern(
+            "log_even_power",
+            ln(&power(&a, &b)),
+            product(vec![b.clone(), ln(&functions::call("abs", &a))]),
+            Some(even(&b)),
+        ),
+        pattern(
+            "exp_log",
+            functions::call("exp", &product(vec![b.clone(), ln(&a)])),
+            power(&a, &b),
+            None,
+        ),
+    ]
+}
+
+/// Whether the rule with the given name is enabled, e.g. a step of the simplifier.
+pub(crate) fn is_enabled(name: &str) -> bool {
+    RULES.with(|rules| {
+        rules
+            .borrow()
+            .rules
+            .iter()
+            .any(|rule| rule.enabled && rule.name == name)
+    })
+}
+
+/// Adds a pattern rule, replacing the rule with the same name. Returns the name of the rule.
+pub fn define(
+    name: Option<String>,
+    pattern: RunVal,
+    replacement: RunVal,
+    condition: Option<RunVal>,
+) -> String {
+    let name = RULES.with(|rules| {
+        let mut rules = rules.borrow_mut();
+        let name = name.unwrap_or_else(|| {
+            rules.unnamed += 1;
+            format!("rule{}", rules.unnamed)
+        });
+
+        let rule = Rule {
+            name: name.clone(),
+            rewrite: Rewrite::Pattern {
+                pattern,
+                replacement,
+                condition,
+            },
+            enabled: true,
+        };
+        rules_debug!("define rule: {:?}", rule);
+
+        let table = Rc::make_mut(&mut rules.rules);
+        match table.iter_mut().find(|it| it.name == name) {
+            Some(existing) => *existing = rule,
+            None => table.push(rule),
+        }
+        name
+    });
+
+    intern::forget_simplifications();
+    name
+}
+
+/// Enables or disables a rule. Returns whether there is a rule with the given name.
+pub fn set_enabled(name: &str, enabled: bool) -> bool {
+    let found = RULES.with(|rules| {
+        let mut rules = rules.borrow_mut();
+        let rule = Rc::make_mut(&mut rules.rules)
+            .iter_mut()
+            .find(|rule| rule.name == name);
+        match rule {
+            Some(rule) => {
+                rule.enabled = enabled;
+                true
+            }
+            None => false,
+        }
+    });
+
+    if found {
+        rules_debug!("{} is enabled: {}", name, enabled);
+        intern::forget_simplifications();
+    }
+    found
+}
+
+/// The names of every rule, in the order they are tried.
+pub fn names() -> Vec<String> {
+    RULES.with(|rules| {
+        rules
+            .borrow()
+            .rules
+            .iter()
+            .map(|rule| rule.name.clone())
+            .collect()
+    })
+}
+
+/// Rewrites a value with the first enabled pattern rule that matches it, and simplifies the result.
+///
+/// The operands of the value have to be simplified already.
+pub(crate) fn apply_patterns(value: &RunVal) -> Option<RunVal> {
+    let rules = RULES.with(|rules| rules.borrow().rules.clone());
+
+    let mut rewritten =
+        rules
+            .iter()
+            .filter(|rule| rule.enabled)
+            .find_map(|rule| match &rule.rewrite {
+                Rewrite::Pattern {
+                    pattern,
+                    replacement,
+                    condition,
+                } => {
+                    let rewritten = trace::untraced(|| {
+                        apply_pattern(pattern, replacement, condition.as_ref(), value)
+                    })?;
+                    rules_debug!("{}: {:?} => {:?}", rule.name, value, rewritten);
+                    trace::step(&rule.name, value, &rewritten);
+                    Some(rewritten)
+                }
+                Rewrite::Builtin => None,
+            })?;
+
+    let depth = DEPTH.with(Cell::get);
+    if depth >= MAX_DEPTH {
+        rules_debug!(
+            "rules rewrote too many times in a row, stopping at {:?}",
+            value
+        );
+        return None;
+    }
+
+    DEPTH.with(|it| it.set(depth + 1));
+    rewritten.simplify();
+    DEPTH.with(|it| it.set(depth));
+
+    Some(rewritten)
+}
+
+/// Applies each of the named rules once to every part of the value, innermost parts first.
+///
+/// Returns `None` if a rule doesn't exist.
+pub fn rewrite(value: &RunVal, names: &[String]) -> Option<RunVal> {
+    let rules = RULES.with(|rules| rules.borrow().rules.clone());
+
+    let mut value = value.clone();
+    for name in names {
+        let rule = rules.iter().find(|rule| &rule.name == name)?;
+        value = rewrite_everywhere(rule, &value);
+    }
+    Some(value)
+}
+
+fn rewrite_everywhere(rule: &Rule, value: &RunVal) -> RunVal {
+    let value = map_operands(value, &|operand| rewrite_everywhere(rule, operand));
+
+    let rewritten = match &rule.rewrite {
+        Rewrite::Builtin => apply_builtin(&rule.name, &value),
+        Rewrite::Pattern {
+            pattern,
+            replacement,
+            condition,
+        } => apply_pattern(pattern, replacement, condition.as_ref(), &value),
+    };
+
+    match rewritten {
+        Some(rewritten) => {
+            trace::step(&rule.name, &value, &rewritten);
+            rewritten
+        }
+        None => value,
+    }
+}
+
+/// A single step of the simplifier on the outermost part of the value.
+fn apply_builtin(name: &str, value: &RunVal) -> Option<RunVal> {
+    use RunType::*;
+
+    let rewritten: RunVal = match (name, &value.typ) {
+        (COLLECT_TERMS, Sum(terms)) => {
+            let mut terms = terms.clone();
+            RunVal::combine_like_terms(&mut terms);
+            RunVal::combine_integers(&mut terms);
+            Sum(terms).into()
+        }
+        (COLLECT_FACTORS, Product(factors)) => {
+            let mut factors = factors.clone();
+            RunVal::combine_like_factors(&mut factors);
+            Product(factors).into()
+        }
+        (MERGE_POWERS, Exponent(base, exp)) => {
+            let Exponent(inner_base, inner_exp) = &base.typ else {
+                return None;
+            };
+            let exp = (**inner_exp).clone().multiply((**exp).clone());
+            Exponent(inner_base.clone(), Box::new(exp)).into()
+        }
+        (EVALUATE_POWERS, Exponent(base, exp)) => RunVal::reduce_power(base, exp)?,
+        (EVALUATE_FUNCTIONS, Function(name, args)) => functions::evaluate(name, args)?,
+        (FUNCTION_IDENTITIES, Function(name, args)) => functions::rewrite(name, args)?,
+        (PYTHAGOREAN, Sum(terms)) => {
+            let mut terms = terms.clone();
+            if !functions::pythagorean_identity(&mut terms) {
+                return None;
+            }
+            Sum(terms).into()
+        }
+        (CANCEL_FACTORIALS, Product(factors)) => {
+            let mut factors = factors.clone();
+            if !integers::cancel_factorials(&mut factors) {
+                return None;
+            }
+            Product(factors).into()
+        }
+        (CANCEL, _) => rational::cancel_product(value)?,
+        (DECIDE_PIECES, Piecewise(pieces)) => RunVal::reduce_pieces(pieces)?,
+        _ => return None,
+    };
+
+    (rewritten != *value).then_some(rewritten)
+}
+
+fn apply_pattern(
+    pattern: &RunVal,
+    replacement: &RunVal,
+    condition: Option<&RunVal>,
+    value: &RunVal,
+) -> Option<RunVal> {
+    use RunType::*;
+
+    let empty = Bindings::new();
+    let matches = match (&pattern.typ, &value.typ) {
+        (Sum(Terms(patterns)), Sum(Terms(values))) => {
+            match_operands(&variables_last(patterns), values, &empty, sum, true)
+        }
+        (Product(Factors(patterns)), Product(Factors(values))) => {
+            match_operands(&variables_last(patterns), values, &empty, product, true)
+        }
+        _ => match_pattern(pattern, value, &empty)
+            .into_iter()
+            .map(|bindings| (bindings, Vec::new()))
+            .collect(),
+    };
+
+    let (bindings, mut rest) = matches.into_iter().find(|(bindings, _)| match condition {
+        Some(condition) => holds(condition, bindings),
+        None => true,
+    })?;
+
+    let replaced = instantiate(replacement, &bindings);
+    if rest.is_empty() {
+        return Some(replaced);
+    }
+
+    // The operands the pattern didn't match stay as they are
+    rest.push(replaced);
+    Some(
+        match pattern.typ {
+            Product(_) => product(rest),
+            _ => sum(rest),
+        }
+        .into(),
+    )
+}
+
+/// Every way the pattern matches the value, extending the given bindings.
+fn match_pattern(pattern: &RunVal, value: &RunVal, bindings: &Bindings) -> Vec<Bindings> {
+    use RunType::*;
+
+    match (&pattern.typ, &value.typ) {
+        (Symbol(name), _) => match bindings.get(name) {
+            Some(bound) if bound == value => vec![bindings.clone()],
+            Some(_) => Vec::new(),
+            None => {
+                let mut bindings = bindings.clone();
+                bindings.insert(name.clone(), value.clone());
+                vec![bindings]
+            }
+        },
+        (Sum(Terms(patterns)), Sum(Terms(values))) => {
+            match_operands(&variables_last(patterns), values, bindings, sum, false)
+                .into_iter()
+                .map(|(bindings, _)| bindings)
+                .collect()
+        }
+        (Product(Factors(patterns)), Product(Factors(values))) => {
+            match_operands(&variables_last(patterns), values, bindings, product, false)
+                .into_iter()
+                .map(|(bindings, _)| bindings)
+                .collect()
+        }
+        (Exponent(base, exp), Exponent(value_base, value_exp)) => {
+            match_pattern(base, value_base, bindings)
+                .iter()
+                .flat_map(|bindings| match_pattern(exp, value_exp, bindings))
+                .collect()
+        }
+        (Function(name, args), Function(value_name, value_args))
+            if name == value_name && args.len() == value_args.len() =>
+        {
+            args.iter()
+                .zip(value_args)
+                .fold(vec![bindings.clone()], |matches, (arg, value_arg)| {
+                    matches
+                        .iter()
+                        .flat_map(|bindings| match_pattern(arg, value_arg, bindings))
+                        .collect()
+                })
+        }
+        (Vector(patterns), Vector(values)) if patterns.len() == values.len() => patterns
+            .iter()
+            .zip(values)
+            .fold(vec![bindings.clone()], |matches, (pattern, value)| {
+                matches
+                    .iter()
+                    .flat_map(|bindings| match_pattern(pattern, value, bindings))
+                    .collect()
+            }),
+        _ if pattern == value => vec![bindings.clone()],
+        _ => Vec::new(),
+    }
+}
+
+/// Every way the operands of a sum (or product) match the operand patterns in any order,
+/// together with the operands that are left.
+///
+/// Unless `partial`, every operand has to be matched, and the last pattern variable takes
+/// every operand that is left, combined with `combine`.
+fn match_operands(
+    patterns: &[RunVal],
+    values: &[RunVal],
+    bindings: &Bindings,
+    combine: fn(Vec<RunVal>) -> RunType,
+    partial: bool,
+) -> Vec<(Bindings, Vec<RunVal>)> {
+    let Some((first, others)) = patterns.split_first() else {
+        if partial || values.is_empty() {
+            return vec![(bindings.clone(), values.to_vec())];
+        }
+        return Vec::new();
+    };
+
+    let unbound = matches!(&first.typ, RunType::Symbol(name) if !bindings.contains_key(name));
+    if others.is_empty() && !partial && unbound && values.len() > 1 {
+        let mut rest: RunVal = combine(values.to_vec()).into();
+        rest.simplify();
+        return match_pattern(first, &rest, bindings)
+            .into_iter()
+            .map(|bindings| (bindings, Vec::new()))
+            .collect();
+    }
+
+    let mut matches = Vec::new();
+    for (index, value) in values.iter().enumerate() {
+        let mut remaining = values.to_vec();
+        remaining.remove(index);
+
+        for bindings in match_pattern(first, value, bindings) {
+            matches.extend(match_operands(
+                others, &remaining, &bindings, combine, partial,
+            ));
+        }
+    }
+    matches
+}
+
+/// Pattern variables match anything, so they are matched after the other operands.
+fn variables_last(patterns: &[RunVal]) -> Vec<RunVal> {
+    let mut patterns = patterns.to_vec();
+    patterns.sort_by_key(|pattern| matches!(pattern.typ, RunType::Symbol(_)));
+    patterns
+}
+
+/// Whether the condition simplifies to `true` for the bound pattern variables, or every condition
+/// of a vector of conditions does.
+fn holds(condition: &RunVal, bindings: &Bindings) -> bool {
+    if let RunType::Vector(conditions) = &condition.typ {
+        return conditions.iter().all(|it| holds(it, bindings));
+    }
+    let mut condition = instantiate(condition, bindings);
+    condition.simplify();
+    matches!(condition.typ, RunType::Bool(true))
+}
+
+/// Replaces the pattern variables in the value with what they are bound to.
+fn instantiate(value: &RunVal, bindings: &Bindings) -> RunVal {
+    match &value.typ {
+        RunType::Symbol(name) => bindings.get(name).cloned().unwrap_or_else(|| value.clone()),
+        _ => map_operands(value, &|operand| instantiate(operand, bindings)),
+    }
+}
+
+/// The value with `f` applied to each of its operands, the value itself if it has none.
+fn map_operands(value: &RunVal, f: &dyn Fn(&RunVal) -> RunVal) -> RunVal {
+    use RunType::*;
+    let map_all = |values: &Vec<RunVal>| values.iter().map(f).collect::<Vec<_>>();
+
+    let typ = match &value.typ {
+        Sum(Terms(terms)) => Sum(Terms(map_all(terms))),
+        Product(Factors(factors)) => Product(Factors(map_all(factors))),
+        Vector(values) => Vector(map_all(values)),
+        Function(name, args) => Function(name.clone(), map_all(args)),
+        Exponent(base, exp) => Exponent(Box::new(f(base)), Box::new(f(exp))),
+        Piecewise(pieces) => Piecewise(pieces.map(f)),
+        Set(set) => Set(set.map(f)),
+        Unit
+        | Undefined
+        | Number(_)
+        | Interval(_)
+        | PhysicalUnit(_)
+        | Symbol(_)
+        | Constant(_)
+        | Bool(_) => {
+            return value.clone()
+        }
+    };
+    RunVal::new(typ)
+}
+
+fn sum(terms: Vec<RunVal>) -> RunType {
+    RunType::Sum(Terms(terms))
+}
+
+fn product(factors: Vec<RunVal>) -> RunType {
+    RunType::Product(Factors(factors))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cas::eval::testing::{eval, Session};
+
+    #[test]
+    fn patterns() {
+        assert_eq!(eval("rule f(a*b) -> f(a) + f(b)\nf(x*y*z)"), "f(x) + f(y) + f(z)");
+        assert_eq!(eval("rule h(x + y) -> h(x) * h(y)\nh(a + b)"), "h(a) * h(b)");
+        assert_eq!(eval("rule g(x) -> x where x > 0\n[g(3), g(-3)]"), "[3, g(-3)]");
+        assert_eq!(eval("rule inv: q(q(x)) -> x\nq(q(q(y)))"), "q(y)");
+    }
+
+    #[test]
+    fn logarithm_rules_need_positive_arguments() {
+        assert_eq!(eval("ln(2*3)"), eval("ln(2) + ln(3)"));
+        assert_eq!(eval("ln(x^2)"), "2 * ln(abs(x))");
+        assert_eq!(eval("exp(2*ln(x))"), "x^2");
+    }
+
+    #[test]
+    fn rewriting_with_disabled_rules() {
+        assert_eq!(eval("disable(collect_terms)\nx + x"), "x + x");
+        assert_eq!(eval("disable(collect_terms)\nrewrite(x + x, collect_terms)"), "2 * x");
+        assert!(eval("rule loop: w(x) -> w(x + 1)\nw(1)").starts_with("w("));
+    }
+
+    #[test]
+    fn rules_belong_to_the_environment() {
+        let mut session = Session::new();
+        session.eval("disable(collect_terms)");
+        session.eval("rule f(a) -> 0");
+        assert_eq!(session.eval("x + x"), "x + x");
+        assert_eq!(session.eval("f(1)"), "0");
+
+        let mut other = Session::new();
+        assert_eq!(other.eval("x + x"), "2 * x");
+        assert_eq!(other.eval("f(1)"), "f(1)");
+    }
+
+    #[test]
+    fn rules_changed_inside_with_are_restored() {
+        let mut session = Session::new();
+        session.eval("with assume(y > 0) { disable(collect_terms) }");
+        assert_eq!(session.eval("x + x"), "2 * x");
+    }
+}
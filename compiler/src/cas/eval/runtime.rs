@@ -1,13 +1,27 @@
-use matex_common::function::{Function, Parameter};
+use matex_common::error::RuntimeError;
+use matex_common::function::Function;
 use matex_common::node::{BinOp, Expr, Program, Statement};
+use matex_common::rule::Rule;
+use matex_common::types::TypeAlias;
+use matex_common::util::Position;
 
 use matex_common::node::Visitor;
 
 use log::{debug, error};
 
-use crate::cas::eval::value::{Factors, Terms};
+use crate::cas::eval::value::{Factors, Pieces, Terms};
 
+use crate::cas::types::{self, Union};
+
+use super::assumptions::{self, Fact};
+use super::functions;
+use super::dispatch::{self, Dispatch};
 use super::environment::{Environment, Scope};
+use super::format::{NormalFormatter, ValueFormatter};
+use super::rules;
+use super::sets::{Numbers, Set};
+use super::trace::{self, Step};
+use super::units::{self, PhysicalUnit};
 use super::value::{RunType, RunVal};
 
 macro_rules! runtime_debug {
@@ -17,15 +31,44 @@ macro_rules! runtime_debug {
 macro_rules! runtime_error {
     ($($arg:tt)+) => (error!(target: "matex::runtime", "[{}:{}] {}", file!(), line!(), &format!($($arg)+)));
 }
+
+pub type RuntimeResult<T> = Result<T, RuntimeError>;
+
+/// How deeply user defined functions may call each other, e.g. `f(x) = f(x + 1)` stops here
+/// instead of overflowing the stack.
+const MAX_CALL_DEPTH: usize = 100;
+
 pub struct Runtime {
     pub environment: Environment,
     assign: bool,
     in_func_call: bool,
+    /// Whether assumptions only last until the end of the `with` around them
+    in_with: bool,
+    /// How many `if`s with a symbolic condition are being evaluated
+    symbolic_branches: usize,
+    /// The user defined functions being evaluated, innermost last
+    calls: Vec<String>,
 }
 
 impl Runtime {
-    pub fn run(&mut self, program: &Program) -> RunVal {
-        self.visit_program(program)
+    pub fn run(&mut self, program: &Program) -> RuntimeResult<RunVal> {
+        let facts = self.environment.assumptions.clone();
+        let rules = self.environment.rules.clone();
+        let (value, rules) =
+            rules::using(rules, || assumptions::assuming(facts, || self.visit_program(program)));
+        self.environment.rules = rules;
+        value
+    }
+
+    /// Runs the program, and returns the steps taken to get to the result.
+    pub fn run_traced(&mut self, program: &Program) -> RuntimeResult<(RunVal, Vec<Step>)> {
+        let facts = self.environment.assumptions.clone();
+        let rules = self.environment.rules.clone();
+        let ((value, steps), rules) = rules::using(rules, || {
+            assumptions::assuming(facts, || trace::record(|| self.visit_program(program)))
+        });
+        self.environment.rules = rules;
+        Ok((value?, steps))
     }
 
     pub fn new() -> Self {
@@ -36,58 +79,176 @@ impl Runtime {
             },
             assign: false,
             in_func_call: false,
+            in_with: false,
+            symbolic_branches: 0,
+            calls: Vec::new(),
         }
     }
 }
 
 impl Runtime {
-    fn visit_program(&mut self, Program(statements): &Program) -> RunVal {
+    fn visit_program(&mut self, Program(statements): &Program) -> RuntimeResult<RunVal> {
         let mut value = RunType::Number(-1.0).into();
         for statement in statements {
-            value = self.visit_statement(statement);
+            value = self.visit_statement(statement)?;
         }
 
-        value
+        Ok(value)
     }
 
-    fn visit_function(
-        &mut self,
-        func_name: &str,
-        params: &Vec<Parameter>,
-        function_body: &Expr,
-    ) -> RunVal {
+    fn visit_function(&mut self, function: &Function) -> RuntimeResult<RunVal> {
         runtime_debug!("Visit function declaration");
-        runtime_debug!("func_name: {}", func_name);
-        runtime_debug!("params: {:?}", params);
-        runtime_debug!("body: {:?}", function_body);
-
-        let function = Function {
-            name: func_name.to_owned(),
-            params: params.clone(),
-            body: function_body.clone(),
-        };
+        runtime_debug!("func_name: {}", function.name);
+        runtime_debug!("params: {:?}", function.params);
+        runtime_debug!("return_type: {:?}", function.return_type);
+        runtime_debug!("domain: {:?}", function.domain);
+        runtime_debug!("body: {:?}", function.body);
 
-        self.environment.set_func(func_name, function);
+        self.environment.set_func(&function.name, function.clone());
 
-        RunType::Unit.into()
+        Ok(RunType::Unit.into())
     }
 
-    fn visit_unset_variable(&mut self, name: &String) -> RunVal {
+    fn visit_unset_variable(&mut self, name: &String) -> RuntimeResult<RunVal> {
         runtime_debug!("Visit unset variable");
         runtime_debug!("name: {}", name);
 
         self.environment.remove_variable(name);
+        if self.environment.scopes.len() == 1 {
+            self.environment.dependencies.remove(name);
+        }
 
-        RunType::Unit.into()
+        Ok(RunType::Unit.into())
     }
 
-    fn visit_vector(&mut self, vec: &Vec<Expr>) -> RunVal {
+    fn visit_type_definition(&mut self, alias: &TypeAlias) -> RuntimeResult<RunVal> {
+        runtime_debug!("Visit type definition");
+        runtime_debug!("alias: {:?}", alias);
+
+        let union = Union::resolve(&alias.type_name, &self.environment.aliases).map_err(|name| {
+            RuntimeError::UnknownType {
+                message: format!("Unknown type {} in the definition of {}", name, alias.name),
+                name,
+                pos: alias.pos,
+            }
+        })?;
+        self.environment.aliases.insert(alias.name.clone(), union);
+
+        Ok(RunType::Unit.into())
+    }
+
+    fn visit_rule_definition(&mut self, rule: &Rule) -> RuntimeResult<RunVal> {
+        runtime_debug!("Visit rule definition");
+        runtime_debug!("rule: {:?}", rule);
+
+        // Every symbol of the rule stands for itself, like on the right side of an assignment
+        self.assign = true;
+        let parts = (|| {
+            let pattern = self.visit_expr(&rule.pattern)?;
+            let replacement = self.visit_expr(&rule.replacement)?;
+            let condition = rule.condition.as_ref().map(|it| self.visit_expr(it)).transpose()?;
+            Ok((pattern, replacement, condition))
+        })();
+        self.assign = false;
+        let (pattern, replacement, condition) = parts?;
+
+        let name = rules::define(rule.name.clone(), pattern, replacement, condition);
+
+        Ok(RunType::Symbol(name).into())
+    }
+
+    fn visit_vector(&mut self, vec: &Vec<Expr>) -> RuntimeResult<RunVal> {
         runtime_debug!("Visit vector");
         runtime_debug!("vec: {:?}", vec);
 
-        let values = vec.iter().map(|it| self.visit_expr(it)).collect();
+        let values = vec.iter().map(|it| self.visit_expr(it)).collect::<RuntimeResult<_>>()?;
         let value = RunVal::new(RunType::Vector(values));
 
+        Ok(value)
+    }
+
+    fn visit_set(&mut self, elements: &[Expr]) -> RuntimeResult<RunVal> {
+        runtime_debug!("Visit set");
+        runtime_debug!("elements: {:?}", elements);
+
+        let values = elements.iter().map(|it| self.visit_expr(it)).collect::<RuntimeResult<_>>()?;
+
+        Ok(RunType::Set(Set::Finite(values)).into())
+    }
+
+    fn visit_interval(
+        &mut self,
+        lower: &Expr,
+        upper: &Expr,
+        lower_closed: bool,
+        upper_closed: bool,
+    ) -> RuntimeResult<RunVal> {
+        runtime_debug!("Visit interval");
+        runtime_debug!("lower: {:?}", lower);
+        runtime_debug!("upper: {:?}", upper);
+
+        let set = Set::Interval {
+            lower: Box::new(self.visit_expr(lower)?),
+            upper: Box::new(self.visit_expr(upper)?),
+            lower_closed,
+            upper_closed,
+        };
+
+        Ok(RunType::Set(set).into())
+    }
+
+    fn visit_set_builder(
+        &mut self,
+        variable: &str,
+        domain: Option<&Expr>,
+        condition: &Expr,
+    ) -> RuntimeResult<RunVal> {
+        runtime_debug!("Visit set builder");
+        runtime_debug!("variable: {}", variable);
+        runtime_debug!("domain: {:?}", domain);
+        runtime_debug!("condition: {:?}", condition);
+
+        let domain = match domain {
+            Some(domain) => self.visit_domain(domain)?,
+            None => RunType::Set(Set::Numbers(Numbers::Reals)).into(),
+        };
+        // The bound variable is a symbol in the condition, even if it is assigned
+        let condition = self.visit_unbound(&[variable], condition)?;
+
+        let set = Set::Builder {
+            variable: variable.to_string(),
+            domain: Box::new(domain),
+            condition: Box::new(condition),
+        };
+
+        Ok(RunType::Set(set).into())
+    }
+
+    /// The right-hand side of `in`, where `N`, `Z`, `Q` and `R` are the sets of numbers unless
+    /// they are variables.
+    fn visit_domain(&mut self, expr: &Expr) -> RuntimeResult<RunVal> {
+        if let Expr::Variable(name) = expr {
+            if let (Some(numbers), None) = (Numbers::from_name(name), self.environment.get_variable(name)) {
+                return Ok(RunType::Set(Set::Numbers(numbers)).into());
+            }
+        }
+        self.visit_expr(expr)
+    }
+
+    /// Visits the expression with the variables unassigned, so they stay symbols.
+    fn visit_unbound(&mut self, names: &[&str], expr: &Expr) -> RuntimeResult<RunVal> {
+        let mut variables = self.environment.get_scope().variables.clone();
+        for name in names {
+            variables.remove(*name);
+        }
+
+        self.environment.push_scope(Scope {
+            variables,
+            ..Default::default()
+        });
+        let value = self.visit_expr(expr);
+        self.environment.pop_scope();
+
         value
     }
     fn visit_variable(&mut self, name: &String) -> RunVal {
@@ -95,13 +256,23 @@ impl Runtime {
         runtime_debug!("name: {}", name);
 
         if self.assign {
+            // Constants never change, so they don't have to be kept as symbols
+            if let Some(constant) = self.environment.constants.get(name) {
+                return constant.clone();
+            }
             return RunType::Symbol(name.clone()).into();
         }
 
-        if let Some(mut value) = self.environment.get_variable(name).cloned() {
+        let value = if self.in_func_call {
+            self.environment.get_variable(name).cloned()
+        } else {
+            self.resolve_variable(name)
+        };
+
+        if let Some(value) = value {
             runtime_debug!("value of variable: {:?}", value);
-            if !self.in_func_call {
-                self.get_reactive_value(&mut value);
+            if !self.environment.constants.contains_key(name) {
+                trace::step_once("substitute", &RunType::Symbol(name.clone()).into(), &value);
             }
             value
         } else {
@@ -110,25 +281,37 @@ impl Runtime {
     }
 
     // TODO: Currently only (-) unary
-    fn visit_unary_operation(&mut self, expr: &Expr) -> RunVal {
+    fn visit_unary_operation(&mut self, expr: &Expr, pos: Position) -> RuntimeResult<RunVal> {
         runtime_debug!("Visit unary operation");
         runtime_debug!("expr: {:?}", expr);
 
-        let expr = self.visit_expr(expr);
+        let expr = self.visit_expr(expr)?;
+        let minus_one: RunVal = RunType::Number(-1.0).into();
+        check_operands(&BinOp::Multiply, &minus_one, &expr, pos)?;
 
-        let value = expr.multiply(RunType::Number(-1.0).into());
+        let value = expr.multiply(minus_one);
 
-        value
+        Ok(value)
     }
 
-    fn visit_binary_operation(&mut self, left: &Expr, operation: &BinOp, right: &Expr) -> RunVal {
+    fn visit_binary_operation(
+        &mut self,
+        left: &Expr,
+        operation: &BinOp,
+        right: &Expr,
+        pos: Position,
+    ) -> RuntimeResult<RunVal> {
         runtime_debug!("Visit binary operation");
         runtime_debug!("left: {:?}", left);
         runtime_debug!("operation: {:?}", operation);
         runtime_debug!("right: {:?}", right);
 
-        let lhs = self.visit_expr(left);
-        let rhs = self.visit_expr(right);
+        let lhs = self.visit_expr(left)?;
+        let rhs = match operation {
+            BinOp::In => self.visit_domain(right)?,
+            _ => self.visit_expr(right)?,
+        };
+        check_operands(operation, &lhs, &rhs, pos)?;
 
         let value = match *operation {
             BinOp::Add => lhs.add(rhs),
@@ -142,6 +325,7 @@ impl Runtime {
                 lhs.multiply(rhs)
             }
             BinOp::Power => lhs.power(rhs),
+            BinOp::Modulo => RunType::Function("mod".to_string(), vec![lhs, rhs]).into(),
 
             BinOp::Equal => lhs.equal(rhs),
 
@@ -150,87 +334,293 @@ impl Runtime {
             BinOp::Greater => lhs.greater(rhs),
             BinOp::GreaterEqual => lhs.greater_equal(rhs),
 
-            _ => {
+            BinOp::In => RunType::Function("element".to_string(), vec![lhs, rhs]).into(),
+
+            BinOp::Assignment | BinOp::None => {
                 runtime_error!("Not a valid binary operation");
-                panic!();
+                return Err(RuntimeError::InvalidOperation {
+                    message: format!("{:?} is not a valid binary operation", operation),
+                    pos,
+                });
             }
         };
 
         // dbg!(&value);
 
-        value
+        Ok(value)
     }
 
-    fn visit_assignment(&mut self, holder: &Expr, value: &Expr) -> RunVal {
+    fn visit_assignment(&mut self, holder: &Expr, value: &Expr, pos: Position) -> RuntimeResult<RunVal> {
         runtime_debug!("Visit assignment");
         runtime_debug!("holder: {:?}", holder);
         runtime_debug!("value: {:?}", value);
 
         // TODO: Move ownership instead?
         let Expr::Variable(holder) = holder else {
-            panic!("Unhandled holder expression");
+            return Err(RuntimeError::InvalidAssignment {
+                message: "Only variables can be assigned to".to_string(),
+                pos,
+            });
         };
 
         self.assign = true;
         let value = self.visit_expr(value);
         self.assign = false;
+        let value = value?;
 
         runtime_debug!("\n\tholder: {:?}\n\tvalue: {:?}", holder, value);
 
+        // Only global variables are reactive, the others are arguments of function calls
+        if self.environment.scopes.len() == 1 {
+            if let Err(cycle) = self.environment.dependencies.set(holder, &value) {
+                return Err(RuntimeError::CyclicDependency {
+                    message: format!("Cyclic dependency {}", cycle.join(" -> ")),
+                    cycle,
+                    pos,
+                });
+            }
+        }
+
         self.environment.set_variable(holder, value.clone());
 
-        value
+        Ok(value)
     }
 
-    fn visit_if(&mut self, condition: &Expr, body: &Expr, else_body: &Expr) -> RunVal {
+    fn visit_if(
+        &mut self,
+        condition: &Expr,
+        body: &Expr,
+        else_body: &Expr,
+        pos: Position,
+    ) -> RuntimeResult<RunVal> {
         runtime_debug!("Visit if");
         runtime_debug!("condition: {:?}", condition);
         runtime_debug!("body: {:?}", body);
         runtime_debug!("else: {:?}", else_body);
 
-        let condition = self.visit_expr(condition);
+        let condition = self.visit_expr(condition)?;
 
-        let RunType::Bool(b) = condition.typ else {
-            eprintln!("Expected a boolean value, got {:?}", condition);
-            return RunType::Unit.into()
+        let symbolic = match &condition.typ {
+            RunType::Bool(b) => {
+                return if *b { self.visit_expr(body) } else { self.visit_expr(else_body) };
+            }
+            RunType::Function(name, _) => {
+                name == "element" || functions::RELATIONS.iter().any(|(relation, _)| relation == name)
+            }
+            RunType::Symbol(_) => true,
+            _ => false,
         };
+        if !symbolic {
+            return Err(RuntimeError::NotBoolean {
+                message: format!("Expected a boolean condition, got {}", NormalFormatter::format(&condition)),
+                pos,
+            });
+        }
 
-        let value = if b {
-            self.visit_expr(body)
-        } else {
-            self.visit_expr(else_body)
-        };
+        // Both branches are kept, the body can assume that the condition holds
+        self.symbolic_branches += 1;
+        let fact = Fact::Holds(condition.clone());
+        let value = assumptions::assuming(vec![fact], || self.visit_expr(body));
+        let else_value = value.and_then(|value| Ok((value, self.visit_expr(else_body)?)));
+        self.symbolic_branches -= 1;
+
+        let (value, else_value) = else_value?;
+        let otherwise = RunType::Bool(true).into();
+        Ok(RunType::Piecewise(Pieces(vec![(condition, value), (otherwise, else_value)])).into())
+    }
+
+    fn visit_substitution(&mut self, expr: &Expr, bindings: &[(String, Expr)]) -> RuntimeResult<RunVal> {
+        runtime_debug!("Visit substitution");
+        runtime_debug!("expr: {:?}", expr);
+        runtime_debug!("bindings: {:?}", bindings);
+
+        let bindings: Vec<(RunVal, RunVal)> = bindings
+            .iter()
+            .map(|(name, value)| Ok((RunType::Symbol(name.clone()).into(), self.visit_expr(value)?)))
+            .collect::<RuntimeResult<_>>()?;
+
+        // The substituted variables are symbols in the expression, even if they are assigned
+        let names: Vec<&str> = bindings
+            .iter()
+            .filter_map(|(target, _)| match &target.typ {
+                RunType::Symbol(name) => Some(name.as_str()),
+                _ => None,
+            })
+            .collect();
+        let value = self.visit_unbound(&names, expr)?;
+
+        Ok(value.substitute_simultaneously(&bindings))
+    }
+
+    fn visit_with(&mut self, facts: &[Expr], body: &Expr, pos: Position) -> RuntimeResult<RunVal> {
+        runtime_debug!("Visit with");
+        runtime_debug!("facts: {:?}", facts);
+
+        let facts = self.visit_facts(facts, pos)?;
+
+        let prev_in_with = self.in_with;
+        self.in_with = true;
+        let value = rules::scoped(|| assumptions::assuming(facts, || self.visit_expr(body)));
+        self.in_with = prev_in_with;
 
         value
     }
 
-    fn visit_function_call(&mut self, name: &String, arguments: &Vec<Expr>) -> RunVal {
+    /// The facts of `assume(x > 0, y < 0)` or `assume(n, integer)`.
+    fn visit_facts(&mut self, facts: &[Expr], pos: Position) -> RuntimeResult<Vec<Fact>> {
+        if let [Expr::Variable(symbol), Expr::Variable(type_name)] = facts {
+            if let Ok(union) = Union::resolve(type_name, &self.environment.aliases) {
+                return Ok(vec![Fact::Belongs(symbol.clone(), union)]);
+            }
+        }
+
+        let mut known = Vec::new();
+        for fact in facts {
+            let value = self.visit_expr(fact)?;
+            match &value.typ {
+                // Facts about known values, e.g. `x > 0` where `x = 2`
+                RunType::Bool(true) => {}
+                RunType::Bool(false) => {
+                    return Err(RuntimeError::InvalidAssumption {
+                        message: "The assumption doesn't hold".to_string(),
+                        pos,
+                    })
+                }
+                RunType::Function(name, _)
+                    if functions::RELATIONS.iter().any(|(relation, _)| relation == name) =>
+                {
+                    known.push(Fact::Holds(value))
+                }
+                _ => {
+                    return Err(RuntimeError::InvalidAssumption {
+                        message: format!(
+                            "Only relations like x > 0 can be assumed, not {}",
+                            NormalFormatter::format(&value)
+                        ),
+                        pos,
+                    })
+                }
+            }
+        }
+        Ok(known)
+    }
+
+    fn visit_function_call(
+        &mut self,
+        name: &String,
+        arguments: &Vec<Expr>,
+        pos: Position,
+    ) -> RuntimeResult<RunVal> {
         runtime_debug!("Visit function call");
         runtime_debug!("func_name: {}", name);
         runtime_debug!("func_args: {:?}", arguments);
 
-        if let Some(intrinsic) = self.environment.get_intrinsic(name).cloned() {
+        // The steps are recorded while the argument is evaluated
+        if let ("steps", [expr]) = (name.as_str(), arguments.as_slice()) {
+            let (value, steps) = trace::record(|| self.visit_expr(expr));
+            value?;
+            return Ok(RunType::Vector(steps.iter().map(Step::to_value).collect()).into());
+        }
+
+        // Assumed from now on, or until the end of the `with` around it
+        if name == "assume" {
+            let facts = self.visit_facts(arguments, pos)?;
+            if !self.in_with {
+                self.environment.assumptions.extend(facts.clone());
+            }
+            assumptions::assume(facts);
+            return Ok(RunType::Unit.into());
+        }
+
+        // The variable itself is asked about, not its value
+        if let ("deps" | "dependents", [Expr::Variable(variable)]) = (name.as_str(), arguments.as_slice()) {
+            let names = if name == "deps" {
+                self.environment.dependencies.dependencies(variable)
+            } else {
+                self.environment.dependencies.dependents(variable)
+            };
+            return Ok(RunType::Vector(names.into_iter().map(|it| RunType::Symbol(it).into()).collect()).into());
+        }
+
+        // Functions defined by the user replace the built-in ones of the same name
+        let overloads = self.environment.get_overloads(name).cloned().unwrap_or_default();
+        if let (true, Some(intrinsic)) = (overloads.is_empty(), self.environment.get_intrinsic(name).cloned()) {
             let prev_assign = self.assign;
             self.assign = false;
-            let args: Vec<RunVal> = arguments.iter().map(|it| self.visit_expr(it)).collect();
+            let args = arguments.iter().map(|it| self.visit_expr(it)).collect::<RuntimeResult<Vec<_>>>();
             self.assign = prev_assign;
-            return intrinsic(&args);
+            let args = args?;
+            match (name.as_str(), args.as_slice()) {
+                ("convert", [value, target]) => check_conversion(value, target, pos)?,
+                ("sum" | "product", [expr, ..]) => check_summand(expr, pos)?,
+                _ => {}
+            }
+            return Ok(intrinsic(&args));
         }
 
-        let Some(Function { name: _, params, body }) = self.environment.get_function(name).cloned() else {
-            let mut vec = Vec::new();
-            for argument in arguments {
-                let value = self.visit_expr(argument);
-                vec.push(value);
+        let mut args = Vec::new();
+        for argument in arguments {
+            let value = self.visit_expr(argument)?;
+            args.push(value);
+        }
+
+        // Recursion under a symbolic condition wouldn't end, e.g. `f(n - 1)` for a symbol `n`
+        let recursive = self.symbolic_branches > 0 && self.calls.contains(name);
+        if overloads.is_empty() || recursive {
+            return Ok(RunVal::new(RunType::Function(name.clone(), args)));
+        }
+
+        if self.calls.len() >= MAX_CALL_DEPTH {
+            return Err(RuntimeError::RecursionLimit {
+                message: format!(
+                    "Too many nested calls of {}, at most {} are allowed",
+                    name, MAX_CALL_DEPTH
+                ),
+                depth: MAX_CALL_DEPTH,
+                pos,
+            });
+        }
+
+        let Function { params, return_type, domain, body, .. } = match dispatch::select(&overloads, &args, &self.environment.aliases) {
+            Dispatch::Call(function) => function.clone(),
+            Dispatch::Unevaluated => return Ok(RunVal::new(RunType::Function(name.clone(), args))),
+            Dispatch::NoMatch => {
+                if let [function] = overloads.as_slice() {
+                    if function.params.len() != args.len() {
+                        return Err(RuntimeError::WrongArgumentCount {
+                            message: format!(
+                                "{} takes {} arguments, but {} were given",
+                                name,
+                                function.params.len(),
+                                args.len()
+                            ),
+                            expected: function.params.len(),
+                            actual: args.len(),
+                            pos,
+                        });
+                    }
+                }
+
+                let args = args.iter().map(NormalFormatter::format).collect::<Vec<_>>();
+                return Err(RuntimeError::NoMatchingOverload {
+                    message: format!("No overload of {} accepts ({})", name, args.join(", ")),
+                    pos,
+                });
+            }
+            Dispatch::Ambiguous(candidates) => {
+                let candidates = candidates.into_iter().map(dispatch::describe).collect::<Vec<_>>();
+                return Err(RuntimeError::AmbiguousCall {
+                    message: format!("Ambiguous call of {}, it could be {}", name, candidates.join(" or ")),
+                    candidates,
+                    pos,
+                });
             }
-            return RunVal::new(RunType::Function(name.clone(), vec));
         };
 
         // TODO: Make this better? Utilise environment.set_variable?
         let mut new_scope = Scope::default();
 
-        for (arg_value, param) in arguments.iter().zip(params) {
-            let val = self.visit_expr(arg_value);
+        for (val, param) in args.into_iter().zip(params) {
             new_scope.variables.insert(param.name.clone(), val);
         }
 
@@ -243,7 +633,9 @@ impl Runtime {
         self.assign = false;
 
         self.in_func_call = true;
-        let value = self.visit_expr(&body);
+        self.calls.push(name.clone());
+        let value = self.visit_function_body(name, domain.as_ref(), &body, pos);
+        self.calls.pop();
         self.in_func_call = false;
 
         self.assign = prev_assign;
@@ -251,65 +643,176 @@ impl Runtime {
         // Reset the scope again???
         self.environment.pop_scope();
 
-        value
+        let value = value?;
+        if !return_type.is_empty() {
+            let typ = Union::resolve(&return_type, &self.environment.aliases).ok();
+            if typ.and_then(|it| it.accepts(&value)) == Some(false) {
+                let actual = types::describe(&value);
+                return Err(RuntimeError::WrongReturnType {
+                    message: format!(
+                        "{} should return {}, but returned {}",
+                        name,
+                        return_type,
+                        NormalFormatter::format(&value)
+                    ),
+                    expected: return_type,
+                    actual,
+                    pos,
+                });
+            }
+        }
+
+        Ok(value)
     }
 }
 
 impl Runtime {
+    /// Evaluates the body of a function, with the arguments in scope already.
+    ///
+    /// A domain that holds for the arguments is assumed while evaluating the body, so it can
+    /// simplify further, e.g. `sqrt(x^2)` => `x` where `x > 0`.
+    fn visit_function_body(
+        &mut self,
+        name: &str,
+        domain: Option<&Expr>,
+        body: &Expr,
+        pos: Position,
+    ) -> RuntimeResult<RunVal> {
+        let Some(domain) = domain else {
+            return self.visit_expr(body);
+        };
+
+        let condition = self.visit_expr(domain)?;
+        runtime_debug!("domain: {:?}", condition);
+        match condition.typ {
+            RunType::Bool(true) => self.visit_expr(body),
+            RunType::Bool(false) => Err(RuntimeError::OutsideDomain {
+                message: format!("The arguments of {} are outside of its domain", name),
+                pos,
+            }),
+            _ => assumptions::assuming(vec![Fact::Holds(condition)], || self.visit_expr(body)),
+        }
+    }
+
+    /// The value of a variable, with the variables it is defined in terms of resolved as well.
+    ///
+    /// Global variables are remembered once resolved, until something they depend on changes.
+    fn resolve_variable(&mut self, name: &str) -> Option<RunVal> {
+        let global = self.environment.scopes.len() == 1;
+        // Resolving again records the steps that resolved the value
+        if global && !trace::is_recording() {
+            if let Some(value) = self.environment.dependencies.resolved(name) {
+                return Some(value.clone());
+            }
+        }
+
+        let mut value = self.environment.get_variable(name).cloned()?;
+        runtime_debug!("variable_value: {:?}", value);
+
+        // The dependency graph has no cycles, so this always ends
+        self.get_reactive_value(&mut value);
+        value.simplify();
+
+        if global {
+            self.environment.dependencies.set_resolved(name, value.clone());
+        }
+        Some(value)
+    }
+
     fn get_reactive_value(&mut self, value: &mut RunVal) {
         use RunType::*;
         runtime_debug!("value reactive: {:?}", value);
-        value.simplified = false;
-
-        match &mut value.typ {
-            Symbol(sym) => {
-                if let Some(mut variable_value) = self.environment.get_variable(sym).cloned() {
-                    // FIXME: Handle recursive cases
-                    runtime_debug!("variable_value: {:?}", variable_value);
-                    self.get_reactive_value(&mut variable_value);
-                    *value = variable_value;
-                }
-            }
 
-            Product(Factors(vec)) | Sum(Terms(vec)) => {
-                for item in vec {
-                    self.get_reactive_value(item);
-                }
-            }
+        let resolved = match &value.typ {
+            Symbol(sym) => match self.resolve_variable(sym) {
+                Some(resolved) => resolved,
+                None => return,
+            },
+
+            Product(Factors(vec)) => Product(Factors(self.get_reactive_values(vec))).into(),
+            Sum(Terms(vec)) => Sum(Terms(self.get_reactive_values(vec))).into(),
+            Vector(vec) => Vector(self.get_reactive_values(vec)).into(),
+            Function(name, args) => Function(name.clone(), self.get_reactive_values(args)).into(),
+            Piecewise(pieces) => Piecewise(pieces.map(|it| {
+                let mut value = it.clone();
+                self.get_reactive_value(&mut value);
+                value
+            }))
+            .into(),
+            Set(set) => Set(set.map(|it| {
+                let mut value = it.clone();
+                self.get_reactive_value(&mut value);
+                value
+            }))
+            .into(),
             Exponent(base, exp) => {
-                self.get_reactive_value(base);
-                self.get_reactive_value(exp);
+                let (mut base, mut exp) = (base.clone(), exp.clone());
+                self.get_reactive_value(&mut base);
+                self.get_reactive_value(&mut exp);
+                Exponent(base, exp).into()
             }
-            Function(_, _) => todo!(),
 
-            Unit | Undefined | Number(_) | Bool(_) | Vector(_) => {}
-        }
+            Unit
+            | Undefined
+            | Number(_)
+            | Interval(_)
+            | PhysicalUnit(_)
+            | Constant(_)
+            | Bool(_) => return,
+        };
+
+        *value = resolved;
+    }
+
+    fn get_reactive_values(&mut self, values: &[RunVal]) -> Vec<RunVal> {
+        values
+            .iter()
+            .map(|it| {
+                let mut value = it.clone();
+                self.get_reactive_value(&mut value);
+                value
+            })
+            .collect()
     }
 }
 
-impl Visitor<RunVal> for Runtime {
+impl Visitor<RuntimeResult<RunVal>> for Runtime {
     // TODO: Take ownership instead, since statements are never visited twice
-    fn visit_statement(&mut self, statement: &Statement) -> RunVal {
+    fn visit_statement(&mut self, statement: &Statement) -> RuntimeResult<RunVal> {
         use Statement::*;
         match statement {
-            FunctionDefinition(Function {
-                name,
-                params,
-                body: function_body,
-            }) => self.visit_function(name, params, function_body),
+            FunctionDefinition(function) => self.visit_function(function),
             UnsetVariable(symbol) => self.visit_unset_variable(symbol),
+            RuleDefinition(rule) => self.visit_rule_definition(rule),
+            TypeDefinition(alias) => self.visit_type_definition(alias),
             Expression(expr) => self.visit_expr(expr),
         }
     }
 
-    fn visit_expr(&mut self, expr: &Expr) -> RunVal {
+    fn visit_expr(&mut self, expr: &Expr) -> RuntimeResult<RunVal> {
         let mut value = match expr {
             Expr::Number(n) => RunType::Number(*n).into(),
             Expr::Variable(name) => self.visit_variable(name),
-            Expr::Vector(vec) => self.visit_vector(vec),
-            Expr::Unary(expr) => self.visit_unary_operation(expr),
+            Expr::Unit(name) => match PhysicalUnit::parse(name) {
+                Some(unit) => RunType::PhysicalUnit(unit).into(),
+                None => RunType::Symbol(name.clone()).into(),
+            },
+            Expr::Vector(vec) => self.visit_vector(vec)?,
+            Expr::Set(elements) => self.visit_set(elements)?,
+            Expr::Interval {
+                lower,
+                upper,
+                lower_closed,
+                upper_closed,
+            } => self.visit_interval(lower, upper, *lower_closed, *upper_closed)?,
+            Expr::SetBuilder {
+                variable,
+                domain,
+                condition,
+            } => self.visit_set_builder(variable, domain.as_deref(), condition)?,
+            Expr::Unary(expr, pos) => self.visit_unary_operation(expr, *pos)?,
             Expr::Simplify(expr) => {
-                let mut expr = self.visit_expr(expr);
+                let mut expr = self.visit_expr(expr)?;
 
                 expr.simplify();
 
@@ -319,16 +822,198 @@ impl Visitor<RunVal> for Runtime {
                 left,
                 operation,
                 right,
-            } => self.visit_binary_operation(left, operation, right),
-            Expr::Assignment { holder, value } => self.visit_assignment(holder, value),
+                pos,
+            } => self.visit_binary_operation(left, operation, right, *pos)?,
+            Expr::Assignment { holder, value, pos } => self.visit_assignment(holder, value, *pos)?,
             Expr::If {
                 condition,
                 body,
                 else_body,
-            } => self.visit_if(condition, body, else_body),
-            Expr::FunctionCall { name, args } => self.visit_function_call(name, args),
+                pos,
+            } => self.visit_if(condition, body, else_body, *pos)?,
+            Expr::FunctionCall { name, args, pos } => self.visit_function_call(name, args, *pos)?,
+            Expr::With { facts, body, pos } => self.visit_with(facts, body, *pos)?,
+            Expr::Substitution { expr, bindings } => self.visit_substitution(expr, bindings)?,
         };
         value.simplify();
-        value
+        Ok(value)
+    }
+}
+
+/// Checks that an operation is defined for its operands, before they are combined.
+fn check_operands(operation: &BinOp, lhs: &RunVal, rhs: &RunVal, pos: Position) -> RuntimeResult<()> {
+    use RunType::*;
+
+    let is_arithmetic = matches!(
+        operation,
+        BinOp::Add
+            | BinOp::Subtract
+            | BinOp::Multiply
+            | BinOp::Divide
+            | BinOp::Modulo
+            | BinOp::Power
+    );
+    let is_order = matches!(
+        operation,
+        BinOp::Less | BinOp::LessEqual | BinOp::Greater | BinOp::GreaterEqual
+    );
+    if is_order {
+        return check_dimensions(lhs, rhs, "compared", pos);
+    }
+    if !is_arithmetic {
+        return Ok(());
+    }
+
+    let message = match (&lhs.typ, &rhs.typ) {
+        (Bool(_), _) | (_, Bool(_)) => Some("Booleans can't be used in arithmetic".to_string()),
+        (Set(_), _) | (_, Set(_)) => Some("Sets can't be used in arithmetic".to_string()),
+        (Vector(lhs), Vector(rhs)) => match operation {
+            BinOp::Add | BinOp::Subtract if lhs.len() != rhs.len() => Some(format!(
+                "Vectors of length {} and {} can't be combined",
+                lhs.len(),
+                rhs.len()
+            )),
+            BinOp::Add | BinOp::Subtract => None,
+            _ => Some(format!("{:?} isn't defined for two vectors", operation)),
+        },
+        (Vector(_), _) => match operation {
+            BinOp::Multiply | BinOp::Divide => None,
+            _ => Some(format!("{:?} isn't defined for a vector and a scalar", operation)),
+        },
+        (_, Vector(_)) => match operation {
+            BinOp::Multiply => None,
+            _ => Some(format!("{:?} isn't defined for a scalar and a vector", operation)),
+        },
+        // Only values of the same dimension can be added, `1 m + 1 s` has no meaning
+        _ => match (operation, units::dimension(lhs), units::dimension(rhs)) {
+            (BinOp::Add | BinOp::Subtract, Some(lhs), Some(rhs)) if lhs != rhs => {
+                Some(format!("Values in {} and {} can't be added", lhs, rhs))
+            }
+            _ => None,
+        },
+    };
+
+    match message {
+        Some(message) => {
+            runtime_error!("{}", message);
+            Err(RuntimeError::InvalidOperation { message, pos })
+        }
+        None => Ok(()),
+    }
+}
+
+/// Only values of the same dimension can be converted into each other, `convert(1 m, s)` has no
+/// meaning.
+fn check_conversion(value: &RunVal, target: &RunVal, pos: Position) -> RuntimeResult<()> {
+    match (units::dimension(value), units::target_dimension(target)) {
+        (Some(from), Some(to)) if from != to => {
+            let message = format!("Values in {} can't be converted to {}", from, to);
+            runtime_error!("{}", message);
+            Err(RuntimeError::InvalidOperation { message, pos })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The terms of sums and products are added or multiplied like any operands, `sum(1 < 2, k, 1, 3)`
+/// has no meaning.
+fn check_summand(expr: &RunVal, pos: Position) -> RuntimeResult<()> {
+    let message = match &expr.typ {
+        RunType::Bool(_) => "Booleans can't be used in arithmetic",
+        RunType::Function(name, _) if functions::RELATIONS.iter().any(|(it, _)| it == name) => {
+            "Booleans can't be used in arithmetic"
+        }
+        RunType::Set(_) => "Sets can't be used in arithmetic",
+        _ => return Ok(()),
+    };
+    runtime_error!("{}", message);
+    Err(RuntimeError::InvalidOperation {
+        message: message.to_string(),
+        pos,
+    })
+}
+
+/// Only values of the same dimension can be compared, `1 m < 2 s` has no meaning.
+fn check_dimensions(lhs: &RunVal, rhs: &RunVal, operation: &str, pos: Position) -> RuntimeResult<()> {
+    match (units::dimension(lhs), units::dimension(rhs)) {
+        (Some(lhs), Some(rhs)) if lhs != rhs => {
+            let message = format!("Values in {} and {} can't be {}", lhs, rhs, operation);
+            runtime_error!("{}", message);
+            Err(RuntimeError::InvalidOperation { message, pos })
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use crate::cas::eval::testing::{eval, Session};
+
+    /// Runs `f` with as much stack as the main thread of the REPL has, more than test threads get.
+    fn with_main_stack(f: impl FnOnce() + Send + 'static) {
+        let thread = thread::Builder::new().stack_size(8 << 20).spawn(f).unwrap();
+        if let Err(panic) = thread.join() {
+            std::panic::resume_unwind(panic);
+        }
+    }
+
+    #[test]
+    fn return_types_are_checked() {
+        let mut session = Session::new();
+        session.eval("g(x) -> integer = x / 2");
+        assert_eq!(session.eval("g(4)"), "2");
+        assert_eq!(
+            session.error("g(3)"),
+            "g should return integer, but returned 1.5 at (row 1, col 1)"
+        );
+    }
+
+    #[test]
+    fn domains_are_assumed() {
+        let mut session = Session::new();
+        session.eval("f(x: real) -> real where x > 0 = sqrt(x^2)");
+        assert_eq!(session.eval("f(y)"), "y");
+        assert_eq!(session.eval("f(4)"), "4");
+        assert_eq!(
+            session.error("f(-1)"),
+            "The arguments of f are outside of its domain at (row 1, col 1)"
+        );
+    }
+
+    #[test]
+    fn user_functions_replace_builtins() {
+        let mut session = Session::new();
+        session.eval("abs(x: real) = if x > 0 then x else -x");
+        session.eval("abs(q: integer) = if q > 0 then 7 else 2");
+        assert_eq!(session.eval("abs(3)"), "7");
+        assert_eq!(session.eval("abs(0 - 3)"), "2");
+        assert_eq!(eval("abs(0 - 3)"), "3");
+    }
+
+    #[test]
+    fn runaway_recursion_is_an_error() {
+        with_main_stack(|| {
+            let mut session = Session::new();
+            session.eval("f(x) = f(x + 1)");
+            assert_eq!(
+                session.error("f(1)"),
+                "Too many nested calls of f, at most 100 are allowed at (row 1, col 8)"
+            );
+            session.eval("g(x) = h(x)");
+            session.eval("h(x) = g(x)");
+            assert!(session.error("g(1)").starts_with("Too many nested calls"));
+        });
+    }
+
+    #[test]
+    fn deep_recursion_that_ends() {
+        with_main_stack(|| {
+            let program = "fact(n) = if n > 0 then n * fact(n - 1) else 1\nfact(10)";
+            assert_eq!(eval(program), "3628800");
+            let program = "count(n) = if n > 0 then 1 + count(n - 1) else 0\ncount(90)";
+            assert_eq!(eval(program), "90");
+        });
     }
 }
@@ -0,0 +1,390 @@
+use std::collections::BTreeMap;
+
+use log::debug;
+
+use super::{
+    diff, functions,
+    value::{Factors, RunType, RunVal, Terms},
+};
+
+macro_rules! series_debug {
+    ($($arg:tt)+) => (debug!(target: "matex::series", "[{}:{}] {}", file!(), line!(), &format!($($arg)+)));
+}
+
+/// Laurent series aren't expanded beyond this order, to keep runaway recursion in check.
+const MAX_ORDER: i32 = 64;
+
+/// A truncated Laurent series `c_k * t^k + ... + O(t^order)` in an expansion variable `t`.
+///
+/// Only coefficients that aren't zero are stored, and every coefficient is simplified.
+#[derive(Clone, Debug)]
+pub struct Series {
+    terms: BTreeMap<i32, RunVal>,
+    order: i32,
+}
+
+/// The point a series is expanded around.
+#[derive(Clone, Debug)]
+pub enum Point {
+    Finite(RunVal),
+    PositiveInfinity,
+    NegativeInfinity,
+}
+
+impl Point {
+    pub fn from_value(value: &RunVal) -> Point {
+        match value.typ {
+            RunType::Number(n) if n == f64::INFINITY => Point::PositiveInfinity,
+            RunType::Number(n) if n == f64::NEG_INFINITY => Point::NegativeInfinity,
+            _ => Point::Finite(value.clone()),
+        }
+    }
+}
+
+/// The variable every expression is expanded in, it can't be written in the language.
+pub(crate) fn expansion_variable() -> RunVal {
+    RunType::Symbol("_t".to_string()).into()
+}
+
+/// Rewrites `value` so that `var` approaching `point` corresponds to
+/// the expansion variable approaching 0 from above (or from both sides for finite points).
+pub(crate) fn shift_to_origin(value: &RunVal, var: &RunVal, point: &Point) -> RunVal {
+    let t = expansion_variable();
+    let replacement = match point {
+        Point::Finite(a) => a.clone().add(t),
+        Point::PositiveInfinity => t.power(RunType::Number(-1.0).into()),
+        Point::NegativeInfinity => t
+            .power(RunType::Number(-1.0).into())
+            .multiply(RunType::Number(-1.0).into()),
+    };
+    let mut shifted = value.substitute(var, &replacement);
+    shifted.simplify();
+    shifted
+}
+
+/// Series expansion of `value` in `var` around `point`, up to (but not including) `order`.
+///
+/// The result is a sum of the terms with an `O(...)` term at the end,
+/// e.g. `series(sin(x), x, 0, 4)` gives `x - 0.16666666666666666 * x^3 + O(x^4)`.
+pub fn series(value: &RunVal, var: &RunVal, point: &Point, order: i32) -> Option<RunVal> {
+    let shifted = shift_to_origin(value, var, point);
+    let series = Series::expand(&shifted, order)?;
+    series_debug!("series: {:?}", series);
+
+    // Replace t with what it stands for again
+    let base: RunVal = match point {
+        Point::Finite(a) => var
+            .clone()
+            .add(a.clone().multiply(RunType::Number(-1.0).into())),
+        Point::PositiveInfinity => var.clone().power(RunType::Number(-1.0).into()),
+        Point::NegativeInfinity => var
+            .clone()
+            .power(RunType::Number(-1.0).into())
+            .multiply(RunType::Number(-1.0).into()),
+    };
+
+    let power = |k: i32| -> RunVal {
+        RunType::Exponent(
+            Box::new(base.clone()),
+            Box::new(RunType::Number(k as f64).into()),
+        )
+        .into()
+    };
+
+    let mut terms: Vec<RunVal> = series
+        .terms
+        .iter()
+        .filter(|(k, _)| **k < order)
+        .map(|(k, coeff)| coeff.clone().multiply(power(*k)))
+        .collect();
+
+    terms.push(functions::call("O", &power(order)));
+
+    Some(RunType::Sum(Terms(terms)).into())
+}
+
+impl Series {
+    fn zero(order: i32) -> Series {
+        Series {
+            terms: BTreeMap::new(),
+            order,
+        }
+    }
+
+    fn constant(value: RunVal, order: i32) -> Series {
+        let mut series = Series::zero(order);
+        series.insert(0, value);
+        series
+    }
+
+    fn insert(&mut self, k: i32, mut coeff: RunVal) {
+        if k >= self.order {
+            return;
+        }
+        coeff.simplify();
+        if is_zero(&coeff) {
+            self.terms.remove(&k);
+        } else {
+            self.terms.insert(k, coeff);
+        }
+    }
+
+    /// The exponent of the first non-zero term, the order if every known term is zero.
+    pub fn valuation(&self) -> i32 {
+        self.terms.keys().next().copied().unwrap_or(self.order)
+    }
+
+    /// The first non-zero term.
+    pub fn leading_term(&self) -> Option<(i32, &RunVal)> {
+        self.terms.iter().next().map(|(k, c)| (*k, c))
+    }
+
+    pub fn coefficient(&self, k: i32) -> RunVal {
+        self.terms
+            .get(&k)
+            .cloned()
+            .unwrap_or_else(|| RunType::Number(0.0).into())
+    }
+
+    fn add(&self, other: &Series) -> Series {
+        let mut sum = Series::zero(self.order.min(other.order));
+        for (k, coeff) in self.terms.iter().chain(&other.terms) {
+            let previous = sum.coefficient(*k);
+            sum.insert(*k, previous.add(coeff.clone()));
+        }
+        sum
+    }
+
+    fn mul(&self, other: &Series) -> Series {
+        let order = (self.order + other.valuation()).min(other.order + self.valuation());
+        let mut product = Series::zero(order);
+        for (k, coeff) in &self.terms {
+            for (other_k, other_coeff) in &other.terms {
+                if k + other_k >= order {
+                    continue;
+                }
+                let previous = product.coefficient(k + other_k);
+                let term = coeff.clone().multiply(other_coeff.clone());
+                product.insert(k + other_k, previous.add(term));
+            }
+        }
+        product
+    }
+
+    fn scale(&self, factor: &RunVal) -> Series {
+        let mut scaled = Series::zero(self.order);
+        for (k, coeff) in &self.terms {
+            scaled.insert(*k, coeff.clone().multiply(factor.clone()));
+        }
+        scaled
+    }
+
+    fn shift(&self, by: i32) -> Series {
+        Series {
+            terms: self
+                .terms
+                .iter()
+                .map(|(k, c)| (k + by, c.clone()))
+                .collect(),
+            order: self.order + by,
+        }
+    }
+
+    /// Composes a power series `sum coeffs[k] * u^k` with a series `u` without a constant term.
+    fn compose(coeffs: &[RunVal], u: &Series, order: i32) -> Series {
+        let mut result = Series::zero(order);
+        let mut power = Series::constant(RunType::Number(1.0).into(), order);
+
+        for coeff in coeffs {
+            if power.terms.is_empty() {
+                break;
+            }
+            result = result.add(&power.scale(coeff));
+            power = power.mul(u);
+            power.order = power.order.min(order);
+        }
+        result.order = result.order.min(order);
+        result
+    }
+
+    /// Expands `value` around the expansion variable being 0.
+    pub fn expand(value: &RunVal, order: i32) -> Option<Series> {
+        use RunType::*;
+        series_debug!("expand {:?} to order {}", value, order);
+
+        if order > MAX_ORDER {
+            return None;
+        }
+
+        let t = expansion_variable();
+
+        if !value.depends_on(&t) {
+            if matches!(value.typ, Unit | Undefined | Bool(_) | Vector(_) | Set(_)) {
+                return None;
+            }
+            return Some(Series::constant(value.clone(), order));
+        }
+
+        match &value.typ {
+            Symbol(_) => {
+                let mut series = Series::zero(order);
+                series.insert(1, Number(1.0).into());
+                Some(series)
+            }
+            Sum(Terms(terms)) => {
+                let mut sum = Series::zero(order);
+                for term in terms {
+                    sum = sum.add(&Series::expand(term, order)?);
+                }
+                Some(sum)
+            }
+            Product(Factors(factors)) => Series::expand_product(factors, order),
+            Exponent(base, exp) => Series::expand_power(base, exp, order),
+            Function(name, args) => match args.as_slice() {
+                [arg] => Series::expand_function(name, arg, order),
+                _ => None,
+            },
+            Unit
+            | Undefined
+            | Number(_)
+            | Constant(_)
+            | Bool(_)
+            | Vector(_)
+            | Piecewise(_)
+            | Set(_)
+            | Interval(_)
+            | PhysicalUnit(_) => None,
+        }
+    }
+
+    fn expand_product(factors: &[RunVal], order: i32) -> Option<Series> {
+        let mut expansions = factors
+            .iter()
+            .map(|it| Series::expand(it, order))
+            .collect::<Option<Vec<_>>>()?;
+
+        // Factors with poles or zeros change how many terms the other factors need
+        let total: i32 = expansions.iter().map(Series::valuation).sum();
+        for (factor, expansion) in factors.iter().zip(expansions.iter_mut()) {
+            let needed = order - (total - expansion.valuation());
+            if needed > expansion.order {
+                *expansion = Series::expand(factor, needed)?;
+            }
+        }
+
+        let mut product = Series::constant(RunType::Number(1.0).into(), MAX_ORDER);
+        for expansion in &expansions {
+            product = product.mul(expansion);
+        }
+        product.order = product.order.min(order);
+        Some(product)
+    }
+
+    fn expand_power(base: &RunVal, exp: &RunVal, order: i32) -> Option<Series> {
+        let t = expansion_variable();
+
+        if exp.depends_on(&t) {
+            // f^g = exp(g * ln(f))
+            let exponent = exp.clone().multiply(functions::call("ln", base));
+            return Series::expand_function("exp", &exponent, order);
+        }
+
+        // The leading term is needed, even if it lies beyond the requested order
+        let mut base_order = order.max(1);
+        let mut expansion = Series::expand(base, base_order)?;
+        while expansion.leading_term().is_none() {
+            base_order *= 2;
+            expansion = Series::expand(base, base_order)?;
+        }
+        let (v, _) = expansion.leading_term()?;
+
+        let scaled_valuation = match exp.typ {
+            RunType::Number(e) if (v as f64 * e).fract() == 0.0 => (v as f64 * e) as i32,
+            _ if v == 0 => 0,
+            _ => return None,
+        };
+
+        let needed = order - scaled_valuation + v;
+        if needed > expansion.order {
+            expansion = Series::expand(base, needed)?;
+        }
+
+        // b = c * t^v * (1 + w), where w has no constant term
+        let (v, c) = expansion.leading_term()?;
+        let c = c.clone();
+        let inverse_c = c.clone().power(RunType::Number(-1.0).into());
+        let one = Series::constant(RunType::Number(1.0).into(), expansion.order - v);
+        let w = expansion
+            .shift(-v)
+            .scale(&inverse_c)
+            .add(&one.scale(&RunType::Number(-1.0).into()));
+
+        // (1 + w)^e = sum binomial(e, k) * w^k
+        let relative_order = expansion.order - v;
+        let mut coeffs = Vec::new();
+        let mut binomial: RunVal = RunType::Number(1.0).into();
+        for k in 0..relative_order.max(0) {
+            coeffs.push(binomial.clone());
+            let factor = exp
+                .clone()
+                .add(RunType::Number(-k as f64).into())
+                .multiply(RunType::Number(1.0 / (k + 1) as f64).into());
+            binomial = binomial.multiply(factor);
+            binomial.simplify();
+        }
+
+        let scale = c.power(exp.clone());
+        let result = Series::compose(&coeffs, &w, relative_order)
+            .scale(&scale)
+            .shift(scaled_valuation);
+        Some(result)
+    }
+
+    fn expand_function(name: &str, arg: &RunVal, order: i32) -> Option<Series> {
+        let y: RunVal = RunType::Symbol("_y".to_string()).into();
+        functions::derivative(name, &y)?;
+
+        let inner = Series::expand(arg, order)?;
+        if inner.valuation() < 0 {
+            series_debug!("essential singularity in {}({:?})", name, arg);
+            return None;
+        }
+
+        let c0 = inner.coefficient(0);
+        if let RunType::Number(c) = c0.typ {
+            let c = if is_zero(&c0) { 0.0 } else { c };
+            if !functions::is_smooth(name, c) {
+                series_debug!("{}({:?}) has no series at {}", name, arg, c);
+                return None;
+            }
+        }
+
+        let u = inner.add(&Series::constant(
+            c0.clone().multiply(RunType::Number(-1.0).into()),
+            inner.order,
+        ));
+
+        // Taylor coefficients f^(k)(c0) / k!
+        let mut coeffs = Vec::new();
+        let mut derivative = functions::call(name, &y);
+        let mut factorial = 1.0;
+        for k in 0..order.max(1) {
+            if k > 0 {
+                factorial *= k as f64;
+                derivative = diff::differentiate(&derivative, &y);
+                derivative.simplify();
+            }
+            let mut coeff = derivative
+                .substitute(&y, &c0)
+                .multiply(RunType::Number(1.0 / factorial).into());
+            coeff.simplify();
+            coeffs.push(coeff);
+        }
+
+        Some(Series::compose(&coeffs, &u, inner.order.min(order)))
+    }
+}
+
+fn is_zero(value: &RunVal) -> bool {
+    matches!(value.typ, RunType::Number(n) if n.abs() < 1e-14)
+}
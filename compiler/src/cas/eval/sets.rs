@@ -0,0 +1,608 @@
+//! Sets of values: finite sets like `{1, 2, x}`, intervals like `[0, 1)`, sets given by a
+//! condition like `{x in Z | x^2 < 10}` and the numbers `N`, `Z`, `Q` and `R`.
+//!
+//! Unions, intersections, differences and complements are kept as functions, e.g. `union(A, B)`,
+//! until they can be written as a single set. Whether a value belongs to a set is decided by what
+//! is known or assumed about the value, and stays unevaluated as `x in A` otherwise.
+
+use std::cmp::Ordering;
+
+use log::debug;
+
+#[cfg(target_arch = "wasm32")]
+use serde::{Deserialize, Serialize};
+
+use crate::cas::types::Type;
+
+use super::{
+    assumptions, functions,
+    value::{RunType, RunVal},
+};
+
+macro_rules! sets_debug {
+    ($($arg:tt)+) => (debug!(target: "matex::sets", "[{}:{}] {}", file!(), line!(), &format!($($arg)+)));
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(target_arch = "wasm32", derive(Serialize, Deserialize))]
+pub enum Set {
+    /// `{1, 2, x}`
+    Finite(Vec<RunVal>),
+    /// `[a, b)`, the bounds may be infinite
+    Interval {
+        lower: Box<RunVal>,
+        upper: Box<RunVal>,
+        lower_closed: bool,
+        upper_closed: bool,
+    },
+    /// `{x in Z | x^2 < 10}`, the values of the domain that the condition holds for
+    Builder {
+        variable: String,
+        domain: Box<RunVal>,
+        condition: Box<RunVal>,
+    },
+    Numbers(Numbers),
+}
+
+/// The sets of numbers, each contains the ones before it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(target_arch = "wasm32", derive(Serialize, Deserialize))]
+pub enum Numbers {
+    /// `0, 1, 2, ...`
+    Naturals,
+    Integers,
+    Rationals,
+    Reals,
+}
+
+impl Numbers {
+    pub fn name(self) -> &'static str {
+        match self {
+            Numbers::Naturals => "N",
+            Numbers::Integers => "Z",
+            Numbers::Rationals => "Q",
+            Numbers::Reals => "R",
+        }
+    }
+
+    /// The numbers named `N`, `Z`, `Q` or `R`, which are only sets after `in`.
+    pub(crate) fn from_name(name: &str) -> Option<Numbers> {
+        match name {
+            "N" => Some(Numbers::Naturals),
+            "Z" => Some(Numbers::Integers),
+            "Q" => Some(Numbers::Rationals),
+            "R" => Some(Numbers::Reals),
+            _ => None,
+        }
+    }
+
+    fn contains(self, value: &RunVal) -> Option<bool> {
+        let typ = match self {
+            Numbers::Naturals | Numbers::Integers => Type::Integer,
+            Numbers::Rationals => Type::Rational,
+            Numbers::Reals => Type::Real,
+        };
+        match (self, typ.accepts(value)) {
+            (Numbers::Naturals, Some(true)) => assumptions::is_nonnegative(value),
+            (_, belongs) => belongs,
+        }
+    }
+}
+
+impl Set {
+    pub(crate) fn empty() -> Set {
+        Set::Finite(Vec::new())
+    }
+
+    /// The set with `f` applied to every value in it, including the condition of a set-builder.
+    pub(crate) fn map(&self, mut f: impl FnMut(&RunVal) -> RunVal) -> Set {
+        match self {
+            Set::Finite(elements) => Set::Finite(elements.iter().map(f).collect()),
+            Set::Interval {
+                lower,
+                upper,
+                lower_closed,
+                upper_closed,
+            } => Set::Interval {
+                lower: Box::new(f(lower)),
+                upper: Box::new(f(upper)),
+                lower_closed: *lower_closed,
+                upper_closed: *upper_closed,
+            },
+            Set::Builder {
+                variable,
+                domain,
+                condition,
+            } => Set::Builder {
+                variable: variable.clone(),
+                domain: Box::new(f(domain)),
+                condition: Box::new(f(condition)),
+            },
+            Set::Numbers(numbers) => Set::Numbers(*numbers),
+        }
+    }
+
+    /// Every value in the set, in order.
+    pub(crate) fn parts(&self) -> Vec<&RunVal> {
+        match self {
+            Set::Finite(elements) => elements.iter().collect(),
+            Set::Interval { lower, upper, .. } => vec![lower, upper],
+            Set::Builder {
+                domain, condition, ..
+            } => vec![domain, condition],
+            Set::Numbers(_) => Vec::new(),
+        }
+    }
+
+    /// Whether the symbol is the variable of a set-builder, which is its own inside of the set.
+    pub(crate) fn binds(&self, symbol: &RunVal) -> bool {
+        match (self, &symbol.typ) {
+            (Set::Builder { variable, .. }, RunType::Symbol(name)) => variable == name,
+            _ => false,
+        }
+    }
+}
+
+/// A simpler way to write a simplified set, e.g. `{2, 1, 1}` => `{1, 2}` and `[1, 0)` => `{}`.
+///
+/// The returned value isn't simplified.
+pub(crate) fn reduce(set: &Set) -> Option<RunVal> {
+    let reduced = match set {
+        Set::Finite(elements) => {
+            let mut distinct: Vec<RunVal> = Vec::new();
+            for element in elements {
+                if !distinct.iter().any(|it| equal(it, element) == Some(true)) {
+                    distinct.push(element.clone());
+                }
+            }
+            distinct.sort();
+            if distinct == *elements {
+                return None;
+            }
+            Set::Finite(distinct)
+        }
+        Set::Interval {
+            lower,
+            upper,
+            lower_closed,
+            upper_closed,
+        } => match order(lower, upper)? {
+            Ordering::Less => return None,
+            Ordering::Equal if *lower_closed && *upper_closed => {
+                Set::Finite(vec![(**lower).clone()])
+            }
+            _ => Set::empty(),
+        },
+        Set::Builder {
+            variable,
+            domain,
+            condition,
+        } => match (&domain.typ, &condition.typ) {
+            (_, RunType::Bool(true)) => return Some((**domain).clone()),
+            (_, RunType::Bool(false)) => Set::empty(),
+            // The elements of a finite domain are checked one by one
+            (RunType::Set(Set::Finite(elements)), _) => {
+                let mut kept = Vec::new();
+                for element in elements {
+                    if satisfies(variable, condition, element)? {
+                        kept.push(element.clone());
+                    }
+                }
+                Set::Finite(kept)
+            }
+            _ => return None,
+        },
+        Set::Numbers(_) => return None,
+    };
+
+    sets_debug!("{:?} => {:?}", set, reduced);
+    Some(RunType::Set(reduced).into())
+}
+
+/// Whether the value belongs to the set, or `None` if that can't be told.
+///
+/// Unions and the like are looked into, e.g. `1 in union({1}, A)` holds for any set `A`.
+pub(crate) fn contains(set: &RunVal, value: &RunVal) -> Option<bool> {
+    let contains_in = |sets: &[RunVal]| sets.iter().map(|it| contains(it, value)).collect();
+
+    match &set.typ {
+        RunType::Set(Set::Finite(elements)) => {
+            any(elements.iter().map(|it| equal(it, value)).collect())
+        }
+        RunType::Set(Set::Interval {
+            lower,
+            upper,
+            lower_closed,
+            upper_closed,
+        }) => {
+            if Numbers::Reals.contains(value) == Some(false) {
+                return Some(false);
+            }
+            let relation = |closed: bool| if closed { "less_equal" } else { "less" };
+            all(vec![
+                holds(relation(*lower_closed), lower, value),
+                holds(relation(*upper_closed), value, upper),
+            ])
+        }
+        RunType::Set(Set::Builder {
+            variable,
+            domain,
+            condition,
+        }) => all(vec![
+            contains(domain, value),
+            satisfies(variable, condition, value),
+        ]),
+        RunType::Set(Set::Numbers(numbers)) => numbers.contains(value),
+        RunType::Function(name, sets) => match (name.as_str(), sets.as_slice()) {
+            ("union", _) => any(contains_in(sets)),
+            ("intersection", _) => all(contains_in(sets)),
+            ("difference", [set, other]) => all(vec![
+                contains(set, value),
+                contains(other, value).map(|it| !it),
+            ]),
+            ("complement", [set]) => all(vec![
+                Numbers::Reals.contains(value),
+                contains(set, value).map(|it| !it),
+            ]),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Set operations on known sets, e.g. `union({1}, {2})` => `{1, 2}`, and `x in A`.
+pub(crate) fn evaluate(name: &str, args: &[RunVal]) -> Option<RunVal> {
+    let mut value = match (name, args) {
+        ("element", [value, set]) => contains(set, value).map(|it| RunType::Bool(it).into()),
+        ("union", [_, ..]) => combine(name, args, union),
+        ("intersection", [_, ..]) => combine(name, args, intersection),
+        ("difference", [set, other]) => difference(set, other),
+        ("complement", [set]) => complement(set),
+        ("card", [set]) => cardinality(set),
+        _ => None,
+    }?;
+    value.simplify();
+    Some(value)
+}
+
+/// Combines the sets of a union or intersection two at a time, while they can be combined.
+fn combine(
+    name: &str,
+    args: &[RunVal],
+    pair: fn(&RunVal, &RunVal) -> Option<RunVal>,
+) -> Option<RunVal> {
+    // Nested operations of the same kind are flattened, e.g. union(A, union(B, C))
+    let flattened = args.iter().flat_map(|arg| match &arg.typ {
+        RunType::Function(inner, sets) if inner == name => sets.clone(),
+        _ => vec![arg.clone()],
+    });
+
+    let mut sets: Vec<RunVal> = Vec::new();
+    for set in flattened {
+        let mut current = set;
+        let mut i = 0;
+        while i < sets.len() {
+            match pair(&sets[i], &current) {
+                Some(mut combined) => {
+                    combined.simplify();
+                    current = combined;
+                    sets.remove(i);
+                    i = 0;
+                }
+                None => i += 1,
+            }
+        }
+        sets.push(current);
+    }
+
+    match sets.as_slice() {
+        [set] => Some(set.clone()),
+        _ if sets != args => Some(RunType::Function(name.to_string(), sets).into()),
+        _ => None,
+    }
+}
+
+fn union(lhs: &RunVal, rhs: &RunVal) -> Option<RunVal> {
+    let set = match (&lhs.typ, &rhs.typ) {
+        (RunType::Set(Set::Finite(lhs)), RunType::Set(Set::Finite(rhs))) => {
+            Set::Finite(lhs.iter().chain(rhs).cloned().collect())
+        }
+        // Elements that are in the other set already add nothing
+        (RunType::Set(Set::Finite(elements)), _) if is_subset(elements, rhs) => {
+            return Some(rhs.clone())
+        }
+        (_, RunType::Set(Set::Finite(elements))) if is_subset(elements, lhs) => {
+            return Some(lhs.clone())
+        }
+        (RunType::Set(Set::Numbers(lhs)), RunType::Set(Set::Numbers(rhs))) => {
+            Set::Numbers(*lhs.max(rhs))
+        }
+        (RunType::Set(Set::Interval { .. }), RunType::Set(Set::Numbers(Numbers::Reals)))
+        | (RunType::Set(Set::Numbers(Numbers::Reals)), RunType::Set(Set::Interval { .. })) => {
+            Set::Numbers(Numbers::Reals)
+        }
+        (RunType::Set(lhs @ Set::Interval { .. }), RunType::Set(rhs @ Set::Interval { .. })) => {
+            let (lhs, rhs) = (interval_bounds(lhs)?, interval_bounds(rhs)?);
+            // Intervals with a gap between them stay apart
+            if !touches(lhs.1, rhs.0) || !touches(rhs.1, lhs.0) {
+                return None;
+            }
+            bounded(
+                outer(lhs.0, rhs.0, Ordering::Less)?,
+                outer(lhs.1, rhs.1, Ordering::Greater)?,
+            )
+        }
+        _ => return None,
+    };
+    Some(RunType::Set(set).into())
+}
+
+fn intersection(lhs: &RunVal, rhs: &RunVal) -> Option<RunVal> {
+    let set = match (&lhs.typ, &rhs.typ) {
+        // The elements of a finite set are checked one by one
+        (RunType::Set(Set::Finite(elements)), _) => Set::Finite(filter(elements, rhs, true)?),
+        (_, RunType::Set(Set::Finite(elements))) => Set::Finite(filter(elements, lhs, true)?),
+        (RunType::Set(Set::Numbers(lhs)), RunType::Set(Set::Numbers(rhs))) => {
+            Set::Numbers(*lhs.min(rhs))
+        }
+        (
+            interval @ RunType::Set(Set::Interval { .. }),
+            RunType::Set(Set::Numbers(Numbers::Reals)),
+        )
+        | (
+            RunType::Set(Set::Numbers(Numbers::Reals)),
+            interval @ RunType::Set(Set::Interval { .. }),
+        ) => return Some(RunVal::new(interval.clone())),
+        (RunType::Set(lhs @ Set::Interval { .. }), RunType::Set(rhs @ Set::Interval { .. })) => {
+            let (lhs, rhs) = (interval_bounds(lhs)?, interval_bounds(rhs)?);
+            bounded(
+                inner(lhs.0, rhs.0, Ordering::Less)?,
+                inner(lhs.1, rhs.1, Ordering::Greater)?,
+            )
+        }
+        _ => return None,
+    };
+    Some(RunType::Set(set).into())
+}
+
+fn difference(set: &RunVal, other: &RunVal) -> Option<RunVal> {
+    let set = match (&set.typ, &other.typ) {
+        (_, RunType::Set(Set::Finite(elements))) if elements.is_empty() => {
+            return Some(set.clone())
+        }
+        (RunType::Set(Set::Finite(elements)), _) => Set::Finite(filter(elements, other, false)?),
+        (_, RunType::Set(Set::Numbers(Numbers::Reals))) => Set::empty(),
+        _ => return None,
+    };
+    Some(RunType::Set(set).into())
+}
+
+/// The real numbers that aren't in the set.
+fn complement(set: &RunVal) -> Option<RunVal> {
+    let infinity =
+        |sign: f64| -> Box<RunVal> { Box::new(RunType::Number(sign * f64::INFINITY).into()) };
+
+    let complement = match &set.typ {
+        RunType::Set(Set::Interval {
+            lower,
+            upper,
+            lower_closed,
+            upper_closed,
+        }) => {
+            let below = Set::Interval {
+                lower: infinity(-1.0),
+                upper: lower.clone(),
+                lower_closed: false,
+                upper_closed: !lower_closed,
+            };
+            let above = Set::Interval {
+                lower: upper.clone(),
+                upper: infinity(1.0),
+                lower_closed: !upper_closed,
+                upper_closed: false,
+            };
+            RunType::Function(
+                "union".to_string(),
+                vec![RunType::Set(below).into(), RunType::Set(above).into()],
+            )
+        }
+        RunType::Set(Set::Numbers(Numbers::Reals)) => RunType::Set(Set::empty()),
+        RunType::Function(name, sets) if name == "complement" && sets.len() == 1 => {
+            return Some(sets[0].clone())
+        }
+        _ => return None,
+    };
+    Some(complement.into())
+}
+
+/// The number of elements, infinite for intervals and the sets of numbers.
+fn cardinality(set: &RunVal) -> Option<RunVal> {
+    let count = match &set.typ {
+        // Only if the elements are known to be different, `{x, y}` may have a single element
+        RunType::Set(Set::Finite(elements)) => {
+            for (i, element) in elements.iter().enumerate() {
+                for other in &elements[i + 1..] {
+                    if equal(element, other) != Some(false) {
+                        return None;
+                    }
+                }
+            }
+            elements.len() as f64
+        }
+        RunType::Set(Set::Interval { lower, upper, .. }) => match order(lower, upper)? {
+            Ordering::Less => f64::INFINITY,
+            _ => return None,
+        },
+        RunType::Set(Set::Numbers(_)) => f64::INFINITY,
+        _ => return None,
+    };
+    Some(RunType::Number(count).into())
+}
+
+/// The elements that are (or aren't) in the set, `None` if that can't be told for all of them.
+fn filter(elements: &[RunVal], set: &RunVal, inside: bool) -> Option<Vec<RunVal>> {
+    let mut kept = Vec::new();
+    for element in elements {
+        if contains(set, element)? == inside {
+            kept.push(element.clone());
+        }
+    }
+    Some(kept)
+}
+
+fn is_subset(elements: &[RunVal], set: &RunVal) -> bool {
+    elements.iter().all(|it| contains(set, it) == Some(true))
+}
+
+/// A bound of an interval, the value and whether it is included.
+type Bound<'a> = (&'a RunVal, bool);
+
+fn interval_bounds(set: &Set) -> Option<(Bound<'_>, Bound<'_>)> {
+    match set {
+        Set::Interval {
+            lower,
+            upper,
+            lower_closed,
+            upper_closed,
+        } => Some(((lower, *lower_closed), (upper, *upper_closed))),
+        _ => None,
+    }
+}
+
+/// The bound further out in the direction, e.g. the lower one of two lower bounds for `Less`.
+/// A bound that is included is further out than an equal one that isn't.
+fn outer<'a>(lhs: Bound<'a>, rhs: Bound<'a>, direction: Ordering) -> Option<Bound<'a>> {
+    match order(lhs.0, rhs.0)? {
+        Ordering::Equal => Some((lhs.0, lhs.1 || rhs.1)),
+        ordering if ordering == direction => Some(lhs),
+        _ => Some(rhs),
+    }
+}
+
+/// The bound further in from the direction, see [`outer`].
+fn inner<'a>(lhs: Bound<'a>, rhs: Bound<'a>, direction: Ordering) -> Option<Bound<'a>> {
+    match order(lhs.0, rhs.0)? {
+        Ordering::Equal => Some((lhs.0, lhs.1 && rhs.1)),
+        ordering if ordering == direction => Some(rhs),
+        _ => Some(lhs),
+    }
+}
+
+/// Whether an interval ending at `upper` reaches one starting at `lower`.
+fn touches(upper: Bound, lower: Bound) -> bool {
+    match order(upper.0, lower.0) {
+        Some(Ordering::Greater) => true,
+        Some(Ordering::Equal) => upper.1 || lower.1,
+        _ => false,
+    }
+}
+
+fn bounded(lower: Bound, upper: Bound) -> Set {
+    Set::Interval {
+        lower: Box::new(lower.0.clone()),
+        upper: Box::new(upper.0.clone()),
+        lower_closed: lower.1,
+        upper_closed: upper.1,
+    }
+}
+
+/// Whether the condition of a set-builder holds for the value.
+fn satisfies(variable: &str, condition: &RunVal, value: &RunVal) -> Option<bool> {
+    let symbol: RunVal = RunType::Symbol(variable.to_string()).into();
+    let mut condition = condition.substitute(&symbol, value);
+    condition.simplify();
+    match condition.typ {
+        RunType::Bool(holds) => Some(holds),
+        _ => None,
+    }
+}
+
+fn holds(relation: &str, lhs: &RunVal, rhs: &RunVal) -> Option<bool> {
+    match functions::evaluate(relation, &[lhs.clone(), rhs.clone()])?.typ {
+        RunType::Bool(holds) => Some(holds),
+        _ => None,
+    }
+}
+
+fn equal(lhs: &RunVal, rhs: &RunVal) -> Option<bool> {
+    holds("equal", lhs, rhs)
+}
+
+/// How two real values compare, if that can be told.
+fn order(lhs: &RunVal, rhs: &RunVal) -> Option<Ordering> {
+    if equal(lhs, rhs)? {
+        Some(Ordering::Equal)
+    } else if holds("less", lhs, rhs)? {
+        Some(Ordering::Less)
+    } else {
+        Some(Ordering::Greater)
+    }
+}
+
+/// Whether any of the answers is yes, `None` unless that can be told from the known ones.
+fn any(answers: Vec<Option<bool>>) -> Option<bool> {
+    if answers.contains(&Some(true)) {
+        Some(true)
+    } else if answers.contains(&None) {
+        None
+    } else {
+        Some(false)
+    }
+}
+
+/// Whether all of the answers are yes, `None` unless that can be told from the known ones.
+fn all(answers: Vec<Option<bool>>) -> Option<bool> {
+    if answers.contains(&Some(false)) {
+        Some(false)
+    } else if answers.contains(&None) {
+        None
+    } else {
+        Some(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cas::eval::testing::{eval, Session};
+
+    #[test]
+    fn sets_are_simplified() {
+        assert_eq!(eval("{1, 2, 2}"), "{1, 2}");
+        assert_eq!(eval("union({1, 2}, {3})"), "{1, 2, 3}");
+        assert_eq!(eval("difference({1, 2, 3}, {2})"), "{1, 3}");
+        assert_eq!(eval("complement([0, 1))"), "union((-inf, 0), [1, inf))");
+        assert_eq!(eval("{x in {1, 2, 3, 4} | x^2 < 10}"), "{1, 2, 3}");
+    }
+
+    #[test]
+    fn membership() {
+        assert_eq!(eval("1/2 in [0, 1)"), "true");
+        assert_eq!(eval("1 in [0, 1)"), "false");
+        assert_eq!(eval("3/2 in Z"), "false");
+        assert_eq!(eval("x in {1, 2}"), "x in {1, 2}");
+    }
+
+    #[test]
+    fn numbers_are_only_sets_after_in() {
+        assert_eq!(eval("2*R"), "2 * R");
+        assert_eq!(eval("3 in N"), "true");
+        assert_eq!(eval("3 in ℕ"), "true");
+        assert_eq!(eval("{x in R | x > 2}"), "{x in R | x > 2}");
+        assert_eq!(eval("typeof(ℝ)"), "set");
+        assert_eq!(eval("convert(3 N, kg*m/s^2)"), "3 kg*m/s^2");
+
+        let mut session = Session::new();
+        session.eval("R = {1, 2}");
+        assert_eq!(session.eval("3 in R"), "false");
+    }
+
+    #[test]
+    fn substitution_doesnt_capture_the_bound_variable() {
+        assert_eq!(eval("{x in R | x > y} | x = 3"), "{x in R | x > y}");
+        assert_eq!(eval("{x in R | x > y} | y = x"), "{x1 in R | x1 > x}");
+        assert_eq!(
+            eval("{x in [0, x] | x > 1} | x = 5"),
+            "{x in [0, 5] | x > 1}"
+        );
+    }
+}
@@ -0,0 +1,161 @@
+//! Solutions of equations in a variable, given as a set, e.g. `solve(x^2 == 4, x)` => `{-2, 2}`.
+
+use log::debug;
+
+use super::{
+    assumptions,
+    poly::Polynomial,
+    sets::{Numbers, Set},
+    value::{RunType, RunVal},
+};
+
+macro_rules! solve_debug {
+    ($($arg:tt)+) => (debug!(target: "matex::solve", "[{}:{}] {}", file!(), line!(), &format!($($arg)+)));
+}
+
+/// The set of values of `var` the equation holds for, `lhs == rhs` or `value == 0`.
+///
+/// Polynomial equations are solved once the rational roots are divided out, up to the quadratic
+/// formula for what is left. Roots that the assumptions about `var` rule out are left out, see
+/// [`assumptions::admits`]. `None` if the equation can't be solved.
+pub(crate) fn solve(equation: &RunVal, var: &RunVal) -> Option<RunVal> {
+    let value = match &equation.typ {
+        RunType::Bool(true) => return Some(RunType::Set(Set::Numbers(Numbers::Reals)).into()),
+        RunType::Bool(false) => return Some(RunType::Set(Set::empty()).into()),
+        RunType::Function(name, args) if name == "equal" && args.len() == 2 => {
+            let mut value = args[0]
+                .clone()
+                .add(args[1].clone().multiply(RunType::Number(-1.0).into()));
+            value.simplify();
+            value
+        }
+        _ => equation.clone(),
+    };
+
+    let poly = Polynomial::from_value(&value)?;
+    let Some(index) = poly.gen_index(var) else {
+        // The equation holds for every value or none, if it is known
+        let set = match poly.as_constant()? {
+            0.0 => Set::Numbers(Numbers::Reals),
+            _ => Set::empty(),
+        };
+        return Some(RunType::Set(set).into());
+    };
+
+    // Other generators like `sin(x)` would have to be solved for as well
+    let independent = poly
+        .gens()
+        .iter()
+        .enumerate()
+        .all(|(i, it)| i == index || !it.depends_on(var));
+    if !independent {
+        return None;
+    }
+
+    let mut poly = poly;
+    let mut roots = Vec::new();
+    for root in poly.rational_roots(index) {
+        let factor =
+            Polynomial::generator(index, poly.gens()).sub(&Polynomial::constant(root, poly.gens()));
+        while let Some((quotient, remainder)) = poly.div_rem(&factor, index) {
+            if !remainder.is_zero() {
+                break;
+            }
+            poly = quotient;
+        }
+        roots.push(RunType::Number(root).into());
+    }
+
+    let coeffs = poly.coeffs(index);
+    solve_debug!(
+        "{:?} has the roots {:?} and the coefficients {:?}",
+        value,
+        roots,
+        coeffs
+    );
+
+    match coeffs.as_slice() {
+        [] | [_] => {}
+        [c, b] => roots.push(linear(b, c)),
+        [c, b, a] => roots.extend(quadratic(a, b, c)),
+        _ => return None,
+    }
+
+    if let RunType::Symbol(name) = &var.typ {
+        roots.retain(|root| assumptions::admits(name, root));
+    }
+    Some(RunType::Set(Set::Finite(roots)).into())
+}
+
+/// The root of `b * x + c`.
+fn linear(b: &Polynomial, c: &Polynomial) -> RunVal {
+    c.neg()
+        .to_value()
+        .multiply(b.to_value().power(RunType::Number(-1.0).into()))
+}
+
+/// The real roots of `a * x^2 + b * x + c`, none if the discriminant is negative.
+fn quadratic(a: &Polynomial, b: &Polynomial, c: &Polynomial) -> Vec<RunVal> {
+    let discriminant = b.mul(b).sub(&a.mul(c).scale(4.0));
+    if discriminant.as_constant().is_some_and(|it| it < 0.0) {
+        return Vec::new();
+    }
+
+    let number = |n: f64| -> RunVal { RunType::Number(n).into() };
+    let root = discriminant.to_value().power(number(0.5));
+    let denominator = a.to_value().multiply(number(2.0)).power(number(-1.0));
+
+    let roots = [1.0, -1.0].map(|sign| {
+        b.neg()
+            .to_value()
+            .add(root.clone().multiply(number(sign)))
+            .multiply(denominator.clone())
+    });
+    roots.to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cas::eval::testing::{eval, Session};
+
+    #[test]
+    fn quadratic_equations() {
+        assert_eq!(eval("solve(x^2 - 4, x)"), "{-2, 2}");
+        assert_eq!(eval("solve(x^2 - 5*x + 6 == 0, x)"), "{2, 3}");
+        assert_eq!(
+            eval("solve(x^2 == 2, x)"),
+            "{-1.4142135623730951, 1.4142135623730951}"
+        );
+    }
+
+    #[test]
+    fn linear_equations() {
+        assert_eq!(eval("solve(2*y + 4 == 0, y)"), "{-2}");
+        assert_eq!(eval("solve(a*x - 1, x)"), "{a^-1}");
+    }
+
+    #[test]
+    fn no_real_roots() {
+        assert_eq!(eval("solve(x^2 + 1, x)"), "{}");
+        assert_eq!(eval("solve(1 == 2, x)"), "{}");
+    }
+
+    #[test]
+    fn unsolved_equations_stay() {
+        assert_eq!(eval("solve(sin(z) == 0, z)"), "solve(sin(z) == 0, z)");
+        assert_eq!(eval("solve(x^5 + x + 1, x)"), "solve(x^5 + x + 1, x)");
+    }
+
+    #[test]
+    fn roots_are_filtered_by_assumptions() {
+        let mut session = Session::new();
+        session.eval("assume(x > 0)");
+        assert_eq!(session.eval("solve(x^2 - 4, x)"), "{2}");
+        assert_eq!(session.eval("solve(x + 3, x)"), "{}");
+        assert_eq!(session.eval("solve(y^2 - 4, y)"), "{-2, 2}");
+
+        session.eval("assume(n, integer)");
+        assert_eq!(session.eval("solve(2*n - 1, n)"), "{}");
+        assert_eq!(session.eval("solve(n^2 - 4, n)"), "{-2, 2}");
+    }
+}
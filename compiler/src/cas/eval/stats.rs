@@ -0,0 +1,621 @@
+//! Statistics of data and probability distributions, e.g. `mean([1, 2, 4])` or
+//! `cdf(normaldist(0, 1), 1.96)`.
+//!
+//! Statistics of vectors are computed from sums of the data with the arithmetic of values, so
+//! integer data is only divided once at the end and symbolic data gives symbolic results, e.g.
+//! `variance([a, b])` => `0.5 * a^2 - a * b + 0.5 * b^2`.
+//!
+//! The distributions `normaldist(mu, sigma)`, `binomialdist(n, p)` and `poissondist(lambda)` are
+//! kept as function values, which `pdf`, `cdf`, `quantile` and `sample` take as their first
+//! argument. Their names keep them apart from functions like `binomial(n, k)`.
+
+use std::f64::consts::{PI, SQRT_2};
+
+use log::debug;
+
+use super::{
+    functions,
+    helpers::{approximate, number, subtract},
+    poly::Polynomial,
+    value::{Constant, RunType, RunVal, Terms},
+};
+
+macro_rules! stats_debug {
+    ($($arg:tt)+) => (debug!(target: "matex::stats", "[{}:{}] {}", file!(), line!(), &format!($($arg)+)));
+}
+
+/// Evaluates a statistic of vectors or a function of a distribution, `None` if the arguments
+/// don't fit, e.g. `median([x, 1])` where `x` can't be ordered.
+pub(crate) fn evaluate(name: &str, args: &[RunVal]) -> Option<RunVal> {
+    if let "normaldist" | "binomialdist" | "poissondist" = name {
+        return Some(match Distribution::new(name, args) {
+            Some(_) => RunType::Function(name.to_string(), args.to_vec()).into(),
+            None => RunType::Undefined.into(),
+        });
+    }
+
+    let value = match (name, args) {
+        ("mean", [data]) => {
+            let data = data_of(data)?;
+            divide(total(data.iter().cloned()), number(data.len() as f64))
+        }
+        ("median", [data]) => quantile(data_of(data)?, 0.5)?,
+        ("mode", [data]) => mode(data_of(data)?),
+        ("variance", [data]) => variance(data_of(data)?)?,
+        ("stdev", [data]) => variance(data_of(data)?)?.power(number(0.5)),
+        ("quantile", [dist, p]) if Distribution::of(dist).is_some() => {
+            Distribution::of(dist)?.quantile(approximate(p)?)?
+        }
+        ("quantile", [data, p]) => quantile(data_of(data)?, approximate(p)?)?,
+        ("covariance", [xs, ys]) => {
+            let sums = Sums::new(data_of(xs)?, data_of(ys)?)?;
+            let n = number(sums.count);
+            divide(
+                sums.deviation_xy(),
+                n.clone().multiply(subtract(n, number(1.0))),
+            )
+        }
+        ("correlation", [xs, ys]) => {
+            let sums = Sums::new(data_of(xs)?, data_of(ys)?)?;
+            let deviations = sums.deviation_xx().multiply(sums.deviation_yy());
+            divide(sums.deviation_xy(), deviations.power(number(0.5)))
+        }
+        // linreg([1, 2, 3, 4], [3, 5, 6, 10], x) => 2.2 * x + 0.5, the least squares line, and
+        // linreg([1, 2, 3, 4], [3, 5, 6, 10]) => [2.2, 0.5], its slope and intercept
+        ("linreg", [xs, ys]) => {
+            let (slope, intercept) = least_squares(xs, ys)?;
+            RunType::Vector(vec![expanded(slope), expanded(intercept)]).into()
+        }
+        ("linreg", [xs, ys, var]) if matches!(var.typ, RunType::Symbol(_)) => {
+            let (slope, intercept) = least_squares(xs, ys)?;
+            expanded(intercept).add(expanded(slope).multiply(var.clone()))
+        }
+        ("pdf", [dist, x]) => Distribution::of(dist)?.pdf(x)?,
+        ("cdf", [dist, x]) => Distribution::of(dist)?.cdf(approximate(x)?)?,
+        ("sample", [dist, count, seed]) => {
+            let dist = Distribution::of(dist)?;
+            let count = natural(count)?;
+            let mut random = Random::new(natural(seed)?);
+            let samples = (0..count)
+                .map(|_| dist.quantile(random.next()))
+                .collect::<Option<Vec<_>>>()?;
+            RunType::Vector(samples).into()
+        }
+        _ => return None,
+    };
+
+    stats_debug!("{}({:?}) = {:?}", name, args, value);
+    Some(expanded(value))
+}
+
+/// The slope and intercept of the least squares line through the points.
+fn least_squares(xs: &RunVal, ys: &RunVal) -> Option<(RunVal, RunVal)> {
+    let sums = Sums::new(data_of(xs)?, data_of(ys)?)?;
+    let slope = divide(sums.deviation_xy(), sums.deviation_xx());
+    let intercept = divide(
+        subtract(sums.y.clone(), slope.clone().multiply(sums.x.clone())),
+        number(sums.count),
+    );
+    Some((slope, intercept))
+}
+
+/// The values of a non-empty vector.
+fn data_of(value: &RunVal) -> Option<&[RunVal]> {
+    match &value.typ {
+        RunType::Vector(values) if !values.is_empty() => Some(values),
+        _ => None,
+    }
+}
+
+fn total(values: impl Iterator<Item = RunVal>) -> RunVal {
+    let values: Vec<RunVal> = values.collect();
+    // Simplifying large sums is slow, and numbers don't need it
+    let numbers = values.iter().map(|it| match it.typ {
+        RunType::Number(n) => Some(n),
+        _ => None,
+    });
+    if let Some(sum) = numbers.sum::<Option<f64>>() {
+        return number(sum);
+    }
+
+    let mut sum: RunVal = RunType::Sum(Terms(values)).into();
+    sum.simplify();
+    sum
+}
+
+fn divide(lhs: RunVal, rhs: RunVal) -> RunVal {
+    // Dividing directly rounds once, where multiplying by the reciprocal would round twice
+    if let (RunType::Number(lhs), RunType::Number(rhs)) = (&lhs.typ, &rhs.typ) {
+        return number(lhs / rhs);
+    }
+    lhs.multiply(rhs.power(number(-1.0)))
+}
+
+/// Polynomials are multiplied out, so that the terms of symbolic statistics cancel.
+///
+/// Numbers are kept as they are, since polynomials drop tiny coefficients like `1e-15`.
+fn expanded(mut value: RunVal) -> RunVal {
+    value.simplify();
+    if let RunType::Number(_) = value.typ {
+        return value;
+    }
+    let mut value = match Polynomial::from_value(&value) {
+        Some(poly) => poly.to_value(),
+        None => value,
+    };
+    value.simplify();
+    value
+}
+
+fn natural(value: &RunVal) -> Option<u64> {
+    match value.typ {
+        RunType::Number(n) if n >= 0.0 && n.fract() == 0.0 => Some(n as u64),
+        _ => None,
+    }
+}
+
+/// The p-quantile of the data, interpolated linearly between the closest values.
+///
+/// The data has to be ordered, so every value has to approximate a number, while the quantile
+/// is computed from the exact values.
+fn quantile(data: &[RunVal], p: f64) -> Option<RunVal> {
+    if !(0.0..=1.0).contains(&p) {
+        return None;
+    }
+
+    let mut sorted = data
+        .iter()
+        .map(|value| Some((approximate(value)?, value.clone())))
+        .collect::<Option<Vec<_>>>()?;
+    sorted.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+
+    let position = (sorted.len() - 1) as f64 * p;
+    let index = position.floor() as usize;
+    let fraction = position - position.floor();
+
+    let lower = sorted[index].1.clone();
+    if fraction == 0.0 {
+        return Some(lower);
+    }
+    let upper = sorted[index + 1].1.clone();
+    Some(
+        lower
+            .clone()
+            .add(number(fraction).multiply(subtract(upper, lower))),
+    )
+}
+
+/// The most common value, or a vector of them if several are equally common.
+fn mode(data: &[RunVal]) -> RunVal {
+    let mut counts: Vec<(&RunVal, usize)> = Vec::new();
+    for value in data {
+        match counts.iter_mut().find(|(it, _)| *it == value) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((value, 1)),
+        }
+    }
+
+    let most = counts
+        .iter()
+        .map(|(_, count)| *count)
+        .max()
+        .unwrap_or_default();
+    let mut modes: Vec<RunVal> = counts
+        .into_iter()
+        .filter(|(_, count)| *count == most)
+        .map(|(value, _)| value.clone())
+        .collect();
+    modes.sort();
+
+    match modes.len() {
+        1 => modes.remove(0),
+        _ => RunType::Vector(modes).into(),
+    }
+}
+
+/// The sample variance, divided by `n - 1`.
+fn variance(data: &[RunVal]) -> Option<RunVal> {
+    let sums = Sums::new(data, data)?;
+    let n = number(sums.count);
+    Some(divide(
+        sums.deviation_xx(),
+        n.clone().multiply(subtract(n, number(1.0))),
+    ))
+}
+
+/// The sums of paired data that the statistics are computed from.
+///
+/// Deviations from the mean are written with sums only, e.g. `n * Σx² - (Σx)²`, so that nothing
+/// is divided before the end.
+struct Sums {
+    count: f64,
+    x: RunVal,
+    y: RunVal,
+    xx: RunVal,
+    yy: RunVal,
+    xy: RunVal,
+}
+
+impl Sums {
+    /// `None` unless there are at least two pairs.
+    fn new(xs: &[RunVal], ys: &[RunVal]) -> Option<Sums> {
+        if xs.len() != ys.len() || xs.len() < 2 {
+            return None;
+        }
+
+        let squares =
+            |values: &[RunVal]| total(values.iter().map(|it| it.clone().multiply(it.clone())));
+        Some(Sums {
+            count: xs.len() as f64,
+            x: total(xs.iter().cloned()),
+            y: total(ys.iter().cloned()),
+            xx: squares(xs),
+            yy: squares(ys),
+            xy: total(
+                xs.iter()
+                    .zip(ys)
+                    .map(|(x, y)| x.clone().multiply(y.clone())),
+            ),
+        })
+    }
+
+    fn deviation(&self, squares: &RunVal, lhs: &RunVal, rhs: &RunVal) -> RunVal {
+        let scaled = number(self.count).multiply(squares.clone());
+        expanded(subtract(scaled, lhs.clone().multiply(rhs.clone())))
+    }
+
+    fn deviation_xx(&self) -> RunVal {
+        self.deviation(&self.xx, &self.x, &self.x)
+    }
+
+    fn deviation_yy(&self) -> RunVal {
+        self.deviation(&self.yy, &self.y, &self.y)
+    }
+
+    fn deviation_xy(&self) -> RunVal {
+        self.deviation(&self.xy, &self.x, &self.y)
+    }
+}
+
+/// A probability distribution, with parameters that may be symbolic where the formulas allow it.
+enum Distribution {
+    Normal { mean: RunVal, deviation: RunVal },
+    Binomial { trials: u64, probability: RunVal },
+    Poisson { rate: RunVal },
+}
+
+impl Distribution {
+    /// The distribution `name(args)`, `None` if a parameter is out of range, e.g. `normaldist(0, -1)`.
+    fn new(name: &str, args: &[RunVal]) -> Option<Distribution> {
+        // Symbolic parameters are allowed, they can't be checked
+        let holds = |value: &RunVal, check: fn(f64) -> bool| match approximate(value) {
+            Some(n) => check(n),
+            None => true,
+        };
+        match (name, args) {
+            ("normaldist", [mean, deviation]) if holds(deviation, |it| it > 0.0) => {
+                Some(Distribution::Normal {
+                    mean: mean.clone(),
+                    deviation: deviation.clone(),
+                })
+            }
+            ("binomialdist", [trials, probability])
+                if holds(probability, |it| (0.0..=1.0).contains(&it)) =>
+            {
+                Some(Distribution::Binomial {
+                    trials: natural(trials)?,
+                    probability: probability.clone(),
+                })
+            }
+            ("poissondist", [rate]) if holds(rate, |it| it >= 0.0) => {
+                Some(Distribution::Poisson { rate: rate.clone() })
+            }
+            _ => None,
+        }
+    }
+
+    fn of(value: &RunVal) -> Option<Distribution> {
+        match &value.typ {
+            RunType::Function(name, args) => Distribution::new(name, args),
+            _ => None,
+        }
+    }
+
+    /// The density at `x`, or the probability of `x` for discrete distributions.
+    fn pdf(&self, x: &RunVal) -> Option<RunVal> {
+        match self {
+            Distribution::Normal { mean, deviation } => {
+                let z = divide(subtract(x.clone(), mean.clone()), deviation.clone());
+                let exponent = z.clone().multiply(z).multiply(number(-0.5));
+                let scale = RunType::Constant(Constant::Pi).into();
+                let scale = deviation
+                    .clone()
+                    .multiply(number(2.0).multiply(scale).power(number(0.5)));
+                Some(divide(exp(exponent), scale))
+            }
+            Distribution::Binomial {
+                trials,
+                probability,
+            } => {
+                let Some(k) = natural(x).filter(|k| k <= trials) else {
+                    return approximate(x).map(|_| number(0.0));
+                };
+                let ways = binomial_coefficient(*trials, k);
+                let failure = subtract(number(1.0), probability.clone());
+                Some(
+                    number(ways)
+                        .multiply(probability.clone().power(number(k as f64)))
+                        .multiply(failure.power(number((trials - k) as f64))),
+                )
+            }
+            Distribution::Poisson { rate } => {
+                let Some(k) = natural(x) else {
+                    return approximate(x).map(|_| number(0.0));
+                };
+                // Large counts would overflow k!, so known rates are computed with logarithms
+                if let Some(rate) = approximate(rate) {
+                    let log_factorial: f64 = (1..=k).map(|i| (i as f64).ln()).sum();
+                    let probability = match rate {
+                        0.0 if k == 0 => 1.0,
+                        _ => (k as f64 * rate.ln() - rate - log_factorial).exp(),
+                    };
+                    return Some(number(probability));
+                }
+                let factorial: f64 = (1..=k).map(|i| i as f64).product();
+                let power = rate.clone().power(number(k as f64));
+                Some(divide(
+                    power.multiply(exp(rate.clone().multiply(number(-1.0)))),
+                    number(factorial),
+                ))
+            }
+        }
+    }
+
+    /// The probability of a value at most `x`.
+    fn cdf(&self, x: f64) -> Option<RunVal> {
+        match self {
+            Distribution::Normal { mean, deviation } => {
+                let z = (x - approximate(mean)?) / approximate(deviation)?;
+                Some(number(normal_cdf(z)))
+            }
+            Distribution::Binomial { .. } | Distribution::Poisson { .. } if x < 0.0 => {
+                Some(number(0.0))
+            }
+            Distribution::Binomial { trials, .. } => {
+                let last = (x.floor() as u64).min(*trials);
+                let probabilities = (0..=last).map(|k| self.pdf(&number(k as f64)));
+                Some(total(
+                    probabilities.collect::<Option<Vec<_>>>()?.into_iter(),
+                ))
+            }
+            Distribution::Poisson { .. } => {
+                let probabilities = (0..=x.floor() as u64).map(|k| self.pdf(&number(k as f64)));
+                Some(total(
+                    probabilities.collect::<Option<Vec<_>>>()?.into_iter(),
+                ))
+            }
+        }
+    }
+
+    /// The smallest value with a cdf of at least `p`.
+    ///
+    /// The normal quantile is `mu + z * sigma`, which stays symbolic in the parameters.
+    fn quantile(&self, p: f64) -> Option<RunVal> {
+        if !(0.0..=1.0).contains(&p) {
+            return None;
+        }
+
+        match self {
+            Distribution::Normal { mean, deviation } => {
+                let z = number(normal_quantile(p));
+                Some(mean.clone().add(z.multiply(deviation.clone())))
+            }
+            Distribution::Binomial { trials, .. } => {
+                let mut cumulative = 0.0;
+                for k in 0..*trials {
+                    cumulative += approximate(&self.pdf(&number(k as f64))?)?;
+                    if cumulative >= p {
+                        return Some(number(k as f64));
+                    }
+                }
+                Some(number(*trials as f64))
+            }
+            Distribution::Poisson { rate } => {
+                approximate(rate)?;
+                if p == 1.0 {
+                    return Some(number(f64::INFINITY));
+                }
+                let mut cumulative = 0.0;
+                let mut k = 0;
+                // Rounding may keep the sum below p, in which case it stops growing
+                loop {
+                    let probability = approximate(&self.pdf(&number(k as f64))?)?;
+                    cumulative += probability;
+                    if cumulative >= p || (probability == 0.0 && k as f64 > approximate(rate)?) {
+                        return Some(number(k as f64));
+                    }
+                    k += 1;
+                }
+            }
+        }
+    }
+}
+
+fn exp(value: RunVal) -> RunVal {
+    let mut value = functions::call("exp", &value);
+    value.simplify();
+    value
+}
+
+/// `n choose k`, exact while it fits into a float.
+fn binomial_coefficient(n: u64, k: u64) -> f64 {
+    let k = k.min(n - k);
+    (0..k).fold(1.0, |ways, i| ways * (n - i) as f64 / (i + 1) as f64)
+}
+
+/// The probability of a standard normal value at most `z`.
+fn normal_cdf(z: f64) -> f64 {
+    0.5 * erfc(-z / SQRT_2)
+}
+
+/// The complementary error function `1 - erf(x)`, accurate to about 1e-15.
+///
+/// Close to zero it is computed from a series of positive terms, further out from a continued
+/// fraction, which keeps the small tail values precise.
+fn erfc(x: f64) -> f64 {
+    if x < 0.0 {
+        return 2.0 - erfc(-x);
+    }
+    if x < 3.0 {
+        // erf(x) = 2/sqrt(π) * e^(-x²) * Σ 2^n x^(2n+1) / (1 * 3 * ... * (2n+1))
+        let mut term = x;
+        let mut sum = x;
+        let mut n = 0.0;
+        while term > sum * f64::EPSILON {
+            n += 1.0;
+            term *= 2.0 * x * x / (2.0 * n + 1.0);
+            sum += term;
+        }
+        return 1.0 - 2.0 / PI.sqrt() * (-x * x).exp() * sum;
+    }
+
+    // erfc(x) = e^(-x²)/sqrt(π) / (x + (1/2)/(x + 1/(x + (3/2)/(x + ...))))
+    let fraction = (1..=60)
+        .rev()
+        .fold(x, |fraction, k| x + k as f64 / 2.0 / fraction);
+    (-x * x).exp() / PI.sqrt() / fraction
+}
+
+/// The standard normal value with a cdf of `p`.
+///
+/// Uses the rational approximation by Peter Acklam, refined with one step of Halley's method.
+fn normal_quantile(p: f64) -> f64 {
+    const A: [f64; 6] = [
+        -3.969683028665376e1,
+        2.209460984245205e2,
+        -2.759285104469687e2,
+        1.38357751867269e2,
+        -3.066479806614716e1,
+        2.506628277459239,
+    ];
+    const B: [f64; 5] = [
+        -5.447609879822406e1,
+        1.615858368580409e2,
+        -1.556989798598866e2,
+        6.680131188771972e1,
+        -1.328068155288572e1,
+    ];
+    const C: [f64; 6] = [
+        -7.784894002430293e-3,
+        -3.223964580411365e-1,
+        -2.400758277161838,
+        -2.549732539343734,
+        4.374664141464968,
+        2.938163982698783,
+    ];
+    const D: [f64; 4] = [
+        7.784695709041462e-3,
+        3.224671290700398e-1,
+        2.445134137142996,
+        3.754408661907416,
+    ];
+    const LOW: f64 = 0.02425;
+
+    let horner = |coefficients: &[f64], x: f64| coefficients.iter().fold(0.0, |acc, c| acc * x + c);
+    let tail = |q: f64| {
+        let q = (-2.0 * q.ln()).sqrt();
+        horner(&C, q) / (horner(&D, q) * q + 1.0)
+    };
+
+    let z = match p {
+        0.0 => return f64::NEG_INFINITY,
+        1.0 => return f64::INFINITY,
+        _ if p < LOW => tail(p),
+        _ if p > 1.0 - LOW => -tail(1.0 - p),
+        _ => {
+            let q = p - 0.5;
+            let r = q * q;
+            horner(&A, r) * q / (horner(&B, r) * r + 1.0)
+        }
+    };
+
+    let error = normal_cdf(z) - p;
+    let u = error * (2.0 * PI).sqrt() * (z * z / 2.0).exp();
+    z - u / (1.0 + z * u / 2.0)
+}
+
+/// The SplitMix64 generator, so that samples with the same seed are the same.
+struct Random(u64);
+
+impl Random {
+    fn new(seed: u64) -> Random {
+        Random(seed)
+    }
+
+    /// A uniform number between 0 and 1, excluding both.
+    fn next(&mut self) -> f64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^= z >> 31;
+        ((z >> 11) as f64 + 0.5) / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cas::eval::testing::eval;
+
+    #[test]
+    fn descriptive_statistics() {
+        assert_eq!(eval("mean([1, 2, 4])"), "2.3333333333333335");
+        assert_eq!(eval("mean([a, b])"), "0.5 * a + 0.5 * b");
+        assert_eq!(eval("median([4, 1, 3, 2])"), "2.5");
+        assert_eq!(eval("median([x, 1])"), "median([x, 1])");
+        assert_eq!(eval("mode([1, 1, 2, 2])"), "[1, 2]");
+        assert_eq!(eval("variance([1, 2, 3, 4])"), "1.6666666666666667");
+        assert_eq!(eval("variance([a, b])"), "0.5 * a^2 - a * b + 0.5 * b^2");
+        assert_eq!(eval("quantile([1, 2, 3, 4, 5], 1/4)"), "2");
+        assert_eq!(eval("correlation([1, 2, 3], [2, 4, 6])"), "1");
+        assert_eq!(
+            eval("linreg([1, 2, 3, 4], [3, 5, 6, 10], x)"),
+            "2.2 * x + 0.5"
+        );
+        assert_eq!(eval("linreg([1, 2, 3, 4], [3, 5, 6, 10])"), "[2.2, 0.5]");
+        assert_eq!(
+            eval("linreg([1, 2, 3], [2, 4, 7])"),
+            "[2.5, -0.6666666666666666]"
+        );
+    }
+
+    #[test]
+    fn distributions() {
+        assert_eq!(eval("pdf(normaldist(0, 1), 0)"), "(2 * PI)^-0.5");
+        assert_eq!(eval("cdf(normaldist(0, 1), 196/100)"), "0.9750021048517794");
+        assert_eq!(eval("quantile(normaldist(m, s), 1/2)"), "m");
+        assert_eq!(eval("pdf(binomialdist(4, 1/2), 2)"), "0.375");
+        assert_eq!(
+            eval("pdf(binomialdist(3, p), 1)"),
+            "3 * p^3 - 6 * p^2 + 3 * p"
+        );
+        assert_eq!(eval("quantile(poissondist(4), 9/10)"), "7");
+    }
+
+    #[test]
+    fn parameters_out_of_range() {
+        assert_eq!(eval("normaldist(0, -1)"), "undefined");
+        assert_eq!(eval("binomialdist(4, 2)"), "undefined");
+        assert_eq!(eval("poissondist(-1)"), "undefined");
+        assert_eq!(eval("normaldist(0, s)"), "normaldist(0, s)");
+    }
+
+    #[test]
+    fn samples_are_reproducible() {
+        assert_eq!(eval("sample(poissondist(4), 5, 7)"), "[3, 0, 7, 4, 4]");
+        assert_eq!(
+            eval("sample(binomialdist(10, 1/2), 5, 7)"),
+            "[5, 2, 7, 5, 5]"
+        );
+    }
+}
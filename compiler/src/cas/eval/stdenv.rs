@@ -1,46 +1,629 @@
+use crate::cas::types;
+
 use super::{
+    assumptions, diff, functions, integrate,
+    interval::{self, Interval},
+    limit::{self, Direction},
+    ode,
+    poly::Polynomial,
+    rational, rules,
     runtime::Runtime,
-    value::{RunType, RunVal},
+    series::{self, Point},
+    sets::{Numbers, Set},
+    solve, stats, sums, trace, units,
+    value::{Constant, RunType, RunVal},
 };
 
 impl Runtime {
     pub fn add_standard_environment(&mut self) {
-        const PI: f64 = std::f64::consts::PI;
-        self.environment
-            .constants
-            .insert("PI".to_string(), RunVal::new(RunType::Number(PI)));
-        self.environment
-            .constants
-            .insert("π".to_string(), RunVal::new(RunType::Number(PI)));
+        let constants = &mut self.environment.constants;
+        constants.insert("PI".to_string(), RunType::Constant(Constant::Pi).into());
+        constants.insert("π".to_string(), RunType::Constant(Constant::Pi).into());
+        constants.insert("E".to_string(), RunType::Constant(Constant::E).into());
+        constants.insert("I".to_string(), RunType::Constant(Constant::I).into());
+
+        constants.insert("inf".to_string(), RunType::Number(f64::INFINITY).into());
+        constants.insert("∞".to_string(), RunType::Number(f64::INFINITY).into());
+
+        constants.insert("undefined".to_string(), RunType::Undefined.into());
+        constants.insert("NaN".to_string(), RunType::Undefined.into());
+
+        // N, Z, Q and R are only sets after `in`, elsewhere they are ordinary names
+        for (name, numbers) in [
+            ("ℕ", Numbers::Naturals),
+            ("ℤ", Numbers::Integers),
+            ("ℚ", Numbers::Rationals),
+            ("ℝ", Numbers::Reals),
+        ] {
+            constants.insert(name.to_string(), RunType::Set(Set::Numbers(numbers)).into());
+        }
 
         self.environment
             .intrinsics
-            .insert("sin".to_string(), |args| {
-                let Some(arg) = args.first() else {
-                panic!("No arguments passed???");
-            };
-
-                match arg.typ {
-                    RunType::Number(n) => RunVal::new(RunType::Number(n.sin())),
-                    _ => {
-                        panic!("Not a number!!!");
+            .insert("N".to_string(), |args| match args.as_slice() {
+                [value] => interval::approximate(value).unwrap_or_else(|| value.approximate()),
+                // N(value, interval) bounds the rounding errors of the approximation
+                [value, mode] if matches!(&mode.typ, RunType::Symbol(it) if it == "interval") => {
+                    match interval::enclose(value) {
+                        Some(interval) => RunType::Interval(interval).into(),
+                        None => unevaluated("N", args),
                     }
                 }
+                _ => unevaluated("N", args),
             });
-
         self.environment
             .intrinsics
-            .insert("cos".to_string(), |args| {
-                let Some(arg) = args.first() else {
-                panic!("No arguments passed???");
+            .insert("typeof".to_string(), |args| match args.as_slice() {
+                [value] => RunType::Symbol(types::describe(value)).into(),
+                _ => unevaluated("typeof", args),
+            });
+
+        self.add_elementary_functions();
+        self.add_piecewise_functions();
+        self.add_set_functions();
+        self.add_interval_functions();
+        self.add_unit_functions();
+        self.add_statistics_functions();
+        self.add_integer_functions();
+        self.add_polynomial_functions();
+        self.add_rational_functions();
+        self.add_calculus_functions();
+        self.add_sum_functions();
+        self.add_rule_functions();
+        self.add_substitution_functions();
+        self.add_assumption_functions();
+    }
+
+    fn add_assumption_functions(&mut self) {
+        let intrinsics = &mut self.environment.intrinsics;
+        intrinsics.insert("is_positive".to_string(), |args| {
+            query("is_positive", args, assumptions::is_positive)
+        });
+        intrinsics.insert("is_negative".to_string(), |args| {
+            query("is_negative", args, assumptions::is_negative)
+        });
+        intrinsics.insert("is_nonnegative".to_string(), |args| {
+            query("is_nonnegative", args, assumptions::is_nonnegative)
+        });
+        intrinsics.insert("is_integer".to_string(), |args| {
+            query("is_integer", args, assumptions::is_integer)
+        });
+    }
+
+    fn add_elementary_functions(&mut self) {
+        let intrinsics = &mut self.environment.intrinsics;
+        intrinsics.insert("sin".to_string(), |args| elementary("sin", args));
+        intrinsics.insert("cos".to_string(), |args| elementary("cos", args));
+        intrinsics.insert("tan".to_string(), |args| elementary("tan", args));
+        intrinsics.insert("asin".to_string(), |args| elementary("asin", args));
+        intrinsics.insert("acos".to_string(), |args| elementary("acos", args));
+        intrinsics.insert("atan".to_string(), |args| elementary("atan", args));
+        intrinsics.insert("atan2".to_string(), |args| elementary("atan2", args));
+        intrinsics.insert("sinh".to_string(), |args| elementary("sinh", args));
+        intrinsics.insert("cosh".to_string(), |args| elementary("cosh", args));
+        intrinsics.insert("tanh".to_string(), |args| elementary("tanh", args));
+        intrinsics.insert("exp".to_string(), |args| elementary("exp", args));
+        intrinsics.insert("ln".to_string(), |args| elementary("ln", args));
+        intrinsics.insert("log".to_string(), |args| elementary("log", args));
+        intrinsics.insert("sqrt".to_string(), |args| elementary("sqrt", args));
+    }
+
+    fn add_piecewise_functions(&mut self) {
+        let intrinsics = &mut self.environment.intrinsics;
+        intrinsics.insert("abs".to_string(), |args| elementary("abs", args));
+        intrinsics.insert("sign".to_string(), |args| elementary("sign", args));
+        intrinsics.insert("min".to_string(), |args| elementary("min", args));
+        intrinsics.insert("max".to_string(), |args| elementary("max", args));
+        intrinsics.insert("floor".to_string(), |args| elementary("floor", args));
+        intrinsics.insert("ceil".to_string(), |args| elementary("ceil", args));
+    }
+
+    fn add_set_functions(&mut self) {
+        let intrinsics = &mut self.environment.intrinsics;
+        intrinsics.insert("union".to_string(), |args| elementary("union", args));
+        intrinsics.insert("intersection".to_string(), |args| elementary("intersection", args));
+        intrinsics.insert("difference".to_string(), |args| elementary("difference", args));
+        intrinsics.insert("complement".to_string(), |args| elementary("complement", args));
+        intrinsics.insert("card".to_string(), |args| elementary("card", args));
+
+        // solve(x^2 == 4, x) => {-2, 2}
+        intrinsics.insert("solve".to_string(), |args| {
+            let result = match args.as_slice() {
+                [equation, var] if is_symbol(var) => solve::solve(equation, var),
+                _ => None,
+            };
+            result.unwrap_or_else(|| unevaluated("solve", args))
+        });
+    }
+
+    fn add_interval_functions(&mut self) {
+        let intrinsics = &mut self.environment.intrinsics;
+
+        // interval(1, 2), where constants like PI are enclosed by the bounds
+        intrinsics.insert("interval".to_string(), |args| {
+            let bounds = match args.as_slice() {
+                [lower, upper] => interval::enclose(lower).zip(interval::enclose(upper)),
+                _ => None,
+            };
+            let interval = bounds.and_then(|(lower, upper)| Interval::new(lower.lower, upper.upper));
+            match interval {
+                Some(interval) => RunType::Interval(interval).into(),
+                None => unevaluated("interval", args),
+            }
+        });
+
+        // pow(x, y) is x^y, e.g. pow(interval(1, 2), interval(1/2, 2))
+        intrinsics.insert("pow".to_string(), |args| match args.as_slice() {
+            [base, exp] => base.clone().power(exp.clone()),
+            _ => unevaluated("pow", args),
+        });
+    }
+
+    fn add_unit_functions(&mut self) {
+        let intrinsics = &mut self.environment.intrinsics;
+
+        // convert(36 km/h, m/s) => 10 m/s
+        intrinsics.insert("convert".to_string(), |args| {
+            let converted = match args.as_slice() {
+                [value, target] => units::convert(value, target),
+                _ => None,
+            };
+            converted.unwrap_or_else(|| unevaluated("convert", args))
+        });
+    }
+
+    fn add_integer_functions(&mut self) {
+        let intrinsics = &mut self.environment.intrinsics;
+        intrinsics.insert("mod".to_string(), |args| elementary("mod", args));
+        intrinsics.insert("isprime".to_string(), |args| elementary("isprime", args));
+        intrinsics.insert("nextprime".to_string(), |args| elementary("nextprime", args));
+        intrinsics.insert("factorint".to_string(), |args| elementary("factorint", args));
+        intrinsics.insert("totient".to_string(), |args| elementary("totient", args));
+        intrinsics.insert("factorial".to_string(), |args| elementary("factorial", args));
+        intrinsics.insert("binomial".to_string(), |args| elementary("binomial", args));
+        intrinsics.insert("fibonacci".to_string(), |args| elementary("fibonacci", args));
+        intrinsics.insert("modinv".to_string(), |args| elementary("modinv", args));
+        intrinsics.insert("modpow".to_string(), |args| elementary("modpow", args));
+    }
+
+    fn add_statistics_functions(&mut self) {
+        let intrinsics = &mut self.environment.intrinsics;
+        intrinsics.insert("mean".to_string(), |args| statistic("mean", args));
+        intrinsics.insert("median".to_string(), |args| statistic("median", args));
+        intrinsics.insert("mode".to_string(), |args| statistic("mode", args));
+        intrinsics.insert("variance".to_string(), |args| statistic("variance", args));
+        intrinsics.insert("stdev".to_string(), |args| statistic("stdev", args));
+        intrinsics.insert("quantile".to_string(), |args| statistic("quantile", args));
+        intrinsics.insert("covariance".to_string(), |args| statistic("covariance", args));
+        intrinsics.insert("correlation".to_string(), |args| statistic("correlation", args));
+        intrinsics.insert("linreg".to_string(), |args| statistic("linreg", args));
+
+        // Distributions are values, e.g. pdf(normaldist(0, 1), x) or sample(poissondist(4), 10, 42)
+        intrinsics.insert("normaldist".to_string(), |args| statistic("normaldist", args));
+        intrinsics.insert("binomialdist".to_string(), |args| statistic("binomialdist", args));
+        intrinsics.insert("poissondist".to_string(), |args| statistic("poissondist", args));
+        intrinsics.insert("pdf".to_string(), |args| statistic("pdf", args));
+        intrinsics.insert("cdf".to_string(), |args| statistic("cdf", args));
+        intrinsics.insert("sample".to_string(), |args| statistic("sample", args));
+    }
+
+    fn add_polynomial_functions(&mut self) {
+        let intrinsics = &mut self.environment.intrinsics;
+
+        intrinsics.insert("degree".to_string(), |args| {
+            let Some((polys, var)) = polynomials_in(args, 1) else {
+                return unevaluated("degree", args);
+            };
+
+            let degree = match var {
+                Some(var) => polys[0].degree(var),
+                None => polys[0].as_constant().map(|_| 0),
+            };
+
+            match degree {
+                Some(degree) => RunType::Number(degree as f64).into(),
+                None => RunType::Number(f64::NEG_INFINITY).into(),
+            }
+        });
+
+        intrinsics.insert("coeffs".to_string(), |args| {
+            let Some((polys, var)) = polynomials_in(args, 1) else {
+                return unevaluated("coeffs", args);
+            };
+
+            let coeffs = match var {
+                Some(var) => polys[0]
+                    .coeffs(var)
+                    .iter()
+                    .rev()
+                    .map(Polynomial::to_value)
+                    .collect(),
+                None => vec![polys[0].to_value()],
+            };
+
+            RunType::Vector(coeffs).into()
+        });
+
+        intrinsics.insert("quo".to_string(), |args| match divide(args) {
+            Some((quotient, _)) => quotient.to_value(),
+            None => unevaluated("quo", args),
+        });
+
+        intrinsics.insert("rem".to_string(), |args| match divide(args) {
+            Some((_, remainder)) => remainder.to_value(),
+            None => unevaluated("rem", args),
+        });
+
+        intrinsics.insert("divrem".to_string(), |args| match divide(args) {
+            Some((quotient, remainder)) => {
+                RunType::Vector(vec![quotient.to_value(), remainder.to_value()]).into()
+            }
+            None => unevaluated("divrem", args),
+        });
+
+        intrinsics.insert("gcd".to_string(), |args| {
+            let values = args.iter().collect::<Vec<_>>();
+            match Polynomial::from_values(&values) {
+                Some(polys) if !polys.is_empty() => polys
+                    .iter()
+                    .skip(1)
+                    .fold(polys[0].clone(), |acc, it| acc.gcd(it))
+                    .to_value(),
+                _ => unevaluated("gcd", args),
+            }
+        });
+
+        intrinsics.insert("lcm".to_string(), |args| {
+            let values = args.iter().collect::<Vec<_>>();
+            match Polynomial::from_values(&values) {
+                Some(polys) if !polys.is_empty() => polys
+                    .iter()
+                    .skip(1)
+                    .fold(polys[0].clone(), |acc, it| acc.lcm(it))
+                    .to_value(),
+                _ => unevaluated("lcm", args),
+            }
+        });
+
+        intrinsics.insert("resultant".to_string(), |args| {
+            match polynomials_in(args, 2) {
+                Some((polys, Some(var))) => polys[0].resultant(&polys[1], var).to_value(),
+                _ => unevaluated("resultant", args),
+            }
+        });
+
+        intrinsics.insert("discriminant".to_string(), |args| {
+            let Some((polys, Some(var))) = polynomials_in(args, 1) else {
+                return unevaluated("discriminant", args);
             };
 
-                match arg.typ {
-                    RunType::Number(n) => RunVal::new(RunType::Number(n.cos())),
-                    _ => {
-                        panic!("Not a number!!!");
+            match polys[0].discriminant(var) {
+                Some(discriminant) => discriminant.to_value(),
+                None => RunType::Undefined.into(),
+            }
+        });
+    }
+}
+
+impl Runtime {
+    fn add_rational_functions(&mut self) {
+        let intrinsics = &mut self.environment.intrinsics;
+
+        intrinsics.insert("cancel".to_string(), |args| match args.as_slice() {
+            [expr] => rational::cancel(expr),
+            _ => unevaluated("cancel", args),
+        });
+
+        intrinsics.insert("together".to_string(), |args| match args.as_slice() {
+            [expr] => rational::together(expr),
+            _ => unevaluated("together", args),
+        });
+
+        intrinsics.insert("apart".to_string(), |args| {
+            let result = match args.as_slice() {
+                [expr] => {
+                    let (numerator, denominator) = rational::numer_denom(expr);
+                    Polynomial::from_values(&[&numerator, &denominator])
+                        .and_then(|polys| polys[0].gens().first().cloned())
+                        .and_then(|var| rational::apart(expr, &var))
+                }
+                [expr, var] => rational::apart(expr, var),
+                _ => None,
+            };
+            result.unwrap_or_else(|| unevaluated("apart", args))
+        });
+    }
+}
+
+impl Runtime {
+    fn add_calculus_functions(&mut self) {
+        let intrinsics = &mut self.environment.intrinsics;
+
+        intrinsics.insert("diff".to_string(), |args| {
+            let derivative = match args.as_slice() {
+                [expr, var] if is_symbol(var) => diff::differentiate(expr, var),
+                [expr, var, n] if is_symbol(var) => match natural_number(n) {
+                    Some(n) => diff::differentiate_n(expr, var, n),
+                    None => return unevaluated("diff", args),
+                },
+                _ => return unevaluated("diff", args),
+            };
+            trace::step("diff", &unevaluated("diff", args), &derivative);
+            derivative
+        });
+
+        intrinsics.insert("integrate".to_string(), |args| match args.as_slice() {
+            [expr, var] if is_symbol(var) => match integrate::antiderivative(expr, var) {
+                Some(mut integral) => {
+                    integral.simplify();
+                    trace::step("integrate", &unevaluated("integrate", args), &integral);
+                    integral
+                }
+                None => unevaluated("integrate", args),
+            },
+            _ => unevaluated("integrate", args),
+        });
+
+        // limit(expr, x, a) from both sides, limit(expr, x, a, 1) from the right
+        // and limit(expr, x, a, -1) from the left.
+        intrinsics.insert("limit".to_string(), |args| {
+            let direction = match args.get(3).map(|it| &it.typ) {
+                None => Some(Direction::Both),
+                Some(RunType::Number(n)) if *n == 1.0 => Some(Direction::Right),
+                Some(RunType::Number(n)) if *n == -1.0 => Some(Direction::Left),
+                Some(_) => None,
+            };
+
+            let result = match (args.as_slice(), direction) {
+                ([expr, var, point] | [expr, var, point, _], Some(direction)) if is_symbol(var) => {
+                    limit::limit(expr, var, point, direction)
+                }
+                _ => None,
+            };
+            result.unwrap_or_else(|| unevaluated("limit", args))
+        });
+
+        // dsolve(diff(y(x), x) == y(x), y(x)), with the values of y, y', ... at a point as in
+        // dsolve(diff(y(x), x, 2) == -y(x), y(x), 0, [0, 1])
+        intrinsics.insert("dsolve".to_string(), |args| {
+            let result = match args.as_slice() {
+                [equation, function] => ode::dsolve(equation, function, None),
+                [equation, function, x0, values] => match &values.typ {
+                    RunType::Vector(values) => ode::dsolve(equation, function, Some((x0, values))),
+                    _ => ode::dsolve(equation, function, Some((x0, std::slice::from_ref(values)))),
+                },
+                _ => None,
+            };
+            result.unwrap_or_else(|| unevaluated("dsolve", args))
+        });
+
+        // odeint([v, -x], [x, v], t, [1, 0], 0, 10) samples [t, x, v] at 11 points,
+        // and odeint(f, y, t, y0, t0, t1, n) at n + 1 points
+        intrinsics.insert("odeint".to_string(), |args| {
+            let result = match args.as_slice() {
+                [f, y, t, y0, t0, t1] => ode::odeint(f, y, t, y0, t0, t1, ode::SAMPLES),
+                [f, y, t, y0, t0, t1, n] => natural_number(n)
+                    .and_then(|n| ode::odeint(f, y, t, y0, t0, t1, n as usize)),
+                _ => None,
+            };
+            result.unwrap_or_else(|| unevaluated("odeint", args))
+        });
+
+        intrinsics.insert("series".to_string(), |args| {
+            let result = match args.as_slice() {
+                [expr, var, point, order] if is_symbol(var) => {
+                    natural_number(order).and_then(|order| {
+                        series::series(expr, var, &Point::from_value(point), order as i32)
+                    })
+                }
+                _ => None,
+            };
+            result.unwrap_or_else(|| unevaluated("series", args))
+        });
+    }
+}
+
+impl Runtime {
+    // sum(k^2, k, 1, n) and product(k, k, 1, n), where the upper bound may be inf for sums
+    fn add_sum_functions(&mut self) {
+        let intrinsics = &mut self.environment.intrinsics;
+        intrinsics.insert("sum".to_string(), |args| summation("sum", args));
+        intrinsics.insert("product".to_string(), |args| summation("product", args));
+    }
+}
+
+impl Runtime {
+    fn add_rule_functions(&mut self) {
+        let intrinsics = &mut self.environment.intrinsics;
+
+        // rewrite(expr, rule) or rewrite(expr, [rule, ...]) applies the rules in order
+        intrinsics.insert("rewrite".to_string(), |args| {
+            let result = match args.as_slice() {
+                [expr, names] => rule_names(names).and_then(|names| rules::rewrite(expr, &names)),
+                _ => None,
+            };
+            result.unwrap_or_else(|| unevaluated("rewrite", args))
+        });
+
+        intrinsics.insert("enable".to_string(), |args| set_enabled("enable", args, true));
+        intrinsics.insert("disable".to_string(), |args| set_enabled("disable", args, false));
+
+        intrinsics.insert("rules".to_string(), |args| match args.as_slice() {
+            [] => RunType::Vector(
+                rules::names()
+                    .into_iter()
+                    .map(|name| RunType::Symbol(name).into())
+                    .collect(),
+            )
+            .into(),
+            _ => unevaluated("rules", args),
+        });
+    }
+}
+
+impl Runtime {
+    fn add_substitution_functions(&mut self) {
+        let intrinsics = &mut self.environment.intrinsics;
+
+        // subs(expr, x, 2), subs(expr, [x, y], [2, 3]) and subs(expr, [x == 2, y == 3])
+        intrinsics.insert("subs".to_string(), |args| {
+            let bindings = match args.as_slice() {
+                [_, equations] => equation_bindings(equations),
+                [_, target, value] => match (&target.typ, &value.typ) {
+                    (RunType::Vector(targets), RunType::Vector(values))
+                        if targets.len() == values.len() =>
+                    {
+                        Some(targets.iter().cloned().zip(values.iter().cloned()).collect())
                     }
+                    (RunType::Vector(_), _) | (_, RunType::Vector(_)) => None,
+                    _ => Some(vec![(target.clone(), value.clone())]),
+                },
+                _ => None,
+            };
+
+            match bindings {
+                Some(bindings) if bindings.iter().all(|(target, _)| is_symbol(target)) => {
+                    args[0].substitute_simultaneously(&bindings)
                 }
-            });
+                _ => unevaluated("subs", args),
+            }
+        });
+    }
+}
+
+/// Bindings from an equation `x == 2`, or a vector of them.
+fn equation_bindings(value: &RunVal) -> Option<Vec<(RunVal, RunVal)>> {
+    match &value.typ {
+        RunType::Function(name, args) if name == "equal" && args.len() == 2 => {
+            Some(vec![(args[0].clone(), args[1].clone())])
+        }
+        RunType::Vector(equations) => equations
+            .iter()
+            .map(equation_bindings)
+            .collect::<Option<Vec<_>>>()
+            .map(|bindings| bindings.concat()),
+        _ => None,
+    }
+}
+
+/// The names of rules, given as a symbol or a vector of symbols.
+fn rule_names(value: &RunVal) -> Option<Vec<String>> {
+    match &value.typ {
+        RunType::Symbol(name) => Some(vec![name.clone()]),
+        RunType::Vector(values) => values
+            .iter()
+            .map(|it| match &it.typ {
+                RunType::Symbol(name) => Some(name.clone()),
+                _ => None,
+            })
+            .collect(),
+        _ => None,
+    }
+}
+
+fn set_enabled(function: &str, args: &[RunVal], enabled: bool) -> RunVal {
+    let names = args
+        .iter()
+        .map(rule_names)
+        .collect::<Option<Vec<_>>>()
+        .map(|names| names.concat());
+
+    match names {
+        Some(names) if names.iter().all(|name| rules::set_enabled(name, enabled)) => {
+            RunType::Unit.into()
+        }
+        _ => unevaluated(function, args),
+    }
+}
+
+/// Converts the first `count` arguments into polynomials. The optional argument after them
+/// is the main variable; without it the first generator is used.
+fn polynomials_in(args: &[RunVal], count: usize) -> Option<(Vec<Polynomial>, Option<usize>)> {
+    if args.len() != count && args.len() != count + 1 {
+        return None;
+    }
+
+    let values = args.iter().collect::<Vec<_>>();
+    let mut polys = Polynomial::from_values(&values)?;
+
+    let var = match args.get(count) {
+        Some(var) => {
+            let RunType::Symbol(_) = var.typ else {
+                return None;
+            };
+            polys.pop();
+            polys[0].gen_index(var)
+        }
+        None if polys[0].gens().is_empty() => None,
+        None => Some(0),
+    };
+
+    Some((polys, var))
+}
+
+fn divide(args: &[RunVal]) -> Option<(Polynomial, Polynomial)> {
+    let (polys, var) = polynomials_in(args, 2)?;
+
+    if polys[1].is_zero() {
+        return None;
+    }
+
+    match var {
+        Some(var) => polys[0].div_rem(&polys[1], var),
+        None => {
+            let quotient = polys[0].as_constant()? / polys[1].as_constant()?;
+            Some((
+                Polynomial::constant(quotient, polys[0].gens()),
+                Polynomial::zero(polys[0].gens()),
+            ))
+        }
+    }
+}
+
+fn is_symbol(value: &RunVal) -> bool {
+    matches!(value.typ, RunType::Symbol(_))
+}
+
+fn natural_number(value: &RunVal) -> Option<u32> {
+    match value.typ {
+        RunType::Number(n) if n >= 0.0 && n.fract() == 0.0 => Some(n as u32),
+        _ => None,
+    }
+}
+
+/// Elementary functions are evaluated for numbers and otherwise left to the simplifier.
+fn elementary(name: &str, args: &[RunVal]) -> RunVal {
+    functions::evaluate(name, args).unwrap_or_else(|| unevaluated(name, args))
+}
+
+/// Statistics of data and distributions, left unevaluated for data they don't apply to.
+fn statistic(name: &str, args: &[RunVal]) -> RunVal {
+    stats::evaluate(name, args).unwrap_or_else(|| unevaluated(name, args))
+}
+
+/// Sums and products with a closed form, which is a step of its own.
+fn summation(name: &str, args: &[RunVal]) -> RunVal {
+    match trace::untraced(|| sums::evaluate(name, args)) {
+        Some(value) => {
+            trace::step(name, &unevaluated(name, args), &value);
+            value
+        }
+        None => unevaluated(name, args),
+    }
+}
+
+/// The function call itself, for arguments an intrinsic can't evaluate.
+fn unevaluated(name: &str, args: &[RunVal]) -> RunVal {
+    RunType::Function(name.to_string(), args.to_vec()).into()
+}
+
+/// Answers a question about a value by what is assumed, `true`, `false` or `unknown`.
+fn query(name: &str, args: &[RunVal], question: fn(&RunVal) -> Option<bool>) -> RunVal {
+    let [value] = args else {
+        return unevaluated(name, args);
+    };
+    match question(value) {
+        Some(answer) => RunType::Bool(answer).into(),
+        None => RunType::Symbol("unknown".to_string()).into(),
     }
 }
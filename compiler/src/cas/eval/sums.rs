@@ -0,0 +1,489 @@
+//! Sums and products over a range of integers, e.g. `sum(k^2, k, 1, n)` or `product(k, k, 1, n)`.
+//!
+//! Ranges with known bounds are added up or multiplied term by term. Otherwise closed forms are
+//! found for polynomial, geometric and telescoping sums, e.g. `sum(1/(k * (k + 1)), k, 1, n)` =>
+//! `1 - 1/(n + 1)`, and for products of linear factors and powers.
+//!
+//! Infinite sums are checked for convergence, a sum that diverges is `inf` or `-inf` by its sign
+//! and `undefined` if it oscillates. Sums and products without a closed form stay unevaluated.
+
+use log::debug;
+
+use super::{
+    helpers::{approximate, linear_coefficient, negate, number, simplified, subtract},
+    limit::{self, Direction},
+    poly::Polynomial,
+    rational,
+    value::{Constant, Factors, RunType, RunVal, Terms},
+};
+
+macro_rules! sums_debug {
+    ($($arg:tt)+) => (debug!(target: "matex::sums", "[{}:{}] {}", file!(), line!(), &format!($($arg)+)));
+}
+
+/// Ranges with more terms than this aren't computed term by term.
+const MAX_TERMS: i64 = 1000;
+
+/// The highest power of the summation variable that has a closed form.
+const MAX_DEGREE: usize = 20;
+
+/// How far apart the canceling terms of a telescoping sum may be, e.g. 2 for `1/k - 1/(k + 2)`.
+const MAX_SHIFT: i64 = 3;
+
+/// Evaluates `sum(expr, k, a, b)` or `product(expr, k, a, b)`, `None` if there's no closed form.
+pub(crate) fn evaluate(name: &str, args: &[RunVal]) -> Option<RunVal> {
+    let [expr, var, lower, upper] = args else {
+        return None;
+    };
+    if !matches!(var.typ, RunType::Symbol(_)) || is_infinite(lower) {
+        return None;
+    }
+
+    let value = match name {
+        "sum" => sum(expr, var, lower, upper)?,
+        "product" => product(expr, var, lower, upper)?,
+        _ => return None,
+    };
+
+    sums_debug!("{}({:?}) = {:?}", name, args, value);
+    Some(value)
+}
+
+fn sum(expr: &RunVal, k: &RunVal, a: &RunVal, b: &RunVal) -> Option<RunVal> {
+    if let Some(range) = range(a, b) {
+        let terms = range
+            .map(|i| expr.substitute(k, &number(i as f64)))
+            .collect();
+        return Some(simplified(RunType::Sum(Terms(terms)).into()));
+    }
+
+    let value = match is_infinite(b) {
+        true => infinite_sum(expr, k, a, true)?,
+        false => finite_sum(expr, k, a, b, true)?,
+    };
+    Some(simplified(value))
+}
+
+fn product(expr: &RunVal, k: &RunVal, a: &RunVal, b: &RunVal) -> Option<RunVal> {
+    if let Some(range) = range(a, b) {
+        let factors = range
+            .map(|i| expr.substitute(k, &number(i as f64)))
+            .collect();
+        return Some(simplified(RunType::Product(Factors(factors)).into()));
+    }
+
+    // Only the empty product has a value at infinity
+    if is_infinite(b) {
+        return is_number(expr, 1.0).then(|| number(1.0));
+    }
+    Some(simplified(finite_product(expr, k, a, b)?))
+}
+
+/// The closed form of a sum with a symbolic bound, the argument `apart` tells whether partial
+/// fractions are still to be tried.
+fn finite_sum(expr: &RunVal, k: &RunVal, a: &RunVal, b: &RunVal, apart: bool) -> Option<RunVal> {
+    if !expr.depends_on(k) {
+        return Some(expr.clone().multiply(count(a, b)));
+    }
+    if let Some(value) = polynomial_sum(expr, k, a, b) {
+        return Some(value);
+    }
+
+    // t(a) * (q^(b - a + 1) - 1)/(q - 1)
+    if let Some((ratio, first)) = geometric(expr, k, a) {
+        let powers = subtract(ratio.clone().power(count(a, b)), number(1.0));
+        return Some(
+            first
+                .multiply(powers)
+                .multiply(subtract(ratio, number(1.0)).power(number(-1.0))),
+        );
+    }
+
+    // f(a) + ... + f(a + m - 1) - f(b + 1) - ... - f(b + m)
+    if let Some((f, shift)) = telescoping(expr, k) {
+        let terms = (0..shift)
+            .flat_map(|i| {
+                let first = f.substitute(k, &a.clone().add(number(i as f64)));
+                let last = f.substitute(k, &b.clone().add(number((i + 1) as f64)));
+                [first, negate(last)]
+            })
+            .collect();
+        return Some(RunType::Sum(Terms(terms)).into());
+    }
+
+    if let RunType::Sum(Terms(terms)) = &expr.typ {
+        let sums = terms.iter().map(|it| finite_sum(it, k, a, b, apart));
+        if let Some(sums) = sums.collect::<Option<Vec<_>>>() {
+            return Some(RunType::Sum(Terms(sums)).into());
+        }
+    }
+
+    let fractions = partial_fractions(expr, k).filter(|_| apart)?;
+    finite_sum(&fractions, k, a, b, false)
+}
+
+/// The value of a sum up to infinity, the argument `apart` tells whether partial fractions are
+/// still to be tried.
+fn infinite_sum(expr: &RunVal, k: &RunVal, a: &RunVal, apart: bool) -> Option<RunVal> {
+    if !expr.depends_on(k) {
+        return match is_number(expr, 0.0) {
+            true => Some(number(0.0)),
+            false => divergent(expr),
+        };
+    }
+
+    // Converges to t(a)/(1 - q) if |q| < 1
+    if let Some((ratio, first)) = geometric(expr, k, a) {
+        let q = approximate(&ratio)?;
+        return match q.abs() < 1.0 {
+            true => Some(first.multiply(subtract(number(1.0), ratio).power(number(-1.0)))),
+            false if q >= 1.0 => divergent(&first),
+            false => Some(RunType::Undefined.into()),
+        };
+    }
+
+    // f(a) + ... + f(a + m - 1) - m * lim f
+    if let Some((f, shift)) = telescoping(expr, k) {
+        let infinity = number(f64::INFINITY);
+        let limit = limit::limit(&f, k, &infinity, Direction::Both)?;
+        let mut terms: Vec<RunVal> = (0..shift)
+            .map(|i| f.substitute(k, &a.clone().add(number(i as f64))))
+            .collect();
+        terms.push(negate(limit.multiply(number(shift as f64))));
+        return Some(RunType::Sum(Terms(terms)).into());
+    }
+
+    if let Some(value) = power_series(expr, k, a) {
+        return Some(value);
+    }
+
+    // A sum with parts that diverge the same way diverges, `inf - inf` is left as it is
+    if let RunType::Sum(Terms(terms)) = &expr.typ {
+        let sums = terms.iter().map(|it| infinite_sum(it, k, a, apart));
+        if let Some(sums) = sums.collect::<Option<Vec<_>>>() {
+            let sum = simplified(RunType::Sum(Terms(sums)).into());
+            if !matches!(sum.typ, RunType::Number(n) if n.is_nan()) {
+                return Some(sum);
+            }
+        }
+    }
+
+    if let Some(fractions) = partial_fractions(expr, k).filter(|_| apart) {
+        if let Some(value) = infinite_sum(&fractions, k, a, false) {
+            return Some(value);
+        }
+    }
+
+    // Terms that don't approach 0 can't be added up
+    let infinity = number(f64::INFINITY);
+    let limit = limit::limit(expr, k, &infinity, Direction::Both)?;
+    match approximate(&limit) {
+        Some(limit) if limit != 0.0 && !limit.is_nan() => divergent(&number(limit)),
+        _ => None,
+    }
+}
+
+/// `sum(k^p, k, a, b)` is `F_p(b) - F_p(a - 1)` with Faulhaber's formula
+/// `F_p(n) = 1^p + ... + n^p`, which is a polynomial in `n`.
+fn polynomial_sum(expr: &RunVal, k: &RunVal, a: &RunVal, b: &RunVal) -> Option<RunVal> {
+    let poly = Polynomial::from_value(expr)?;
+    let var = poly.gen_index(k)?;
+    // The variable can't be part of another generator, e.g. `sin(k)`
+    let gens = poly.gens().iter().enumerate();
+    if gens
+        .filter(|(i, _)| *i != var)
+        .any(|(_, gen)| gen.depends_on(k))
+    {
+        return None;
+    }
+
+    let coeffs = poly.coeffs(var);
+    if coeffs.len() > MAX_DEGREE + 1 {
+        return None;
+    }
+    let before = a.clone().add(number(-1.0));
+    let terms = coeffs
+        .iter()
+        .enumerate()
+        .filter(|(_, coeff)| !coeff.is_zero())
+        .map(|(p, coeff)| {
+            let powers = subtract(faulhaber(p, b), faulhaber(p, &before));
+            coeff.to_value().multiply(powers)
+        })
+        .collect();
+    Some(expanded(simplified(RunType::Sum(Terms(terms)).into())))
+}
+
+/// `1^p + ... + n^p` as a polynomial in `n`.
+fn faulhaber(p: usize, n: &RunVal) -> RunVal {
+    let bernoulli = bernoulli_numbers(p);
+    let terms = (0..=p)
+        .map(|j| {
+            // The sum starts at 1, so B_1 is +1/2
+            let b_j = if j == 1 { 0.5 } else { bernoulli[j] };
+            let coeff = binomial(p + 1, j) * b_j / (p + 1) as f64;
+            number(coeff).multiply(n.clone().power(number((p + 1 - j) as f64)))
+        })
+        .collect();
+    RunType::Sum(Terms(terms)).into()
+}
+
+/// The Bernoulli numbers `B_0` to `B_n`, with `B_1 = -1/2`.
+fn bernoulli_numbers(n: usize) -> Vec<f64> {
+    let mut numbers = vec![1.0];
+    for m in 1..=n {
+        let sum: f64 = (0..m).map(|j| binomial(m + 1, j) * numbers[j]).sum();
+        numbers.push(-sum / (m + 1) as f64);
+    }
+    numbers
+}
+
+fn binomial(n: usize, k: usize) -> f64 {
+    (0..k).fold(1.0, |acc, i| acc * (n - i) as f64 / (i + 1) as f64)
+}
+
+/// The ratio `q` of consecutive terms and the first term `t(a)` if every term is `t(a) * q^(k - a)`,
+/// i.e. the variable only occurs linearly in exponents, e.g. `3 * 2^(2 * k + 1)`.
+fn geometric(expr: &RunVal, k: &RunVal, a: &RunVal) -> Option<(RunVal, RunVal)> {
+    let factors = match &expr.typ {
+        RunType::Product(Factors(factors)) => factors.as_slice(),
+        _ => std::slice::from_ref(expr),
+    };
+
+    let mut ratios = Vec::new();
+    for factor in factors.iter().filter(|it| it.depends_on(k)) {
+        let (base, exp) = match &factor.typ {
+            RunType::Exponent(base, exp) => (base.as_ref().clone(), exp.as_ref()),
+            RunType::Function(name, args) if name == "exp" && args.len() == 1 => {
+                (RunType::Constant(Constant::E).into(), &args[0])
+            }
+            _ => return None,
+        };
+        if base.depends_on(k) {
+            return None;
+        }
+        ratios.push(base.power(linear_coefficient(exp, k)?));
+    }
+
+    let ratio = simplified(RunType::Product(Factors(ratios)).into());
+    if is_number(&ratio, 1.0) {
+        return None;
+    }
+    Some((ratio, simplified(expr.substitute(k, a))))
+}
+
+/// A term `f` of the summand and a shift `m` so that the summand is `f(k) - f(k + m)`.
+fn telescoping(expr: &RunVal, k: &RunVal) -> Option<(RunVal, i64)> {
+    let RunType::Sum(Terms(terms)) = &expr.typ else {
+        return None;
+    };
+
+    for shift in 1..=MAX_SHIFT {
+        for f in terms {
+            let shifted = f.substitute(k, &k.clone().add(number(shift as f64)));
+            // Polynomials cancel what the simplifier keeps apart, e.g. `a - (a - b) - b`
+            let rest = simplified(subtract(expr.clone(), subtract(f.clone(), shifted)));
+            if Polynomial::from_value(&rest).is_some_and(|it| it.is_zero()) {
+                return Some((f.clone(), shift));
+            }
+        }
+    }
+    None
+}
+
+/// `c * (zeta(s) - 1^-s - ... - (a - 1)^-s)` for a summand `c * k^-s` with an even `s`.
+/// The sum diverges for `s <= 1`, other values of `s` have no closed form.
+fn power_series(expr: &RunVal, k: &RunVal, a: &RunVal) -> Option<RunVal> {
+    let (coeff, factor) = match &expr.typ {
+        RunType::Product(Factors(factors)) => match factors.as_slice() {
+            [coeff, factor] if !coeff.depends_on(k) => (coeff.clone(), factor),
+            _ => return None,
+        },
+        _ => (number(1.0), expr),
+    };
+    let exp = match &factor.typ {
+        _ if factor == k => 1.0,
+        RunType::Exponent(base, exp) if base.as_ref() == k => match exp.typ {
+            RunType::Number(exp) => exp,
+            _ => return None,
+        },
+        _ => return None,
+    };
+
+    if exp >= -1.0 {
+        return divergent(&coeff);
+    }
+    let zeta = match exp {
+        -2.0 => number(1.0 / 6.0).multiply(pi().power(number(2.0))),
+        -4.0 => number(1.0 / 90.0).multiply(pi().power(number(4.0))),
+        -6.0 => number(1.0 / 945.0).multiply(pi().power(number(6.0))),
+        _ => return None,
+    };
+
+    // The terms before the first one are taken away again
+    let RunType::Number(first) = a.typ else {
+        return None;
+    };
+    if first.fract() != 0.0 || first < 1.0 || first > MAX_TERMS as f64 {
+        return None;
+    }
+    let skipped: f64 = (1..first as i64).map(|i| (i as f64).powf(exp)).sum();
+    Some(coeff.multiply(subtract(zeta, number(skipped))))
+}
+
+/// The closed form of a product with a symbolic bound.
+fn finite_product(expr: &RunVal, k: &RunVal, a: &RunVal, b: &RunVal) -> Option<RunVal> {
+    if !expr.depends_on(k) {
+        return Some(expr.clone().power(count(a, b)));
+    }
+
+    // c * (k + d) for an integer d multiplies to c^(b - a + 1) * (b + d)!/(a + d - 1)!
+    if let Some((slope, shift)) = linear_factor(expr, k) {
+        let last = factorial(b.clone().add(number(shift)));
+        let before = factorial(a.clone().add(number(shift - 1.0)));
+        return Some(
+            number(slope)
+                .power(count(a, b))
+                .multiply(last)
+                .multiply(before.power(number(-1.0))),
+        );
+    }
+
+    match &expr.typ {
+        // c^f(k) multiplies to c^(f(a) + ... + f(b))
+        RunType::Exponent(base, exp) if !base.depends_on(k) => {
+            let exp = finite_sum(exp, k, a, b, true)?;
+            Some(base.as_ref().clone().power(exp))
+        }
+        // f(k)^c multiplies to (f(a) * ... * f(b))^c
+        RunType::Exponent(base, exp) if !exp.depends_on(k) => {
+            Some(finite_product(base, k, a, b)?.power(exp.as_ref().clone()))
+        }
+        RunType::Product(Factors(factors)) => {
+            let products = factors.iter().map(|it| finite_product(it, k, a, b));
+            Some(RunType::Product(Factors(products.collect::<Option<_>>()?)).into())
+        }
+        _ => None,
+    }
+}
+
+/// The `c` and `d` of a factor `c * (k + d)` with numbers `c` and `d`, where `d` is an integer.
+fn linear_factor(expr: &RunVal, k: &RunVal) -> Option<(f64, f64)> {
+    let poly = Polynomial::from_value(expr)?;
+    let coeffs = poly.coeffs(poly.gen_index(k)?);
+    let [constant, slope] = coeffs.as_slice() else {
+        return None;
+    };
+    let (constant, slope) = (constant.as_constant()?, slope.as_constant()?);
+    let shift = constant / slope;
+    (shift.fract() == 0.0).then_some((slope, shift))
+}
+
+/// The integers from `a` to `b` if they are few enough to go through one by one.
+fn range(a: &RunVal, b: &RunVal) -> Option<std::ops::RangeInclusive<i64>> {
+    let (RunType::Number(a), RunType::Number(b)) = (&a.typ, &b.typ) else {
+        return None;
+    };
+    if a.fract() != 0.0 || b.fract() != 0.0 || b - a >= MAX_TERMS as f64 {
+        return None;
+    }
+    Some(*a as i64..=*b as i64)
+}
+
+/// The partial fractions of a summand, if that changes it.
+fn partial_fractions(expr: &RunVal, k: &RunVal) -> Option<RunVal> {
+    let fractions = simplified(rational::apart(expr, k)?);
+    (fractions != *expr).then_some(fractions)
+}
+
+/// `inf` or `-inf` by the sign of a diverging sum's terms, `None` if the sign isn't known.
+fn divergent(sign: &RunVal) -> Option<RunVal> {
+    let sign = approximate(sign)?;
+    (sign != 0.0).then(|| number(f64::INFINITY.copysign(sign)))
+}
+
+/// The number of integers from `a` to `b`.
+fn count(a: &RunVal, b: &RunVal) -> RunVal {
+    subtract(b.clone(), a.clone()).add(number(1.0))
+}
+
+fn factorial(value: RunVal) -> RunVal {
+    let mut value = value;
+    value.simplify();
+    RunType::Function("factorial".to_string(), vec![value]).into()
+}
+
+/// Multiplies out polynomial results, e.g. `0.5 * n * (n + 1)` => `0.5 * n^2 + 0.5 * n`.
+fn expanded(value: RunVal) -> RunVal {
+    match Polynomial::from_value(&value) {
+        Some(poly) if !matches!(value.typ, RunType::Number(_)) => simplified(poly.to_value()),
+        _ => value,
+    }
+}
+
+fn is_infinite(value: &RunVal) -> bool {
+    matches!(value.typ, RunType::Number(n) if n.is_infinite())
+}
+
+fn is_number(value: &RunVal, number: f64) -> bool {
+    matches!(value.typ, RunType::Number(n) if n == number)
+}
+
+fn pi() -> RunVal {
+    RunType::Constant(Constant::Pi).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cas::eval::testing::{eval, Session};
+
+    #[test]
+    fn closed_forms() {
+        assert_eq!(eval("sum(k, k, 1, 10)"), "55");
+        assert_eq!(eval("sum(k, k, 1, n)"), "0.5 * n^2 + 0.5 * n");
+        assert_eq!(eval("sum(2 * k + 1, k, 0, n)"), "n^2 + 2 * n + 1");
+        assert_eq!(eval("sum(2^k, k, 0, n)"), "2^(n + 1) - 1");
+        assert_eq!(eval("sum(3 * 2^(2 * k + 1), k, 1, n)"), "8 * (4^n - 1)");
+        assert_eq!(eval("sum(c, k, 1, n)"), "c * n");
+        assert_eq!(eval("sum(k, k, 5, 1)"), "0");
+    }
+
+    #[test]
+    fn telescoping_sums() {
+        assert_eq!(eval("sum(1/(k * (k + 1)), k, 1, n)"), "1 - (n + 1)^-1");
+        assert_eq!(eval("sum(1/(k * (k + 2)), k, 1, inf)"), "0.75");
+    }
+
+    #[test]
+    fn infinite_sums() {
+        assert_eq!(eval("sum((1/2)^k, k, 0, inf)"), "2");
+        assert_eq!(eval("sum((-1)^k, k, 0, inf)"), "undefined");
+        assert_eq!(eval("sum(1/k^2, k, 1, inf)"), "0.16666666666666666 * PI^2");
+        assert_eq!(eval("sum(1/k, k, 1, inf)"), "inf");
+        assert_eq!(eval("sum(1/k^3, k, 1, inf)"), "Σ_{k=1}^{inf} k^-3");
+    }
+
+    #[test]
+    fn products() {
+        assert_eq!(eval("product(k, k, 1, 5)"), "120");
+        assert_eq!(eval("product(k + 2, k, 1, n)"), "0.5 * (n + 2)!");
+        assert_eq!(eval("product(2^k, k, 1, n)"), "2^(0.5 * n^2 + 0.5 * n)");
+        assert_eq!(eval("product(sin(k), k, 1, n)"), "Π_{k=1}^{n} sin(k)");
+    }
+
+    #[test]
+    fn terms_must_be_arithmetic() {
+        assert_eq!(
+            Session::new().error("sum(1 < 2, k, 1, 3)"),
+            "Booleans can't be used in arithmetic at (row 1, col 3)"
+        );
+        assert_eq!(
+            Session::new().error("sum(k < 2, k, 1, n)"),
+            "Booleans can't be used in arithmetic at (row 1, col 3)"
+        );
+        assert_eq!(
+            Session::new().error("product({1}, k, 1, 3)"),
+            "Sets can't be used in arithmetic at (row 1, col 7)"
+        );
+    }
+}
@@ -0,0 +1,62 @@
+//! Running programs in tests the way the REPL does, e.g. `assert_eq!(eval("2 * 3"), "6")`.
+
+use crate::cas::{
+    syntax::{lexer::Lexer, parser::Parser},
+    types::checker::TypeChecker,
+};
+
+use super::{
+    format::{NormalFormatter, ValueFormatter},
+    runtime::Runtime,
+    value::RunVal,
+};
+
+/// A runtime with the standard environment, that keeps its variables and functions between
+/// programs.
+pub(crate) struct Session {
+    runtime: Runtime,
+    checker: TypeChecker,
+}
+
+impl Session {
+    pub(crate) fn new() -> Self {
+        let mut runtime = Runtime::new();
+        runtime.add_standard_environment();
+        Self {
+            runtime,
+            checker: TypeChecker::new(),
+        }
+    }
+
+    /// Parses, checks and runs the program, any error is returned as its message.
+    pub(crate) fn run(&mut self, input: &str) -> Result<RunVal, String> {
+        let program = Parser::new(Lexer::new(input).collect())
+            .parse()
+            .map_err(|e| e.to_string())?;
+        self.checker.check(&program).map_err(|e| e.to_string())?;
+        let mut value = self.runtime.run(&program).map_err(|e| e.to_string())?;
+        value.rearrange();
+        Ok(value)
+    }
+
+    /// The formatted value of the program, panics if it fails.
+    pub(crate) fn eval(&mut self, input: &str) -> String {
+        match self.run(input) {
+            Ok(value) => NormalFormatter::format(&value),
+            Err(message) => panic!("{} failed: {}", input, message),
+        }
+    }
+
+    /// The error message of the program, panics if it succeeds.
+    pub(crate) fn error(&mut self, input: &str) -> String {
+        match self.run(input) {
+            Ok(value) => panic!("{} gave {}", input, NormalFormatter::format(&value)),
+            Err(message) => message,
+        }
+    }
+}
+
+/// The formatted value of a program run on its own.
+pub(crate) fn eval(input: &str) -> String {
+    Session::new().eval(input)
+}
@@ -0,0 +1,235 @@
+//! Recording the steps of a computation, to show how a result came to be.
+//!
+//! While [`record`] runs, every rule that changes a value adds a [`Step`] with the value
+//! before and after the rule, in the order the rules were applied.
+
+use std::cell::RefCell;
+
+#[cfg(target_arch = "wasm32")]
+use serde::Serialize;
+
+use log::debug;
+
+use super::{
+    format::{NormalFormatter, ValueFormatter},
+    value::{Factors, RunType, RunVal, Terms},
+};
+
+macro_rules! trace_debug {
+    ($($arg:tt)+) => (debug!(target: "matex::trace", "[{}:{}] {}", file!(), line!(), &format!($($arg)+)));
+}
+
+/// A named transformation of a value.
+#[derive(Clone, Debug)]
+#[cfg_attr(target_arch = "wasm32", derive(Serialize))]
+pub struct Step {
+    /// The name of the rule, e.g. `collect_terms`
+    pub rule: String,
+    pub before: RunVal,
+    pub after: RunVal,
+}
+
+impl Step {
+    /// The step as `rule: before => after`.
+    pub fn format<T: ValueFormatter>(&self) -> String {
+        format!(
+            "{}: {} => {}",
+            self.rule,
+            T::format(&self.before),
+            T::format(&self.after)
+        )
+    }
+
+    /// The step as a value `step(rule, hold(before), hold(after))`, which isn't simplified any further.
+    pub fn to_value(&self) -> RunVal {
+        RunType::Function(
+            "step".to_string(),
+            vec![
+                RunType::Symbol(self.rule.clone()).into(),
+                hold(&self.before),
+                hold(&self.after),
+            ],
+        )
+        .into()
+    }
+}
+
+impl std::fmt::Display for Step {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.format::<NormalFormatter>())
+    }
+}
+
+thread_local! {
+    static TRACE: RefCell<Option<Vec<Step>>> = const { RefCell::new(None) };
+}
+
+/// Runs `f`, and returns its result together with the steps it took.
+///
+/// Recordings can be nested, the steps then belong to the innermost one.
+pub fn record<T>(f: impl FnOnce() -> T) -> (T, Vec<Step>) {
+    let outer = TRACE.with(|trace| trace.replace(Some(Vec::new())));
+    let result = f();
+    let steps = TRACE.with(|trace| trace.replace(outer)).unwrap_or_default();
+
+    trace_debug!("recorded {} steps", steps.len());
+    (result, steps)
+}
+
+/// Whether steps are being recorded, so that work that would only be skipped
+/// because it was done before (see [`super::intern`]) is done again.
+pub(crate) fn is_recording() -> bool {
+    TRACE.with(|trace| trace.borrow().is_some())
+}
+
+/// Runs `f` without recording its steps, for values that are only computed along the way.
+pub(crate) fn untraced<T>(f: impl FnOnce() -> T) -> T {
+    let outer = TRACE.with(|trace| trace.take());
+    let result = f();
+    TRACE.with(|trace| trace.replace(outer));
+    result
+}
+
+/// Records a step, unless nothing but the order of the operands changed.
+pub(crate) fn step(rule: &str, before: &RunVal, after: &RunVal) {
+    if before == after || sorted(before) == sorted(after) {
+        return;
+    }
+
+    TRACE.with(|trace| {
+        if let Some(steps) = trace.borrow_mut().as_mut() {
+            steps.push(Step {
+                rule: rule.to_string(),
+                before: before.clone(),
+                after: after.clone(),
+            });
+        }
+    })
+}
+
+/// Records a step, unless the same step was recorded before, e.g. substituting a variable that
+/// occurs several times.
+pub(crate) fn step_once(rule: &str, before: &RunVal, after: &RunVal) {
+    let recorded = TRACE.with(|trace| {
+        trace.borrow().as_ref().is_some_and(|steps| {
+            steps
+                .iter()
+                .any(|it| it.rule == rule && it.before == *before && it.after == *after)
+        })
+    });
+    if !recorded {
+        step(rule, before, after);
+    }
+}
+
+fn sorted(value: &RunVal) -> RunVal {
+    match &value.typ {
+        RunType::Sum(Terms(terms)) => {
+            let mut terms = terms.clone();
+            terms.sort();
+            RunType::Sum(Terms(terms)).into()
+        }
+        RunType::Product(Factors(factors)) => {
+            let mut factors = factors.clone();
+            factors.sort();
+            RunType::Product(Factors(factors)).into()
+        }
+        _ => value.clone(),
+    }
+}
+
+/// Applies a step to a part of a value, recording it with the part converted to a value.
+///
+/// What the step does along the way isn't recorded.
+pub(crate) fn traced<T>(
+    rule: &str,
+    part: &mut T,
+    to_value: impl Fn(&T) -> RunVal,
+    f: impl FnOnce(&mut T),
+) {
+    if !is_recording() {
+        f(part);
+        return;
+    }
+
+    let before = to_value(part);
+    untraced(|| f(part));
+    step(rule, &before, &to_value(part));
+}
+
+/// Keeps a value as it is, the simplifier doesn't change what is inside of `hold(...)`.
+pub fn hold(value: &RunVal) -> RunVal {
+    RunType::Function("hold".to_string(), vec![value.clone()]).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cas::eval::testing::{eval, Session};
+
+    fn rules(steps: &[Step]) -> Vec<&str> {
+        steps.iter().map(|step| step.rule.as_str()).collect()
+    }
+
+    #[test]
+    fn steps_function() {
+        assert_eq!(eval("steps(x + x)"), "[step(collect_terms, x + x, 2 * x)]");
+        assert_eq!(eval("steps(y * y)"), "[step(collect_factors, y * y, y^2)]");
+        assert_eq!(
+            eval("steps(sin(x)^2 + cos(x)^2)"),
+            "[step(pythagorean, cos(x)^2 + sin(x)^2, 1)]"
+        );
+    }
+
+    #[test]
+    fn remembered_simplifications_are_recorded_again() {
+        let mut session = Session::new();
+        session.eval("z + z");
+
+        let (value, steps) = record(|| session.run("z + z"));
+        assert!(value.is_ok());
+        assert_eq!(rules(&steps), ["collect_terms"]);
+        assert_eq!(steps[0].to_string(), "collect_terms: z + z => 2 * z");
+    }
+
+    #[test]
+    fn resolved_variables_are_recorded_again() {
+        let mut session = Session::new();
+        session.eval("x = 2");
+        session.eval("y = x^2 + x");
+        for _ in 0..2 {
+            let (_, steps) = record(|| session.run("simplify y"));
+            assert_eq!(
+                rules(&steps),
+                ["evaluate_powers", "collect_terms", "substitute"]
+            );
+        }
+    }
+
+    #[test]
+    fn variables_are_substituted_once() {
+        let mut session = Session::new();
+        session.eval("x = 2");
+        let (_, steps) = record(|| session.run("(x^2 - 1)/(x - 1)"));
+        assert_eq!(rules(&steps), ["substitute"]);
+        assert_eq!(steps[0].to_string(), "substitute: x => 2");
+    }
+
+    #[test]
+    fn nested_recordings() {
+        let mut session = Session::new();
+        let ((_, inner), outer) = record(|| {
+            let inner = record(|| session.run("a + a"));
+            let _ = untraced(|| session.run("b * b"));
+            inner
+        });
+        assert_eq!(rules(&inner), ["collect_terms"]);
+        assert!(outer.is_empty());
+    }
+
+    #[test]
+    fn reordering_isnt_a_step() {
+        let (_, steps) = record(|| eval("y + x"));
+        assert!(steps.is_empty());
+    }
+}
@@ -0,0 +1,446 @@
+//! Physical units, e.g. `5 m / 2 s` => `2.5 m/s`.
+//!
+//! Units are factors of a value like symbols are, `2.5 m/s` is `2.5 * m * s^-1`. Every unit has a
+//! dimension in the SI base units and a scale relative to them, so that units of the same
+//! dimension can be converted into each other, and values of different dimensions can't be added,
+//! compared or converted into each other.
+
+use std::{
+    cmp::Ordering,
+    fmt::{self, Display},
+    hash::{Hash, Hasher},
+};
+
+use log::debug;
+
+#[cfg(target_arch = "wasm32")]
+use serde::{Deserialize, Serialize};
+
+use super::value::{Factors, RunType, RunVal, Terms};
+
+macro_rules! units_debug {
+    ($($arg:tt)+) => (debug!(target: "matex::units", "[{}:{}] {}", file!(), line!(), &format!($($arg)+)));
+}
+
+/// The SI base units, in the order of the exponents of a [`Dimension`].
+const BASE_UNITS: [&str; 7] = ["m", "kg", "s", "A", "K", "mol", "cd"];
+
+/// The exponents of the SI base units, e.g. `[1, 0, -1, 0, 0, 0, 0]` for a velocity.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(target_arch = "wasm32", derive(Serialize, Deserialize))]
+pub struct Dimension(pub [i8; 7]);
+
+impl Dimension {
+    pub fn is_dimensionless(self) -> bool {
+        self == Dimension::default()
+    }
+
+    fn multiply(self, other: Dimension) -> Dimension {
+        let mut exponents = self.0;
+        for (exponent, other) in exponents.iter_mut().zip(other.0) {
+            *exponent += other;
+        }
+        Dimension(exponents)
+    }
+
+    fn power(self, n: i8) -> Dimension {
+        Dimension(self.0.map(|it| it * n))
+    }
+}
+
+/// Written in the base units, e.g. `kg*m/s^2`.
+impl Display for Dimension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_dimensionless() {
+            return f.write_str("1");
+        }
+        let powers = BASE_UNITS
+            .iter()
+            .zip(self.0)
+            .filter(|(_, exponent)| *exponent != 0)
+            .map(|(name, exponent)| (name.to_string(), exponent as f64));
+        f.write_str(&format_powers(powers))
+    }
+}
+
+/// A unit like `km`, with the number of base units it is, e.g. 1000 for `km`.
+///
+/// Units are told apart by their name only, the rest follows from it.
+#[derive(Clone, Debug)]
+#[cfg_attr(target_arch = "wasm32", derive(Serialize, Deserialize))]
+pub struct PhysicalUnit {
+    pub name: String,
+    pub scale: f64,
+    pub dimension: Dimension,
+}
+
+/// The units without a prefix: name, scale, dimension and whether prefixes can be used.
+#[rustfmt::skip]
+const UNITS: [(&str, f64, [i8; 7], bool); 17] = [
+    ("m", 1.0, [1, 0, 0, 0, 0, 0, 0], true),
+    // The kilogram is the base unit, but the prefixes go on the gram
+    ("g", 1e-3, [0, 1, 0, 0, 0, 0, 0], true),
+    ("s", 1.0, [0, 0, 1, 0, 0, 0, 0], true),
+    ("A", 1.0, [0, 0, 0, 1, 0, 0, 0], true),
+    ("K", 1.0, [0, 0, 0, 0, 1, 0, 0], true),
+    ("mol", 1.0, [0, 0, 0, 0, 0, 1, 0], true),
+    ("cd", 1.0, [0, 0, 0, 0, 0, 0, 1], true),
+    ("N", 1.0, [1, 1, -2, 0, 0, 0, 0], true),
+    ("J", 1.0, [2, 1, -2, 0, 0, 0, 0], true),
+    ("W", 1.0, [2, 1, -3, 0, 0, 0, 0], true),
+    ("Pa", 1.0, [-1, 1, -2, 0, 0, 0, 0], true),
+    ("Hz", 1.0, [0, 0, -1, 0, 0, 0, 0], true),
+    ("C", 1.0, [0, 0, 1, 1, 0, 0, 0], true),
+    ("V", 1.0, [2, 1, -3, -1, 0, 0, 0], true),
+    ("min", 60.0, [0, 0, 1, 0, 0, 0, 0], false),
+    ("h", 3600.0, [0, 0, 1, 0, 0, 0, 0], false),
+    ("L", 1e-3, [3, 0, 0, 0, 0, 0, 0], true),
+];
+
+const PREFIXES: [(&str, f64); 8] = [
+    ("G", 1e9),
+    ("M", 1e6),
+    ("k", 1e3),
+    ("c", 1e-2),
+    ("m", 1e-3),
+    ("µ", 1e-6),
+    ("u", 1e-6),
+    ("n", 1e-9),
+];
+
+impl PhysicalUnit {
+    /// The unit with the name, e.g. `m`, `kN` or `µs`.
+    pub fn parse(name: &str) -> Option<PhysicalUnit> {
+        let unit = |name: &str, scale: f64, dimension: [i8; 7]| PhysicalUnit {
+            name: name.to_string(),
+            scale,
+            dimension: Dimension(dimension),
+        };
+
+        if let Some((_, scale, dimension, _)) = UNITS.iter().find(|(it, ..)| *it == name) {
+            return Some(unit(name, *scale, *dimension));
+        }
+
+        PREFIXES.iter().find_map(|(prefix, factor)| {
+            let base = name.strip_prefix(prefix)?;
+            let (_, scale, dimension, _) = UNITS
+                .iter()
+                .find(|(it, _, _, prefixed)| *it == base && *prefixed)?;
+            Some(unit(name, factor * scale, *dimension))
+        })
+    }
+}
+
+impl PartialEq for PhysicalUnit {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl Eq for PhysicalUnit {}
+
+impl Ord for PhysicalUnit {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.name.cmp(&other.name)
+    }
+}
+
+impl PartialOrd for PhysicalUnit {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Hash for PhysicalUnit {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+    }
+}
+
+/// A unit, or a power of a unit, e.g. `s^-2`.
+fn unit_power(value: &RunVal) -> Option<(&PhysicalUnit, f64)> {
+    match &value.typ {
+        RunType::PhysicalUnit(unit) => Some((unit, 1.0)),
+        RunType::Exponent(base, exp) => match (&base.typ, &exp.typ) {
+            (RunType::PhysicalUnit(unit), RunType::Number(n)) => Some((unit, *n)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+pub(crate) fn is_unit_factor(value: &RunVal) -> bool {
+    unit_power(value).is_some()
+}
+
+/// The dimension of a value, `None` if it isn't known, e.g. for symbols.
+pub(crate) fn dimension(value: &RunVal) -> Option<Dimension> {
+    use RunType::*;
+    match &value.typ {
+        Number(_) | Constant(_) | Interval(_) => Some(Dimension::default()),
+        PhysicalUnit(unit) => Some(unit.dimension),
+        Product(Factors(factors)) => factors.iter().try_fold(Dimension::default(), |acc, it| {
+            Some(acc.multiply(dimension(it)?))
+        }),
+        Exponent(base, exp) => {
+            let base = dimension(base)?;
+            match &exp.typ {
+                _ if base.is_dimensionless() => Some(base),
+                Number(n) if n.fract() == 0.0 => Some(base.power(*n as i8)),
+                _ => None,
+            }
+        }
+        Sum(Terms(terms)) => {
+            let dimensions = terms.iter().map(dimension).collect::<Option<Vec<_>>>()?;
+            let first = *dimensions.first()?;
+            dimensions.iter().all(|it| *it == first).then_some(first)
+        }
+        // Functions like `sin` are only defined for numbers without units
+        Function(_, args) => args
+            .iter()
+            .all(|it| dimension(it).is_some_and(Dimension::is_dimensionless))
+            .then_some(Dimension::default()),
+        _ => None,
+    }
+}
+
+/// Powers of values with units are taken factor by factor, e.g. `(2 s)^-1` => `0.5 s^-1`, so
+/// that the units can be combined with others.
+pub(crate) fn power(base: &RunVal, exp: &RunVal) -> Option<RunVal> {
+    let (RunType::Product(Factors(factors)), RunType::Number(_)) = (&base.typ, &exp.typ) else {
+        return None;
+    };
+    if !factors.iter().any(is_unit_factor) {
+        return None;
+    }
+
+    let factors = factors
+        .iter()
+        .map(|it| it.clone().power(exp.clone()))
+        .collect();
+    let mut power: RunVal = RunType::Product(Factors(factors)).into();
+    power.simplify();
+    Some(power)
+}
+
+/// The units of a term and the rest of it, e.g. `2.5` and `[m, s^-1]` for `2.5 m/s`.
+fn split(value: &RunVal) -> (Vec<RunVal>, Vec<RunVal>) {
+    match &value.typ {
+        RunType::Product(Factors(factors)) => {
+            factors.iter().cloned().partition(|it| !is_unit_factor(it))
+        }
+        _ if is_unit_factor(value) => (Vec::new(), vec![value.clone()]),
+        _ => (vec![value.clone()], Vec::new()),
+    }
+}
+
+/// The number of base units the units are together, e.g. `1000 / 3600` for `km/h`.
+fn scale(units: &[RunVal]) -> f64 {
+    units
+        .iter()
+        .filter_map(unit_power)
+        .map(|(unit, exponent)| unit.scale.powf(exponent))
+        .product()
+}
+
+/// The dimension of units, `None` for fractional powers.
+fn units_dimension(units: &[RunVal]) -> Option<Dimension> {
+    units.iter().try_fold(Dimension::default(), |acc, it| {
+        let (unit, exponent) = unit_power(it)?;
+        (exponent.fract() == 0.0).then(|| acc.multiply(unit.dimension.power(exponent as i8)))
+    })
+}
+
+/// Writes units of the same dimension in the same unit, e.g. `km * m` => `1000 * m * m`, so
+/// that they can be combined.
+pub(crate) fn unify_factors(factors: &mut Vec<RunVal>) {
+    let mut kept: Vec<PhysicalUnit> = Vec::new();
+    let mut scale = 1.0;
+
+    for factor in factors.iter_mut() {
+        let Some((unit, exponent)) = unit_power(factor) else {
+            continue;
+        };
+        let same = kept
+            .iter()
+            .find(|it| it.dimension == unit.dimension && it.name != unit.name);
+        match same {
+            Some(same) => {
+                scale *= (unit.scale / same.scale).powf(exponent);
+                let same: RunVal = RunType::PhysicalUnit(same.clone()).into();
+                *factor = same.power(RunType::Number(exponent).into());
+            }
+            None => kept.push(unit.clone()),
+        }
+    }
+
+    if scale != 1.0 {
+        units_debug!("unified units of {:?} with the scale {}", factors, scale);
+        factors.push(RunType::Number(scale).into());
+    }
+}
+
+/// Writes terms of the same dimension in the units of the first one, e.g. `1 m + 1 km` =>
+/// `1 m + 1000 m`, so that they can be combined.
+pub(crate) fn unify_terms(terms: &mut [RunVal]) {
+    let mut kept: Vec<(Dimension, Vec<RunVal>)> = Vec::new();
+
+    for term in terms.iter_mut() {
+        let (rest, units) = split(term);
+        let Some(dimension) = units_dimension(&units).filter(|_| !units.is_empty()) else {
+            continue;
+        };
+
+        match kept.iter().find(|(it, _)| *it == dimension) {
+            Some((_, same)) if *same != units => {
+                let ratio = scale(&units) / scale(same);
+                let mut factors = rest;
+                factors.push(RunType::Number(ratio).into());
+                factors.extend(same.iter().cloned());
+                *term = RunType::Product(Factors(factors)).into();
+                term.simplify();
+            }
+            Some(_) => {}
+            None => kept.push((dimension, units)),
+        }
+    }
+}
+
+/// The value written in the units of `target`, e.g. `convert(36 km/h, m/s)` => `10 m/s`.
+///
+/// Symbols of the target that are names of units are the units, since `m/s` is only read as
+/// units after a number. `None` if the dimensions differ.
+pub(crate) fn convert(value: &RunVal, target: &RunVal) -> Option<RunVal> {
+    let target = as_units(target);
+    let (_, target) = split(&target);
+    let (rest, units) = split(value);
+
+    let expected = units_dimension(&target)?;
+    if units_dimension(&units)? != expected || dimension(value)? != expected {
+        return None;
+    }
+
+    let mut factors = rest;
+    factors.push(RunType::Number(scale(&units) / scale(&target)).into());
+    factors.extend(target);
+    let mut converted: RunVal = RunType::Product(Factors(factors)).into();
+    converted.simplify();
+    Some(converted)
+}
+
+/// The dimension of the target of `convert`, e.g. length for `km`.
+pub(crate) fn target_dimension(target: &RunVal) -> Option<Dimension> {
+    dimension(&as_units(target))
+}
+
+/// A value with units written in the base units without them, and its dimension, e.g. `2000` and
+/// length for `2 km`. `None` if the value has no units.
+pub(crate) fn magnitude(value: &RunVal) -> Option<(RunVal, Dimension)> {
+    let (mut rest, units) = split(value);
+    if units.is_empty() {
+        return None;
+    }
+    let dimension = units_dimension(&units)?;
+    rest.push(RunType::Number(scale(&units)).into());
+    Some((RunType::Product(Factors(rest)).into(), dimension))
+}
+
+/// The value with the symbols that name units replaced by the units.
+fn as_units(value: &RunVal) -> RunVal {
+    match &value.typ {
+        RunType::Symbol(name) => match PhysicalUnit::parse(name) {
+            Some(unit) => RunType::PhysicalUnit(unit).into(),
+            None => value.clone(),
+        },
+        RunType::Product(Factors(factors)) => {
+            RunType::Product(Factors(factors.iter().map(as_units).collect())).into()
+        }
+        RunType::Exponent(base, exp) => as_units(base).power((**exp).clone()),
+        _ => value.clone(),
+    }
+}
+
+/// Writes units like they are entered, e.g. `kg*m/s^2`.
+pub(crate) fn format(units: &[RunVal]) -> String {
+    let powers = units
+        .iter()
+        .filter_map(unit_power)
+        .map(|(unit, exponent)| (unit.name.clone(), exponent));
+    format_powers(powers)
+}
+
+fn format_powers(powers: impl Iterator<Item = (String, f64)>) -> String {
+    let power = |(name, exponent): &(String, f64)| match exponent.abs() {
+        1.0 => name.clone(),
+        exponent => format!("{}^{}", name, exponent),
+    };
+
+    let (numerator, denominator): (Vec<_>, Vec<_>) = powers.partition(|(_, it)| *it > 0.0);
+    let numerator = match numerator.is_empty() {
+        true => "1".to_string(),
+        false => numerator.iter().map(power).collect::<Vec<_>>().join("*"),
+    };
+    match denominator.as_slice() {
+        [] => numerator,
+        [single] => format!("{}/{}", numerator, power(single)),
+        _ => {
+            let denominator = denominator.iter().map(power).collect::<Vec<_>>().join("*");
+            format!("{}/({})", numerator, denominator)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cas::eval::testing::{eval, Session};
+
+    #[test]
+    fn arithmetic_with_units() {
+        assert_eq!(eval("5 m / 2 s"), "2.5 m/s");
+        assert_eq!(eval("1 km + 1 m"), "1.001 km");
+        assert_eq!(eval("2 N * 3 m"), "6 N*m");
+        assert_eq!(eval("10 m / 5 m"), "2");
+    }
+
+    #[test]
+    fn conversion() {
+        assert_eq!(eval("convert(36 km/h, m/s)"), "10 m/s");
+        assert_eq!(eval("convert(2 kg*m/s^2, N)"), "2 N");
+        assert_eq!(eval("convert(x, m)"), "convert(x, m)");
+        assert_eq!(
+            Session::new().error("convert(1 m, s)"),
+            "Values in m can't be converted to s at (row 1, col 7)"
+        );
+    }
+
+    #[test]
+    fn comparison() {
+        assert_eq!(eval("1 m < 2 km"), "true");
+        assert_eq!(eval("2 km >= 2000 m"), "true");
+        assert_eq!(eval("3 s > 1 h"), "false");
+        assert_eq!(
+            Session::new().error("1 m < 2 s"),
+            "Values in m and s can't be compared at (row 1, col 4)"
+        );
+        assert_eq!(
+            Session::new().error("3 m > 2"),
+            "Values in m and 1 can't be compared at (row 1, col 4)"
+        );
+    }
+
+    #[test]
+    fn units_are_positive() {
+        assert_eq!(eval("sqrt(4 m^2)"), "2 m");
+        assert_eq!(eval("(4 m^2)^(1/2)"), "2 m");
+        assert_eq!(eval("N(sqrt(4 m^2))"), "2 m");
+        assert_eq!(eval("sqrt(9 m^2/s^2)"), "3 m/s");
+    }
+
+    #[test]
+    fn different_dimensions_cant_be_added() {
+        assert_eq!(
+            Session::new().error("1 m + 1 s"),
+            "Values in m and s can't be added at (row 1, col 4)"
+        );
+    }
+}
@@ -1,12 +1,25 @@
 use std::{
-    cmp::Ordering,
+    cell::Cell,
+    collections::hash_map::DefaultHasher,
     fmt::{self, Debug},
+    hash::{Hash, Hasher},
+    ops::{Deref, DerefMut},
+    rc::Rc,
 };
 
 #[cfg(target_arch = "wasm32")]
 use serde::{Deserialize, Serialize};
 
-use super::format::ValueFormatter;
+use super::{
+    assumptions,
+    format::ValueFormatter,
+    functions, integers, intern,
+    interval::{self, Interval},
+    rational, rules,
+    sets::{self, Set},
+    trace,
+    units::{self, PhysicalUnit},
+};
 use log::{debug, error};
 
 macro_rules! value_debug {
@@ -18,16 +31,27 @@ macro_rules! value_error {
     ($($arg:tt)+) => (error!(target: "matex::value", $($arg)+));
 }
 
-#[derive(Clone, PartialEq)]
-#[cfg_attr(target_arch = "wasm32", derive(Serialize, Deserialize))]
+/// A value, i.e. a node in an expression.
+///
+/// Nodes are reference counted, so cloning a value is cheap, and structurally identical
+/// nodes are shared (see [`intern`]). The node is reached through `Deref`, e.g. `value.typ`.
+/// Changing it through `DerefMut` copies the node first if it is shared with other values.
+#[derive(Clone)]
 // Better name
-pub struct RunVal {
-    pub(crate) simplified: bool,
+pub struct RunVal(Rc<Node>);
+
+#[derive(Clone)]
+pub struct Node {
     pub typ: RunType,
+    /// Cached hash of `typ`, computed when first needed
+    hash: Cell<Option<u64>>,
+    /// The generation of the simplification rules `typ` is known to be simplified with,
+    /// shared by every value using the node. 0 if it isn't known to be simplified.
+    simplified: Cell<u32>,
 }
 
 #[cfg_attr(target_arch = "wasm32", derive(Serialize, Deserialize))]
-#[derive(Clone, PartialEq)]
+#[derive(Clone)]
 pub enum RunType {
     Unit,
 
@@ -36,48 +60,138 @@ pub enum RunType {
     // TODO: Add complex, real, etc
     Number(f64),
     Symbol(String),
+    Constant(Constant),
     Bool(bool),
     Vector(Vec<RunVal>),
 
     Sum(Terms),
     Product(Factors),
-    
+
     Exponent(Box<RunVal>, Box<RunVal>),
 
     Function(String, Vec<RunVal>),
+
+    /// The value of the first piece whose condition holds, e.g. from `if x > 0 then x else -x`
+    Piecewise(Pieces),
+
+    Set(Set),
+
+    /// An unknown real number between two bounds, e.g. from `interval(1, 2)`
+    Interval(Interval),
+
+    /// A unit like the `m` of `5 m`
+    PhysicalUnit(PhysicalUnit),
+}
+
+/// Mathematical constants that are kept exact until they are approximated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(target_arch = "wasm32", derive(Serialize, Deserialize))]
+pub enum Constant {
+    Pi,
+    E,
+    /// The imaginary unit
+    I,
+}
+
+impl Constant {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Constant::Pi => "PI",
+            Constant::E => "E",
+            Constant::I => "I",
+        }
+    }
+
+    /// The numeric value of a real constant.
+    pub fn approximate(&self) -> Option<f64> {
+        match self {
+            Constant::Pi => Some(std::f64::consts::PI),
+            Constant::E => Some(std::f64::consts::E),
+            Constant::I => None,
+        }
+    }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 #[cfg_attr(target_arch = "wasm32", derive(Serialize, Deserialize))]
 pub struct Factors(pub Vec<RunVal>);
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 #[cfg_attr(target_arch = "wasm32", derive(Serialize, Deserialize))]
 pub struct Terms(pub Vec<RunVal>);
 
+/// Conditions and the values that apply while they hold, the last condition is `true` if there
+/// is a value otherwise.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(target_arch = "wasm32", derive(Serialize, Deserialize))]
+pub struct Pieces(pub Vec<(RunVal, RunVal)>);
+
+impl Pieces {
+    /// The pieces with `f` applied to every condition and value.
+    pub(crate) fn map(&self, mut f: impl FnMut(&RunVal) -> RunVal) -> Pieces {
+        Pieces(self.0.iter().map(|(cond, value)| (f(cond), f(value))).collect())
+    }
+
+    /// Every condition and value, in order.
+    pub(crate) fn parts(&self) -> impl Iterator<Item = &RunVal> {
+        self.0.iter().flat_map(|(cond, value)| [cond, value])
+    }
+}
+
 impl RunVal {
     pub fn format<T: ValueFormatter>(&self) {
         T::format(self);
     }
 
     pub(crate) fn new(typ: RunType) -> Self {
-        Self {
-            simplified: false,
+        RunVal(intern::intern(Rc::new(Node {
             typ,
+            hash: Cell::new(None),
+            simplified: Cell::new(0),
+        })))
+    }
+
+    pub(crate) fn is_simplified(&self) -> bool {
+        self.0.simplified.get() == intern::generation()
+    }
+
+    fn set_simplified(&self) {
+        self.0.simplified.set(intern::generation());
+    }
+
+    /// The value's type, without copying it unless the node is shared.
+    pub(crate) fn into_typ(self) -> RunType {
+        match Rc::try_unwrap(self.0) {
+            Ok(node) => node.typ,
+            Err(node) => node.typ.clone(),
         }
     }
 
+    /// Whether both values are the same node.
+    pub(crate) fn ptr_eq(&self, other: &RunVal) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+
+    pub(crate) fn hash_value(&self) -> u64 {
+        self.0.hash_value()
+    }
+
     // TODO: Make these take references and return new one...
     pub(crate) fn add(self, other: RunVal) -> RunVal {
         value_debug!("add: {:?} + {:?}", self, other);
         use RunType::*;
 
-        let typ = match (self.typ, other.typ) {
+        let typ = match (self.into_typ(), other.into_typ()) {
             (Unit, _) | (_, Unit) | (Undefined, _) | (_, Undefined) => Undefined,
 
-            (Bool(_), _) | (_, Bool(_)) => panic!("No addition with booleans"),
+            // The runtime reports these, see `Runtime::check_operands`
+            (Bool(_), _) | (_, Bool(_)) => Undefined,
 
             (Number(lhs), Number(rhs)) => Number(lhs + rhs),
+            (Interval(lhs), Interval(rhs)) => Interval(lhs.add(&rhs)),
+            (Interval(i), Number(n)) | (Number(n), Interval(i)) => {
+                Interval(i.add(&self::Interval::point(n)))
+            }
 
             (Sum(Terms(mut v)), Sum(Terms(other_v))) => {
                 v.extend(other_v);
@@ -94,43 +208,45 @@ impl RunVal {
                 Sum(Terms(v))
             }
 
-            (s @ Number(_), o @ Symbol(_))
+            (s @ Number(_), o @ (Symbol(_) | Constant(_)))
             | (s @ Number(_), o @ Product(_))
             | (s @ Number(_), o @ Exponent(_, _))
             | (s @ Number(_), o @ Function(_, _))
-            | (s @ Symbol(_), o @ Number(_))
-            | (s @ Symbol(_), o @ Symbol(_))
-            | (s @ Symbol(_), o @ Product(_))
-            | (s @ Symbol(_), o @ Exponent(_, _))
-            | (s @ Symbol(_), o @ Function(_, _))
+            | (s @ (Symbol(_) | Constant(_)), o @ Number(_))
+            | (s @ (Symbol(_) | Constant(_)), o @ (Symbol(_) | Constant(_)))
+            | (s @ (Symbol(_) | Constant(_)), o @ Product(_))
+            | (s @ (Symbol(_) | Constant(_)), o @ Exponent(_, _))
+            | (s @ (Symbol(_) | Constant(_)), o @ Function(_, _))
             | (s @ Product(_), o @ Number(_))
-            | (s @ Product(_), o @ Symbol(_))
+            | (s @ Product(_), o @ (Symbol(_) | Constant(_)))
             | (s @ Product(_), o @ Product(_))
             | (s @ Product(_), o @ Exponent(_, _))
             | (s @ Product(_), o @ Function(_, _))
             | (s @ Exponent(_, _), o @ Number(_))
-            | (s @ Exponent(_, _), o @ Symbol(_))
+            | (s @ Exponent(_, _), o @ (Symbol(_) | Constant(_)))
             | (s @ Exponent(_, _), o @ Product(_))
             | (s @ Exponent(_, _), o @ Exponent(_, _))
             | (s @ Exponent(_, _), o @ Function(_, _))
             | (s @ Function(_, _), o @ Number(_))
-            | (s @ Function(_, _), o @ Symbol(_))
+            | (s @ Function(_, _), o @ (Symbol(_) | Constant(_)))
             | (s @ Function(_, _), o @ Product(_))
             | (s @ Function(_, _), o @ Exponent(_, _))
             | (s @ Function(_, _), o @ Function(_, _)) => {
                 RunType::Sum(Terms(Vec::from([s.into(), o.into()])))
             }
-            (Number(_), Vector(_)) => todo!(),
-            (Symbol(_), Vector(_)) => todo!(),
-            (Vector(_), Number(_)) => todo!(),
-            (Vector(_), Symbol(_)) => todo!(),
-            (Vector(_), Vector(_)) => todo!(),
-            (Vector(_), Product(_)) => todo!(),
-            (Vector(_), Exponent(_, _)) => todo!(),
-            (Vector(_), Function(_, _)) => todo!(),
-            (Product(_), Vector(_)) => todo!(),
-            (Exponent(_, _), Vector(_)) => todo!(),
-            (Function(_, _), Vector(_)) => todo!(),
+            // Vectors are added element by element
+            (Vector(lhs), Vector(rhs)) if lhs.len() == rhs.len() => Vector(
+                lhs.into_iter()
+                    .zip(rhs)
+                    .map(|(lhs, rhs)| lhs.add(rhs))
+                    .collect(),
+            ),
+            (Vector(_), _) | (_, Vector(_)) => Undefined,
+            (Set(_), _) | (_, Set(_)) => Undefined,
+            (s @ (Piecewise(_) | Interval(_) | PhysicalUnit(_)), o)
+            | (s, o @ (Piecewise(_) | Interval(_) | PhysicalUnit(_))) => {
+                RunType::Sum(Terms(Vec::from([s.into(), o.into()])))
+            }
         };
 
         RunVal::new(typ)
@@ -139,12 +255,16 @@ impl RunVal {
     pub(crate) fn multiply(self, other: RunVal) -> RunVal {
         value_debug!("multiply: {:?} * {:?}", self, other);
         use RunType::*;
-        let typ = match (self.typ, other.typ) {
+        let typ = match (self.into_typ(), other.into_typ()) {
             (Unit, _) | (_, Unit) | (Undefined, _) | (_, Undefined) => Undefined,
 
-            (Bool(_), _) | (_, Bool(_)) => panic!("No multiplication with booleans"),
+            (Bool(_), _) | (_, Bool(_)) => Undefined,
 
             (Number(lhs), Number(rhs)) => Number(lhs * rhs),
+            (Interval(lhs), Interval(rhs)) => Interval(lhs.multiply(&rhs)),
+            (Interval(i), Number(n)) | (Number(n), Interval(i)) => {
+                Interval(i.multiply(&self::Interval::point(n)))
+            }
 
             (Product(Factors(mut v)), Product(Factors(other_v))) => {
                 v.extend(other_v);
@@ -161,44 +281,49 @@ impl RunVal {
                 Product(Factors(v))
             }
 
-            (s @ Number(_), o @ Symbol(_))
+            (s @ Number(_), o @ (Symbol(_) | Constant(_)))
             | (s @ Number(_), o @ Sum(_))
             | (s @ Number(_), o @ Exponent(_, _))
             | (s @ Number(_), o @ Function(_, _))
-            | (s @ Symbol(_), o @ Number(_))
-            | (s @ Symbol(_), o @ Symbol(_))
-            | (s @ Symbol(_), o @ Sum(_))
-            | (s @ Symbol(_), o @ Exponent(_, _))
-            | (s @ Symbol(_), o @ Function(_, _))
+            | (s @ (Symbol(_) | Constant(_)), o @ Number(_))
+            | (s @ (Symbol(_) | Constant(_)), o @ (Symbol(_) | Constant(_)))
+            | (s @ (Symbol(_) | Constant(_)), o @ Sum(_))
+            | (s @ (Symbol(_) | Constant(_)), o @ Exponent(_, _))
+            | (s @ (Symbol(_) | Constant(_)), o @ Function(_, _))
             | (s @ Sum(_), o @ Number(_))
-            | (s @ Sum(_), o @ Symbol(_))
+            | (s @ Sum(_), o @ (Symbol(_) | Constant(_)))
             | (s @ Sum(_), o @ Sum(_))
             | (s @ Sum(_), o @ Exponent(_, _))
             | (s @ Sum(_), o @ Function(_, _))
             | (s @ Exponent(_, _), o @ Number(_))
-            | (s @ Exponent(_, _), o @ Symbol(_))
+            | (s @ Exponent(_, _), o @ (Symbol(_) | Constant(_)))
             | (s @ Exponent(_, _), o @ Sum(_))
             | (s @ Exponent(_, _), o @ Exponent(_, _))
             | (s @ Exponent(_, _), o @ Function(_, _))
             | (s @ Function(_, _), o @ Number(_))
-            | (s @ Function(_, _), o @ Symbol(_))
+            | (s @ Function(_, _), o @ (Symbol(_) | Constant(_)))
             | (s @ Function(_, _), o @ Sum(_))
             | (s @ Function(_, _), o @ Exponent(_, _))
             | (s @ Function(_, _), o @ Function(_, _)) => {
                 RunType::Product(Factors(Vec::from([s.into(), o.into()])))
             }
 
-            (Number(_), Vector(_)) => todo!(),
-            (Symbol(_), Vector(_)) => todo!(),
-            (Vector(_), Number(_)) => todo!(),
-            (Vector(_), Symbol(_)) => todo!(),
-            (Vector(_), Vector(_)) => todo!(),
-            (Vector(_), Sum(_)) => todo!(),
-            (Vector(_), Exponent(_, _)) => todo!(),
-            (Vector(_), Function(_, _)) => todo!(),
-            (Sum(_), Vector(_)) => todo!(),
-            (Exponent(_, _), Vector(_)) => todo!(),
-            (Function(_, _), Vector(_)) => todo!(),
+            // Scalars multiply every element of a vector
+            (Vector(_), Vector(_)) => Undefined,
+            (Vector(values), o) | (o, Vector(values)) => {
+                let factor: RunVal = o.into();
+                Vector(
+                    values
+                        .into_iter()
+                        .map(|it| it.multiply(factor.clone()))
+                        .collect(),
+                )
+            }
+            (Set(_), _) | (_, Set(_)) => Undefined,
+            (s @ (Piecewise(_) | Interval(_) | PhysicalUnit(_)), o)
+            | (s, o @ (Piecewise(_) | Interval(_) | PhysicalUnit(_))) => {
+                RunType::Product(Factors(Vec::from([s.into(), o.into()])))
+            }
         };
 
         RunVal::new(typ)
@@ -206,128 +331,136 @@ impl RunVal {
     pub(crate) fn power(self, other: RunVal) -> RunVal {
         value_debug!("power: {:?} ^ {:?}", self, other);
         use RunType::*;
-        match (self.typ, other.typ) {
+        match (self.into_typ(), other.into_typ()) {
             (Unit, _) | (_, Unit) | (Undefined, _) | (_, Undefined) => Undefined.into(),
 
-            (Bool(_), _) | (_, Bool(_)) => panic!("No powering with booleans"),
+            (Bool(_), _) | (_, Bool(_)) | (Vector(_), _) | (_, Vector(_)) => Undefined.into(),
+            (Set(_), _) | (_, Set(_)) => Undefined.into(),
 
             // TODO: Calculate directly or keep as exponent?
-            (Number(lhs), Number(rhs)) => Number(lhs.powf(rhs)).into(),
+            (Number(lhs), Number(rhs)) => RunVal::number_power(lhs, rhs),
             // (s@Number(_), o@Number(_)) => Exponent(Box::new(s.into()), Box::new(o.into())).into(),
             (Exponent(base, exp), o @ Number(_))
-            | (Exponent(base, exp), o @ Symbol(_))
+            | (Exponent(base, exp), o @ (Symbol(_) | Constant(_)))
             | (Exponent(base, exp), o @ Sum(_))
             | (Exponent(base, exp), o @ Product(_))
             | (Exponent(base, exp), o @ Exponent(_, _))
             | (Exponent(base, exp), o @ Function(_, _)) => {
-                Exponent(base, Box::new(exp.multiply(o.into()))).into()
+                let o: RunVal = o.into();
+                if RunVal::can_merge_powers(&base, &exp, &o) {
+                    Exponent(base, Box::new(exp.multiply(o))).into()
+                } else {
+                    Exponent(Box::new(Exponent(base, exp).into()), Box::new(o)).into()
+                }
             }
 
-            (s @ Number(_), o @ Symbol(_))
+            (s @ Number(_), o @ (Symbol(_) | Constant(_)))
             | (s @ Number(_), o @ Sum(_))
             | (s @ Number(_), o @ Product(_))
             | (s @ Number(_), o @ Exponent(_, _))
             | (s @ Number(_), o @ Function(_, _))
-            | (s @ Symbol(_), o @ Number(_))
-            | (s @ Symbol(_), o @ Symbol(_))
-            | (s @ Symbol(_), o @ Sum(_))
-            | (s @ Symbol(_), o @ Product(_))
-            | (s @ Symbol(_), o @ Exponent(_, _))
-            | (s @ Symbol(_), o @ Function(_, _))
+            | (s @ (Symbol(_) | Constant(_)), o @ Number(_))
+            | (s @ (Symbol(_) | Constant(_)), o @ (Symbol(_) | Constant(_)))
+            | (s @ (Symbol(_) | Constant(_)), o @ Sum(_))
+            | (s @ (Symbol(_) | Constant(_)), o @ Product(_))
+            | (s @ (Symbol(_) | Constant(_)), o @ Exponent(_, _))
+            | (s @ (Symbol(_) | Constant(_)), o @ Function(_, _))
             | (s @ Sum(_), o @ Number(_))
-            | (s @ Sum(_), o @ Symbol(_))
+            | (s @ Sum(_), o @ (Symbol(_) | Constant(_)))
             | (s @ Sum(_), o @ Sum(_))
             | (s @ Sum(_), o @ Product(_))
             | (s @ Sum(_), o @ Exponent(_, _))
             | (s @ Sum(_), o @ Function(_, _))
             | (s @ Product(_), o @ Number(_))
-            | (s @ Product(_), o @ Symbol(_))
+            | (s @ Product(_), o @ (Symbol(_) | Constant(_)))
             | (s @ Product(_), o @ Sum(_))
             | (s @ Product(_), o @ Product(_))
             | (s @ Product(_), o @ Exponent(_, _))
             | (s @ Product(_), o @ Function(_, _))
             | (s @ Function(_, _), o @ Number(_))
-            | (s @ Function(_, _), o @ Symbol(_))
+            | (s @ Function(_, _), o @ (Symbol(_) | Constant(_)))
             | (s @ Function(_, _), o @ Sum(_))
             | (s @ Function(_, _), o @ Product(_))
             | (s @ Function(_, _), o @ Exponent(_, _))
-            | (s @ Function(_, _), o @ Function(_, _)) => {
+            | (s @ Function(_, _), o @ Function(_, _))
+            | (s @ (Piecewise(_) | Interval(_) | PhysicalUnit(_)), o)
+            | (s, o @ (Piecewise(_) | Interval(_) | PhysicalUnit(_))) => {
                 Exponent(Box::new(s.into()), Box::new(o.into())).into()
             }
 
-            (Number(_), Vector(_)) => todo!(),
-            (Symbol(_), Vector(_)) => todo!(),
-            (Vector(_), Number(_)) => todo!(),
-            (Vector(_), Symbol(_)) => todo!(),
-            (Vector(_), Vector(_)) => todo!(),
-            (Vector(_), Sum(_)) => todo!(),
-            (Vector(_), Product(_)) => todo!(),
-            (Vector(_), Exponent(_, _)) => todo!(),
-            (Vector(_), Function(_, _)) => todo!(),
-            (Sum(_), Vector(_)) => todo!(),
-            (Product(_), Vector(_)) => todo!(),
-            (Exponent(_, _), Vector(_)) => todo!(),
-            (Function(_, _), Vector(_)) => todo!(),
         }
     }
     pub(crate) fn less(self, other: RunVal) -> RunVal {
         match (&self.typ, &other.typ) {
             (RunType::Number(lhs), RunType::Number(rhs)) => RunType::Bool(lhs < rhs).into(),
-
-            _ => {
-                todo!("Not implemented: {:?} {:?}", self, other);
-            }
+            _ => RunVal::relation("less", self, other),
         }
     }
     pub(crate) fn equal(self, other: RunVal) -> RunVal {
         match (&self.typ, &other.typ) {
             (RunType::Number(lhs), RunType::Number(rhs)) => RunType::Bool(lhs == rhs).into(),
-
-            _ => {
-                todo!("Not implemented: {:?} {:?}", self, other);
-            }
+            _ => RunVal::relation("equal", self, other),
         }
     }
     pub(crate) fn less_equal(self, other: RunVal) -> RunVal {
         match (&self.typ, &other.typ) {
             (RunType::Number(lhs), RunType::Number(rhs)) => RunType::Bool(lhs <= rhs).into(),
-
-            _ => {
-                todo!("Not implemented: {:?} {:?}", self, other);
-            }
+            _ => RunVal::relation("less_equal", self, other),
         }
     }
     pub(crate) fn greater(self, other: RunVal) -> RunVal {
         match (&self.typ, &other.typ) {
             (RunType::Number(lhs), RunType::Number(rhs)) => RunType::Bool(lhs > rhs).into(),
-
-            _ => {
-                todo!("Not implemented: {:?} {:?}", self, other);
-            }
+            _ => RunVal::relation("greater", self, other),
         }
     }
     pub(crate) fn greater_equal(self, other: RunVal) -> RunVal {
         match (&self.typ, &other.typ) {
             (RunType::Number(lhs), RunType::Number(rhs)) => RunType::Bool(lhs >= rhs).into(),
-
-            _ => {
-                todo!("Not implemented: {:?} {:?}", self, other);
-            }
+            _ => RunVal::relation("greater_equal", self, other),
         }
     }
+
+    /// A relation that can't be decided yet, e.g. `x > 0`, kept as a function.
+    /// It is decided when simplified, once the operands are known.
+    fn relation(name: &str, lhs: RunVal, rhs: RunVal) -> RunVal {
+        RunType::Function(name.to_string(), vec![lhs, rhs]).into()
+    }
 }
 
 impl RunVal {
     pub(crate) fn simplify(&mut self) {
         value_debug!("simplify: {:?}", self);
-        use RunType::*;
 
-        if self.simplified {
+        if self.is_simplified() {
             self.flatten();
             value_debug!("skipping simplification; already simplified!");
             return;
         }
 
+        // Steps that were taken before are taken again, to be recorded
+        let remembered = (!trace::is_recording())
+            .then(|| intern::simplified(self))
+            .flatten();
+        if let Some(simplified) = remembered {
+            value_debug!("already simplified before: {:?}", simplified);
+            *self = simplified;
+            return;
+        }
+
+        let original = self.clone();
+        self.simplify_node();
+
+        // Share the result with equal values that exist already
+        *self = RunVal(intern::intern(self.0.clone()));
+        self.set_simplified();
+
+        intern::remember_simplified(original, self.clone());
+    }
+
+    fn simplify_node(&mut self) {
+        use RunType::*;
+
         match &mut self.typ {
             Sum(terms) => {
                 value_debug!("simplify sum");
@@ -336,9 +469,31 @@ impl RunVal {
                     term.simplify();
                 }
 
+                RunVal::merge_nested_terms(terms);
+                units::unify_terms(&mut terms.0);
+
+                let to_value = |terms: &Terms| -> RunVal { Sum(terms.clone()).into() };
+
                 //RunVal::combine_integers(terms);
-                RunVal::combine_like_terms(terms);
-                RunVal::combine_integers(terms);
+                if rules::is_enabled(rules::COLLECT_TERMS) {
+                    trace::traced(rules::COLLECT_TERMS, terms, to_value, |terms| {
+                        RunVal::combine_like_terms(terms);
+                        RunVal::combine_integers(terms);
+                    });
+                }
+
+                if rules::is_enabled(rules::PYTHAGOREAN) {
+                    trace::traced(rules::PYTHAGOREAN, terms, to_value, |terms| {
+                        if functions::pythagorean_identity(terms) {
+                            RunVal::combine_like_terms(terms);
+                            RunVal::combine_integers(terms);
+                        }
+                    });
+                }
+
+                interval::collect(&mut terms.0, self::Interval::point(0.0), self::Interval::add);
+
+                terms.0.sort();
 
                 //RunVal::rearrange(terms);
             }
@@ -350,17 +505,48 @@ impl RunVal {
                     factor.simplify();
                 }
 
-                let coeff = RunVal::extract_coefficient(factors);
+                RunVal::merge_nested_factors(factors);
+                units::unify_factors(&mut factors.0);
+
+                if rules::is_enabled(rules::CANCEL_FACTORIALS) {
+                    let to_value = |factors: &Factors| -> RunVal { Product(factors.clone()).into() };
+                    trace::traced(rules::CANCEL_FACTORIALS, factors, to_value, |factors| {
+                        if integers::cancel_factorials(factors) {
+                            RunVal::merge_nested_factors(factors);
+                        }
+                    });
+                }
+
+                let before = trace::is_recording().then(|| RunVal::new(Product(factors.clone())));
+                let mut coeff = RunVal::extract_coefficient(factors);
 
-                RunVal::combine_like_factors(factors);
+                if rules::is_enabled(rules::COLLECT_FACTORS) {
+                    trace::untraced(|| RunVal::combine_like_factors(factors));
+                    RunVal::merge_nested_factors(factors);
+                }
 
                 value_debug!("after combined: {:?}", factors);
 
+                // Combining can leave numbers behind, e.g. x * x^-1 => 1
+                coeff *= RunVal::extract_coefficient(factors);
+
                 let Factors(factors) = factors;
 
-                if coeff != 1.0 {
+                if coeff == 0.0 {
+                    factors.clear();
+                }
+
+                if coeff != 1.0 || factors.is_empty() {
                     factors.push(Number(coeff).into());
                 }
+
+                interval::collect(factors, self::Interval::point(1.0), self::Interval::multiply);
+                factors.sort();
+
+                if let Some(before) = before {
+                    let after = Product(Factors(factors.clone())).into();
+                    trace::step(rules::COLLECT_FACTORS, &before, &after);
+                }
             }
             Exponent(base, exp) => {
                 value_debug!("simplify exponent");
@@ -369,22 +555,269 @@ impl RunVal {
 
                 // Merge exponents, e.g. (e^a)^b => e^(ab)
                 // TODO: Move to its own function?
-                if let Exponent(b_base, b_exp) = base.typ.clone() {
+                let merge = match &base.typ {
+                    Exponent(b_base, b_exp) => {
+                        rules::is_enabled(rules::MERGE_POWERS)
+                            && RunVal::can_merge_powers(b_base, b_exp, exp)
+                    }
+                    _ => false,
+                };
+                if let (true, Exponent(b_base, b_exp)) = (merge, base.typ.clone()) {
+                    let before = Exponent(base.clone(), exp.clone()).into();
                     let mut exponents: Box<RunVal> =
                         Box::new(RunType::Product(Factors(Vec::new())).into());
                     exponents = Box::new(exponents.multiply(*b_exp));
                     exponents = Box::new(exponents.multiply(*exp.clone()));
                     *base = b_base;
                     *exp = exponents;
+                    trace::untraced(|| exp.simplify());
+
+                    let after = Exponent(base.clone(), exp.clone()).into();
+                    trace::step(rules::MERGE_POWERS, &before, &after);
+                }
+            }
+            // What is held is kept as it is
+            Function(name, _) if name == "hold" => return,
+            Function(_, args) | Vector(args) => {
+                for arg in args.iter_mut() {
+                    arg.simplify();
+                }
+            }
+            Piecewise(Pieces(pieces)) => {
+                for (cond, value) in pieces.iter_mut() {
+                    cond.simplify();
+                    value.simplify();
+                }
+            }
+            Set(set) => {
+                *set = set.map(|it| {
+                    let mut it = it.clone();
+                    it.simplify();
+                    it
+                });
+                if let Some(mut reduced) = sets::reduce(set) {
+                    value_debug!("reduced set: {:?}", reduced);
+                    reduced.simplify();
+                    *self = reduced;
+                    return;
                 }
             }
             _ => {}
         }
+
+        if let (true, Piecewise(pieces)) = (rules::is_enabled(rules::DECIDE_PIECES), &self.typ) {
+            if let Some(mut reduced) = RunVal::reduce_pieces(pieces) {
+                trace::step(rules::DECIDE_PIECES, self, &reduced);
+                reduced.simplify();
+                *self = reduced;
+                return;
+            }
+        }
+
+        if let (true, Exponent(base, exp)) = (rules::is_enabled(rules::EVALUATE_POWERS), &self.typ)
+        {
+            if let Some(mut reduced) = RunVal::reduce_power(base, exp) {
+                trace::step(rules::EVALUATE_POWERS, self, &reduced);
+                reduced.simplify();
+                *self = reduced;
+            }
+        }
+
+        if let Function(name, args) = &self.typ {
+            let evaluated = rules::is_enabled(rules::EVALUATE_FUNCTIONS)
+                .then(|| trace::untraced(|| functions::evaluate(name, args)))
+                .flatten();
+            if let Some(value) = evaluated {
+                value_debug!("evaluated function: {:?}", value);
+                trace::step(rules::EVALUATE_FUNCTIONS, self, &value);
+                *self = value;
+            } else if let (true, Some(mut rewritten)) = (
+                rules::is_enabled(rules::FUNCTION_IDENTITIES),
+                trace::untraced(|| functions::rewrite(name, args)),
+            ) {
+                value_debug!("rewrote function: {:?}", rewritten);
+                trace::step(rules::FUNCTION_IDENTITIES, self, &rewritten);
+                rewritten.simplify();
+                *self = rewritten;
+                return;
+            }
+        }
+
+        if rules::is_enabled(rules::CANCEL) {
+            if let Some(mut cancelled) = trace::untraced(|| rational::cancel_product(self)) {
+                value_debug!("cancelled common factors: {:?}", cancelled);
+                trace::step(rules::CANCEL, self, &cancelled);
+                cancelled.simplify();
+                *self = cancelled;
+                return;
+            }
+        }
+
+        if let Some(rewritten) = rules::apply_patterns(self) {
+            *self = rewritten;
+            return;
+        }
+
+        // Undefined parts, e.g. from 0 * inf, make the whole expression undefined
+        let undefined = match &self.typ {
+            Number(n) => n.is_nan(),
+            Sum(Terms(values)) | Product(Factors(values)) | Function(_, values) => {
+                values.iter().any(RunVal::is_undefined)
+            }
+            Exponent(base, exp) => base.is_undefined() || exp.is_undefined(),
+            _ => false,
+        };
+        if undefined {
+            value_debug!("undefined: {:?}", self);
+            trace::step("undefined", self, &Undefined.into());
+            *self = Undefined.into();
+        }
+
         self.flatten();
-        self.simplified = true;
         value_debug!("current self after simplify: {:?}", self);
     }
 
+    /// Whether `(base^inner)^outer` => `base^(inner * outer)` holds for every real base.
+    ///
+    /// It does for integer outer exponents, also assumed ones, and bases that aren't negative, and when both powers are
+    /// even, e.g. `(x^4)^(1/2)` => `x^2`, but not `sqrt(x^2)` => `x` unless `x >= 0` is assumed.
+    fn can_merge_powers(base: &RunVal, inner: &RunVal, outer: &RunVal) -> bool {
+        use RunType::*;
+        let is_even = |n: f64| n % 2.0 == 0.0;
+        match (&inner.typ, &outer.typ) {
+            (_, Number(outer)) if outer.fract() == 0.0 => true,
+            (Number(inner), Number(outer)) if is_even(*inner) && is_even(inner * outer) => true,
+            _ => {
+                assumptions::is_integer(outer) == Some(true)
+                    || assumptions::is_nonnegative(base) == Some(true)
+            }
+        }
+    }
+
+    /// Drops the pieces that can't apply, and the whole piecewise value once its value is known,
+    /// e.g. `if 1 > 0 then x else y` => `x`.
+    ///
+    /// The returned value isn't simplified.
+    pub(crate) fn reduce_pieces(Pieces(pieces): &Pieces) -> Option<RunVal> {
+        use RunType::*;
+
+        let mut reduced: Vec<(RunVal, RunVal)> = Vec::new();
+        for (cond, value) in pieces {
+            match cond.typ {
+                Bool(false) => continue,
+                Bool(true) => {
+                    // Later pieces never apply
+                    reduced.push((cond.clone(), value.clone()));
+                    break;
+                }
+                _ => reduced.push((cond.clone(), value.clone())),
+            }
+        }
+
+        let otherwise = reduced.last().is_some_and(|(cond, _)| cond.typ == Bool(true));
+        match reduced.as_slice() {
+            [] => return Some(Undefined.into()),
+            [(cond, value), ..] if cond.typ == Bool(true) => return Some(value.clone()),
+            [(_, first), rest @ ..] if otherwise && rest.iter().all(|(_, it)| it == first) => {
+                return Some(first.clone())
+            }
+            _ => {}
+        }
+
+        (reduced != *pieces).then(|| Piecewise(Pieces(reduced)).into())
+    }
+
+    /// Powers that can be written without the exponent, e.g. `2^3` => `8` and `x^0` => `1`.
+    ///
+    /// The returned value isn't simplified.
+    pub(crate) fn reduce_power(base: &RunVal, exp: &RunVal) -> Option<RunVal> {
+        use RunType::*;
+        if let Some(power) = interval::power(base, exp).or_else(|| units::power(base, exp)) {
+            return Some(power);
+        }
+        match (&base.typ, &exp.typ) {
+            (Number(b), Number(e)) if *b == 0.0 && *e <= 0.0 => Some(RunVal::number_power(*b, *e)),
+            (Number(b), Number(e)) if !b.powf(*e).is_nan() => Some(Number(b.powf(*e)).into()),
+            (_, Number(e)) if *e == 1.0 => Some(base.clone()),
+            (_, Number(e)) if *e == 0.0 => Some(Number(1.0).into()),
+            (Number(b), _) if *b == 1.0 => Some(Number(1.0).into()),
+            // sqrt(-b) => sqrt(b) * I
+            (Number(b), Number(e)) if *b < 0.0 && *e == 0.5 => Some(
+                Product(Factors(vec![
+                    Number((-b).sqrt()).into(),
+                    Constant(self::Constant::I).into(),
+                ]))
+                .into(),
+            ),
+            // sqrt(x^2) => abs(x) and (x^6)^(1/2) => abs(x)^3, as x may be negative
+            (Exponent(inner_base, inner_exp), Number(e)) => match inner_exp.typ {
+                Number(m) if m % 2.0 == 0.0 && (m * e).fract() == 0.0 && (m * e) % 2.0 != 0.0 => {
+                    let abs = functions::call("abs", inner_base);
+                    Some(Exponent(Box::new(abs), Box::new(Number(m * e).into())).into())
+                }
+                _ => None,
+            },
+            // abs(x)^2 => x^2
+            (Function(name, args), Number(e)) if name == "abs" && e % 2.0 == 0.0 => {
+                Some(Exponent(Box::new(args[0].clone()), Box::new(exp.clone())).into())
+            }
+            // I^2 = -1
+            (Constant(self::Constant::I), Number(e)) if e.fract() == 0.0 => {
+                let i: RunVal = Constant(self::Constant::I).into();
+                Some(match (*e as i64).rem_euclid(4) {
+                    0 => Number(1.0).into(),
+                    1 => i,
+                    2 => Number(-1.0).into(),
+                    _ => Product(Factors(vec![Number(-1.0).into(), i])).into(),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// `b^e` for numbers, where `0^0` and division by zero, e.g. `0^-1`, are undefined. So are
+    /// `1^inf` and `inf^0`, and powers like `(-2)^inf` that oscillate without a limit.
+    fn number_power(b: f64, e: f64) -> RunVal {
+        let indeterminate = (b.is_infinite() && e == 0.0)
+            || (e.is_infinite() && (b.abs() == 1.0 || (b < 0.0 && (b.abs() > 1.0) == (e > 0.0))));
+        match (b == 0.0 && e <= 0.0) || indeterminate {
+            true => RunType::Undefined.into(),
+            false => RunType::Number(b.powf(e)).into(),
+        }
+    }
+
+    fn is_undefined(&self) -> bool {
+        match self.typ {
+            RunType::Undefined => true,
+            RunType::Number(n) => n.is_nan(),
+            _ => false,
+        }
+    }
+
+    /// Sums inside of sums are merged with the outer sum, e.g. x + (y + z) => x + y + z
+    pub(crate) fn merge_nested_terms(Terms(terms): &mut Terms) {
+        let mut merged = Vec::with_capacity(terms.len());
+        for term in terms.drain(..) {
+            match &term.typ {
+                RunType::Sum(Terms(inner)) => merged.extend(inner.iter().cloned()),
+                _ => merged.push(term),
+            }
+        }
+        *terms = merged;
+    }
+
+    /// Products inside of products are merged with the outer product, e.g. x * (y * z) => x * y * z
+    pub(crate) fn merge_nested_factors(Factors(factors): &mut Factors) {
+        let mut merged = Vec::with_capacity(factors.len());
+        for factor in factors.drain(..) {
+            match &factor.typ {
+                RunType::Product(Factors(inner)) => merged.extend(inner.iter().cloned()),
+                _ => merged.push(factor),
+            }
+        }
+        *factors = merged;
+    }
+
     pub(crate) fn combine_like_terms(terms: &mut Terms) {
         value_debug!("combining like terms");
         use RunType::*;
@@ -425,13 +858,11 @@ impl RunVal {
 
                 term.simplify();
 
-                if coefficient_total.is_sign_positive() {
-                    new_terms = new_terms.add(term);
-                }
+                new_terms = new_terms.add(term);
             }
             value_debug!("current new_terms: {:?}", new_terms);
         }
-        let Sum(new_terms) = new_terms.typ else {
+        let Sum(new_terms) = new_terms.into_typ() else {
             value_error!("There is no way that we should end up here...");
             panic!("STOPPING");
         };
@@ -457,11 +888,17 @@ impl RunVal {
 
             let mut found = false;
 
-            let base = if let Exponent(base, exp) = factor.typ {
+            // Intervals are multiplied as independent values, x * x is wider than x^2
+            if let Interval(_) = factor.typ {
+                new_factors.push(factor);
+                continue;
+            }
+
+            let base = if let Exponent(base, exp) = &factor.typ {
                 value_debug!("starting with exp: {:?}", exp);
-                exponents_vec.push(*exp);
+                exponents_vec.push((**exp).clone());
                 found = true;
-                *base
+                (**base).clone()
             } else {
                 exponents_vec.push(Number(1.0).into());
                 factor
@@ -498,8 +935,10 @@ impl RunVal {
 
                 if let Number(n) = exponents.typ {
                     if n != 0.0 {
-                        let exponent = Exponent(Box::new(base), Box::new(exponents));
-                        new_factors.push(exponent.into());
+                        let mut exponent: RunVal =
+                            Exponent(Box::new(base), Box::new(exponents)).into();
+                        exponent.simplify();
+                        new_factors.push(exponent);
                     } else {
                         new_factors.push(Number(1.0).into());
                     }
@@ -528,9 +967,7 @@ impl RunVal {
             }
         }
 
-        if total == 0.0 && terms.is_empty() {
-            // TODO: If there are no terms left, then add the zero as the only term?
-            // Alternatively just check at printing if the most outer expression is an empty sum?
+        if total == 0.0 && !terms.is_empty() {
             return;
         }
 
@@ -539,103 +976,102 @@ impl RunVal {
         terms.push(constant.into());
     }
 
+    /// Reorders sums for display: terms of higher degree first, without a leading negative term
+    /// when possible, e.g. `1 + x^2 - x` => `x^2 - x + 1`.
+    ///
+    /// The result isn't in canonical order anymore, so this is only meant for output.
     pub fn rearrange(&mut self) {
         use RunType::*;
         match &mut self.typ {
-            Sum(Terms(terms)) => {
-                terms.sort_by(|a, b| {
-                    match (&a.typ, &b.typ) {
-                        (_, o @ Number(_)) | (_, o @ Product(_)) => {
-                            if RunVal::value_is_negative(o) {
-                                Ordering::Less
-                            } else {
-                                Ordering::Greater
-                            }
-                        }
-                        (s @ Number(_), _) | (s @ Product(_), _)
-                            if RunVal::value_is_negative(s) =>
-                        {
-                            Ordering::Greater
-                        }
-                        (_, _) => Ordering::Less,
-                    }
-                });
+            Sum(Terms(values)) => {
+                values.iter_mut().for_each(RunVal::rearrange);
+                values.sort_by(|a, b| b.display_degree().total_cmp(&a.display_degree()));
+                if let Some(positive) = values
+                    .iter()
+                    .position(|it| !RunVal::value_is_negative(&it.typ))
+                {
+                    let term = values.remove(positive);
+                    values.insert(0, term);
+                }
+            }
+            Product(Factors(values)) | Vector(values) | Function(_, values) => {
+                values.iter_mut().for_each(RunVal::rearrange)
+            }
+            Exponent(base, exp) => {
+                base.rearrange();
+                exp.rearrange();
+            }
+            Piecewise(Pieces(pieces)) => pieces.iter_mut().for_each(|(cond, value)| {
+                cond.rearrange();
+                value.rearrange();
+            }),
+            Set(set) => {
+                *set = set.map(|it| {
+                    let mut it = it.clone();
+                    it.rearrange();
+                    it
+                })
             }
             Unit
             | Undefined
             | Number(_)
+            | Interval(_)
+            | PhysicalUnit(_)
             | Symbol(_)
+            | Constant(_)
+            | Bool(_) => {}
+        }
+    }
+
+    /// The degree of a term in all of its symbols, orders terms like `x^2 + x + 1` for display.
+    fn display_degree(&self) -> f64 {
+        use RunType::*;
+        match &self.typ {
+            Symbol(_) => 1.0,
+            Exponent(base, exp) => match exp.typ {
+                Number(n) => n * base.display_degree(),
+                _ => base.display_degree(),
+            },
+            Product(Factors(values)) => values.iter().map(RunVal::display_degree).sum(),
+            Sum(Terms(values)) => values
+                .iter()
+                .map(RunVal::display_degree)
+                .fold(0.0, f64::max),
+            // The order term of a series goes last
+            Function(name, _) if name == "O" => f64::NEG_INFINITY,
+            Piecewise(Pieces(pieces)) => pieces
+                .iter()
+                .map(|(_, value)| value.display_degree())
+                .fold(0.0, f64::max),
+            Unit
+            | Undefined
+            | Number(_)
+            | Interval(_)
+            | PhysicalUnit(_)
+            | Constant(_)
             | Bool(_)
             | Vector(_)
-            | Product(_)
-            | Exponent(_, _)
-            | Function(_, _) => {}
+            | Function(_, _)
+            | Set(_) => 0.0,
         }
     }
 
     pub(crate) fn flatten(&mut self) {
         use RunType::*;
-        match &mut self.typ {
+        match &self.typ {
             Sum(Terms(v)) | Product(Factors(v)) if v.len() == 1 => {
-                let value = &mut v[0];
+                let mut value = v[0].clone();
                 value.flatten();
-                *self = value.clone();
+                *self = value;
             }
             _ => {}
         }
     }
 
+    /// Structural equality. The operands of simplified sums and products are kept
+    /// in canonical order, so this compares them pairwise.
     pub(crate) fn struct_equal(&self, other: &RunVal) -> bool {
-        match (&self.typ, &other.typ) {
-            (RunType::Sum(Terms(terms)), RunType::Sum(Terms(other))) => {
-                if terms.len() != other.len() {
-                    return false;
-                }
-
-                let mut terms_remaining = other.iter().collect::<Vec<_>>();
-
-                'outer: for term in terms {
-                    for i in 0..terms_remaining.len() {
-                        let other_term = &terms_remaining[i];
-                        if term.struct_equal(other_term) {
-                            terms_remaining.remove(i);
-                            continue 'outer;
-                        }
-                    }
-
-                    return false;
-                }
-
-                true
-            }
-            (RunType::Product(Factors(factors)), RunType::Product(Factors(other))) => {
-                if factors.len() != other.len() {
-                    return false;
-                }
-
-                let mut factors_remaining = other.iter().collect::<Vec<_>>();
-
-                'outer: for factor in factors {
-                    for i in 0..factors_remaining.len() {
-                        let other_term = &factors_remaining[i];
-                        if factor.struct_equal(other_term) {
-                            factors_remaining.remove(i);
-                            continue 'outer;
-                        }
-                    }
-
-                    return false;
-                }
-
-                true
-            }
-            (RunType::Exponent(base, exp), RunType::Exponent(other_base, other_exp)) => {
-                base.struct_equal(other_base) && exp.struct_equal(other_exp)
-            }
-            (RunType::Number(num), RunType::Number(other)) => num == other,
-            (RunType::Symbol(symbol), RunType::Symbol(other)) => symbol == other,
-            _ => false,
-        }
+        self == other
     }
 
     pub(crate) fn extract_coefficients(Terms(terms): &mut Terms) -> Vec<(f64, RunVal)> {
@@ -699,7 +1135,18 @@ impl RunVal {
             }
             Exponent(base, _) => RunVal::value_is_negative(&base.typ),
 
-            Unit | Undefined | Vector(_) | Sum(_) | Function(_, _) | Symbol(_) | Bool(_) => false,
+            Unit
+            | Undefined
+            | Vector(_)
+            | Sum(_)
+            | Function(_, _)
+            | Piecewise(_)
+            | Set(_)
+            | Interval(_)
+            | PhysicalUnit(_)
+            | Symbol(_)
+            | Constant(_)
+            | Bool(_) => false,
         };
 
         value_debug!("is negative: {}", is_negative);
@@ -708,17 +1155,253 @@ impl RunVal {
     }
 }
 
+impl RunVal {
+    /// Whether `var` occurs anywhere in the value.
+    pub(crate) fn depends_on(&self, var: &RunVal) -> bool {
+        use RunType::*;
+        if self.struct_equal(var) {
+            return true;
+        }
+        match &self.typ {
+            Sum(Terms(values))
+            | Product(Factors(values))
+            | Vector(values)
+            | Function(_, values) => values.iter().any(|it| it.depends_on(var)),
+            Exponent(base, exp) => base.depends_on(var) || exp.depends_on(var),
+            Piecewise(pieces) => pieces.parts().any(|it| it.depends_on(var)),
+            Set(set) => !set.binds(var) && set.parts().iter().any(|it| it.depends_on(var)),
+            Unit
+            | Undefined
+            | Number(_)
+            | Interval(_)
+            | PhysicalUnit(_)
+            | Symbol(_)
+            | Constant(_)
+            | Bool(_) => {
+                false
+            }
+        }
+    }
+
+    /// Replaces every occurrence of `target` with `replacement`, without simplifying.
+    pub(crate) fn substitute(&self, target: &RunVal, replacement: &RunVal) -> RunVal {
+        use RunType::*;
+        if self.struct_equal(target) {
+            return replacement.clone();
+        }
+
+        let substitute_all = |values: &Vec<RunVal>| {
+            values
+                .iter()
+                .map(|it| it.substitute(target, replacement))
+                .collect::<Vec<_>>()
+        };
+
+        let typ = match &self.typ {
+            Sum(Terms(terms)) => Sum(Terms(substitute_all(terms))),
+            Product(Factors(factors)) => Product(Factors(substitute_all(factors))),
+            Vector(values) => Vector(substitute_all(values)),
+            Function(name, args) => Function(name.clone(), substitute_all(args)),
+            Exponent(base, exp) => Exponent(
+                Box::new(base.substitute(target, replacement)),
+                Box::new(exp.substitute(target, replacement)),
+            ),
+            Piecewise(pieces) => Piecewise(pieces.map(|it| it.substitute(target, replacement))),
+            Set(set) if !set.binds(target) => {
+                Set(set.map(|it| it.substitute(target, replacement)))
+            }
+            Unit
+            | Undefined
+            | Number(_)
+            | Interval(_)
+            | PhysicalUnit(_)
+            | Symbol(_)
+            | Constant(_)
+            | Bool(_)
+            | Set(_) => return self.clone(),
+        };
+
+        RunVal::new(typ)
+    }
+
+    /// Replaces every target with its replacement at the same time, without simplifying,
+    /// so that e.g. `x` => `y` and `y` => `x` swap `x` and `y`.
+    ///
+    /// Variables bound by a function or a set, like `x` in `diff(f, x)` or `{x | x > 0}`, aren't
+    /// replaced, and are renamed where a replacement depends on them.
+    pub fn substitute_simultaneously(&self, bindings: &[(RunVal, RunVal)]) -> RunVal {
+        use RunType::*;
+        if let Some((_, replacement)) = bindings.iter().find(|(target, _)| target == self) {
+            return replacement.clone();
+        }
+
+        let substitute_all = |values: &Vec<RunVal>, bindings: &[(RunVal, RunVal)]| {
+            values
+                .iter()
+                .map(|it| it.substitute_simultaneously(bindings))
+                .collect::<Vec<_>>()
+        };
+
+        let typ = match &self.typ {
+            Sum(Terms(terms)) => Sum(Terms(substitute_all(terms, bindings))),
+            Product(Factors(factors)) => Product(Factors(substitute_all(factors, bindings))),
+            Vector(values) => Vector(substitute_all(values, bindings)),
+            Function(name, args)
+                if BINDING_FUNCTIONS.contains(&name.as_str()) && args.len() > 1 =>
+            {
+                let (body, variable) = RunVal::rename_bound(&args[0], &args[1], bindings);
+                let free: Vec<_> = bindings
+                    .iter()
+                    .filter(|(target, _)| *target != variable)
+                    .cloned()
+                    .collect();
+
+                // The other arguments, like the bounds of a sum, are outside of the binding
+                let mut args = args.clone();
+                args[0] = body.substitute_simultaneously(&free);
+                args[1] = variable;
+                for arg in &mut args[2..] {
+                    *arg = arg.substitute_simultaneously(bindings);
+                }
+                Function(name.clone(), args)
+            }
+            Function(name, args) => Function(name.clone(), substitute_all(args, bindings)),
+            Exponent(base, exp) => Exponent(
+                Box::new(base.substitute_simultaneously(bindings)),
+                Box::new(exp.substitute_simultaneously(bindings)),
+            ),
+            Piecewise(pieces) => Piecewise(pieces.map(|it| it.substitute_simultaneously(bindings))),
+            // The domain is outside of the binding, like the bounds of a sum
+            Set(self::Set::Builder {
+                variable,
+                domain,
+                condition,
+            }) => {
+                let symbol = Symbol(variable.clone()).into();
+                let (condition, symbol) = RunVal::rename_bound(condition, &symbol, bindings);
+                let free: Vec<_> = bindings
+                    .iter()
+                    .filter(|(target, _)| *target != symbol)
+                    .cloned()
+                    .collect();
+                let Symbol(variable) = &symbol.typ else {
+                    unreachable!("A symbol is renamed to a symbol")
+                };
+                Set(self::Set::Builder {
+                    variable: variable.clone(),
+                    domain: Box::new(domain.substitute_simultaneously(bindings)),
+                    condition: Box::new(condition.substitute_simultaneously(&free)),
+                })
+            }
+            Set(set) => Set(set.map(|it| it.substitute_simultaneously(bindings))),
+            Unit
+            | Undefined
+            | Number(_)
+            | Interval(_)
+            | PhysicalUnit(_)
+            | Symbol(_)
+            | Constant(_)
+            | Bool(_) => {
+                return self.clone()
+            }
+        };
+
+        RunVal::new(typ)
+    }
+}
+
+impl RunVal {
+    /// Renames the variable bound in `body` if a replacement depends on it, which would
+    /// otherwise be captured, e.g. `n` => `k` in `sum(k * n, k, 1, 3)`.
+    fn rename_bound(
+        body: &RunVal,
+        variable: &RunVal,
+        bindings: &[(RunVal, RunVal)],
+    ) -> (RunVal, RunVal) {
+        let RunType::Symbol(name) = &variable.typ else {
+            return (body.clone(), variable.clone());
+        };
+        if !bindings.iter().any(|(_, replacement)| replacement.depends_on(variable)) {
+            return (body.clone(), variable.clone());
+        }
+
+        let fresh = (1..)
+            .map(|i| RunVal::from(RunType::Symbol(format!("{}{}", name, i))))
+            .find(|symbol| {
+                !body.depends_on(symbol)
+                    && bindings.iter().all(|(target, replacement)| {
+                        !target.depends_on(symbol) && !replacement.depends_on(symbol)
+                    })
+            })
+            .expect("There are infinitely many symbols");
+        (body.substitute(variable, &fresh), fresh)
+    }
+}
+
+/// Functions with a variable of their own as the second argument, e.g. `diff(f, x)`.
+const BINDING_FUNCTIONS: [&str; 6] = ["diff", "integrate", "limit", "series", "sum", "product"];
+
+impl RunVal {
+    /// Replaces the real constants with their numeric values, without simplifying.
+    pub(crate) fn approximate(&self) -> RunVal {
+        use RunType::*;
+        let approximate_all =
+            |values: &Vec<RunVal>| values.iter().map(RunVal::approximate).collect::<Vec<_>>();
+
+        let typ = match &self.typ {
+            Constant(c) => match c.approximate() {
+                Some(n) => Number(n),
+                None => return self.clone(),
+            },
+            Sum(Terms(terms)) => Sum(Terms(approximate_all(terms))),
+            Product(Factors(factors)) => Product(Factors(approximate_all(factors))),
+            Vector(values) => Vector(approximate_all(values)),
+            Function(name, args) => {
+                let args = approximate_all(args);
+                match (name.as_str(), args.as_slice()) {
+                    // Otherwise `exp(1)` would be `E` again
+                    ("exp", [arg]) => match arg.typ {
+                        Number(n) => Number(n.exp()),
+                        _ => Function(name.clone(), args),
+                    },
+                    _ => Function(name.clone(), args),
+                }
+            }
+            Exponent(base, exp) => {
+                Exponent(Box::new(base.approximate()), Box::new(exp.approximate()))
+            }
+            Piecewise(pieces) => Piecewise(pieces.map(RunVal::approximate)),
+            Set(set) => Set(set.map(RunVal::approximate)),
+            Unit
+            | Undefined
+            | Number(_)
+            | Interval(_)
+            | PhysicalUnit(_)
+            | Symbol(_)
+            | Bool(_) => {
+                return self.clone()
+            }
+        };
+
+        RunVal::new(typ)
+    }
+}
+
 impl fmt::Debug for RunVal {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self.typ {
             RunType::Unit
             | RunType::Undefined
             | RunType::Number(_)
+            | RunType::Interval(_)
+            | RunType::PhysicalUnit(_)
             | RunType::Symbol(_)
+            | RunType::Constant(_)
             | RunType::Bool(_)
-            | RunType::Function(_, _) => write!(f, "{:?}", self.typ),
+            | RunType::Function(_, _)
+            | RunType::Set(_) => write!(f, "{:?}", self.typ),
             _ => {
-                if self.simplified {
+                if self.is_simplified() {
                     write!(f, "({:?})", self.typ)
                 } else {
                     write!(f, "{{{:?}}}", self.typ)
@@ -728,6 +1411,51 @@ impl fmt::Debug for RunVal {
     }
 }
 
+impl Node {
+    pub(crate) fn hash_value(&self) -> u64 {
+        if let Some(hash) = self.hash.get() {
+            return hash;
+        }
+        let mut hasher = DefaultHasher::new();
+        self.typ.hash(&mut hasher);
+        let hash = hasher.finish();
+        self.hash.set(Some(hash));
+        hash
+    }
+}
+
+impl Deref for RunVal {
+    type Target = Node;
+
+    fn deref(&self) -> &Node {
+        &self.0
+    }
+}
+
+impl DerefMut for RunVal {
+    fn deref_mut(&mut self) -> &mut Node {
+        let node = Rc::make_mut(&mut self.0);
+        // The node is about to change
+        node.hash.set(None);
+        node.simplified.set(0);
+        node
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Serialize for RunVal {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.typ.serialize(serializer)
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl<'de> Deserialize<'de> for RunVal {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        RunType::deserialize(deserializer).map(RunVal::new)
+    }
+}
+
 impl From<RunType> for RunVal {
     fn from(value: RunType) -> Self {
         Self::new(value)
@@ -740,7 +1468,10 @@ impl fmt::Debug for RunType {
             RunType::Unit => write!(f, "unit"),
             RunType::Undefined => write!(f, "undefined"),
             RunType::Number(n) => write!(f, "{}", n),
+            RunType::Interval(i) => write!(f, "[{}, {}]", i.lower, i.upper),
+            RunType::PhysicalUnit(unit) => write!(f, "{}", unit.name),
             RunType::Symbol(s) => write!(f, "'{}'", s),
+            RunType::Constant(c) => write!(f, "{}", c.name()),
             RunType::Bool(b) => write!(f, "{}", b),
             RunType::Vector(vec) => write!(f, "{:?}", vec),
             RunType::Sum(Terms(terms)) => {
@@ -778,6 +1509,16 @@ impl fmt::Debug for RunType {
             RunType::Function(name, args) => {
                 write!(f, "{}({:?})", name, args)
             }
+            RunType::Piecewise(Pieces(pieces)) => {
+                write!(f, "(if, ")?;
+                let pieces = pieces
+                    .iter()
+                    .map(|(cond, value)| format!("{:?}: {:?}", cond, value))
+                    .collect::<Vec<_>>();
+                f.write_str(&pieces.join(", "))?;
+                write!(f, ")")
+            }
+            RunType::Set(set) => write!(f, "{:?}", set),
         }
     }
 }
@@ -793,3 +1534,78 @@ impl From<f64> for RunType {
         RunType::Number(value)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::cas::eval::testing::eval;
+
+    #[test]
+    fn constants_stay_exact() {
+        assert_eq!(eval("sin(PI)"), "0");
+        assert_eq!(eval("PI*2"), "2 * PI");
+        assert_eq!(eval("E"), "E");
+        assert_eq!(eval("I^2"), "-1");
+        assert_eq!(eval("sqrt(-4)"), "2 * I");
+        assert_eq!(eval("PI > 3"), "true");
+    }
+
+    #[test]
+    fn constants_are_approximated() {
+        assert_eq!(eval("N(PI)"), "3.141592653589793");
+        assert_eq!(eval("exp(1)"), "E");
+        assert_eq!(eval("exp(2)"), "E^2");
+        assert_eq!(eval("N(exp(2))"), "7.3890560989306495");
+        assert_eq!(eval("limit((1 + 1/n)^n, n, inf)"), "E");
+    }
+
+    #[test]
+    fn infinity() {
+        assert_eq!(eval("inf + 1"), "inf");
+        assert_eq!(eval("2/inf"), "0");
+        assert_eq!(eval("-inf"), "-inf");
+        assert_eq!(eval("0*inf"), "undefined");
+        assert_eq!(eval("inf - inf"), "undefined");
+        assert_eq!(eval("1^inf"), "undefined");
+        assert_eq!(eval("inf^0"), "undefined");
+        assert_eq!(eval("(-2)^inf"), "undefined");
+        assert_eq!(eval("2^inf"), "inf");
+        assert_eq!(eval("2^(-inf)"), "0");
+    }
+
+    #[test]
+    fn division_by_zero_is_undefined() {
+        assert_eq!(eval("1/0"), "undefined");
+        assert_eq!(eval("0^-1"), "undefined");
+        assert_eq!(eval("x/0"), "undefined");
+        assert_eq!(eval("0^0"), "undefined");
+        assert_eq!(eval("0^2"), "0");
+        assert_eq!(eval("x^0"), "1");
+    }
+
+    #[test]
+    fn substitution() {
+        assert_eq!(eval("x^2 + y | x = 2"), "y + 4");
+        assert_eq!(eval("subs((x + 1)^2, x, 3)"), "16");
+        assert_eq!(eval("subs(x - y, [x, y], [y, x])"), eval("y - x"));
+        assert_eq!(eval("subs(x*y, [x == 2, y == 3])"), "6");
+        assert_eq!(eval("diff(x^2 + y, x) | x = 2"), "4");
+    }
+
+    #[test]
+    fn substitution_doesnt_capture_bound_variables() {
+        assert_eq!(eval("subs(sum(f(k)*n, k, 1, m), n, k)"), "Σ_{k1=1}^{m} f(k1) * k");
+        assert_eq!(eval("subs(sum(f(k)*n, k, 1, m), k, 2)"), "Σ_{k=1}^{m} f(k) * n");
+        assert_eq!(eval("subs(sum(f(k), k, 1, n), n, k)"), "Σ_{k1=1}^{k} f(k1)");
+        assert_eq!(
+            eval("subs(sum(f(k)*n*k1, k, 1, m), n, k)"),
+            "Σ_{k2=1}^{m} f(k2) * k * k1"
+        );
+    }
+
+    #[test]
+    fn undefined_propagates() {
+        assert_eq!(eval("undefined + 1"), "undefined");
+        assert_eq!(eval("NaN"), "undefined");
+        assert_eq!(eval("sin(1/0)"), "undefined");
+    }
+}
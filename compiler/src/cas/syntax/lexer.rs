@@ -32,7 +32,7 @@ impl Lexer {
         }
 
         if ch.is_alphabetic() {
-            let identifier = self.collect_while(|c| c.is_alphabetic());
+            let identifier = self.collect_while(|c| c.is_alphanumeric() || c == '_');
             // TODO: Extract out from the next_token function, also change to something that allows for I18N?
             let keyword = identifier.parse::<KeywordType>().ok();
 
@@ -108,10 +108,21 @@ impl Lexer {
         };
         let token_type = match ch {
             '+' => TokenType::Plus,
-            '-' => TokenType::Minus,
+            '-' => {
+                let mut token_type = TokenType::Minus;
+                if let Some(next) = self.peek_char() {
+                    if next == '>' {
+                        self.next_char();
+                        token_type = TokenType::Arrow
+                    }
+                }
+                token_type
+            }
             '*' => TokenType::Star,
             '/' => TokenType::Slash,
+            '%' => TokenType::Percent,
             '^' => TokenType::Caret,
+            '!' => TokenType::Bang,
 
             '=' => {
                 let mut token_type = TokenType::Equal;
@@ -148,6 +159,7 @@ impl Lexer {
             ';' => TokenType::Semicolon,
 
             '~' => TokenType::Tilde,
+            '|' => TokenType::Pipe,
 
             '.' => TokenType::Dot,
             ',' => TokenType::Comma,
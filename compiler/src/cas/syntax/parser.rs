@@ -5,10 +5,14 @@ use matex_common::{
     error::ParseError,
     function::{Function, Parameter},
     node::{BinOp, Expr, Precedence, Program, Statement},
+    rule::Rule,
     token::{KeywordType, Token, TokenType},
-    util::SymbolTable,
+    types::TypeAlias,
+    util::{Position, SymbolTable},
 };
 
+use crate::cas::eval::units::PhysicalUnit;
+
 macro_rules! parser_debug {
     ($($arg:tt)+) => (debug!(target: "matex::parser", "[{}:{}] {}", file!(), line!(), &format!($($arg)+)));
 }
@@ -84,6 +88,8 @@ impl Parser {
         parser_debug!("Parsing statement");
         match self.get_token()?.typ {
             TokenType::Tilde => self.parse_unset_statement(),
+            TokenType::Keyword(KeywordType::Rule) => self.parse_rule_definition(),
+            TokenType::Keyword(KeywordType::Type) => self.parse_type_definition(),
             _ => {
                 let expression = Statement::Expression(self.parse_expression()?);
                 self.consume_newline_or_eof("Expected newline after expression statement.")?;
@@ -99,14 +105,11 @@ impl Parser {
 
         let func_name = id;
 
-        // FIXME: Might not be function definition, could just be function call. Check/peek for TokenType::Equal after
-        // matching parenthesis: abs(....(...)) =
-        //                          ^---------^ Match this one
-        if next_token.typ != TokenType::LeftParenthesis {
+        if next_token.typ != TokenType::LeftParenthesis || !self.is_function_definition() {
             return self.parse_statement();
         }
 
-        self.expect_identifier("Expected function name")?;
+        let (name_token, _) = self.expect_identifier("Expected function name")?;
         // Function!
         // parse parameters
         self.expect(
@@ -128,6 +131,19 @@ impl Parser {
             "Expected closing parenthesis after parameter list!",
         )?;
 
+        // The range and domain are optional, e.g. `f(x: real) -> real where x > 0 = ln(x)`
+        let mut return_type = "".to_string();
+        if self.token_matches(TokenType::Arrow) {
+            self.consume()?;
+            return_type = self.parse_type_name("Expected return type after ->")?;
+        }
+
+        let mut domain = None;
+        if self.token_matches(TokenType::Keyword(KeywordType::Where)) {
+            self.consume()?;
+            domain = Some(self.expect_expression(Precedence::Comparison)?);
+        }
+
         self.expect(
             TokenType::Equal,
             "Expected assignment operator after function definition...",
@@ -150,24 +166,61 @@ impl Parser {
             Function {
                 name: func_name.clone(),
                 params: params.clone(),
+                return_type: return_type.clone(),
+                domain: domain.clone(),
                 body: function_body.clone(),
+                pos: name_token.pos,
             },
         );
 
         Ok(Statement::FunctionDefinition(Function {
             name: func_name,
             params: params.clone(),
+            return_type,
+            domain,
             body: function_body,
+            pos: name_token.pos,
         }))
     }
 
+    /// Checks for an assignment operator, return type or domain after the parenthesis matching the
+    /// one following the current identifier, to tell `abs(x) = ...` apart from a call like `abs(x)`.
+    fn is_function_definition(&mut self) -> bool {
+        let mut depth = 0;
+        let mut offset = 1;
+
+        while let Some(token) = self.peek(offset) {
+            match token.typ {
+                TokenType::LeftParenthesis => depth += 1,
+                TokenType::RightParenthesis => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return self.peek(offset + 1).is_some_and(|token| {
+                            matches!(
+                                token.typ,
+                                TokenType::Equal
+                                    | TokenType::Arrow
+                                    | TokenType::Keyword(KeywordType::Where)
+                            )
+                        });
+                    }
+                }
+                TokenType::NewLine => return false,
+                _ => {}
+            }
+            offset += 1;
+        }
+        false
+    }
+
     fn parse_parameter_definition(&mut self) -> ParseResult<Parameter> {
         parser_debug!("Parsing parameter definition");
-        let (_, param_name) = self.expect_identifier("Expected parameter name")?;
+        let (token, param_name) = self.expect_identifier("Expected parameter name")?;
 
         let mut param = Parameter {
             name: param_name,
             type_name: "".to_string(),
+            pos: token.pos,
         };
 
         let Some(next) = self.peek(0) else {
@@ -179,12 +232,46 @@ impl Parser {
         }
 
         self.expect(TokenType::Colon, "Expected colon after parameter name.")?;
-        let (_, type_name) = self.expect_identifier("Expected type name after semicolon.")?;
+        param.type_name = self.parse_type_name("Expected type name after semicolon.")?;
 
-        param.type_name = type_name;
         Ok(param)
     }
 
+    /// One or several type names separated by `|`, e.g. `integer | vector`.
+    fn parse_type_name(&mut self, message: &str) -> ParseResult<String> {
+        let (_, type_name) = self.expect_identifier(message)?;
+        let mut names = vec![type_name];
+
+        while self.token_matches(TokenType::Pipe) {
+            self.consume()?;
+            let (_, type_name) = self.expect_identifier("Expected type name after |")?;
+            names.push(type_name);
+        }
+
+        Ok(names.join(" | "))
+    }
+
+    fn parse_type_definition(&mut self) -> ParseResult<Statement> {
+        parser_debug!("Parsing type definition");
+        self.expect_keyword(KeywordType::Type, "Expected type keyword")?;
+
+        let (token, name) = self.expect_identifier("Expected name of the type")?;
+        self.expect(TokenType::Equal, "Expected = after the name of the type")?;
+        let type_name = self.parse_type_name("Expected type name after =")?;
+
+        self.consume_newline_or_eof("Expected newline after type definition")?;
+
+        let alias = Statement::TypeDefinition(TypeAlias {
+            name,
+            type_name,
+            pos: token.pos,
+        });
+
+        parser_debug!("Returning {:?}", alias);
+
+        Ok(alias)
+    }
+
     fn parse_unset_statement(&mut self) -> ParseResult<Statement> {
         parser_debug!("Parsing unset statement");
         self.expect(TokenType::Tilde, "Expected unset operator")?;
@@ -197,6 +284,47 @@ impl Parser {
         Ok(statement)
     }
 
+    fn parse_rule_definition(&mut self) -> ParseResult<Statement> {
+        parser_debug!("Parsing rule definition");
+        self.expect_keyword(KeywordType::Rule, "Expected rule keyword")?;
+
+        // The name is optional, e.g. `rule expand: ...`
+        let mut name = None;
+        if let TokenType::Identifier(id) = self.get_token()?.typ {
+            if self
+                .peek(1)
+                .is_some_and(|token| token.typ == TokenType::Colon)
+            {
+                self.consume()?;
+                self.consume()?;
+                name = Some(id);
+            }
+        }
+
+        let pattern = self.expect_expression(Precedence::None)?;
+        self.expect(TokenType::Arrow, "Expected -> after the pattern of the rule")?;
+        let replacement = self.expect_expression(Precedence::None)?;
+
+        let mut condition = None;
+        if let Ok(TokenType::Keyword(KeywordType::Where)) = self.get_token().map(|it| it.typ) {
+            self.consume()?;
+            condition = Some(self.expect_expression(Precedence::None)?);
+        }
+
+        self.consume_newline_or_eof("Expected newline after rule definition")?;
+
+        let rule = Statement::RuleDefinition(Rule {
+            name,
+            pattern,
+            replacement,
+            condition,
+        });
+
+        parser_debug!("Returning {:?}", rule);
+
+        Ok(rule)
+    }
+
     fn parse_expression(&mut self) -> ParseResult<Expr> {
         parser_debug!("Parsing expression");
         self.parse_precedence(Precedence::None)
@@ -216,24 +344,35 @@ impl Parser {
             Minus => self.parse_unary_minus()?,
             LeftParenthesis => self.parse_grouping()?,
             LeftSquareBracket => self.parse_vector()?,
-            RightBrace => todo!("Implement blocks"),
-            LeftBrace => todo!("Implement blocks"),
-            Plus | Star | Slash | Caret | Equal | EqualEqual | Less | Greater | LessEqual
-            | GreaterEqual | Colon | Semicolon | Tilde | Dot | Comma | RightParenthesis
-            | NewLine | RightSquareBracket => panic!("Failed prefix on: {}", token),
+            LeftBrace => self.parse_set()?,
+            RightBrace | Plus | Star | Slash | Percent | Caret | Bang | Equal | EqualEqual | Less | Greater
+            | LessEqual | GreaterEqual | Arrow | Pipe | Colon | Semicolon | Tilde | Dot | Comma
+            | RightParenthesis | NewLine | RightSquareBracket => {
+                return Err(ParseError::UnexpectedToken {
+                    message: format!("Expected an expression, found {:?}", token.typ),
+                    actual: token,
+                })
+            }
         };
 
+        // Postfix operators bind tighter than any infix operator, e.g. 2^3! is 2^(3!)
+        while self.token_matches(Bang) {
+            node = self.parse_factorial(node)?;
+        }
+
         while !self.at_end() && prec <= BinOp::from(&self.get_token()?.typ).precedence() {
             node = match self.get_token()?.typ {
                 Plus => self.parse_addition(node)?,
                 Minus => self.parse_subtraction(node)?,
                 Star => self.parse_multiplication(node)?,
                 Slash => self.parse_division(node)?,
+                Percent => self.parse_modulo(node)?,
                 Caret => self.parse_power(node)?,
-                EqualEqual | Less | LessEqual | Greater | GreaterEqual => {
+                EqualEqual | Less | LessEqual | Greater | GreaterEqual | Keyword(KeywordType::In) => {
                     self.parse_comparison(node)?
                 }
                 Equal => self.parse_assignment(node)?,
+                Pipe => self.parse_substitution(node)?,
                 _ => {
                     // TODO: Support custom infix operators?
                     break;
@@ -246,24 +385,92 @@ impl Parser {
 
     fn parse_number(&mut self) -> ParseResult<Expr> {
         parser_debug!("Parsing number");
-        let TokenType::Number(n) = self.consume()?.typ else {
+        let token = self.consume()?;
+        let TokenType::Number(n) = token.typ else {
             panic!("Expected a number.");
         };
 
-        let number = Expr::Number(n);
+        let mut number = Expr::Number(n);
+
+        if self.at_unit(0) {
+            let unit = self.parse_units()?;
+            number = Expr::BinaryOp {
+                left: Box::new(number),
+                operation: BinOp::Multiply,
+                right: Box::new(unit),
+                pos: token.pos,
+            };
+        }
 
         parser_debug!("Returning {:?}", number);
 
         Ok(number)
     }
 
+    /// Units like `km/h` or `kg*m/s^2` after a number.
+    fn parse_units(&mut self) -> ParseResult<Expr> {
+        parser_debug!("Parsing units");
+        let mut units = self.parse_unit()?;
+
+        // `5 m / 2 s` divides by 2 s, while `5 m/s` is a speed
+        while self.at_unit(1)
+            && (self.token_matches(TokenType::Star) || self.token_matches(TokenType::Slash))
+        {
+            let operator = self.consume()?;
+            let operation = match operator.typ {
+                TokenType::Star => BinOp::Multiply,
+                _ => BinOp::Divide,
+            };
+            let right = self.parse_unit()?;
+            units = Expr::BinaryOp {
+                left: Box::new(units),
+                operation,
+                right: Box::new(right),
+                pos: operator.pos,
+            };
+        }
+
+        parser_debug!("Returning units {:?}", units);
+        Ok(units)
+    }
+
+    /// A unit with an optional power, e.g. `s^2`.
+    fn parse_unit(&mut self) -> ParseResult<Expr> {
+        let (_, name) = self.expect_identifier("Expected a unit.")?;
+        let unit = Expr::Unit(name);
+
+        if !self.token_matches(TokenType::Caret) {
+            return Ok(unit);
+        }
+
+        let operator = self.consume()?;
+        let exponent = self.expect_expression(Precedence::Exponent)?;
+        Ok(Expr::BinaryOp {
+            left: Box::new(unit),
+            operation: BinOp::Power,
+            right: Box::new(exponent),
+            pos: operator.pos,
+        })
+    }
+
+    /// Whether the token at the offset is the name of a unit, and not a function call like `m(x)`.
+    fn at_unit(&mut self, offset: isize) -> bool {
+        let is_unit = self.peek(offset).is_some_and(|token| {
+            matches!(&token.typ, TokenType::Identifier(name) if PhysicalUnit::parse(name).is_some())
+        });
+        is_unit
+            && !self
+                .peek(offset + 1)
+                .is_some_and(|token| token.typ == TokenType::LeftParenthesis)
+    }
+
     fn parse_identifier(&mut self) -> ParseResult<Expr> {
         parser_debug!("Parsing identifier");
-        let (_, id) = self.expect_identifier("Expected identifier.")?;
+        let (token, id) = self.expect_identifier("Expected identifier.")?;
 
         if self.token_matches(TokenType::LeftParenthesis) {
             // Assume function call
-            return self.parse_function_call(id);
+            return self.parse_function_call(id, token.pos);
         }
 
         let variable = Expr::Variable(id);
@@ -279,8 +486,14 @@ impl Parser {
         let expr = match kw {
             KeywordType::If => self.parse_if()?,
             KeywordType::Simplify => self.parse_simplify()?,
+            KeywordType::With => self.parse_with()?,
+            // Keywords that only continue an expression, e.g. a lone `where`
             _ => {
-                panic!("Unhandled: {:?}", kw);
+                let name = format!("{:?}", kw).to_lowercase();
+                return Err(ParseError::UnexpectedToken {
+                    message: format!("Unexpected keyword `{}`", name),
+                    actual: self.get_token()?,
+                })
             }
         };
 
@@ -299,7 +512,7 @@ impl Parser {
 
     fn parse_if(&mut self) -> ParseResult<Expr> {
         parser_debug!("Parsing if expression");
-        self.expect_keyword(KeywordType::If, "Expected if")?;
+        let if_token = self.expect_keyword(KeywordType::If, "Expected if")?;
 
         let condition = Box::new(self.expect_expression(Precedence::None)?);
 
@@ -315,6 +528,7 @@ impl Parser {
             condition,
             body,
             else_body,
+            pos: if_token.pos,
         };
 
         parser_debug!("Returning {:?}", if_expr);
@@ -322,7 +536,39 @@ impl Parser {
         Ok(if_expr)
     }
 
-    fn parse_function_call(&mut self, id: String) -> ParseResult<Expr> {
+    fn parse_with(&mut self) -> ParseResult<Expr> {
+        parser_debug!("Parsing with expression");
+        let with_token = self.expect_keyword(KeywordType::With, "Expected with")?;
+
+        let (token, name) = self.expect_identifier("Expected assume after with")?;
+        if name != "assume" {
+            return Err(ParseError::NotIdentifier {
+                message: "Expected assume after with".to_string(),
+                actual: token,
+            });
+        }
+        let Expr::FunctionCall { args: facts, .. } = self.parse_function_call(name, token.pos)? else {
+            unreachable!("Function calls are parsed as function calls");
+        };
+
+        self.expect(TokenType::LeftBrace, "Expected { after the assumptions")?;
+        self.consume_newlines()?;
+        let body = Box::new(self.expect_expression(Precedence::None)?);
+        self.consume_newlines()?;
+        self.expect(TokenType::RightBrace, "Expected } after the body of with")?;
+
+        let with = Expr::With {
+            facts,
+            body,
+            pos: with_token.pos,
+        };
+
+        parser_debug!("Returning {:?}", with);
+
+        Ok(with)
+    }
+
+    fn parse_function_call(&mut self, id: String, pos: Position) -> ParseResult<Expr> {
         parser_debug!("Parsing function call");
         self.expect(
             TokenType::LeftParenthesis,
@@ -331,9 +577,11 @@ impl Parser {
 
         if self.get_token()?.typ == TokenType::RightParenthesis {
             parser_debug!("No arguments passed");
+            self.consume()?;
             return Ok(Expr::FunctionCall {
                 name: id,
                 args: vec![],
+                pos,
             });
         }
 
@@ -351,7 +599,7 @@ impl Parser {
             "Expected parenthesis after arguments of function call",
         )?;
 
-        let function_call = Expr::FunctionCall { name: id, args };
+        let function_call = Expr::FunctionCall { name: id, args, pos };
         parser_debug!("Returning {:?}", function_call);
         Ok(function_call)
     }
@@ -363,6 +611,10 @@ impl Parser {
         )?;
 
         let expression = self.parse_expression()?;
+        if self.token_matches(TokenType::Dot) {
+            // [0 .. 1]
+            return self.parse_interval(expression, true);
+        }
         let mut expressions: Vec<Expr> = Vec::from([expression]);
 
         while self.get_token()?.typ == TokenType::Comma {
@@ -370,6 +622,19 @@ impl Parser {
             expressions.push(self.parse_expression()?);
         }
 
+        if expressions.len() == 2 && self.token_matches(TokenType::RightParenthesis) {
+            // [0, 1)
+            self.consume()?;
+            let upper = expressions.pop().unwrap();
+            let lower = expressions.pop().unwrap();
+            return Ok(Expr::Interval {
+                lower: Box::new(lower),
+                upper: Box::new(upper),
+                lower_closed: true,
+                upper_closed: false,
+            });
+        }
+
         self.expect(
             TokenType::RightSquareBracket,
             "Expected closing square bracket for vector",
@@ -381,19 +646,120 @@ impl Parser {
     fn parse_grouping(&mut self) -> ParseResult<Expr> {
         self.expect(TokenType::LeftParenthesis, "Expected opening parenthesis")?;
         let node = self.expect_expression(Precedence::None)?;
+        if self.token_matches(TokenType::Comma) || self.token_matches(TokenType::Dot) {
+            // (0, 1] or (0 .. 1)
+            return self.parse_interval(node, false);
+        }
         self.expect(TokenType::RightParenthesis, "Expected closing parenthesis")?;
 
         Ok(node)
     }
 
+    /// The rest of an interval after its lower bound, e.g. `, 1)` or `.. 1]`.
+    fn parse_interval(&mut self, lower: Expr, lower_closed: bool) -> ParseResult<Expr> {
+        parser_debug!("Parsing interval");
+        if self.token_matches(TokenType::Dot) {
+            self.consume()?;
+            self.expect(TokenType::Dot, "Expected .. between the bounds of the interval")?;
+        } else {
+            self.expect(TokenType::Comma, "Expected , or .. between the bounds of the interval")?;
+        }
+
+        let upper = self.expect_expression(Precedence::None)?;
+        let upper_closed = self.token_matches(TokenType::RightSquareBracket);
+        if upper_closed {
+            self.consume()?;
+        } else {
+            self.expect(TokenType::RightParenthesis, "Expected ] or ) after the interval")?;
+        }
+
+        let interval = Expr::Interval {
+            lower: Box::new(lower),
+            upper: Box::new(upper),
+            lower_closed,
+            upper_closed,
+        };
+
+        parser_debug!("Returning {:?}", interval);
+        Ok(interval)
+    }
+
+    /// A finite set like `{1, 2, x}` or a set-builder like `{x in Z | x^2 < 10}`.
+    fn parse_set(&mut self) -> ParseResult<Expr> {
+        parser_debug!("Parsing set");
+        self.expect(TokenType::LeftBrace, "Expected opening brace for set")?;
+
+        let mut elements = Vec::new();
+        if !self.token_matches(TokenType::RightBrace) {
+            let element = self.expect_expression(Precedence::Comparison)?;
+            if self.token_matches(TokenType::Pipe) {
+                return self.parse_set_builder(element);
+            }
+            elements.push(element);
+
+            while self.token_matches(TokenType::Comma) {
+                self.consume()?;
+                elements.push(self.expect_expression(Precedence::Comparison)?);
+            }
+        }
+
+        self.expect(TokenType::RightBrace, "Expected closing brace for set")?;
+
+        let set = Expr::Set(elements);
+        parser_debug!("Returning {:?}", set);
+        Ok(set)
+    }
+
+    /// The rest of a set-builder after `x` or `x in domain`, e.g. `| x^2 < 10}`.
+    fn parse_set_builder(&mut self, head: Expr) -> ParseResult<Expr> {
+        let pipe = self.expect(TokenType::Pipe, "Expected | after the variable of the set")?;
+
+        let (variable, domain) = match head {
+            Expr::Variable(name) => (name, None),
+            Expr::BinaryOp {
+                left,
+                operation: BinOp::In,
+                right,
+                pos: _,
+            } => match *left {
+                Expr::Variable(name) => (name, Some(right)),
+                _ => {
+                    return Err(ParseError::NotIdentifier {
+                        message: "Expected a variable before in".to_string(),
+                        actual: pipe,
+                    })
+                }
+            },
+            _ => {
+                return Err(ParseError::NotIdentifier {
+                    message: "Expected a variable before | in the set".to_string(),
+                    actual: pipe,
+                })
+            }
+        };
+
+        let condition = self.expect_expression(Precedence::Comparison)?;
+        self.expect(TokenType::RightBrace, "Expected closing brace for set")?;
+
+        let builder = Expr::SetBuilder {
+            variable,
+            domain,
+            condition: Box::new(condition),
+        };
+
+        parser_debug!("Returning {:?}", builder);
+        Ok(builder)
+    }
+
     fn parse_addition(&mut self, left: Expr) -> ParseResult<Expr> {
         parser_debug!("Parsing addition");
-        self.expect(TokenType::Plus, "Expected addition operator")?;
+        let operator = self.expect(TokenType::Plus, "Expected addition operator")?;
         let right = self.expect_expression(Precedence::Factor)?;
         let node = Expr::BinaryOp {
             left: left.into(),
             operation: BinOp::Add,
             right: right.into(),
+            pos: operator.pos,
         };
 
         parser_debug!("Returning addition {:?}", node);
@@ -402,13 +768,14 @@ impl Parser {
 
     fn parse_subtraction(&mut self, left: Expr) -> ParseResult<Expr> {
         parser_debug!("Parsing subtraction");
-        self.expect(TokenType::Minus, "Expected subtraction operator")?;
+        let operator = self.expect(TokenType::Minus, "Expected subtraction operator")?;
         let right = self.expect_expression(Precedence::Factor)?;
 
         let node = Expr::BinaryOp {
             left: Box::new(left),
             operation: BinOp::Subtract,
             right: Box::new(right),
+            pos: operator.pos,
         };
 
         parser_debug!("Returning subtraction {:?}", node);
@@ -417,7 +784,7 @@ impl Parser {
 
     fn parse_multiplication(&mut self, left: Expr) -> ParseResult<Expr> {
         parser_debug!("Parsing multiplication");
-        self.expect(TokenType::Star, "Expected multiplication operator")?;
+        let operator = self.expect(TokenType::Star, "Expected multiplication operator")?;
         let right = self.expect_expression(Precedence::Exponent)?;
         // let Ok(right) = right else {
         // parser_error!("ERROR: {:?}", right.clone().err().unwrap());
@@ -427,6 +794,7 @@ impl Parser {
             left: left.into(),
             operation: BinOp::Multiply,
             right: right.into(),
+            pos: operator.pos,
         };
 
         parser_debug!("Returning multiplication {:?}", node);
@@ -437,29 +805,61 @@ impl Parser {
     // TODO: Merge with parse_multiplication
     fn parse_division(&mut self, left: Expr) -> ParseResult<Expr> {
         parser_debug!("Parsing division");
-        self.consume()?;
+        let operator = self.consume()?;
         let right = self.expect_expression(Precedence::Exponent)?;
 
         let node = Expr::BinaryOp {
             left: Box::new(left),
             operation: BinOp::Divide,
             right: Box::new(right),
+            pos: operator.pos,
         };
 
         parser_debug!("Returning division {:?}", node);
         Ok(node)
     }
 
+    fn parse_modulo(&mut self, left: Expr) -> ParseResult<Expr> {
+        parser_debug!("Parsing modulo");
+        let operator = self.expect(TokenType::Percent, "Expected modulo operator")?;
+        let right = self.expect_expression(Precedence::Exponent)?;
+
+        let node = Expr::BinaryOp {
+            left: Box::new(left),
+            operation: BinOp::Modulo,
+            right: Box::new(right),
+            pos: operator.pos,
+        };
+
+        parser_debug!("Returning modulo {:?}", node);
+        Ok(node)
+    }
+
+    fn parse_factorial(&mut self, expr: Expr) -> ParseResult<Expr> {
+        parser_debug!("Parsing factorial");
+        let operator = self.expect(TokenType::Bang, "Expected factorial operator")?;
+
+        let node = Expr::FunctionCall {
+            name: "factorial".to_string(),
+            args: vec![expr],
+            pos: operator.pos,
+        };
+
+        parser_debug!("Returning factorial {:?}", node);
+        Ok(node)
+    }
+
     fn parse_power(&mut self, left: Expr) -> ParseResult<Expr> {
         parser_debug!("Parsing power");
         // TODO: Something higher than Exponent?
-        self.expect(TokenType::Caret, "Expected power operator")?;
+        let operator = self.expect(TokenType::Caret, "Expected power operator")?;
         let right = self.expect_expression(Precedence::Exponent)?;
 
         let node = Expr::BinaryOp {
             left: Box::new(left),
             operation: BinOp::Power,
             right: Box::new(right),
+            pos: operator.pos,
         };
 
         parser_debug!("Returning power {:?}", node);
@@ -487,12 +887,13 @@ impl Parser {
             left: Box::new(left),
             operation,
             right: Box::new(right),
+            pos: token.pos,
         })
     }
 
     fn parse_assignment(&mut self, holder: Expr) -> ParseResult<Expr> {
         parser_debug!("Parsing assignment");
-        self.expect(
+        let operator = self.expect(
             TokenType::Equal,
             "Expected assignment operator after variable name.",
         )?;
@@ -502,16 +903,45 @@ impl Parser {
         Ok(Expr::Assignment {
             holder: Box::new(holder),
             value: Box::new(value),
+            pos: operator.pos,
         })
     }
 
+    fn parse_substitution(&mut self, expr: Expr) -> ParseResult<Expr> {
+        parser_debug!("Parsing substitution");
+        self.expect(TokenType::Pipe, "Expected | before substitutions")?;
+
+        let mut bindings = vec![self.parse_binding()?];
+        while self.token_matches(TokenType::Comma) {
+            self.consume()?;
+            bindings.push(self.parse_binding()?);
+        }
+
+        let substitution = Expr::Substitution {
+            expr: Box::new(expr),
+            bindings,
+        };
+
+        parser_debug!("Returning {:?}", substitution);
+        Ok(substitution)
+    }
+
+    /// A single `x = value` of a substitution.
+    fn parse_binding(&mut self) -> ParseResult<(String, Expr)> {
+        let (_, name) = self.expect_identifier("Expected the variable to substitute")?;
+        self.expect(TokenType::Equal, "Expected = after the variable to substitute")?;
+        let value = self.expect_expression(Precedence::Comparison)?;
+
+        Ok((name, value))
+    }
+
     fn parse_unary_minus(&mut self) -> ParseResult<Expr> {
         parser_debug!("Parsing unary minus");
-        self.expect(TokenType::Minus, "Expected unary minus before expression")?;
+        let operator = self.expect(TokenType::Minus, "Expected unary minus before expression")?;
 
         let expr = self.expect_expression(Precedence::Unary)?;
 
-        let unary = Expr::Unary(Box::new(expr));
+        let unary = Expr::Unary(Box::new(expr), operator.pos);
 
         debug!("Returning {:?}", unary);
 
@@ -631,3 +1061,37 @@ impl Parser {
         self.cur_token.is_none()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::cas::syntax::lexer::Lexer;
+
+    use super::Parser;
+
+    fn parse_error(input: &str) -> String {
+        match Parser::new(Lexer::new(input).collect()).parse() {
+            Ok(program) => panic!("{} parsed as {:?}", input, program),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn operators_without_operands_are_errors() {
+        assert_eq!(parse_error("%"), "Expected an expression, found Percent");
+        assert_eq!(parse_error("!"), "Expected an expression, found Bang");
+        assert_eq!(parse_error("3 + ->"), "Expected an expression, found Arrow");
+        assert_eq!(parse_error("|"), "Expected an expression, found Pipe");
+        assert_eq!(parse_error("."), "Expected an expression, found Dot");
+        assert_eq!(parse_error("}"), "Expected an expression, found RightBrace");
+    }
+
+    #[test]
+    fn keywords_without_expression_are_errors() {
+        assert_eq!(parse_error("where"), "Unexpected keyword `where`");
+        assert_eq!(parse_error("1 + where"), "Unexpected keyword `where`");
+        assert_eq!(parse_error("in"), "Unexpected keyword `in`");
+        assert_eq!(parse_error("1 + in"), "Unexpected keyword `in`");
+        assert_eq!(parse_error("then"), "Unexpected keyword `then`");
+        assert_eq!(parse_error("else"), "Unexpected keyword `else`");
+    }
+}
@@ -0,0 +1,487 @@
+//! Checking the declared parameter types of functions before a program runs.
+//!
+//! Values are only given a type when it is certain, e.g. `2 * 3` is an integer while `2 * x` isn't
+//! known, since `x` could be anything. Calls are only checked for the arguments with a known type.
+//! The declared return type of a function is checked against its body, and is the type of a call
+//! that can only go to that function.
+
+use log::debug;
+use matex_common::{
+    error::TypeError,
+    function::{Function, Parameter},
+    node::{BinOp, Expr, Program, Statement},
+    types::TypeAlias,
+    util::{Position, SymbolTable},
+};
+
+use super::{Type, Union};
+
+macro_rules! checker_debug {
+    ($($arg:tt)+) => (debug!(target: "matex::checker", "[{}:{}] {}", file!(), line!(), &format!($($arg)+)));
+}
+
+type CheckResult<T> = Result<T, TypeError>;
+
+/// The declared types of an overload, `None` where no type is declared
+#[derive(Debug, Clone, PartialEq)]
+struct Overload {
+    params: Vec<Option<Union>>,
+    returns: Option<Union>,
+}
+
+pub struct TypeChecker {
+    /// Every overload of a function
+    functions: SymbolTable<Vec<Overload>>,
+    /// The types declared by `type`, like the aliases of the runtime
+    aliases: SymbolTable<Union>,
+    /// The known types of variables, like the scopes of the runtime
+    scopes: Vec<SymbolTable<Option<Type>>>,
+}
+
+impl TypeChecker {
+    pub fn new() -> Self {
+        Self {
+            functions: SymbolTable::default(),
+            aliases: SymbolTable::default(),
+            scopes: vec![SymbolTable::default()],
+        }
+    }
+
+    /// Checks the program, remembering its functions and variables for the programs after it.
+    pub fn check(&mut self, Program(statements): &Program) -> CheckResult<()> {
+        for statement in statements {
+            self.check_statement(statement)?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for TypeChecker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TypeChecker {
+    fn check_statement(&mut self, statement: &Statement) -> CheckResult<()> {
+        match statement {
+            Statement::FunctionDefinition(function) => self.check_function(function),
+            Statement::UnsetVariable(name) => {
+                self.scope_mut().remove(name);
+                Ok(())
+            }
+            // Rules only rewrite symbolic values
+            Statement::RuleDefinition(_) => Ok(()),
+            Statement::TypeDefinition(alias) => self.check_type_definition(alias),
+            Statement::Expression(expr) => self.infer(expr).map(|_| ()),
+        }
+    }
+
+    fn check_type_definition(&mut self, alias: &TypeAlias) -> CheckResult<()> {
+        let union = resolve(&alias.type_name, &self.aliases, alias.pos, || {
+            format!("in the definition of {}", alias.name)
+        })?;
+        checker_debug!("Type {} is {}", alias.name, union);
+        self.aliases.insert(alias.name.clone(), union);
+        Ok(())
+    }
+
+    fn check_function(&mut self, function: &Function) -> CheckResult<()> {
+        checker_debug!("Check function {}", function.name);
+
+        let types = function
+            .params
+            .iter()
+            .map(|param| self.resolve_parameter(param))
+            .collect::<CheckResult<Vec<_>>>()?;
+        let returns = match function.return_type.as_str() {
+            "" => None,
+            typ => Some(resolve(typ, &self.aliases, function.pos, || {
+                format!("of the result of {}", function.name)
+            })?),
+        };
+
+        // Known before the body is checked, so that recursive calls are checked too
+        let overload = Overload {
+            params: types.clone(),
+            returns: returns.clone(),
+        };
+        let overloads = self.functions.entry(function.name.clone()).or_default();
+        match overloads.iter_mut().find(|it| it.params == types) {
+            Some(known) => *known = overload,
+            None => overloads.push(overload),
+        }
+
+        let scope = function
+            .params
+            .iter()
+            .zip(types)
+            .map(|(param, typ)| (param.name.clone(), typ.and_then(|it| it.join())))
+            .collect();
+
+        self.scopes.push(scope);
+        let checked = self.check_function_body(function, returns);
+        self.scopes.pop();
+
+        checked
+    }
+
+    fn check_function_body(
+        &mut self,
+        function: &Function,
+        returns: Option<Union>,
+    ) -> CheckResult<()> {
+        if let Some(domain) = &function.domain {
+            let domain = self.infer(domain)?;
+            expect_type(&Type::Bool.into(), domain, function.pos, || {
+                format!("The domain of {}", function.name)
+            })?;
+        }
+
+        let body = self.infer(&function.body)?;
+        if let Some(returns) = returns {
+            expect_type(&returns, body, function.pos, || {
+                format!("The result of {}", function.name)
+            })?;
+        }
+        Ok(())
+    }
+
+    /// The type of the value of an expression, if it is known.
+    fn infer(&mut self, expr: &Expr) -> CheckResult<Option<Type>> {
+        let typ = match expr {
+            Expr::Number(n) if n.fract() == 0.0 => Some(Type::Integer),
+            Expr::Number(_) => Some(Type::Real),
+            Expr::Variable(name) => self.scope().get(name).copied().flatten(),
+            // Values with units aren't numbers
+            Expr::Unit(_) => None,
+            Expr::Vector(values) => {
+                for value in values {
+                    self.infer(value)?;
+                }
+                Some(Type::Vector)
+            }
+            Expr::Set(elements) => {
+                for element in elements {
+                    self.infer(element)?;
+                }
+                Some(Type::Set)
+            }
+            Expr::Interval { lower, upper, .. } => {
+                self.infer(lower)?;
+                self.infer(upper)?;
+                Some(Type::Set)
+            }
+            Expr::SetBuilder {
+                variable,
+                domain,
+                condition,
+            } => {
+                if let Some(domain) = domain {
+                    self.infer(domain)?;
+                }
+
+                // The bound variable can be any element of the domain
+                let mut scope = self.scope().clone();
+                scope.insert(variable.clone(), None);
+                self.scopes.push(scope);
+                let condition = self.infer(condition);
+                self.scopes.pop();
+
+                condition?;
+                Some(Type::Set)
+            }
+            Expr::Unary(expr, _) => self.infer(expr)?.filter(|it| it.is_numeric()),
+            Expr::Simplify(expr) => self.infer(expr)?,
+            Expr::BinaryOp {
+                left,
+                operation,
+                right,
+                pos: _,
+            } => {
+                let lhs = self.infer(left)?;
+                let rhs = self.infer(right)?;
+                infer_operation(operation, lhs, rhs)
+            }
+            Expr::Assignment {
+                holder,
+                value,
+                pos: _,
+            } => {
+                let typ = self.infer(value)?;
+                if let Expr::Variable(name) = holder.as_ref() {
+                    // Values of other variables can change later, see `Runtime::get_reactive_value`
+                    let known = typ.filter(|_| is_constant(value));
+                    self.scope_mut().insert(name.clone(), known);
+                }
+                typ
+            }
+            Expr::If {
+                condition,
+                body,
+                else_body,
+                pos,
+            } => {
+                let condition = self.infer(condition)?;
+                expect_type(&Type::Bool.into(), condition, *pos, || {
+                    "The condition".to_string()
+                })?;
+
+                let body = self.infer(body)?;
+                let else_body = self.infer(else_body)?;
+                match (body, else_body) {
+                    (Some(body), Some(else_body)) => body.join(else_body),
+                    _ => None,
+                }
+            }
+            Expr::FunctionCall { name, args, pos } => {
+                let args = args
+                    .iter()
+                    .map(|it| self.infer(it))
+                    .collect::<CheckResult<Vec<_>>>()?;
+                self.check_call(name, &args, *pos)?
+            }
+            Expr::With {
+                facts,
+                body,
+                pos: _,
+            } => {
+                for fact in facts {
+                    self.infer(fact)?;
+                }
+                self.infer(body)?
+            }
+            Expr::Substitution { expr, bindings } => {
+                self.infer(expr)?;
+                for (_, value) in bindings {
+                    self.infer(value)?;
+                }
+                None
+            }
+        };
+
+        checker_debug!("{:?} has type {:?}", expr, typ);
+        Ok(typ)
+    }
+
+    /// Checks the arguments of a call, giving the type of the result if it is known.
+    fn check_call(
+        &self,
+        name: &str,
+        args: &[Option<Type>],
+        pos: Position,
+    ) -> CheckResult<Option<Type>> {
+        // Functions that aren't defined are kept as they are, e.g. `f(x)`
+        let Some(overloads) = self.functions.get(name) else {
+            return Ok(None);
+        };
+
+        let candidates = overloads
+            .iter()
+            .filter(|it| it.params.len() == args.len())
+            .collect::<Vec<_>>();
+
+        match (overloads.as_slice(), candidates.as_slice()) {
+            ([overload], []) => Err(TypeError::WrongArgumentCount {
+                message: format!(
+                    "{} takes {} arguments, but {} were given",
+                    name,
+                    overload.params.len(),
+                    args.len()
+                ),
+                expected: overload.params.len(),
+                actual: args.len(),
+                pos,
+            }),
+            // The types of a single overload are checked one by one, for a precise error
+            (_, [overload]) => {
+                for (index, (param, arg)) in overload.params.iter().zip(args).enumerate() {
+                    if let Some(param) = param {
+                        expect_type(param, *arg, pos, || {
+                            format!("Argument {} of {}", index + 1, name)
+                        })?;
+                    }
+                }
+                Ok(overload.returns.as_ref().and_then(Union::join))
+            }
+            _ if candidates.iter().any(|it| accepts_all(&it.params, args)) => Ok(None),
+            _ => {
+                let args = args
+                    .iter()
+                    .map(|it| it.map_or("_".to_string(), |it| it.to_string()))
+                    .collect::<Vec<_>>();
+                Err(TypeError::NoMatchingOverload {
+                    message: format!("No overload of {} accepts ({})", name, args.join(", ")),
+                    pos,
+                })
+            }
+        }
+    }
+
+    fn resolve_parameter(&self, param: &Parameter) -> CheckResult<Option<Union>> {
+        if param.type_name.is_empty() {
+            return Ok(None);
+        }
+
+        let union = resolve(&param.type_name, &self.aliases, param.pos, || {
+            format!("of parameter {}", param.name)
+        })?;
+        Ok(Some(union))
+    }
+
+    fn scope(&self) -> &SymbolTable<Option<Type>> {
+        self.scopes.last().expect("the global scope is never popped")
+    }
+
+    fn scope_mut(&mut self) -> &mut SymbolTable<Option<Type>> {
+        self.scopes.last_mut().expect("the global scope is never popped")
+    }
+}
+
+fn resolve(
+    type_name: &str,
+    aliases: &SymbolTable<Union>,
+    pos: Position,
+    what: impl FnOnce() -> String,
+) -> CheckResult<Union> {
+    Union::resolve(type_name, aliases).map_err(|name| TypeError::UnknownType {
+        message: format!("Unknown type {} {}", name, what()),
+        name,
+        pos,
+    })
+}
+
+/// Fails if the type is known, and isn't the expected one.
+fn expect_type(
+    expected: &Union,
+    actual: Option<Type>,
+    pos: Position,
+    what: impl FnOnce() -> String,
+) -> CheckResult<()> {
+    match actual {
+        Some(actual) if !expected.contains(actual) => Err(TypeError::Mismatch {
+            message: format!("{} should be {}, but is {}", what(), expected, actual),
+            expected: expected.to_string(),
+            actual: actual.to_string(),
+            pos,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Whether the known types of the arguments are all accepted by the parameters.
+fn accepts_all(params: &[Option<Union>], args: &[Option<Type>]) -> bool {
+    params.iter().zip(args).all(|it| match it {
+        (Some(param), Some(arg)) => param.contains(*arg),
+        _ => true,
+    })
+}
+
+fn infer_operation(operation: &BinOp, lhs: Option<Type>, rhs: Option<Type>) -> Option<Type> {
+    let (lhs, rhs) = (lhs?, rhs?);
+    if !lhs.is_numeric() || !rhs.is_numeric() {
+        return None;
+    }
+
+    match operation {
+        BinOp::Add | BinOp::Subtract | BinOp::Multiply | BinOp::Modulo => lhs.join(rhs),
+        BinOp::Divide => lhs.join(rhs)?.join(Type::Rational),
+        // Negative exponents give fractions, other exponents can give anything
+        BinOp::Power if rhs == Type::Integer => lhs.join(Type::Rational),
+        BinOp::Power => None,
+        // Only comparisons of numbers are decided, others stay relations
+        BinOp::Equal | BinOp::Less | BinOp::LessEqual | BinOp::Greater | BinOp::GreaterEqual
+            if lhs.is_subtype_of(Type::Real) && rhs.is_subtype_of(Type::Real) =>
+        {
+            Some(Type::Bool)
+        }
+        _ => None,
+    }
+}
+
+/// Whether an expression has the same value no matter what the variables are.
+fn is_constant(expr: &Expr) -> bool {
+    match expr {
+        Expr::Number(_) => true,
+        Expr::Vector(values) => values.iter().all(is_constant),
+        Expr::Unary(expr, _) | Expr::Simplify(expr) => is_constant(expr),
+        Expr::BinaryOp { left, right, .. } => is_constant(left) && is_constant(right),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TypeChecker;
+    use crate::cas::{
+        eval::testing::Session,
+        syntax::{lexer::Lexer, parser::Parser},
+    };
+
+    #[test]
+    fn arguments_of_the_declared_type() {
+        let mut session = Session::new();
+        session.eval("f(x: integer) = x + 1");
+        assert_eq!(session.eval("f(2)"), "3");
+        assert_eq!(session.eval("f(y)"), "y + 1");
+        assert_eq!(
+            session.error("f([1, 2])"),
+            "Argument 1 of f should be integer, but is vector at (row 1, col 1)"
+        );
+        assert_eq!(
+            session.error("f(1, 2)"),
+            "f takes 1 arguments, but 2 were given at (row 1, col 1)"
+        );
+    }
+
+    #[test]
+    fn unknown_types() {
+        assert_eq!(
+            Session::new().error("g(x: donkey) = x"),
+            "Unknown type donkey of parameter x at (row 1, col 3)"
+        );
+    }
+
+    #[test]
+    fn bodies_are_checked() {
+        let mut session = Session::new();
+        assert_eq!(
+            session.error("h(v: vector) = if v then 1 else 2"),
+            "The condition should be bool, but is vector at (row 1, col 17)"
+        );
+        assert_eq!(
+            session.error("if 1 then 2 else 3"),
+            "The condition should be bool, but is integer at (row 1, col 2)"
+        );
+    }
+
+    #[test]
+    fn declared_return_types() {
+        let mut session = Session::new();
+        assert_eq!(
+            session.error("h(x: integer) -> integer = x / 2"),
+            "The result of h should be integer, but is rational at (row 1, col 1)"
+        );
+        assert_eq!(
+            session.error("g(x) -> foo = x"),
+            "Unknown type foo of the result of g at (row 1, col 1)"
+        );
+
+        session.eval("k(x) -> vector = [x]");
+        assert_eq!(
+            session.error("if k(1) then 1 else 2"),
+            "The condition should be bool, but is vector at (row 1, col 2)"
+        );
+    }
+
+    #[test]
+    fn examples_pass_the_checker() {
+        for example in [
+            include_str!("../../../../examples/test.mx"),
+            include_str!("../../../../examples/test2.mx"),
+        ] {
+            let program = Parser::new(Lexer::new(example).collect()).parse().unwrap();
+            TypeChecker::new().check(&program).unwrap();
+        }
+    }
+}
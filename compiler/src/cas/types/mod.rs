@@ -0,0 +1,339 @@
+//! The types of values, as written in parameter declarations like `abs(x: real)`.
+//!
+//! A declaration may allow several types, `f(x: integer | vector)`, or name a type declared by
+//! `type Num = integer | rational | real`.
+
+pub mod checker;
+
+use std::{fmt::Display, str::FromStr};
+
+use matex_common::util::SymbolTable;
+
+use super::eval::{
+    assumptions, units,
+    value::{self, Factors, RunType, RunVal, Terms},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Type {
+    Integer,
+    Rational,
+    Real,
+    Complex,
+    Bool,
+    Vector,
+    Symbol,
+    Set,
+}
+
+impl Type {
+    /// Whether every value of this type is also a value of `other`, e.g. every integer is real.
+    pub fn is_subtype_of(self, other: Type) -> bool {
+        match (self.numeric_rank(), other.numeric_rank()) {
+            (Some(lhs), Some(rhs)) => lhs <= rhs,
+            _ => self == other,
+        }
+    }
+
+    /// The smallest type containing both types, if there is one.
+    pub fn join(self, other: Type) -> Option<Type> {
+        if self.is_subtype_of(other) {
+            Some(other)
+        } else if other.is_subtype_of(self) {
+            Some(self)
+        } else {
+            None
+        }
+    }
+
+    /// The most specific type of a value, if it has one.
+    ///
+    /// Numeric expressions like `sqrt(2)` are real, while symbolic ones like `x + 1` have no type.
+    pub fn of(value: &RunVal) -> Option<Type> {
+        use RunType::*;
+        match &value.typ {
+            Number(n) if n.fract() == 0.0 => Some(Type::Integer),
+            Number(_) => Some(Type::Real),
+            Constant(value::Constant::I) => Some(Type::Complex),
+            Constant(_) => Some(Type::Real),
+            Bool(_) => Some(Type::Bool),
+            Vector(_) => Some(Type::Vector),
+            Symbol(_) => Some(Type::Symbol),
+            Set(_) => Some(Type::Set),
+            // Some real number between the bounds
+            Interval(_) => Some(Type::Real),
+            Sum(_) | Product(_) | Exponent(_, _) if is_numeric_expression(value) => {
+                let mut approximated = value.approximate();
+                approximated.simplify();
+                match approximated.typ {
+                    Number(_) => Type::of(&approximated),
+                    _ => Some(Type::Complex),
+                }
+            }
+            Unit
+            | Undefined
+            | Sum(_)
+            | Product(_)
+            | Exponent(_, _)
+            | Function(_, _)
+            | Piecewise(_)
+            | PhysicalUnit(_) => None,
+        }
+    }
+
+    /// Whether a value belongs to the type, or `None` if that can't be told yet.
+    ///
+    /// A symbol stands for any value, so symbolic values can't be told apart until they're known,
+    /// unless the symbol is assumed to be of a type, e.g. `assume(n, integer)`.
+    pub fn accepts(self, value: &RunVal) -> Option<bool> {
+        match (Type::of(value), &value.typ) {
+            (Some(Type::Symbol), RunType::Symbol(name)) if self != Type::Symbol => {
+                let assumed = assumptions::assumed_type(name)?;
+                if assumed.is_subset_of(&self.into()) {
+                    Some(true)
+                } else if assumed.types().iter().all(|it| it.join(self).is_none()) {
+                    Some(false)
+                } else {
+                    None
+                }
+            }
+            // Numbers are fractions, even the approximations of real numbers
+            (Some(Type::Real), RunType::Number(n)) if self == Type::Rational => Some(n.is_finite()),
+            (Some(typ), _) => Some(typ.is_subtype_of(self)),
+            (None, RunType::Unit | RunType::Undefined) => Some(false),
+            (None, _) => None,
+        }
+    }
+
+    pub fn is_numeric(self) -> bool {
+        self.numeric_rank().is_some()
+    }
+
+    /// The position in the chain of numbers `integer ⊂ rational ⊂ real ⊂ complex`.
+    fn numeric_rank(self) -> Option<u8> {
+        match self {
+            Type::Integer => Some(0),
+            Type::Rational => Some(1),
+            Type::Real => Some(2),
+            Type::Complex => Some(3),
+            Type::Bool | Type::Vector | Type::Symbol | Type::Set => None,
+        }
+    }
+}
+
+/// A declared type, a value of any of the types belongs to it, e.g. `integer | vector`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Union(Vec<Type>);
+
+impl Union {
+    /// Resolves a type as it is declared, with the names of the aliases, e.g. `Num | vector`.
+    ///
+    /// Returns the first name that is neither a type nor an alias.
+    pub fn resolve(type_name: &str, aliases: &SymbolTable<Union>) -> Result<Union, String> {
+        let mut types = Vec::new();
+        for name in type_name.split('|').map(str::trim) {
+            match (name.parse::<Type>(), aliases.get(name)) {
+                (Ok(typ), _) => types.push(typ),
+                (Err(()), Some(Union(aliased))) => types.extend(aliased),
+                (Err(()), None) => return Err(name.to_string()),
+            }
+        }
+
+        // Types contained in others are left out, `integer | real` is the same as `real`
+        let mut union: Vec<Type> = Vec::new();
+        for typ in types {
+            if union.iter().any(|it| typ.is_subtype_of(*it)) {
+                continue;
+            }
+            union.retain(|it| !it.is_subtype_of(typ));
+            union.push(typ);
+        }
+        Ok(Union(union))
+    }
+
+    pub fn types(&self) -> &[Type] {
+        &self.0
+    }
+
+    /// Whether the values of the type belong to the union.
+    pub fn contains(&self, typ: Type) -> bool {
+        self.0.iter().any(|it| typ.is_subtype_of(*it))
+    }
+
+    /// Whether every value belonging to this union belongs to `other` as well.
+    pub fn is_subset_of(&self, other: &Union) -> bool {
+        self.0.iter().all(|it| other.contains(*it))
+    }
+
+    /// The smallest single type containing the union, if there is one.
+    pub fn join(&self) -> Option<Type> {
+        let (first, rest) = self.0.split_first()?;
+        rest.iter().try_fold(*first, |joined, it| joined.join(*it))
+    }
+
+    /// Whether a value belongs to any of the types, or `None` if that can't be told yet.
+    pub fn accepts(&self, value: &RunVal) -> Option<bool> {
+        let accepted = self
+            .0
+            .iter()
+            .map(|it| it.accepts(value))
+            .collect::<Vec<_>>();
+        if accepted.contains(&Some(true)) {
+            Some(true)
+        } else if accepted.contains(&None) {
+            None
+        } else {
+            Some(false)
+        }
+    }
+}
+
+impl From<Type> for Union {
+    fn from(typ: Type) -> Self {
+        Union(vec![typ])
+    }
+}
+
+impl Display for Union {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names = self.0.iter().map(Type::to_string).collect::<Vec<_>>();
+        f.write_str(&names.join(" | "))
+    }
+}
+
+/// The type of any value, as reported by `typeof`, e.g. `integer` or `sum` for `x + 1`.
+pub fn describe(value: &RunVal) -> String {
+    use RunType::*;
+    if let Some(typ) = Type::of(value) {
+        return typ.to_string();
+    }
+    // Values with units are told by their dimension, e.g. `quantity in m/s` for `3 m/s`
+    if let Some(dimension) = units::dimension(value).filter(|it| !it.is_dimensionless()) {
+        return format!("quantity in {}", dimension);
+    }
+
+    let name = match &value.typ {
+        Unit => "unit",
+        Undefined => "undefined",
+        Sum(_) => "sum",
+        Product(_) => "product",
+        Exponent(_, _) => "exponent",
+        Function(_, _) => "function",
+        Piecewise(_) => "piecewise",
+        PhysicalUnit(_) => "quantity",
+        Number(_) | Interval(_) | Constant(_) | Bool(_) | Vector(_) | Symbol(_) | Set(_) => {
+            "unknown"
+        }
+    };
+    name.to_string()
+}
+
+impl FromStr for Type {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "integer" => Ok(Type::Integer),
+            "rational" => Ok(Type::Rational),
+            "real" => Ok(Type::Real),
+            "complex" => Ok(Type::Complex),
+            "bool" => Ok(Type::Bool),
+            "vector" => Ok(Type::Vector),
+            "symbol" => Ok(Type::Symbol),
+            "set" => Ok(Type::Set),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Type::Integer => "integer",
+            Type::Rational => "rational",
+            Type::Real => "real",
+            Type::Complex => "complex",
+            Type::Bool => "bool",
+            Type::Vector => "vector",
+            Type::Symbol => "symbol",
+            Type::Set => "set",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Whether a value is made up of numbers and constants only.
+fn is_numeric_expression(value: &RunVal) -> bool {
+    use RunType::*;
+    match &value.typ {
+        Number(_) | Interval(_) | Constant(_) => true,
+        Sum(Terms(values)) | Product(Factors(values)) => values.iter().all(is_numeric_expression),
+        Exponent(base, exp) => is_numeric_expression(base) && is_numeric_expression(exp),
+        Unit
+        | Undefined
+        | Symbol(_)
+        | Bool(_)
+        | Vector(_)
+        | Function(_, _)
+        | Piecewise(_)
+        | Set(_)
+        | PhysicalUnit(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cas::eval::testing::{eval, Session};
+
+    #[test]
+    fn subtypes() {
+        assert!(Type::Integer.is_subtype_of(Type::Real));
+        assert!(!Type::Real.is_subtype_of(Type::Rational));
+        assert_eq!(Type::Integer.join(Type::Rational), Some(Type::Rational));
+        assert_eq!(Type::Integer.join(Type::Vector), None);
+    }
+
+    #[test]
+    fn unions_and_aliases() {
+        let mut aliases = SymbolTable::default();
+        let num = Union::resolve("integer | real", &aliases).unwrap();
+        assert_eq!(num.types(), [Type::Real]);
+
+        aliases.insert("Num".to_string(), num);
+        let union = Union::resolve("Num | vector", &aliases).unwrap();
+        assert_eq!(union.types(), [Type::Real, Type::Vector]);
+        assert!(union.contains(Type::Integer));
+        assert_eq!(union.join(), None);
+        assert_eq!(Union::resolve("Num | foo", &aliases), Err("foo".to_string()));
+    }
+
+    #[test]
+    fn union_parameters() {
+        let mut session = Session::new();
+        session.eval("type Num = integer | rational | real");
+        session.eval("g(x: Num) -> Num = x * 2");
+        assert_eq!(session.eval("g(3)"), "6");
+        assert_eq!(session.error("g(I)"), "No overload of g accepts (I) at (row 1, col 1)");
+
+        session.eval("h(x: integer) = 1");
+        session.eval("h(x: integer | vector) = 2");
+        assert_eq!(session.eval("[h(3), h([1])]"), "[1, 2]");
+        assert_eq!(
+            session.error("type Bad = integer | foo"),
+            "Unknown type foo in the definition of Bad at (row 1, col 8)"
+        );
+    }
+
+    #[test]
+    fn type_of_values() {
+        assert_eq!(eval("typeof(2)"), "integer");
+        assert_eq!(eval("typeof([1])"), "vector");
+        assert_eq!(eval("typeof(x)"), "symbol");
+        assert_eq!(eval("typeof(1 < 2)"), "bool");
+        assert_eq!(eval("typeof(x + 1)"), "sum");
+        assert_eq!(eval("typeof(1 m)"), "quantity in m");
+        assert_eq!(eval("typeof(4 m^2)"), "quantity in m^2");
+        assert_eq!(eval("typeof(3 km/h)"), "quantity in m/s");
+    }
+}
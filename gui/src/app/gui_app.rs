@@ -6,6 +6,7 @@ use matex_compiler::cas::{
         runtime::Runtime,
     },
     syntax::{lexer::Lexer, parser::Parser},
+    types::checker::TypeChecker,
 };
 
 use super::widgets::inspector::Inspector;
@@ -18,16 +19,27 @@ pub struct MatexApp {
     state: State,
     source: String,
 
-    executed: Vec<(String, String)>,
+    /// The source, the output and the steps taken to get to the output
+    executed: Vec<(String, String, Vec<String>)>,
     runtime: Runtime,
+    checker: TypeChecker,
 }
 
 impl MatexApp {
     fn render_executions(&self, ui: &mut Ui) {
-        for (source, output) in &self.executed {
+        for (source, output, steps) in &self.executed {
             ui.add(Label::new(
                 RichText::new("i>".to_owned() + source).font(FontId::monospace(12.0)),
             ));
+            if !steps.is_empty() {
+                ui.collapsing(format!("{} steps", steps.len()), |ui| {
+                    for step in steps {
+                        ui.add(Label::new(
+                            RichText::new(step).font(FontId::monospace(10.0)),
+                        ));
+                    }
+                });
+            }
             ui.add(Label::new(
                 RichText::new("o>".to_owned() + output).font(FontId::monospace(12.0)),
             ));
@@ -45,6 +57,7 @@ impl Default for MatexApp {
             source: "".to_owned(),
             executed: Vec::new(),
             runtime: Runtime::new(),
+            checker: TypeChecker::new(),
         }
     }
 }
@@ -79,11 +92,19 @@ impl App for MatexApp {
 
                     if ui.button("Run").clicked() {
                         if let Ok(program) = Parser::new(Lexer::new(&self.source).collect()).parse() {
-                            let last_value = self.runtime.run(&program);
-                            self.executed.push((
-                                self.source.clone(),
-                                format!("{}", NormalFormatter::format(&last_value)),
-                            ));
+                            let executed = match self
+                                .checker
+                                .check(&program)
+                                .map_err(|e| e.to_string())
+                                .and_then(|_| self.runtime.run_traced(&program).map_err(|e| e.to_string()))
+                            {
+                                Ok((last_value, steps)) => (
+                                    format!("{}", NormalFormatter::format(&last_value)),
+                                    steps.iter().map(ToString::to_string).collect(),
+                                ),
+                                Err(e) => (format!("Error: {}", e), Vec::new()),
+                            };
+                            self.executed.push((self.source.clone(), executed.0, executed.1));
                             self.source.clear();
                         }
                     }
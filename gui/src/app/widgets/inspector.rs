@@ -11,15 +11,22 @@ use matex_compiler::cas::eval::{
 pub struct Inspector {}
 
 impl Inspector {
-    pub fn ui(ui: &mut Ui, functions: &SymbolTable<Function>, variables: &SymbolTable<RunVal>) {
+    pub fn ui(ui: &mut Ui, functions: &SymbolTable<Vec<Function>>, variables: &SymbolTable<RunVal>) {
         ui.collapsing(RichText::new("Functions").heading(), |ui| {
             egui::Grid::new("func_grid")
                 .num_columns(1)
                 .spacing(egui::vec2(0.0, 4.0))
                 .striped(true)
                 .show(ui, |ui| {
-                    for value in functions.values() {
-                        let Function { name, params, body } = value;
+                    for value in functions.values().flatten() {
+                        let Function {
+                            name,
+                            params,
+                            return_type,
+                            domain,
+                            body,
+                            ..
+                        } = value;
                         let params_string = params
                             .iter()
                             .map(|param| param.name.clone())
@@ -27,9 +34,15 @@ impl Inspector {
                             .join(", ");
                         let function_string = format!("{}({})", name, params_string);
                         ui.collapsing(function_string, |ui| {
-                            for Parameter { name, type_name } in params {
+                            for Parameter { name, type_name, .. } in params {
                                 ui.label(format!("{name}: {type_name}"));
                             }
+                            if !return_type.is_empty() {
+                                ui.label(format!("returns: {return_type}"));
+                            }
+                            if let Some(domain) = domain {
+                                ui.label(format!("where: {:?}", domain));
+                            }
                             ui.label(format!("body: {:?}", body));
                         });
                         ui.end_row();
@@ -5,9 +5,10 @@ use matex_compiler::cas::{
     backend::{
         format::{NormalFormatter, ValueFormatter},
         runtime::Runtime,
-        value::RuntimeVal,
+        trace::Step,
     },
     frontend::{lexer::Lexer, parser::Parser},
+    types::checker::TypeChecker,
 };
 use wasm_bindgen::JsValue;
 
@@ -36,12 +37,34 @@ pub fn new_debug_app(canvas_id: String) {
 
 #[wasm_bindgen]
 pub fn run(source: String) -> JsValue {
+    let mut parser = Parser::new(Lexer::new(&source).collect());
+    match parser.parse() {
+        Ok(program) => {
+            if let Err(e) = TypeChecker::new().check(&program) {
+                return serde_wasm_bindgen::to_value(&e).unwrap();
+            }
+
+            let mut rt = Runtime::new();
+            match rt.run(&program) {
+                Ok(value) => serde_wasm_bindgen::to_value(&value).unwrap(),
+                Err(e) => serde_wasm_bindgen::to_value(&e).unwrap(),
+            }
+        }
+        Err(e) => serde_wasm_bindgen::to_value(&e).unwrap(),
+    }
+}
+
+/// The steps taken to run the source, as `{ rule, before, after }` objects.
+#[wasm_bindgen]
+pub fn steps(source: String) -> JsValue {
     let mut parser = Parser::new(Lexer::new(&source).collect());
     match parser.parse() {
         Ok(program) => {
             let mut rt = Runtime::new();
-            let value = rt.run(&program);
-            return serde_wasm_bindgen::to_value(&value).unwrap();
+            match rt.run_traced(&program) {
+                Ok((_, steps)) => serde_wasm_bindgen::to_value::<Vec<Step>>(&steps).unwrap(),
+                Err(e) => serde_wasm_bindgen::to_value(&e).unwrap(),
+            }
         }
         Err(e) => serde_wasm_bindgen::to_value(&e).unwrap(),
     }